@@ -0,0 +1,45 @@
+// Prefix matching on a large index: the binary search of
+// find_words_by_prefix and its finite state transducer against comparing
+// every headword.
+//
+//   cargo bench --bench prefix
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dictrdlib::{IndexEntry, IndexReader};
+
+// An index of 200000 made-up headwords
+fn large_index() -> IndexReader {
+    let mut lines = String::new();
+    for i in 0..200_000u32 {
+        let word: String = format!("{:06}", i)
+            .bytes()
+            .map(|b| (b'a' + (b - b'0') * 2) as char)
+            .collect();
+        lines.push_str(&format!("{}\tA\tB\n", word));
+    }
+    let mut di = IndexReader::new();
+    di.parse_dict_index(lines.as_bytes());
+    di
+}
+
+fn prefix(c: &mut Criterion) {
+    let di = large_index();
+    c.bench_function("prefix binary search", |b| {
+        b.iter(|| di.find_words_by_prefix(black_box("acg")).unwrap())
+    });
+    let mut fst = large_index();
+    fst.build_fst_index(1);
+    c.bench_function("prefix fst", |b| {
+        b.iter(|| fst.find_words_by_prefix(black_box("acg")).unwrap())
+    });
+    c.bench_function("prefix linear scan", |b| {
+        b.iter(|| {
+            di.iter()
+                .filter(|entry| entry.word.starts_with(black_box("acg")))
+                .cloned()
+                .collect::<Vec<IndexEntry>>()
+        })
+    });
+}
+
+criterion_group!(benches, prefix);
+criterion_main!(benches);
@@ -0,0 +1,230 @@
+// All configured servers queried at once with --all-servers, e.g. servers
+// with the dictionaries of different languages. Their results are merged,
+// dropping what a server repeats of another one, and their databases are
+// labeled with the server: "wn@dict.example.org". A labeled database is
+// only looked up on its server.
+use super::{open, DEFAULT_PORT};
+use crate::config::ServerConfig;
+use crate::connection::{Definition, Lookup};
+use crate::exitcode;
+use dictrdlib::client::DictClient;
+use std::collections::HashSet;
+use std::io;
+use std::thread;
+
+pub struct Aggregate {
+    // Label of each server and the connection to it, in the order of the
+    // configuration
+    servers: Vec<(String, DictClient)>,
+}
+
+// The host, and the port unless it is the default
+fn label(server: &ServerConfig) -> String {
+    if server.port == DEFAULT_PORT {
+        server.host.clone()
+    } else {
+        format!("{}:{}", server.host, server.port)
+    }
+}
+
+fn labeled(database: &str, label: &str) -> String {
+    format!("{}@{}", database, label)
+}
+
+// The answers of the servers that gave one. Servers failing, e.g. because
+// they do not have a database, are left out unless all of them failed.
+fn answers<T>(results: Vec<(String, io::Result<T>)>) -> io::Result<Vec<(String, T)>> {
+    let mut answers = Vec::new();
+    let mut error = None;
+    for (label, result) in results {
+        match result {
+            Ok(answer) => answers.push((label, answer)),
+            Err(e) => {
+                error.get_or_insert(e);
+            }
+        }
+    }
+    match error {
+        Some(e) if answers.is_empty() => Err(e),
+        _ => Ok(answers),
+    }
+}
+
+impl Aggregate {
+    // Connects to the servers at once, those that do not answer are left
+    // out
+    pub fn connect(servers: &[ServerConfig]) -> io::Result<Aggregate> {
+        let connections: Vec<io::Result<DictClient>> = thread::scope(|scope| {
+            let handles: Vec<_> = servers
+                .iter()
+                .map(|server| scope.spawn(move || open(server)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        let mut connected = Vec::new();
+        for (server, connection) in servers.iter().zip(connections) {
+            match connection {
+                Ok(connection) => connected.push((label(server), connection)),
+                Err(e) => eprintln!(
+                    "Could not connect to {}:{}: {}",
+                    server.host,
+                    server.port,
+                    exitcode::describe(&e)
+                ),
+            }
+        }
+        if connected.is_empty() {
+            return Err(io::Error::other("No server answered"));
+        }
+        Ok(Aggregate { servers: connected })
+    }
+
+    // Asks the servers at once, or only the server a database is labeled
+    // with, which is asked for the database without the label. Returns the
+    // label of each server asked with its answer.
+    fn ask<T, F>(&mut self, database: &str, ask: F) -> Vec<(String, io::Result<T>)>
+    where
+        T: Send,
+        F: Fn(&mut DictClient, &str) -> io::Result<T> + Sync,
+    {
+        let (database, only) = match database.rsplit_once('@') {
+            Some((name, label)) if self.servers.iter().any(|(l, _)| l == label) => {
+                (name, Some(label))
+            }
+            _ => (database, None),
+        };
+        let ask = &ask;
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .servers
+                .iter_mut()
+                .filter(|(label, _)| only.is_none_or(|only| only == label))
+                .map(|(label, connection)| {
+                    (
+                        label.clone(),
+                        scope.spawn(move || ask(connection, database)),
+                    )
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|(label, handle)| (label, handle.join().unwrap()))
+                .collect()
+        })
+    }
+
+    // The texts of the servers, each under its label
+    fn texts(&mut self, ask: fn(&mut DictClient) -> io::Result<String>) -> io::Result<String> {
+        let mut text = String::new();
+        for (label, answer) in answers(self.ask("*", |connection, _| ask(connection)))? {
+            text.push_str(&format!("{}:\n{}", label, answer));
+        }
+        Ok(text)
+    }
+}
+
+impl Lookup for Aggregate {
+    fn define(&mut self, database: &str, word: &str) -> io::Result<Vec<Definition>> {
+        let results = self.ask(database, |connection, database| {
+            connection.define(database, word)
+        });
+        let mut seen = HashSet::new();
+        let mut definitions = Vec::new();
+        for (label, found) in answers(results)? {
+            for mut definition in found {
+                let key = (
+                    definition.database.clone(),
+                    definition.headword.clone(),
+                    definition.text.clone(),
+                );
+                if seen.insert(key) {
+                    definition.database = labeled(&definition.database, &label);
+                    definitions.push(definition);
+                }
+            }
+            // The definitions of the first server that has some
+            if database == "!" && !definitions.is_empty() {
+                break;
+            }
+        }
+        Ok(definitions)
+    }
+
+    fn match_words(
+        &mut self,
+        database: &str,
+        strategy: &str,
+        word: &str,
+    ) -> io::Result<Vec<(String, String)>> {
+        let results = self.ask(database, |connection, database| {
+            Lookup::match_words(connection, database, strategy, word)
+        });
+        let mut seen = HashSet::new();
+        let mut matches = Vec::new();
+        for (label, found) in answers(results)? {
+            for (database, headword) in found {
+                if seen.insert((database.clone(), headword.clone())) {
+                    matches.push((labeled(&database, &label), headword));
+                }
+            }
+            if database == "!" && !matches.is_empty() {
+                break;
+            }
+        }
+        Ok(matches)
+    }
+
+    fn show_databases(&mut self) -> io::Result<Vec<(String, String)>> {
+        let results = self.ask("*", |connection, _| Lookup::show_databases(connection));
+        let mut databases = Vec::new();
+        for (label, found) in answers(results)? {
+            for (name, description) in found {
+                databases.push((labeled(&name, &label), description));
+            }
+        }
+        Ok(databases)
+    }
+
+    fn show_strategies(&mut self) -> io::Result<Vec<(String, String)>> {
+        let results = self.ask("*", |connection, _| Lookup::show_strategies(connection));
+        let mut seen = HashSet::new();
+        let mut strategies = Vec::new();
+        for (_, found) in answers(results)? {
+            for (name, description) in found {
+                if seen.insert(name.clone()) {
+                    strategies.push((name, description));
+                }
+            }
+        }
+        Ok(strategies)
+    }
+
+    // An unlabeled database is described by the first server that has it
+    fn show_info(&mut self, database: &str) -> io::Result<Option<String>> {
+        let results = self.ask(database, |connection, database| {
+            Lookup::show_info(connection, database)
+        });
+        Ok(answers(results)?.into_iter().find_map(|(_, info)| info))
+    }
+
+    fn show_server(&mut self) -> io::Result<String> {
+        self.texts(Lookup::show_server)
+    }
+
+    fn help(&mut self) -> io::Result<String> {
+        self.texts(Lookup::help)
+    }
+
+    fn quit(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for (_, connection) in self.servers.iter_mut() {
+            if let Err(e) = Lookup::quit(connection) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
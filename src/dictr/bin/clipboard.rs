@@ -0,0 +1,73 @@
+// Access to the desktop clipboard through the tools of the platform. The
+// first tool that can be run is used.
+use std::io::{self, ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+#[cfg(target_os = "macos")]
+const PASTE: &[&[&str]] = &[&["pbpaste"]];
+#[cfg(target_os = "macos")]
+const COPY: &[&[&str]] = &[&["pbcopy"]];
+
+#[cfg(not(target_os = "macos"))]
+const PASTE: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-out"],
+    &["xsel", "--clipboard", "--output"],
+];
+#[cfg(not(target_os = "macos"))]
+const COPY: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard", "-in"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+fn no_tool(tools: &[&[&str]]) -> io::Error {
+    let names: Vec<&str> = tools.iter().map(|tool| tool[0]).collect();
+    io::Error::new(
+        ErrorKind::NotFound,
+        format!(
+            "No clipboard tool found, install one of {}",
+            names.join(", ")
+        ),
+    )
+}
+
+pub fn paste() -> io::Result<String> {
+    for tool in PASTE {
+        match Command::new(tool[0])
+            .args(&tool[1..])
+            .stderr(Stdio::null())
+            .output()
+        {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+            Ok(_) => continue,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(no_tool(PASTE))
+}
+
+pub fn copy(text: &str) -> io::Result<()> {
+    for tool in COPY {
+        let mut child = match Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        if child.wait()?.success() {
+            return Ok(());
+        }
+    }
+    Err(no_tool(COPY))
+}
@@ -0,0 +1,134 @@
+// Headwords completing the word typed in interactive mode, matched with the
+// prefix strategy on a connection of its own. The word is looked up in the
+// background once typing paused, so that Tab mostly finds its headwords
+// there already.
+use crate::connection::Lookup;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Only the last of the words typed in quick succession is looked up
+const DEBOUNCE: Duration = Duration::from_millis(150);
+// Headwords kept of a word, more are of no use as completions
+const MAX_COMPLETIONS: usize = 100;
+// Longest wait for the headwords after Tab
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+// The headwords of the word looked up last
+struct Found {
+    word: String,
+    headwords: Vec<String>,
+    // Whether these are all headwords starting with the word, and so
+    // contain those of longer words
+    all: bool,
+}
+
+impl Found {
+    fn completing(&self, word: &str) -> Option<Vec<String>> {
+        let word = word.to_lowercase();
+        if word == self.word.to_lowercase() {
+            return Some(self.headwords.clone());
+        }
+        if !self.all || !word.starts_with(&self.word.to_lowercase()) {
+            return None;
+        }
+        Some(
+            self.headwords
+                .iter()
+                .filter(|headword| headword.to_lowercase().starts_with(&word))
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+pub struct Completer {
+    requests: Sender<String>,
+    found: Arc<(Mutex<Option<Found>>, Condvar)>,
+}
+
+impl Completer {
+    // Completes with the headwords of database, on the connection open
+    // returns. Without a connection there are no completions.
+    pub fn start<F>(database: &str, open: F) -> Completer
+    where
+        F: FnOnce() -> Option<Box<dyn Lookup>> + Send + 'static,
+    {
+        let (requests, received) = mpsc::channel::<String>();
+        let found = Arc::new((Mutex::new(None), Condvar::new()));
+        let shared = found.clone();
+        let database = database.to_string();
+        thread::spawn(move || {
+            let mut connection = match open() {
+                Some(connection) => connection,
+                None => return,
+            };
+            while let Ok(mut word) = received.recv() {
+                while let Ok(newer) = received.recv_timeout(DEBOUNCE) {
+                    word = newer;
+                }
+                let mut headwords: Vec<String> = connection
+                    .match_words(&database, "prefix", &word)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(_, headword)| headword)
+                    .collect();
+                headwords.sort();
+                headwords.dedup();
+                let all = headwords.len() <= MAX_COMPLETIONS;
+                headwords.truncate(MAX_COMPLETIONS);
+                let (lock, changed) = &*shared;
+                *lock.lock().unwrap() = Some(Found {
+                    word,
+                    headwords,
+                    all,
+                });
+                changed.notify_all();
+            }
+            let _ = connection.quit();
+        });
+        Completer { requests, found }
+    }
+
+    fn cached(&self, word: &str) -> Option<Vec<String>> {
+        let found = self.found.0.lock().unwrap();
+        found.as_ref().and_then(|found| found.completing(word))
+    }
+
+    // Looks up the headwords of a word in the background, unless those
+    // found already complete it. Commands are not completed.
+    pub fn prefetch(&self, word: &str) {
+        if word.trim().is_empty() || word.starts_with(':') || self.cached(word).is_some() {
+            return;
+        }
+        let _ = self.requests.send(word.to_string());
+    }
+
+    // The headwords starting with a word, at most MAX_COMPLETIONS
+    pub fn complete(&self, word: &str) -> Vec<String> {
+        if word.trim().is_empty() || word.starts_with(':') {
+            return Vec::new();
+        }
+        if let Some(headwords) = self.cached(word) {
+            return headwords;
+        }
+        // The connection could not be opened
+        if self.requests.send(word.to_string()).is_err() {
+            return Vec::new();
+        }
+        let deadline = Instant::now() + TIMEOUT;
+        let (lock, changed) = &*self.found;
+        let mut found = lock.lock().unwrap();
+        loop {
+            if let Some(headwords) = found.as_ref().and_then(|found| found.completing(word)) {
+                return headwords;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Vec::new();
+            }
+            found = changed.wait_timeout(found, deadline - now).unwrap().0;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// Configuration file of the client, ~/.dictr.toml unless --config says
+// otherwise:
+//
+//   [[server]]
+//   host = "dict.example.org"
+//   port = 2628
+//   # Authenticate with SASL CRAM-MD5, keep the file private
+//   user = "tim"
+//   key = "tanstaaftanstaaf"
+//   # Give up after 5 seconds without an answer, try connecting 3 times
+//   timeout = 5
+//   retries = 2
+//   # Have the server compress its responses, for slow links
+//   compress = true
+//
+//   [[server]]
+//   host = "localhost"
+//
+// The servers are tried in order until one answers, or with --all-servers
+// queried at once. -h, -p, --timeout, --retries and --compress take
+// precedence over the file.
+use super::DEFAULT_PORT;
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    server: Vec<ServerConfig>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub user: Option<String>,
+    pub key: Option<String>,
+    // Seconds to wait for the server to connect and to answer, by default
+    // only to connect
+    pub timeout: Option<u64>,
+    // Connection attempts after the first while the server cannot be
+    // reached
+    #[serde(default)]
+    pub retries: u32,
+    // Whether to ask the server to compress its responses
+    #[serde(default)]
+    pub compress: bool,
+    // Whether to write the exchange with the server to stderr, only set by
+    // --raw
+    #[serde(skip)]
+    pub trace: bool,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+impl ServerConfig {
+    pub fn new(host: &str, port: u16) -> ServerConfig {
+        ServerConfig {
+            host: host.to_string(),
+            port,
+            user: None,
+            key: None,
+            timeout: None,
+            retries: 0,
+            compress: false,
+            trace: false,
+        }
+    }
+}
+
+impl Config {
+    // Reads a configuration file. A missing file is only an error if
+    // required.
+    pub fn load(path: &Path, required: bool) -> Result<Config, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        let config: Config =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        for server in config.server.iter() {
+            if server.user.is_some() != server.key.is_some() {
+                return Err(format!(
+                    "{}: server {} needs both user and key",
+                    path.display(),
+                    server.host
+                ));
+            }
+            if server.timeout == Some(0) {
+                return Err(format!(
+                    "{}: server {} needs a timeout of at least 1 second",
+                    path.display(),
+                    server.host
+                ));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn servers(&self) -> &[ServerConfig] {
+        &self.server
+    }
+}
@@ -0,0 +1,92 @@
+// What the client looks words up in: a DICT server through DictClient, or
+// the databases of --local
+pub use dictrdlib::client::Definition;
+use dictrdlib::client::{DictClient, Item};
+use dictrdlib::mime::ContentType;
+use std::io;
+
+pub trait Lookup {
+    // Looks up a word, no definitions is not an error
+    fn define(&mut self, database: &str, word: &str) -> io::Result<Vec<Definition>>;
+
+    // Matches a word, returning pairs of database and headword
+    fn match_words(
+        &mut self,
+        database: &str,
+        strategy: &str,
+        word: &str,
+    ) -> io::Result<Vec<(String, String)>>;
+
+    // Names and descriptions of the databases
+    fn show_databases(&mut self) -> io::Result<Vec<(String, String)>>;
+
+    // Names and descriptions of the strategies
+    fn show_strategies(&mut self) -> io::Result<Vec<(String, String)>>;
+
+    // Information about a database, None if the database is unknown
+    fn show_info(&mut self, database: &str) -> io::Result<Option<String>>;
+
+    // Information about the server
+    fn show_server(&mut self) -> io::Result<String>;
+
+    // The commands the server understands
+    fn help(&mut self) -> io::Result<String>;
+
+    fn quit(&mut self) -> io::Result<()>;
+}
+
+fn pairs(items: Vec<Item>) -> Vec<(String, String)> {
+    items
+        .into_iter()
+        .map(|item| (item.name, item.description))
+        .collect()
+}
+
+impl Lookup for DictClient {
+    // HTML and Markdown articles are shown as plain text
+    fn define(&mut self, database: &str, word: &str) -> io::Result<Vec<Definition>> {
+        let mut definitions = DictClient::define(self, database, word)?;
+        for definition in definitions.iter_mut() {
+            definition.text = definition.content_type.to_plain_text(&definition.text);
+            definition.content_type = ContentType::Plain;
+        }
+        Ok(definitions)
+    }
+
+    fn match_words(
+        &mut self,
+        database: &str,
+        strategy: &str,
+        word: &str,
+    ) -> io::Result<Vec<(String, String)>> {
+        Ok(self
+            .match_(database, strategy, word)?
+            .into_iter()
+            .map(|found| (found.database, found.headword))
+            .collect())
+    }
+
+    fn show_databases(&mut self) -> io::Result<Vec<(String, String)>> {
+        DictClient::show_databases(self).map(pairs)
+    }
+
+    fn show_strategies(&mut self) -> io::Result<Vec<(String, String)>> {
+        DictClient::show_strategies(self).map(pairs)
+    }
+
+    fn show_info(&mut self, database: &str) -> io::Result<Option<String>> {
+        DictClient::show_info(self, database)
+    }
+
+    fn show_server(&mut self) -> io::Result<String> {
+        DictClient::show_server(self)
+    }
+
+    fn help(&mut self) -> io::Result<String> {
+        DictClient::help(self)
+    }
+
+    fn quit(&mut self) -> io::Result<()> {
+        DictClient::quit(self)
+    }
+}
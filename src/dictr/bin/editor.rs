@@ -0,0 +1,149 @@
+// The lines of interactive mode. On a terminal they are edited with
+// Backspace, Ctrl-C clearing the line and Ctrl-D ending the input, and Tab
+// completes the word being typed with the headwords of the server. Other
+// input, and terminals without terminal::raw_mode, are read line by line.
+use crate::completion::Completer;
+use crate::terminal;
+use std::io::{self, Lines, Read, StdinLock, Write};
+
+const CTRL_C: u8 = 3;
+const CTRL_D: u8 = 4;
+const BACKSPACE: u8 = 8;
+const DELETE: u8 = 127;
+const ESCAPE: u8 = 0x1b;
+
+pub enum Input {
+    Editor(Completer),
+    Lines(Lines<StdinLock<'static>>),
+}
+
+impl Input {
+    // The next line after showing the prompt, None at the end of the input
+    pub fn line(&mut self, prompt: &str) -> Option<String> {
+        match self {
+            Input::Editor(completer) => edit(prompt, completer).unwrap_or_else(|e| {
+                eprintln!("Could not read the terminal: {}", e);
+                None
+            }),
+            Input::Lines(lines) => {
+                print!("{}", prompt);
+                let _ = io::stdout().flush();
+                lines.next().and_then(Result::ok)
+            }
+        }
+    }
+}
+
+fn edit(prompt: &str, completer: &Completer) -> io::Result<Option<String>> {
+    let _raw = terminal::raw_mode()?;
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout();
+    // Only the last line of the prompt is redrawn
+    let last = prompt.rsplit('\n').next().unwrap_or("");
+    let redraw = |stdout: &mut io::Stdout, line: &str| -> io::Result<()> {
+        write!(stdout, "\r{}{}\x1b[K", last, line)?;
+        stdout.flush()
+    };
+    write!(stdout, "{}", prompt)?;
+    stdout.flush()?;
+    let mut line = String::new();
+    // Bytes of a character not read completely
+    let mut pending = Vec::new();
+    loop {
+        let key = match read_byte(&mut stdin)? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        match key {
+            b'\r' | b'\n' => {
+                writeln!(stdout)?;
+                return Ok(Some(line));
+            }
+            CTRL_D if line.is_empty() => {
+                writeln!(stdout)?;
+                return Ok(None);
+            }
+            CTRL_C => {
+                line.clear();
+                writeln!(stdout, "^C")?;
+                redraw(&mut stdout, &line)?;
+                continue;
+            }
+            BACKSPACE | DELETE => {
+                if line.pop().is_none() {
+                    continue;
+                }
+                redraw(&mut stdout, &line)?;
+            }
+            b'\t' => {
+                let headwords = completer.complete(&line);
+                match complete(&line, &headwords) {
+                    Some(completed) => line = completed,
+                    None if headwords.is_empty() => write!(stdout, "\x07")?,
+                    None => {
+                        writeln!(stdout)?;
+                        writeln!(stdout, "{}", headwords.join("  "))?;
+                    }
+                }
+                redraw(&mut stdout, &line)?;
+                continue;
+            }
+            // Arrows and other keys sending escape sequences are ignored
+            ESCAPE => {
+                if let Some(b'[') | Some(b'O') = read_byte(&mut stdin)? {
+                    while let Some(byte) = read_byte(&mut stdin)? {
+                        if (0x40..=0x7e).contains(&byte) {
+                            break;
+                        }
+                    }
+                }
+                continue;
+            }
+            key if key < 0x20 => continue,
+            key => {
+                pending.push(key);
+                match std::str::from_utf8(&pending) {
+                    Ok(text) => {
+                        line.push_str(text);
+                        write!(stdout, "{}", text)?;
+                        stdout.flush()?;
+                        pending.clear();
+                    }
+                    Err(e) if e.error_len().is_some() => {
+                        pending.clear();
+                        continue;
+                    }
+                    Err(_) => continue,
+                }
+            }
+        }
+        completer.prefetch(&line);
+    }
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+// The line completed to the longest beginning the headwords share, None if
+// that is no longer than the line
+fn complete(line: &str, headwords: &[String]) -> Option<String> {
+    let first = headwords.first()?;
+    let mut shared = first.chars().count();
+    for headword in &headwords[1..] {
+        shared = first
+            .chars()
+            .zip(headword.chars())
+            .take(shared)
+            .take_while(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+            .count();
+    }
+    if shared <= line.chars().count() {
+        return None;
+    }
+    Some(first.chars().take(shared).collect())
+}
@@ -0,0 +1,81 @@
+// Exit statuses of dict(1), so that scripts and editors written for it can
+// tell what went wrong
+use dictrdlib::client::Status;
+use std::io;
+
+pub const NO_MATCH: i32 = 20;
+// Only headwords close to a word were found and shown
+pub const APPROXIMATE: i32 = 21;
+pub const NO_DATABASES: i32 = 22;
+pub const NO_STRATEGIES: i32 = 23;
+pub const UNEXPECTED: i32 = 30;
+pub const TEMPORARILY_UNAVAILABLE: i32 = 31;
+pub const SHUTTING_DOWN: i32 = 32;
+pub const SYNTAX_ERROR: i32 = 33;
+pub const ILLEGAL_PARAMETERS: i32 = 34;
+pub const COMMAND_NOT_IMPLEMENTED: i32 = 35;
+pub const PARAMETER_NOT_IMPLEMENTED: i32 = 36;
+pub const ACCESS_DENIED: i32 = 37;
+pub const AUTHENTICATION_FAILED: i32 = 38;
+pub const INVALID_DATABASE: i32 = 39;
+pub const INVALID_STRATEGY: i32 = 40;
+pub const CONNECTION_FAILED: i32 = 41;
+
+// The exit status for an error of a lookup, from the status the server
+// answered with. Errors without a status are the connection's.
+pub fn of_error(e: &io::Error) -> i32 {
+    let status = e.get_ref().and_then(|e| e.downcast_ref::<Status>());
+    match status.map(|status| status.code) {
+        Some(420) => TEMPORARILY_UNAVAILABLE,
+        Some(421) => SHUTTING_DOWN,
+        Some(500) => SYNTAX_ERROR,
+        Some(501) => ILLEGAL_PARAMETERS,
+        Some(502) => COMMAND_NOT_IMPLEMENTED,
+        Some(503) => PARAMETER_NOT_IMPLEMENTED,
+        Some(530) | Some(532) => ACCESS_DENIED,
+        Some(531) => AUTHENTICATION_FAILED,
+        Some(550) => INVALID_DATABASE,
+        Some(551) => INVALID_STRATEGY,
+        Some(552) => NO_MATCH,
+        Some(554) => NO_DATABASES,
+        Some(555) => NO_STRATEGIES,
+        Some(_) => UNEXPECTED,
+        None if e.kind() == io::ErrorKind::InvalidData => UNEXPECTED,
+        None => CONNECTION_FAILED,
+    }
+}
+
+// An error of a lookup for messages, telling a server that cannot be
+// reached or stopped answering from one that does not speak DICT, and both
+// from the errors the server answered with
+pub fn describe(e: &io::Error) -> String {
+    match of_error(e) {
+        CONNECTION_FAILED
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            "server unreachable: timed out".to_string()
+        }
+        CONNECTION_FAILED => format!("server unreachable: {}", e),
+        UNEXPECTED => format!("protocol error: {}", e),
+        _ => e.to_string(),
+    }
+}
+
+// The exit status of several lookups: errors go before words not found,
+// which go before words only close headwords were found for
+pub fn worst(status: i32, other: i32) -> i32 {
+    let rank = |status| match status {
+        0 => 0,
+        APPROXIMATE => 1,
+        NO_MATCH => 2,
+        _ => 3,
+    };
+    if rank(other) > rank(status) {
+        other
+    } else {
+        status
+    }
+}
@@ -0,0 +1,720 @@
+// Output formats of the client
+use crate::connection::Definition;
+use dictrdlib::json;
+
+pub enum Format {
+    Text(Style),
+    // Cross references link to the server at the base URL, e.g.
+    // dict://localhost:2628
+    Html(String),
+    // Like Html, for notes and wikis
+    Markdown(String),
+    // One JSON object per word and line, for scripts
+    Json,
+}
+
+// How definitions are shown as text
+#[derive(Clone, Copy, Default)]
+pub struct Style {
+    // Headwords in bold, cross references and pronunciations in color
+    pub color: bool,
+    // Lines longer than this are folded, e.g. to the width of the terminal
+    pub width: Option<usize>,
+}
+
+const BOLD: &str = "\x1b[1m";
+const REFERENCE: &str = "\x1b[36m";
+const PRONUNCIATION: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+const HTML_BEGIN: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>dictr</title>
+<style>
+body { font-family: sans-serif; max-width: 50em; margin: auto; }
+section.database { border-top: 1px solid #ccc; }
+pre { white-space: pre-wrap; }
+table.comparison { width: 100%; table-layout: fixed; border-collapse: collapse; }
+table.comparison th, table.comparison td { vertical-align: top; border: 1px solid #ccc; padding: 0 0.5em; }
+</style>
+</head>
+<body>
+";
+
+impl Format {
+    pub fn begin(&self) -> String {
+        match self {
+            Format::Text(_) | Format::Markdown(_) | Format::Json => String::new(),
+            Format::Html(_) => HTML_BEGIN.to_string(),
+        }
+    }
+
+    pub fn end(&self) -> String {
+        match self {
+            Format::Text(_) | Format::Markdown(_) | Format::Json => String::new(),
+            Format::Html(_) => "</body>\n</html>\n".to_string(),
+        }
+    }
+
+    // Separates the results of the words when several are looked up. The
+    // other formats name the word in each result already.
+    pub fn heading(&self, number: usize, word: &str) -> String {
+        match self {
+            Format::Text(_) if number == 0 => format!("==> {} <==\n", word),
+            Format::Text(_) => format!("\n==> {} <==\n", word),
+            Format::Markdown(_) if number > 0 => "\n".to_string(),
+            Format::Html(_) | Format::Markdown(_) | Format::Json => String::new(),
+        }
+    }
+
+    pub fn no_match(&self, word: &str) -> String {
+        match self {
+            Format::Text(_) => format!("No definitions found for \"{}\"\n", word),
+            Format::Html(_) => format!(
+                "<h1>{}</h1>\n<p>No definitions found</p>\n",
+                escape_html(word)
+            ),
+            Format::Markdown(_) => format!("# {}\n\nNo definitions found\n", escape_markdown(word)),
+            Format::Json => json_definitions(word, &[]),
+        }
+    }
+
+    pub fn no_matches(&self, word: &str) -> String {
+        match self {
+            Format::Text(_) => format!("No matches found for \"{}\"\n", word),
+            Format::Html(_) => format!("<h1>{}</h1>\n<p>No matches found</p>\n", escape_html(word)),
+            Format::Markdown(_) => format!("# {}\n\nNo matches found\n", escape_markdown(word)),
+            Format::Json => json_matches(word, &[]),
+        }
+    }
+
+    // Headwords close to a word without definitions, as pairs of database
+    // and headword
+    pub fn suggestions(&self, word: &str, suggestions: &[(String, String)]) -> String {
+        match self {
+            Format::Text(_) => text_suggestions(word, suggestions),
+            Format::Html(base) => format!(
+                "<h1>{}</h1>\n<p>No definitions found, perhaps you mean:</p>\n{}",
+                escape_html(word),
+                html_match_list(base, suggestions)
+            ),
+            Format::Markdown(base) => format!(
+                "# {}\n\nNo definitions found, perhaps you mean:\n\n{}",
+                escape_markdown(word),
+                markdown_match_list(base, suggestions)
+            ),
+            Format::Json => format!(
+                "{{\"word\":{},\"definitions\":[],\"suggestions\":{}}}\n",
+                json::string(word),
+                json_match_list(suggestions)
+            ),
+        }
+    }
+
+    // Headwords matching a word as pairs of database and headword
+    pub fn matches(&self, word: &str, matches: &[(String, String)]) -> String {
+        match self {
+            Format::Text(_) => matches
+                .iter()
+                .map(|(database, headword)| format!("{}  {}\n", database, headword))
+                .collect(),
+            Format::Html(base) => html_matches(base, word, matches),
+            Format::Markdown(base) => format!(
+                "# {}\n\n{}",
+                escape_markdown(word),
+                markdown_match_list(base, matches)
+            ),
+            Format::Json => json_matches(word, matches),
+        }
+    }
+
+    pub fn definitions(&self, word: &str, definitions: &[Definition]) -> String {
+        match self {
+            Format::Text(style) => text(definitions, *style),
+            Format::Html(base) => html(base, word, definitions),
+            Format::Markdown(base) => markdown(base, word, definitions),
+            Format::Json => json_definitions(word, definitions),
+        }
+    }
+
+    // The definitions of a word in several databases next to each other,
+    // one column per database. Databases without a definition get an empty
+    // column. width is the width of the terminal for text output.
+    pub fn comparison(
+        &self,
+        word: &str,
+        columns: &[(String, Vec<Definition>)],
+        width: usize,
+    ) -> String {
+        match self {
+            Format::Text(_) => text_columns(word, columns, width),
+            Format::Html(base) => html_columns(base, word, columns),
+            Format::Markdown(base) => markdown_columns(base, word, columns),
+            Format::Json => json_columns(word, columns),
+        }
+    }
+}
+
+// Label of a column, e.g. "Jargon File [jargon]"
+fn column_label(database: &str, definitions: &[Definition]) -> String {
+    match definitions.first() {
+        Some(definition) => format!("{} [{}]", definition.description, database),
+        None => database.to_string(),
+    }
+}
+
+fn column_text(definitions: &[Definition]) -> String {
+    if definitions.is_empty() {
+        return "(no definition)".to_string();
+    }
+    let texts: Vec<&str> = definitions.iter().map(|d| d.text.trim_end()).collect();
+    texts.join("\n\n")
+}
+
+// Columns narrower than this are hard to read, the databases are shown one
+// after the other then
+const MIN_COLUMN_WIDTH: usize = 24;
+const COLUMN_GAP: &str = " | ";
+
+fn text_columns(word: &str, columns: &[(String, Vec<Definition>)], width: usize) -> String {
+    let gaps = COLUMN_GAP.len() * columns.len().saturating_sub(1);
+    let column_width = width.saturating_sub(gaps) / columns.len().max(1);
+    if column_width < MIN_COLUMN_WIDTH {
+        let mut result = format!("Comparison of \"{}\"\n", word);
+        for (database, definitions) in columns {
+            result.push_str(&format!(
+                "\n== {} ==\n\n{}\n",
+                column_label(database, definitions),
+                column_text(definitions)
+            ));
+        }
+        return result;
+    }
+    let cells: Vec<Vec<String>> = columns
+        .iter()
+        .map(|(database, definitions)| {
+            let mut lines = wrap(&column_label(database, definitions), column_width);
+            lines.push("-".repeat(column_width));
+            lines.extend(wrap(&column_text(definitions), column_width));
+            lines
+        })
+        .collect();
+    let height = cells.iter().map(|lines| lines.len()).max().unwrap_or(0);
+    let mut result = String::new();
+    for row in 0..height {
+        let line: Vec<String> = cells
+            .iter()
+            .map(|lines| {
+                let cell = lines.get(row).map_or("", |line| line.as_str());
+                format!("{:width$}", cell, width = column_width)
+            })
+            .collect();
+        result.push_str(line.join(COLUMN_GAP).trim_end());
+        result.push('\n');
+    }
+    result
+}
+
+// Fills the paragraphs of a text into lines of at most width characters,
+// keeping the indentation of their first line. Words longer than a line are
+// split.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split("\n\n") {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        let indent_len = paragraph.len() - paragraph.trim_start_matches(' ').len();
+        let indent = " ".repeat(indent_len.min(width / 2));
+        let mut current = indent.clone();
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            while !word.is_empty() {
+                let used = current.chars().count();
+                let space = if used > indent.len() { 1 } else { 0 };
+                if used + space + word.len() <= width {
+                    if space == 1 {
+                        current.push(' ');
+                    }
+                    current.extend(word.drain(..));
+                } else if used > indent.len() {
+                    lines.push(std::mem::replace(&mut current, indent.clone()));
+                } else {
+                    let rest = word.split_off(width - used);
+                    current.extend(word.drain(..));
+                    lines.push(std::mem::replace(&mut current, indent.clone()));
+                    word = rest;
+                }
+            }
+        }
+        lines.push(current.trim_end().to_string());
+    }
+    lines
+}
+
+fn html_columns(base: &str, word: &str, columns: &[(String, Vec<Definition>)]) -> String {
+    let mut result = format!(
+        "<h1>{}</h1>\n<table class=\"comparison\">\n<tr>\n",
+        escape_html(word)
+    );
+    for (database, definitions) in columns {
+        result.push_str(&format!(
+            "<th>{}</th>\n",
+            escape_html(&column_label(database, definitions))
+        ));
+    }
+    result.push_str("</tr>\n<tr>\n");
+    for (database, definitions) in columns {
+        result.push_str(&format!(
+            "<td><pre>{}</pre></td>\n",
+            link_references(base, database, &column_text(definitions))
+        ));
+    }
+    result.push_str("</tr>\n</table>\n");
+    result
+}
+
+// The headwords of each database on a line, like dict(1):
+//
+//   No definitions found for "hackr", perhaps you mean:
+//   jargon:  hack  hacker
+fn text_suggestions(word: &str, suggestions: &[(String, String)]) -> String {
+    let mut result = format!("No definitions found for \"{}\", perhaps you mean:\n", word);
+    let mut current: Option<&str> = None;
+    for (database, headword) in suggestions {
+        if current != Some(database) {
+            if current.is_some() {
+                result.push('\n');
+            }
+            result.push_str(database);
+            result.push(':');
+            current = Some(database);
+        }
+        result.push_str("  ");
+        result.push_str(headword);
+    }
+    result.push('\n');
+    result
+}
+
+fn text(definitions: &[Definition], style: Style) -> String {
+    let mut result = match definitions.len() {
+        1 => "1 definition found\n".to_string(),
+        n => format!("{} definitions found\n", n),
+    };
+    for definition in definitions {
+        result.push_str(&format!(
+            "\nFrom {} [{}]:\n\n",
+            definition.description, definition.database
+        ));
+        // The first line holds the headword
+        let mut headword = true;
+        let mut in_reference = false;
+        for line in definition.text.lines() {
+            let lines = match style.width {
+                Some(width) => fold(line, width.saturating_sub(2)),
+                None => vec![line.to_string()],
+            };
+            for line in lines {
+                if !line.is_empty() {
+                    result.push_str("  ");
+                    if style.color {
+                        result.push_str(&colorize(&line, headword, &mut in_reference));
+                    } else {
+                        result.push_str(&line);
+                    }
+                }
+                result.push('\n');
+            }
+            headword = false;
+        }
+    }
+    result
+}
+
+// Splits a line longer than width at spaces, the lines after the first
+// indented like it. Words longer than a line are not split.
+fn fold(line: &str, width: usize) -> Vec<String> {
+    if width == 0 || line.chars().count() <= width {
+        return vec![line.to_string()];
+    }
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let mut lines = Vec::new();
+    let mut current = indent.to_string();
+    for word in line.split_whitespace() {
+        let used = current.chars().count();
+        if used > indent.len() && used + 1 + word.chars().count() > width {
+            lines.push(std::mem::replace(&mut current, indent.to_string()));
+        }
+        if current.len() > indent.len() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    lines.push(current);
+    lines
+}
+
+// Shows the headword in bold, cross references in {braces} in color without
+// the braces, and pronunciations between slashes or, after the headword, in
+// [brackets] in another color. A reference may continue on the next line,
+// in_reference carries that over.
+fn colorize(line: &str, headword: bool, in_reference: &mut bool) -> String {
+    if !headword {
+        return color_spans(line, false, in_reference);
+    }
+    // The headword ends where a pronunciation or its part of speech starts,
+    // e.g. "hus /hʉːs/" or "ABATIS, n."
+    let end = [" [", " /", " \\", ","]
+        .iter()
+        .filter_map(|separator| line.find(separator))
+        .min();
+    let (word, rest) = line.split_at(end.unwrap_or(line.len()));
+    let rest = color_spans(rest, true, in_reference);
+    format!("{}{}{}{}", BOLD, word, RESET, rest)
+}
+
+fn color_spans(line: &str, brackets: bool, in_reference: &mut bool) -> String {
+    let mut result = String::new();
+    if *in_reference {
+        result.push_str(REFERENCE);
+    }
+    let chars: Vec<char> = line.chars().collect();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        let after_space = pos == 0 || chars[pos - 1].is_whitespace();
+        if *in_reference {
+            if c == '}' {
+                result.push_str(RESET);
+                *in_reference = false;
+            } else {
+                result.push(c);
+            }
+        } else if c == '{' {
+            result.push_str(REFERENCE);
+            *in_reference = true;
+        } else if (c == '/' || (c == '[' && brackets)) && after_space {
+            let close = if c == '/' { '/' } else { ']' };
+            match pronunciation(&chars[pos + 1..], close) {
+                Some(len) => {
+                    result.push_str(PRONUNCIATION);
+                    result.extend(&chars[pos..pos + len + 2]);
+                    result.push_str(RESET);
+                    pos += len + 2;
+                    continue;
+                }
+                None => result.push(c),
+            }
+        } else {
+            result.push(c);
+        }
+        pos += 1;
+    }
+    if *in_reference {
+        result.push_str(RESET);
+    }
+    result
+}
+
+// Removes the colors of text output, e.g. before it is copied
+pub fn strip_colors(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b[") {
+        result.push_str(&rest[..start]);
+        rest = match rest[start..].find('m') {
+            Some(end) => &rest[start + end + 1..],
+            None => "",
+        };
+    }
+    result.push_str(rest);
+    result
+}
+
+// Length of a pronunciation up to the closing character, which must end a
+// word, e.g. /klooj/ or /n./
+fn pronunciation(chars: &[char], close: char) -> Option<usize> {
+    let len = chars.iter().position(|&c| c == close || c == '\n')?;
+    let ends_word = chars
+        .get(len + 1)
+        .is_none_or(|c| c.is_whitespace() || ",.;:)".contains(*c));
+    if len == 0 || chars[len] != close || chars[0].is_whitespace() || !ends_word {
+        return None;
+    }
+    Some(len)
+}
+
+// Every database is a section, cross references in {braces} become links
+fn html(base: &str, word: &str, definitions: &[Definition]) -> String {
+    let mut result = format!("<h1>{}</h1>\n", escape_html(word));
+    for definition in definitions {
+        result.push_str(&format!(
+            "<section class=\"database\">\n<h2>{} [{}]</h2>\n<pre>",
+            escape_html(&definition.description),
+            escape_html(&definition.database)
+        ));
+        result.push_str(&link_references(
+            base,
+            &definition.database,
+            &definition.text,
+        ));
+        result.push_str("</pre>\n</section>\n");
+    }
+    result
+}
+
+// The headwords link to their definitions
+fn html_matches(base: &str, word: &str, matches: &[(String, String)]) -> String {
+    format!(
+        "<h1>{}</h1>\n{}",
+        escape_html(word),
+        html_match_list(base, matches)
+    )
+}
+
+fn html_match_list(base: &str, matches: &[(String, String)]) -> String {
+    let mut result = "<ul>\n".to_string();
+    for (database, headword) in matches {
+        result.push_str(&format!(
+            "<li>{} <a href=\"{}/d:{}:{}\">{}</a></li>\n",
+            escape_html(database),
+            base,
+            encode_url(headword),
+            encode_url(database),
+            escape_html(headword)
+        ));
+    }
+    result.push_str("</ul>\n");
+    result
+}
+
+// Escapes a definition, cross references in {braces} become links
+fn link_references(base: &str, database: &str, text: &str) -> String {
+    map_references(text, escape_html, |reference, headword| {
+        format!(
+            "<a href=\"{}/d:{}:{}\">{}</a>",
+            base,
+            encode_url(headword),
+            encode_url(database),
+            escape_html(reference)
+        )
+    })
+}
+
+// Converts the text between cross references in {braces} with plain, and
+// the references with link, which gets the reference and its headword
+fn map_references<P, L>(text: &str, plain: P, link: L) -> String
+where
+    P: Fn(&str) -> String,
+    L: Fn(&str, &str) -> String,
+{
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let reference = &rest[start + 1..end];
+        // References may be wrapped, the headword is on one line
+        let headword = reference
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        result.push_str(&plain(&rest[..start]));
+        result.push_str(&link(reference, &headword));
+        rest = &rest[end + 1..];
+    }
+    result.push_str(&plain(rest));
+    result
+}
+
+// Every database is a section. The lines of a definition lose their
+// indentation, which would make them code blocks, and cross references in
+// {braces} become links.
+fn markdown(base: &str, word: &str, definitions: &[Definition]) -> String {
+    let mut result = format!("# {}\n", escape_markdown(word));
+    for definition in definitions {
+        result.push_str(&format!(
+            "\n## {} \\[{}\\]\n\n{}",
+            escape_markdown(&definition.description),
+            escape_markdown(&definition.database),
+            markdown_text(base, &definition.database, &definition.text)
+        ));
+    }
+    result
+}
+
+fn markdown_columns(base: &str, word: &str, columns: &[(String, Vec<Definition>)]) -> String {
+    let mut result = format!("# {}\n", escape_markdown(word));
+    for (database, definitions) in columns {
+        result.push_str(&format!(
+            "\n## {}\n\n{}",
+            escape_markdown(&column_label(database, definitions)),
+            markdown_text(base, database, &column_text(definitions))
+        ));
+    }
+    result
+}
+
+// The paragraphs of a definition with cross references as links
+fn markdown_text(base: &str, database: &str, text: &str) -> String {
+    let mut paragraphs = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let lines: Vec<&str> = paragraph
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.is_empty() {
+            continue;
+        }
+        let paragraph =
+            map_references(&lines.join("\n"), escape_markdown, |reference, headword| {
+                format!(
+                    "[{}]({}/d:{}:{})",
+                    escape_markdown(reference),
+                    base,
+                    encode_url(headword),
+                    encode_url(database)
+                )
+            });
+        // Lines must not start lists or headings
+        let lines: Vec<String> = paragraph.lines().map(escape_line_start).collect();
+        paragraphs.push(lines.join("\n"));
+    }
+    let mut result = paragraphs.join("\n\n");
+    result.push('\n');
+    result
+}
+
+fn markdown_match_list(base: &str, matches: &[(String, String)]) -> String {
+    matches
+        .iter()
+        .map(|(database, headword)| {
+            format!(
+                "- {}: [{}]({}/d:{}:{})\n",
+                escape_markdown(database),
+                escape_markdown(headword),
+                base,
+                encode_url(headword),
+                encode_url(database)
+            )
+        })
+        .collect()
+}
+
+// Backslash escapes the characters that mark up text anywhere in a line
+fn escape_markdown(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]<>".contains(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+// Escapes what would start a list, heading or quote at the start of a line,
+// e.g. "1. /n./" in the Jargon File
+fn escape_line_start(line: &str) -> String {
+    if line.starts_with(&['-', '+', '#', '='][..]) {
+        return format!("\\{}", line);
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && line[digits..].starts_with(&['.', ')'][..]) {
+        return format!("{}\\{}", &line[..digits], &line[digits..]);
+    }
+    line.to_string()
+}
+
+// {"word":...,"definitions":[{"database":...,"description":...,"definition":...}]}
+fn json_definitions(word: &str, definitions: &[Definition]) -> String {
+    format!(
+        "{{\"word\":{},\"definitions\":{}}}\n",
+        json::string(word),
+        json_definition_list(definitions)
+    )
+}
+
+fn json_definition_list(definitions: &[Definition]) -> String {
+    let items: Vec<String> = definitions
+        .iter()
+        .map(|definition| {
+            format!(
+                "{{\"database\":{},\"description\":{},\"definition\":{}}}",
+                json::string(&definition.database),
+                json::string(&definition.description),
+                json::string(&definition.text)
+            )
+        })
+        .collect();
+    format!("[{}]", items.join(","))
+}
+
+// {"word":...,"matches":[{"database":...,"headword":...}]}
+fn json_matches(word: &str, matches: &[(String, String)]) -> String {
+    format!(
+        "{{\"word\":{},\"matches\":{}}}\n",
+        json::string(word),
+        json_match_list(matches)
+    )
+}
+
+fn json_match_list(matches: &[(String, String)]) -> String {
+    let items: Vec<String> = matches
+        .iter()
+        .map(|(database, headword)| {
+            format!(
+                "{{\"database\":{},\"headword\":{}}}",
+                json::string(database),
+                json::string(headword)
+            )
+        })
+        .collect();
+    format!("[{}]", items.join(","))
+}
+
+// {"word":...,"databases":[{"database":...,"definitions":[...]}]}
+fn json_columns(word: &str, columns: &[(String, Vec<Definition>)]) -> String {
+    let items: Vec<String> = columns
+        .iter()
+        .map(|(database, definitions)| {
+            format!(
+                "{{\"database\":{},\"definitions\":{}}}",
+                json::string(database),
+                json_definition_list(definitions)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"word\":{},\"databases\":[{}]}}\n",
+        json::string(word),
+        items.join(",")
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Percent-encodes everything but unreserved characters (RFC 3986)
+fn encode_url(text: &str) -> String {
+    let mut result = String::new();
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(b as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    result
+}
@@ -0,0 +1,96 @@
+// History of the words looked up in interactive mode. Every lookup that
+// found definitions is appended to the history file as a line
+// "timestamp<TAB>database<TAB>word", the timestamp in seconds since the epoch.
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Entry {
+    pub time: u64,
+    pub database: String,
+    pub word: String,
+}
+
+pub struct History {
+    path: Option<PathBuf>,
+    pub entries: Vec<Entry>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl History {
+    // Reads the history file, a missing file is an empty history
+    pub fn load(path: Option<PathBuf>) -> History {
+        let mut entries = Vec::new();
+        if let Some(file) = path.as_ref().and_then(|path| File::open(path).ok()) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                let mut fields = line.splitn(3, '\t');
+                if let (Some(time), Some(database), Some(word)) =
+                    (fields.next(), fields.next(), fields.next())
+                {
+                    entries.push(Entry {
+                        time: time.parse().unwrap_or(0),
+                        database: database.to_string(),
+                        word: word.to_string(),
+                    });
+                }
+            }
+        }
+        History { path, entries }
+    }
+
+    pub fn add(&mut self, database: &str, word: &str) -> io::Result<()> {
+        let entry = Entry {
+            time: now(),
+            database: database.to_string(),
+            word: word.to_string(),
+        };
+        if let Some(ref path) = self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}\t{}\t{}", entry.time, entry.database, entry.word)?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    // Most recent entry containing text, like reverse-i-search in a shell
+    pub fn search(&self, text: &str) -> Option<&Entry> {
+        let text = text.to_lowercase();
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.word.to_lowercase().contains(&text))
+    }
+
+    // The last count different words, oldest first
+    pub fn recent(&self, count: usize) -> Vec<&Entry> {
+        let mut recent: Vec<&Entry> = Vec::new();
+        for entry in self.entries.iter().rev() {
+            if recent.len() == count {
+                break;
+            }
+            if !recent.iter().any(|seen| seen.word == entry.word) {
+                recent.push(entry);
+            }
+        }
+        recent.reverse();
+        recent
+    }
+}
+
+// How long ago a timestamp was, e.g. "3 days ago"
+pub fn ago(time: u64) -> String {
+    let secs = now().saturating_sub(time);
+    let (count, unit) = match secs {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+}
@@ -0,0 +1,274 @@
+// Databases read from their files with --local, for looking up words
+// without a server
+use crate::connection::{Definition, Lookup};
+use dictrdlib::client::{self, Status};
+use dictrdlib::dictionary::Dictionary;
+use dictrdlib::mime::ContentType;
+use dictrdlib::phonetic::Phonetic;
+use dictrdlib::strategy::{self, MatchStrategy};
+use rust_stemmers::Algorithm;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Like the defaults of dictrd
+const DEFAULT_STRATEGY: &str = "lev";
+const LEV_DISTANCE: usize = 1;
+const NGRAM_SIMILARITY: f64 = 0.4;
+
+// Errors of queries a server would refuse carry its status, like those of
+// DictClient
+fn refused(code: u16, text: String) -> io::Error {
+    Status { code, text }.into_error()
+}
+
+pub struct Local {
+    paths: Vec<PathBuf>,
+    databases: Vec<(String, Dictionary<File>)>,
+    strategies: Vec<Box<dyn MatchStrategy>>,
+    // Databases and strategies whose side index has been built, they are
+    // built on first use
+    prepared: HashSet<(String, String)>,
+    // Language of the stem strategy, which is left out if None
+    stem_language: Option<Algorithm>,
+}
+
+impl Local {
+    // Opens the databases at the paths. A path is a directory, whose .index
+    // files are opened, or a database given by its .index file or its path
+    // without an extension.
+    pub fn open(paths: &[PathBuf], stem_language: Option<Algorithm>) -> io::Result<Local> {
+        let mut databases = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                let mut indexes: Vec<PathBuf> = std::fs::read_dir(path)?
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|e| e == "index"))
+                    .collect();
+                indexes.sort();
+                for index in indexes {
+                    databases.push(open_database(&index.with_extension(""))?);
+                }
+            } else if path.extension().is_some_and(|e| e == "index") {
+                databases.push(open_database(&path.with_extension(""))?);
+            } else {
+                databases.push(open_database(path)?);
+            }
+        }
+        let mut strategies: Vec<Box<dyn MatchStrategy>> = vec![
+            Box::new(strategy::Exact),
+            Box::new(strategy::Prefix),
+            Box::new(strategy::NPrefix),
+            Box::new(strategy::Ngram {
+                min_similarity: NGRAM_SIMILARITY,
+            }),
+            Box::new(strategy::Lev),
+            Box::new(strategy::Suffix),
+            Box::new(strategy::Substring),
+            Box::new(strategy::Word),
+        ];
+        if stem_language.is_some() {
+            strategies.push(Box::new(strategy::Stem));
+        }
+        for phonetic in Phonetic::ALL.iter() {
+            strategies.push(Box::new(*phonetic));
+        }
+        Ok(Local {
+            paths: paths.to_vec(),
+            databases,
+            strategies,
+            prepared: HashSet::new(),
+            stem_language,
+        })
+    }
+
+    // Positions of the databases a database name selects, "*" and "!"
+    // meaning all of them
+    fn select(&self, database: &str) -> io::Result<Vec<usize>> {
+        if database == "*" || database == "!" {
+            return Ok((0..self.databases.len()).collect());
+        }
+        match self.databases.iter().position(|(name, _)| name == database) {
+            Some(pos) => Ok(vec![pos]),
+            None => Err(refused(
+                client::INVALID_DATABASE,
+                format!("Unknown database {}", database),
+            )),
+        }
+    }
+
+    // Builds the side index a strategy needs, as dictrd does when it loads
+    // a database
+    fn prepare(&mut self, pos: usize, strategy: &str) {
+        let (name, dictionary) = &mut self.databases[pos];
+        if !self.prepared.insert((name.clone(), strategy.to_string())) {
+            return;
+        }
+        match (strategy, self.stem_language) {
+            ("lev", _) => dictionary.index.build_deletion_index(LEV_DISTANCE),
+            ("stem", Some(language)) => dictionary.index.set_stem_language(language),
+            (name, _) => {
+                if let Some(phonetic) = Phonetic::from_name(name) {
+                    dictionary.index.build_phonetic_index(phonetic);
+                }
+            }
+        }
+    }
+}
+
+// Opens the .index and .dict file of a database, the dict file compressed
+// with dictzip or gzip if there is no plain one
+fn open_database(path: &Path) -> io::Result<(String, Dictionary<File>)> {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Err(io::Error::other(format!("No database {}", path.display()))),
+    };
+    let with = |extension: &str| path.with_file_name(format!("{}.{}", name, extension));
+    let dict = ["dict", "dict.dz", "dict.gz"]
+        .iter()
+        .map(|extension| with(extension))
+        .find(|dict| dict.exists())
+        .unwrap_or_else(|| with("dict"));
+    let dictionary = Dictionary::open(with("index"), &dict).map_err(|e| {
+        let message = format!("Could not open {}: {}", path.display(), e);
+        io::Error::other(message)
+    })?;
+    Ok((name, dictionary))
+}
+
+impl Lookup for Local {
+    fn define(&mut self, database: &str, word: &str) -> io::Result<Vec<Definition>> {
+        let mut definitions = Vec::new();
+        for pos in self.select(database)? {
+            let (name, dictionary) = &self.databases[pos];
+            let found = dictionary.define(word).map_err(io::Error::other)?;
+            for definition in found {
+                definitions.push(Definition {
+                    headword: definition.headword,
+                    database: name.clone(),
+                    description: dictionary.metadata().short.clone(),
+                    text: definition.text,
+                    content_type: ContentType::Plain,
+                });
+            }
+            if database == "!" && !definitions.is_empty() {
+                break;
+            }
+        }
+        Ok(definitions)
+    }
+
+    fn match_words(
+        &mut self,
+        database: &str,
+        strategy: &str,
+        word: &str,
+    ) -> io::Result<Vec<(String, String)>> {
+        let strategy = if strategy == "." {
+            DEFAULT_STRATEGY
+        } else {
+            strategy
+        };
+        let known = match self.strategies.iter().position(|s| s.name() == strategy) {
+            Some(known) => known,
+            None => {
+                return Err(refused(
+                    client::INVALID_STRATEGY,
+                    format!("Unknown strategy {}", strategy),
+                ))
+            }
+        };
+        let (window, word) = match strategy {
+            "nprefix" => match strategy::parse_nprefix(word) {
+                Some((skip, count, prefix)) => (Some((skip, count)), prefix),
+                None => {
+                    return Err(refused(
+                        client::ILLEGAL_PARAMETERS,
+                        "Expected skip#count#prefix".to_string(),
+                    ))
+                }
+            },
+            _ => (None, word),
+        };
+        let mut matches = Vec::new();
+        for pos in self.select(database)? {
+            self.prepare(pos, strategy);
+            let (name, dictionary) = &mut self.databases[pos];
+            // Databases lacking what the strategy needs are skipped
+            if let Ok(headwords) = dictionary.match_(&*self.strategies[known], word) {
+                for headword in headwords {
+                    matches.push((name.clone(), headword));
+                }
+            }
+            if database == "!" && !matches.is_empty() {
+                break;
+            }
+        }
+        if let Some((skip, count)) = window {
+            matches = matches.into_iter().skip(skip).take(count).collect();
+        }
+        Ok(matches)
+    }
+
+    fn show_databases(&mut self) -> io::Result<Vec<(String, String)>> {
+        Ok(self
+            .databases
+            .iter()
+            .map(|(name, dictionary)| (name.clone(), dictionary.metadata().short.clone()))
+            .collect())
+    }
+
+    fn show_strategies(&mut self) -> io::Result<Vec<(String, String)>> {
+        Ok(self
+            .strategies
+            .iter()
+            .map(|strategy| {
+                (
+                    strategy.name().to_string(),
+                    strategy.description().to_string(),
+                )
+            })
+            .collect())
+    }
+
+    fn show_info(&mut self, database: &str) -> io::Result<Option<String>> {
+        Ok(self
+            .databases
+            .iter()
+            .find(|(name, _)| name == database)
+            .map(|(_, dictionary)| format!("{}\n", dictionary.metadata().info)))
+    }
+
+    // The paths and databases instead of a server
+    fn show_server(&mut self) -> io::Result<String> {
+        let mut text = format!(
+            "{} {}: local databases\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        for path in &self.paths {
+            text.push_str(&format!("  {}\n", path.display()));
+        }
+        let headwords: usize = self
+            .databases
+            .iter()
+            .map(|(_, dictionary)| dictionary.index.entries().len())
+            .sum();
+        text.push_str(&format!(
+            "{} databases, {} headwords\n",
+            self.databases.len(),
+            headwords
+        ));
+        Ok(text)
+    }
+
+    fn help(&mut self) -> io::Result<String> {
+        Err(io::Error::other("No server to ask with --local"))
+    }
+
+    fn quit(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,17 +1,670 @@
-use clap::{App, Arg};
+use aggregate::Aggregate;
+use clap::{App, Arg, ArgMatches, Shell, SubCommand};
+use completion::Completer;
+use config::{Config, ServerConfig};
+use connection::{Definition, Lookup};
+use dictrdlib::client::DictClient;
+use dictrdlib::strategy;
+use editor::Input;
+use format::{Format, Style};
+use history::{ago, History};
+use local::Local;
+use rust_stemmers::Algorithm;
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::exit;
+use std::thread::sleep;
+use std::time::Duration;
+use url::DictUrl;
+
+mod aggregate;
+mod clipboard;
+mod completion;
+mod config;
+mod connection;
+mod editor;
+mod exitcode;
+mod format;
+mod history;
+mod local;
+mod terminal;
+mod url;
+
+const DEFAULT_PORT: u16 = 2628;
+
+// Time waited before connecting again, doubled for each further attempt
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+const BASH_WORDS: &str = r#"
+_dictr_words() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local prev="${COMP_WORDS[COMP_CWORD-1]}"
+    case "$prev" in
+        -h|--host|-p|--port|--timeout|--retries|-d|--database|-s|--strategy|-i|--info|completions)
+            _dictr "$@"
+            return 0
+            ;;
+    esac
+    if [[ "$cur" == -* || "${COMP_WORDS[1]}" == completions ]]; then
+        _dictr "$@"
+        return 0
+    fi
+    local IFS=$'\n'
+    COMPREPLY=( $(compgen -W "$(dictr --complete "$cur" "${COMP_WORDS[@]:1:COMP_CWORD-1}" 2>/dev/null)" -- "$cur") )
+}
+complete -F _dictr_words -o bashdefault -o default dictr
+"#;
+
+const ZSH_WORDS: &str = r#"
+(( $+functions[_dictr_words] )) ||
+_dictr_words() {
+    local -a words
+    words=(${(f)"$(dictr --complete "$PREFIX" 2>/dev/null)"})
+    compadd -a words
+}
+"#;
+
+const FISH_WORDS: &str = "complete -c dictr -f -a '(dictr --complete (commandline -ct) 2>/dev/null)'\n";
+
+// Value of an option that may be given several times, applying to the words
+// following it
+fn option_for_word<'a>(
+    matches: &'a ArgMatches,
+    option: &str,
+    word_index: usize,
+) -> Option<&'a str> {
+    let values = matches.values_of(option)?;
+    let indices = matches.indices_of(option)?;
+    indices
+        .zip(values)
+        .take_while(|(index, _)| *index < word_index)
+        .last()
+        .map(|(_, value)| value)
+}
+
+// Servers to try in order: the one given with -h and -p, else those of the
+// configuration file, else localhost
+fn servers(matches: &ArgMatches) -> Vec<ServerConfig> {
+    if matches.is_present("host") || matches.is_present("port") {
+        let host = matches.value_of("host").unwrap_or("localhost");
+        return match matches
+            .value_of("port")
+            .map_or(Ok(DEFAULT_PORT), |port| port.parse())
+        {
+            Ok(port) => vec![with_options(matches, ServerConfig::new(host, port))],
+            Err(_) => {
+                eprintln!("Invalid port {}", matches.value_of("port").unwrap());
+                exit(1);
+            }
+        };
+    }
+    // Only a configuration file given with -c must exist
+    let path = match matches.value_of_os("config") {
+        Some(path) => Some((PathBuf::from(path), true)),
+        None => {
+            std::env::var_os("HOME").map(|home| (PathBuf::from(home).join(".dictr.toml"), false))
+        }
+    };
+    let config = match path {
+        Some((path, required)) => Config::load(&path, required).unwrap_or_else(|e| {
+            eprintln!("Could not read configuration {}", e);
+            exit(1);
+        }),
+        None => Config::default(),
+    };
+    let servers = if config.servers().is_empty() {
+        vec![ServerConfig::new("localhost", DEFAULT_PORT)]
+    } else {
+        config.servers().to_vec()
+    };
+    servers.into_iter().map(|server| with_options(matches, server)).collect()
+}
+
+// Applies --timeout, --retries and --compress, which take precedence over
+// the configuration file, and --raw
+fn with_options(matches: &ArgMatches, mut server: ServerConfig) -> ServerConfig {
+    if let Some(timeout) = matches.value_of("timeout") {
+        match timeout.parse() {
+            Ok(seconds) if seconds > 0 => server.timeout = Some(seconds),
+            _ => {
+                eprintln!("Invalid timeout {}", timeout);
+                exit(1);
+            }
+        }
+    }
+    if let Some(retries) = matches.value_of("retries") {
+        match retries.parse() {
+            Ok(retries) => server.retries = retries,
+            Err(_) => {
+                eprintln!("Invalid number of retries {}", retries);
+                exit(1);
+            }
+        }
+    }
+    if matches.is_present("compress") {
+        server.compress = true;
+    }
+    server.trace = matches.is_present("raw");
+    server
+}
+
+// Language of the stem strategy of the databases given with --local
+fn stem_language(matches: &ArgMatches) -> Option<Algorithm> {
+    let language = matches.value_of("stem-language")?;
+    match strategy::stem_language(language) {
+        Some(language) => Some(language),
+        None => {
+            eprintln!("Unknown stemming language {}", language);
+            exit(1);
+        }
+    }
+}
+
+// The databases given with --local, all servers that answer with
+// --all-servers, else the first server that answers
+fn connect(matches: &ArgMatches) -> Box<dyn Lookup> {
+    let paths: Vec<PathBuf> = match matches.values_of_os("local") {
+        Some(paths) => paths.map(PathBuf::from).collect(),
+        None if matches.is_present("all-servers") => {
+            return match Aggregate::connect(&servers(matches)) {
+                Ok(aggregate) => Box::new(aggregate),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(exitcode::CONNECTION_FAILED);
+                }
+            };
+        }
+        None => return Box::new(connect_first(&servers(matches))),
+    };
+    match Local::open(&paths, stem_language(matches)) {
+        Ok(local) => Box::new(local),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+// Connects to the first of the servers that answers
+fn connect_first(servers: &[ServerConfig]) -> DictClient {
+    for server in servers {
+        match open(server) {
+            Ok(connection) => return connection,
+            Err(e) => eprintln!(
+                "Could not connect to {}:{}: {}",
+                server.host,
+                server.port,
+                exitcode::describe(&e)
+            ),
+        }
+    }
+    exit(exitcode::CONNECTION_FAILED);
+}
+
+// Connects to a server, trying again with growing delays while it cannot
+// be reached or is temporarily unavailable
+fn open(server: &ServerConfig) -> io::Result<DictClient> {
+    let mut delay = RETRY_DELAY;
+    for _ in 0..server.retries {
+        match open_once(server) {
+            Err(ref e) if retryable(e) => {
+                sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    open_once(server)
+}
+
+fn retryable(e: &io::Error) -> bool {
+    matches!(
+        exitcode::of_error(e),
+        exitcode::CONNECTION_FAILED | exitcode::TEMPORARILY_UNAVAILABLE | exitcode::SHUTTING_DOWN
+    )
+}
+
+fn open_once(server: &ServerConfig) -> io::Result<DictClient> {
+    let timeout = server.timeout.map(Duration::from_secs);
+    let mut connection = match timeout {
+        _ if server.trace => DictClient::connect_traced(
+            &server.host,
+            server.port,
+            timeout,
+            Box::new(io::stderr()),
+        )?,
+        Some(timeout) => DictClient::connect_with_timeout(&server.host, server.port, timeout)?,
+        None => DictClient::connect(&server.host, server.port)?,
+    };
+    if let (Some(user), Some(key)) = (&server.user, &server.key) {
+        connection.authenticate(user, key)?;
+    }
+    // Servers without compression answer uncompressed
+    if server.compress {
+        connection.compress()?;
+    }
+    // Servers without MIME headers send plain text
+    connection.mime()?;
+    Ok(connection)
+}
+
+// Opens another connection like connect for completing headwords in
+// interactive mode, while the first one looks words up. Errors only mean
+// that nothing is completed.
+fn completion_connection(
+    matches: &ArgMatches,
+) -> impl FnOnce() -> Option<Box<dyn Lookup>> + Send {
+    let paths: Option<Vec<PathBuf>> = matches
+        .values_of_os("local")
+        .map(|paths| paths.map(PathBuf::from).collect());
+    let stem_language = stem_language(matches);
+    // Completing headwords while typing is left out of the trace
+    let servers: Vec<ServerConfig> = if paths.is_none() {
+        servers(matches)
+            .into_iter()
+            .map(|server| ServerConfig {
+                trace: false,
+                ..server
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let all = matches.is_present("all-servers");
+    move || -> Option<Box<dyn Lookup>> {
+        if let Some(paths) = paths {
+            let local = Local::open(&paths, stem_language).ok()?;
+            return Some(Box::new(local));
+        }
+        if all {
+            let aggregate = Aggregate::connect(&servers).ok()?;
+            return Some(Box::new(aggregate));
+        }
+        let connection = servers.iter().find_map(|server| open(server).ok())?;
+        Some(Box::new(connection))
+    }
+}
+
+// Connection to a server, opened on first use: None for the configured
+// server, or the server of a dict:// URL
+fn connection_to<'a>(
+    connections: &'a mut HashMap<Option<(String, u16)>, Box<dyn Lookup>>,
+    matches: &ArgMatches,
+    server: Option<(&str, u16)>,
+) -> &'a mut dyn Lookup {
+    let connection = connections
+        .entry(server.map(|(host, port)| (host.to_string(), port)))
+        .or_insert_with(|| match server {
+            Some((host, port)) => {
+                let server = with_options(matches, ServerConfig::new(host, port));
+                Box::new(connect_first(&[server]))
+            }
+            None => connect(matches),
+        });
+    &mut **connection
+}
+
+// Prints the headwords starting with prefix for completion. Nothing is
+// printed on errors, they would end up on the command line.
+fn complete(matches: &ArgMatches, prefix: &str) {
+    let database = matches
+        .values_of("database")
+        .and_then(|mut databases| databases.next_back())
+        .unwrap_or("*");
+    let mut connection = connect(matches);
+    if let Ok(words) = connection.match_words(database, "prefix", prefix) {
+        let mut words: Vec<String> = words.into_iter().map(|(_, word)| word).collect();
+        words.sort();
+        words.dedup();
+        for word in words {
+            println!("{}", word);
+        }
+    }
+    let _ = connection.quit();
+}
+
+// Completions generated by clap complete the options, the functions added
+// complete words with the headwords on the server
+fn print_completions(app: &mut App, shell: &str) {
+    let name = env!("CARGO_PKG_NAME");
+    let mut script = Vec::new();
+    match shell {
+        "bash" => {
+            app.gen_completions_to(name, Shell::Bash, &mut script);
+            script.extend_from_slice(BASH_WORDS.as_bytes());
+        }
+        "zsh" => {
+            app.gen_completions_to(name, Shell::Zsh, &mut script);
+            let zsh = String::from_utf8_lossy(&script)
+                .replace("words to define:_files", "words to define:_dictr_words")
+                .replace("\n_dictr \"$@\"", &format!("{}\n_dictr \"$@\"", ZSH_WORDS));
+            script = zsh.into_bytes();
+        }
+        _ => {
+            app.gen_completions_to(name, Shell::Fish, &mut script);
+            script.extend_from_slice(FISH_WORDS.as_bytes());
+        }
+    }
+    let _ = io::stdout().write_all(&script);
+}
+
+// Writes a page to a temporary file and opens it in the default browser
+fn browse(page: &str) -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("dictr-{}.html", std::process::id()));
+    std::fs::write(&path, page)?;
+    let status = if cfg!(target_os = "macos") {
+        Command::new("open").arg(&path).status()?
+    } else if cfg!(windows) {
+        Command::new("cmd").arg("/c").arg("start").arg("").arg(&path).status()?
+    } else {
+        Command::new("xdg-open").arg(&path).status()?
+    };
+    if !status.success() {
+        return Err(io::Error::other(format!("Could not open {}", path.display())));
+    }
+    Ok(())
+}
+
+// Defines a word, or with a strategy every word it matches
+fn define(
+    connection: &mut dyn Lookup,
+    database: &str,
+    strategy: Option<&str>,
+    word: &str,
+) -> io::Result<Vec<Definition>> {
+    let strategy = match strategy {
+        Some(strategy) => strategy,
+        None => return connection.define(database, word),
+    };
+    let mut definitions = Vec::new();
+    for (database, word) in connection.match_words(database, strategy, word)? {
+        definitions.append(&mut connection.define(&database, &word)?);
+    }
+    Ok(definitions)
+}
+
+// Defines a word in each of the databases, for --compare
+fn define_in_each(
+    connection: &mut dyn Lookup,
+    databases: &[String],
+    strategy: Option<&str>,
+    word: &str,
+) -> io::Result<Vec<(String, Vec<Definition>)>> {
+    let mut columns = Vec::new();
+    for database in databases {
+        columns.push((
+            database.clone(),
+            define(connection, database, strategy, word)?,
+        ));
+    }
+    Ok(columns)
+}
+
+// Headwords close to a word that was not found, for spelling correction.
+// Errors only mean there are no suggestions.
+fn suggestions(connection: &mut dyn Lookup, database: &str, word: &str) -> Vec<(String, String)> {
+    // The first database with similar headwords
+    let database = if database == "*" { "!" } else { database };
+    connection
+        .match_words(database, "lev", word)
+        .unwrap_or_default()
+}
+
+// Databases compared with --compare: those given with -d, or all databases
+// on the server
+fn compared_databases(connection: &mut dyn Lookup, matches: &ArgMatches) -> Vec<String> {
+    if let Some(databases) = matches.values_of("database") {
+        return databases.map(String::from).collect();
+    }
+    match connection.show_databases() {
+        Ok(databases) => databases
+            .into_iter()
+            .filter(|(_, description)| description != "(unavailable)")
+            .map(|(name, _)| name)
+            .collect(),
+        Err(e) => {
+            eprintln!("Could not list the databases: {}", exitcode::describe(&e));
+            exit(exitcode::of_error(&e));
+        }
+    }
+}
+
+// Prints names with their descriptions in a column, like dict(1)
+fn print_list(title: &str, list: &[(String, String)]) {
+    println!("{}:", title);
+    let width = list.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+    for (name, description) in list {
+        println!(" {:width$}  {}", name, description, width = width);
+    }
+}
+
+// Shows what --dbs, --strats, --serverinfo, --serverhelp and --info ask
+// for, in that order. Returns the exit status, 0 if everything was shown.
+fn show_server_information(connection: &mut dyn Lookup, matches: &ArgMatches) -> i32 {
+    let mut status = 0;
+    let fail = |what: &str, e: io::Error| {
+        eprintln!("Could not show {}: {}", what, exitcode::describe(&e));
+        exitcode::of_error(&e)
+    };
+    if matches.is_present("dbs") {
+        match connection.show_databases() {
+            Ok(ref databases) if databases.is_empty() => {
+                eprintln!("No databases present");
+                status = exitcode::worst(status, exitcode::NO_DATABASES);
+            }
+            Ok(databases) => print_list("Databases available", &databases),
+            Err(e) => status = exitcode::worst(status, fail("the databases", e)),
+        }
+    }
+    if matches.is_present("strats") {
+        match connection.show_strategies() {
+            Ok(ref strategies) if strategies.is_empty() => {
+                eprintln!("No strategies available");
+                status = exitcode::worst(status, exitcode::NO_STRATEGIES);
+            }
+            Ok(strategies) => print_list("Strategies available", &strategies),
+            Err(e) => status = exitcode::worst(status, fail("the strategies", e)),
+        }
+    }
+    if matches.is_present("serverinfo") {
+        match connection.show_server() {
+            Ok(text) => print!("{}", text),
+            Err(e) => {
+                status = exitcode::worst(status, fail("information about the server", e));
+            }
+        }
+    }
+    if matches.is_present("serverhelp") {
+        match connection.help() {
+            Ok(text) => print!("{}", text),
+            Err(e) => status = exitcode::worst(status, fail("the server help", e)),
+        }
+    }
+    if let Some(database) = matches.value_of("info") {
+        match connection.show_info(database) {
+            Ok(Some(info)) => print!("{}", info),
+            Ok(None) => {
+                eprintln!("Unknown database \"{}\", see --dbs for a list", database);
+                status = exitcode::worst(status, exitcode::INVALID_DATABASE);
+            }
+            Err(e) => {
+                let what = format!("information about {}", database);
+                status = exitcode::worst(status, fail(&what, e));
+            }
+        }
+    }
+    status
+}
+
+const INTERACTIVE_HELP: &str = "\
+word              define word
+:history [text]   list the words looked up, or those containing text
+:search text      define the last word looked up containing text
+:review [count]   quiz yourself on the last count words (default 10)
+:help             show this help
+:quit             leave
+Tab completes the word with the headwords starting with it.
+";
+
+// Colors follow --color, and definitions shown on a terminal are folded to
+// its width
+fn text_style(matches: &ArgMatches) -> Style {
+    let terminal = terminal::is_terminal();
+    let color = match matches.value_of("color") {
+        Some("always") => true,
+        Some("never") => false,
+        _ => terminal && std::env::var_os("NO_COLOR").is_none(),
+    };
+    let width = if terminal { terminal::columns() } else { None };
+    Style { color, width }
+}
+
+// Looks up a word and prints the definitions, recording found words in
+// the history. Without definitions similar headwords are suggested if
+// correct is set.
+fn lookup(
+    connection: &mut dyn Lookup,
+    format: &Format,
+    history: &mut History,
+    database: &str,
+    strategy: Option<&str>,
+    word: &str,
+    correct: bool,
+) {
+    match define(connection, database, strategy, word) {
+        Ok(ref definitions) if definitions.is_empty() => {
+            let found = if correct && strategy.is_none() {
+                suggestions(connection, database, word)
+            } else {
+                Vec::new()
+            };
+            if found.is_empty() {
+                print!("{}", format.no_match(word));
+            } else {
+                print!("{}", format.suggestions(word, &found));
+            }
+        }
+        Ok(definitions) => {
+            print!("{}", format.definitions(word, &definitions));
+            if let Err(e) = history.add(database, word) {
+                eprintln!("Could not write history: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Could not define \"{}\": {}", word, exitcode::describe(&e)),
+    }
+}
+
+// Reads words from stdin and defines them until EOF or :quit
+fn interactive(connection: &mut dyn Lookup, matches: &ArgMatches) {
+    let path = match matches.value_of_os("history") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".dictr_history")),
+    };
+    let mut history = History::load(path);
+    let database = option_for_word(matches, "database", usize::MAX).unwrap_or("*");
+    let strategy = option_for_word(matches, "strategy", usize::MAX);
+    let correct = !matches.is_present("nocorrect");
+    let format = Format::Text(text_style(matches));
+    // Lines typed on a terminal are edited and completed, where it can be
+    // read a key at a time
+    let mut input = if io::stdin().is_terminal() && terminal::has_raw_mode() {
+        Input::Editor(Completer::start(database, completion_connection(matches)))
+    } else {
+        Input::Lines(io::stdin().lock().lines())
+    };
+    while let Some(line) = input.line("dictr> ") {
+        let line = line.trim();
+        let (command, argument) = match line.find(' ') {
+            Some(pos) => (&line[..pos], line[pos + 1..].trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => (),
+            ":quit" | ":q" => break,
+            ":help" => print!("{}", INTERACTIVE_HELP),
+            ":history" => {
+                for (number, entry) in history.entries.iter().enumerate() {
+                    if entry.word.to_lowercase().contains(&argument.to_lowercase()) {
+                        println!(
+                            "{:5}  {:14} {} [{}]",
+                            number + 1,
+                            ago(entry.time),
+                            entry.word,
+                            entry.database
+                        );
+                    }
+                }
+            }
+            ":search" => match history.search(argument) {
+                Some(entry) => {
+                    let (database, word) = (entry.database.clone(), entry.word.clone());
+                    println!("(reverse-i-search)`{}': {}", argument, word);
+                    lookup(
+                        connection,
+                        &format,
+                        &mut history,
+                        &database,
+                        strategy,
+                        &word,
+                        correct,
+                    );
+                }
+                None => println!("No word in the history contains \"{}\"", argument),
+            },
+            ":review" => {
+                let count = argument.parse().unwrap_or(10);
+                let words: Vec<(String, String)> = history
+                    .recent(count)
+                    .iter()
+                    .map(|entry| (entry.database.clone(), entry.word.clone()))
+                    .collect();
+                for (number, (database, word)) in words.iter().enumerate() {
+                    let question = format!(
+                        "\n{}/{}: {}  (Enter shows the definition, q stops) ",
+                        number + 1,
+                        words.len(),
+                        word
+                    );
+                    match input.line(&question) {
+                        Some(ref answer) if answer.trim() != "q" => (),
+                        _ => break,
+                    }
+                    match connection.define(database, word) {
+                        Ok(definitions) => {
+                            print!("{}", format.definitions(word, &definitions))
+                        }
+                        Err(e) => eprintln!(
+                            "Could not define \"{}\": {}",
+                            word,
+                            exitcode::describe(&e)
+                        ),
+                    }
+                }
+            }
+            _ if command.starts_with(':') => println!("Unknown command {}, see :help", command),
+            _ => lookup(
+                connection,
+                &format,
+                &mut history,
+                database,
+                strategy,
+                line,
+                correct,
+            ),
+        }
+    }
+    println!();
+}
 
 fn main() {
 /*
--d --database <dbname>    select a database to search
--s --strategy <strategy>  strategy for matching or defining
--c --config <file>        specify configuration file
--C --nocorrect            disable attempted spelling correction
--i --info <dbname>        show information about a database
 -a --noauth               disable authentication
 -u --user <username>      username for authentication
 -k --key <key>            shared secret for authentication
 */
-    let _matches = App::new(env!("CARGO_PKG_NAME"))
+    let mut app = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .author("Jan Schreiber <jan@mecinus.com>")
         .about("Dictionary query client")
@@ -19,6 +672,12 @@ fn main() {
              .long("license")
              .short("L")
              .help("display copyright and license information"))
+        .arg(Arg::with_name("config")
+             .long("config")
+             .short("c")
+             .value_name("file")
+             .help("configuration file with the servers to try [default: ~/.dictr.toml]")
+             .takes_value(true))
         .arg(Arg::with_name("host")
              .long("host")
              .short("h")
@@ -31,10 +690,49 @@ fn main() {
              .value_name("port")
              .help("specify port")
              .takes_value(true))
+        .arg(Arg::with_name("timeout")
+             .long("timeout")
+             .value_name("seconds")
+             .help("give up on a server not answering within seconds")
+             .takes_value(true))
+        .arg(Arg::with_name("retries")
+             .long("retries")
+             .value_name("count")
+             .help("connect again up to count times, waiting longer each time, while the server cannot be reached")
+             .takes_value(true))
+        .arg(Arg::with_name("compress")
+             .long("compress")
+             .help("have the server compress its responses, for slow links"))
+        .arg(Arg::with_name("raw")
+             .long("raw")
+             .visible_alias("verbose-protocol")
+             .help("write the lines sent to and received from the server to stderr, with the seconds since connecting"))
+        .arg(Arg::with_name("local")
+             .long("local")
+             .value_name("path")
+             .help("look up words in the .index and .dict files at path, a database or a directory, instead of on a server")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .conflicts_with_all(&["host", "port"]))
+        .arg(Arg::with_name("stem-language")
+             .long("stem-language")
+             .value_name("language")
+             .help("match the headwords of the --local databases with the stem strategy in language, e.g. english or de")
+             .takes_value(true)
+             .requires("local"))
+        .arg(Arg::with_name("all-servers")
+             .long("all-servers")
+             .help("query all configured servers at once and merge their results, labeling the databases with the server")
+             .conflicts_with("local"))
+        .arg(Arg::with_name("nocorrect")
+             .long("nocorrect")
+             .short("C")
+             .help("do not suggest headwords for words not found"))
         .arg(Arg::with_name("match")
              .long("match")
              .short("m")
-             .help("match instead of define"))
+             .help("list the headwords matching the words instead of defining them"))
         .arg(Arg::with_name("serverinfo")
              .long("serverinfo")
              .short("I")
@@ -51,6 +749,339 @@ fn main() {
              .long("strats")
              .short("S")
              .help("show available search strategies"))
-        .get_matches();
-    println!("Not implemented!");
+        .arg(Arg::with_name("database")
+             .long("database")
+             .short("d")
+             .value_name("dbname")
+             .help("select a database to search for the following words")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("strategy")
+             .long("strategy")
+             .short("s")
+             .value_name("strategy")
+             .help("define the words matching the following words with strategy")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1))
+        .arg(Arg::with_name("info")
+             .long("info")
+             .short("i")
+             .value_name("dbname")
+             .help("show information about a database")
+             .takes_value(true))
+        .arg(Arg::with_name("clipboard")
+             .long("clipboard")
+             .help("define the text on the clipboard"))
+        .arg(Arg::with_name("copy")
+             .long("copy")
+             .help("copy the definitions to the clipboard"))
+        .arg(Arg::with_name("format")
+             .long("format")
+             .value_name("format")
+             .help("output format of the definitions")
+             .possible_values(&["text", "html", "markdown", "json"])
+             .takes_value(true))
+        .arg(Arg::with_name("color")
+             .long("color")
+             .value_name("when")
+             .help("color the definitions on a terminal (auto), always or never")
+             .possible_values(&["auto", "always", "never"])
+             .takes_value(true))
+        .arg(Arg::with_name("no-pager")
+             .long("no-pager")
+             .help("do not show long output in $PAGER"))
+        .arg(Arg::with_name("browse")
+             .long("browse")
+             .help("show the definitions as HTML in the browser"))
+        .arg(Arg::with_name("compare")
+             .long("compare")
+             .help("show the definitions of each database side by side"))
+        .arg(Arg::with_name("interactive")
+             .long("interactive")
+             .help("read words to define from the terminal"))
+        .arg(Arg::with_name("stdin")
+             .long("stdin")
+             .help("define the words read from standard input, one per line")
+             .conflicts_with("interactive"))
+        .arg(Arg::with_name("history")
+             .long("history")
+             .value_name("file")
+             .help("history file of interactive mode [default: ~/.dictr_history]")
+             .takes_value(true))
+        .arg(Arg::with_name("words")
+             .help("words to define")
+             .multiple(true))
+        .arg(Arg::with_name("complete")
+             .long("complete")
+             .value_name("prefix")
+             .help("list the words starting with prefix, used by the shell completions")
+             .takes_value(true)
+             .hidden(true))
+        .subcommand(SubCommand::with_name("completions")
+             .about("print the completions for a shell")
+             .arg(Arg::with_name("shell")
+                  .possible_values(&["bash", "zsh", "fish"])
+                  .required(true)));
+    let matches = app.clone().get_matches();
+    if let Some(matches) = matches.subcommand_matches("completions") {
+        print_completions(&mut app, matches.value_of("shell").unwrap());
+        return;
+    }
+    if let Some(prefix) = matches.value_of("complete") {
+        complete(&matches, prefix);
+        return;
+    }
+    let mut words: Vec<(usize, &str)> = match (
+        matches.indices_of("words"),
+        matches.values_of("words"),
+    ) {
+        (Some(indices), Some(values)) => indices.zip(values).collect(),
+        _ => Vec::new(),
+    };
+    // The selection may span lines, it is looked up as a single phrase
+    let selection = if matches.is_present("clipboard") {
+        match clipboard::paste() {
+            Ok(text) => text.split_whitespace().collect::<Vec<&str>>().join(" "),
+            Err(e) => {
+                eprintln!("Could not read the clipboard: {}", e);
+                exit(1);
+            }
+        }
+    } else {
+        String::new()
+    };
+    if !selection.is_empty() {
+        // Options given on the command line apply to it
+        words.push((usize::MAX, &selection));
+    } else if matches.is_present("clipboard") {
+        eprintln!("The clipboard is empty");
+        exit(1);
+    }
+    // Each line read is a query, options given on the command line apply
+    let lines: Vec<String> = if matches.is_present("stdin") {
+        let stdin = io::stdin();
+        let lines = stdin.lock().lines().collect::<io::Result<Vec<String>>>();
+        lines.unwrap_or_else(|e| {
+            eprintln!("Could not read standard input: {}", e);
+            exit(1);
+        })
+    } else {
+        Vec::new()
+    };
+    for line in &lines {
+        let line = line.trim();
+        if !line.is_empty() {
+            words.push((usize::MAX, line));
+        }
+    }
+    // Like dict(1), asking about the server replaces looking up words
+    let information = ["dbs", "strats", "serverinfo", "serverhelp", "info"];
+    if information.iter().any(|flag| matches.is_present(flag)) {
+        let mut connection = connect(&matches);
+        let status = show_server_information(&mut *connection, &matches);
+        let _ = connection.quit();
+        if status != 0 {
+            exit(status);
+        }
+        return;
+    }
+    if words.is_empty() && matches.is_present("stdin") {
+        return;
+    }
+    if words.is_empty() && !matches.is_present("interactive") {
+        println!("Not implemented!");
+        return;
+    }
+    if matches.is_present("interactive") {
+        let mut connection = connect(&matches);
+        interactive(&mut *connection, &matches);
+        let _ = connection.quit();
+        return;
+    }
+    // All words on a server are looked up over the same connection
+    let mut connections = HashMap::new();
+    // Cross references link to the server
+    let base = || {
+        let server = &servers(&matches)[0];
+        format!("dict://{}:{}", server.host, server.port)
+    };
+    let format = match matches.value_of("format") {
+        _ if matches.is_present("browse") => Format::Html(base()),
+        Some("html") => Format::Html(base()),
+        Some("markdown") => Format::Markdown(base()),
+        Some("json") => Format::Json,
+        _ => Format::Text(text_style(&matches)),
+    };
+    // Output for a terminal goes through the pager if it is longer than a
+    // screen, so it is collected first
+    let size = terminal::size();
+    let paging = !matches.is_present("browse")
+        && !matches.is_present("no-pager")
+        && terminal::is_terminal()
+        && size.is_some();
+    // Pages for the browser are shown when complete
+    let show = |text: &str| {
+        if !matches.is_present("browse") && !paging {
+            print!("{}", text);
+        }
+    };
+    let compare = if matches.is_present("compare") {
+        Some(compared_databases(
+            connection_to(&mut connections, &matches, None),
+            &matches,
+        ))
+    } else {
+        None
+    };
+    let width = match size {
+        Some((columns, _)) => columns,
+        None => std::env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+            .unwrap_or(80),
+    };
+    let match_only = matches.is_present("match");
+    let correct = !matches.is_present("nocorrect");
+    // The results of several words are told apart by a heading
+    let batch = words.len() > 1;
+    let mut missing = Vec::new();
+    let mut unmatched = Vec::new();
+    // Exit status like dict(1)
+    let mut status = 0;
+    let mut output = format.begin();
+    show(&output);
+    for (number, (index, text)) in words.into_iter().enumerate() {
+        let url = DictUrl::parse(text);
+        if url.is_none() && text.to_lowercase().starts_with("dict://") {
+            eprintln!("Invalid URL {}", text);
+            exit(1);
+        }
+        // A URL names the server, database and strategy itself
+        let (server, word, database, strategy, matching) = match url {
+            Some(ref url) => (
+                Some((url.host.as_str(), url.port.unwrap_or(DEFAULT_PORT))),
+                url.word.as_str(),
+                url.database.as_str(),
+                url.strategy.as_deref(),
+                url.strategy.is_some(),
+            ),
+            None => (
+                None,
+                text,
+                option_for_word(&matches, "database", index).unwrap_or("*"),
+                option_for_word(&matches, "strategy", index),
+                match_only,
+            ),
+        };
+        let connection = connection_to(&mut connections, &matches, server);
+        let result = match compare {
+            // "." is the default strategy of the server
+            _ if matching => connection
+                .match_words(database, strategy.unwrap_or("."), word)
+                .map(|found| {
+                    if found.is_empty() {
+                        None
+                    } else {
+                        Some(format.matches(word, &found))
+                    }
+                }),
+            Some(ref databases) if url.is_none() => {
+                define_in_each(connection, databases, strategy, word).map(|columns| {
+                    if columns
+                        .iter()
+                        .all(|(_, definitions)| definitions.is_empty())
+                    {
+                        None
+                    } else {
+                        Some(format.comparison(word, &columns, width))
+                    }
+                })
+            }
+            _ => define(connection, database, strategy, word).map(|definitions| {
+                if definitions.is_empty() {
+                    None
+                } else {
+                    Some(format.definitions(word, &definitions))
+                }
+            }),
+        };
+        if batch {
+            let heading = format.heading(number, word);
+            show(&heading);
+            output.push_str(&heading);
+        }
+        match result {
+            Ok(text) => {
+                let text = text.unwrap_or_else(|| {
+                    if matching {
+                        unmatched.push(word.to_string());
+                        status = exitcode::worst(status, exitcode::NO_MATCH);
+                        format.no_matches(word)
+                    } else {
+                        missing.push(word.to_string());
+                        let found = if correct && strategy.is_none() && compare.is_none() {
+                            suggestions(connection, database, word)
+                        } else {
+                            Vec::new()
+                        };
+                        if found.is_empty() {
+                            status = exitcode::worst(status, exitcode::NO_MATCH);
+                            format.no_match(word)
+                        } else {
+                            status = exitcode::worst(status, exitcode::APPROXIMATE);
+                            format.suggestions(word, &found)
+                        }
+                    }
+                });
+                show(&text);
+                output.push_str(&text);
+            }
+            Err(e) if matching => {
+                eprintln!("Could not match \"{}\": {}", word, exitcode::describe(&e));
+                unmatched.push(word.to_string());
+                status = exitcode::worst(status, exitcode::of_error(&e));
+            }
+            Err(e) => {
+                eprintln!("Could not define \"{}\": {}", word, exitcode::describe(&e));
+                missing.push(word.to_string());
+                status = exitcode::worst(status, exitcode::of_error(&e));
+            }
+        }
+    }
+    for connection in connections.values_mut() {
+        let _ = connection.quit();
+    }
+    let end = format.end();
+    show(&end);
+    output.push_str(&end);
+    if paging {
+        let rows = size.map_or(0, |(_, rows)| rows);
+        if output.lines().count() < rows {
+            print!("{}", output);
+        } else if let Err(e) = terminal::page(&output) {
+            eprintln!("Could not run the pager: {}", e);
+            print!("{}", output);
+        }
+    }
+    if matches.is_present("browse") {
+        if let Err(e) = browse(&output) {
+            eprintln!("Could not open the browser: {}", e);
+        }
+    }
+    if matches.is_present("copy") && !output.is_empty() {
+        if let Err(e) = clipboard::copy(&format::strip_colors(&output)) {
+            eprintln!("Could not copy to the clipboard: {}", e);
+        }
+    }
+    if !unmatched.is_empty() {
+        eprintln!("\nNo matches for: {}", unmatched.join(", "));
+    }
+    if !missing.is_empty() {
+        eprintln!("\nNo definitions for: {}", missing.join(", "));
+    }
+    if status != 0 {
+        exit(status);
+    }
 }
@@ -0,0 +1,113 @@
+// The terminal the client writes to: its size, and the pager long output
+// is shown in. The size is only known on Unix, elsewhere output is folded
+// to a fixed width and not paged.
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+// Width of terminals whose size is unknown
+#[cfg(not(unix))]
+const COLUMNS: usize = 80;
+
+pub fn is_terminal() -> bool {
+    io::stdout().is_terminal()
+}
+
+// Columns and rows of the terminal on stdout, None if it is not one
+#[cfg(unix)]
+pub fn size() -> Option<(usize, usize)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result == -1 || size.ws_col == 0 {
+        return None;
+    }
+    Some((usize::from(size.ws_col), usize::from(size.ws_row)))
+}
+
+#[cfg(not(unix))]
+pub fn size() -> Option<(usize, usize)> {
+    None
+}
+
+// Columns of the terminal on stdout that text is folded to
+pub fn columns() -> Option<usize> {
+    #[cfg(unix)]
+    return size().map(|(columns, _)| columns);
+    #[cfg(not(unix))]
+    Some(COLUMNS)
+}
+
+// Keys are read from the terminal on stdin as they are typed, without echo,
+// until this is dropped
+#[cfg(unix)]
+pub struct RawMode(libc::termios);
+
+#[cfg(unix)]
+pub fn raw_mode() -> io::Result<RawMode> {
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut raw = original;
+    // Ctrl-C and Ctrl-D are keys like the others
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+    raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(RawMode(original))
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.0) };
+    }
+}
+
+// Elsewhere interactive mode reads whole lines, see has_raw_mode
+#[cfg(not(unix))]
+pub struct RawMode;
+
+#[cfg(not(unix))]
+pub fn raw_mode() -> io::Result<RawMode> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Keys cannot be read as they are typed",
+    ))
+}
+
+// Whether raw_mode can read keys as they are typed
+pub fn has_raw_mode() -> bool {
+    cfg!(unix)
+}
+
+// Shows text in $PAGER, less by default. Like git, less is told to pass
+// colors through unless LESS says otherwise.
+pub fn page(text: &str) -> io::Result<()> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    let mut words = pager.split_whitespace();
+    let program = match words.next() {
+        Some(program) => program,
+        None => {
+            print!("{}", text);
+            return Ok(());
+        }
+    };
+    let mut command = Command::new(program);
+    command.args(words).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may be left before it read everything
+        match stdin.write_all(text.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => (),
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
@@ -0,0 +1,126 @@
+// dict:// URLs (RFC 2229, 5), e.g. dict://dict.org/d:hacker:jargon to define
+// a word or dict://dict.org:2628/m:hack:jargon:prefix to match it. User and
+// authentication parts are ignored.
+use dictrdlib::percent::decode;
+
+pub struct DictUrl {
+    pub host: String,
+    pub port: Option<u16>,
+    pub word: String,
+    pub database: String,
+    // Strategy of a match URL, None for a define URL
+    pub strategy: Option<String>,
+}
+
+impl DictUrl {
+    // None if the text is not a dict:// URL of a definition or match
+    pub fn parse(text: &str) -> Option<DictUrl> {
+        let scheme = text.get(..7)?;
+        if !scheme.eq_ignore_ascii_case("dict://") {
+            return None;
+        }
+        let rest = &text[7..];
+        let (authority, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos + 1..]),
+            None => (rest, ""),
+        };
+        let authority = match authority.rfind('@') {
+            Some(pos) => &authority[pos + 1..],
+            None => authority,
+        };
+        // IPv6 addresses are in brackets
+        let (host, port) = match authority.rfind(':') {
+            Some(pos) if !authority[pos..].contains(']') => {
+                (&authority[..pos], Some(authority[pos + 1..].parse().ok()?))
+            }
+            _ => (authority, None),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return None;
+        }
+        let mut fields = path.split(':').map(decode);
+        let kind = fields.next()?;
+        let word = fields.next().filter(|word| !word.is_empty())?;
+        // The first database having the word and the server's default
+        // strategy unless given
+        let database = fields
+            .next()
+            .filter(|database| !database.is_empty())
+            .unwrap_or_else(|| "!".to_string());
+        let strategy = match kind.as_str() {
+            "d" => None,
+            "m" => Some(
+                fields
+                    .next()
+                    .filter(|strategy| !strategy.is_empty())
+                    .unwrap_or_else(|| ".".to_string()),
+            ),
+            _ => return None,
+        };
+        Some(DictUrl {
+            host: host.to_string(),
+            port,
+            word,
+            database,
+            strategy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn define_urls() {
+        let url = DictUrl::parse("dict://dict.org/d:hacker:jargon").unwrap();
+        assert_eq!(url.host, "dict.org");
+        assert_eq!(url.port, None);
+        assert_eq!(url.word, "hacker");
+        assert_eq!(url.database, "jargon");
+        assert_eq!(url.strategy, None);
+        // The first database having the word
+        let url = DictUrl::parse("DICT://dict.org:2628/d:hacker").unwrap();
+        assert_eq!((url.port, url.database.as_str()), (Some(2628), "!"));
+    }
+
+    #[test]
+    fn match_urls() {
+        let url = DictUrl::parse("dict://dict.org/m:hack:jargon:prefix").unwrap();
+        assert_eq!(url.strategy.as_deref(), Some("prefix"));
+        // The server's default strategy
+        let url = DictUrl::parse("dict://dict.org/m:hack::").unwrap();
+        assert_eq!(url.database, "!");
+        assert_eq!(url.strategy.as_deref(), Some("."));
+    }
+
+    #[test]
+    fn authorities() {
+        let url = DictUrl::parse("dict://tim;AUTH=*@dict.org:2629/d:hack").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("dict.org", Some(2629)));
+        let url = DictUrl::parse("dict://[::1]/d:hack").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", None));
+        let url = DictUrl::parse("dict://[2001:db8::1]:2628/d:hack").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("2001:db8::1", Some(2628)));
+        assert!(DictUrl::parse("dict://dict.org:port/d:hack").is_none());
+    }
+
+    #[test]
+    fn escapes() {
+        let url = DictUrl::parse("dict://dict.org/d:free%20software:foldoc").unwrap();
+        assert_eq!(url.word, "free software");
+        let url = DictUrl::parse("dict://dict.org/d:a%3Ab").unwrap();
+        assert_eq!(url.word, "a:b");
+    }
+
+    #[test]
+    fn not_dict_urls() {
+        assert!(DictUrl::parse("http://dict.org/d:hack").is_none());
+        assert!(DictUrl::parse("dict:").is_none());
+        assert!(DictUrl::parse("dict:///d:hack").is_none());
+        assert!(DictUrl::parse("dict://dict.org").is_none());
+        assert!(DictUrl::parse("dict://dict.org/d:").is_none());
+        assert!(DictUrl::parse("dict://dict.org/x:hack").is_none());
+    }
+}
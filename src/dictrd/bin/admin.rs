@@ -0,0 +1,238 @@
+// Admin commands on a Unix socket, one command per line:
+//
+//   snapshot         state of the server as JSON on a single line
+//   stats            counters of the server as JSON
+//   connections      active connections as JSON
+//   disconnect <id>  close a connection
+//   reload           reload the databases that changed, as on SIGHUP
+//   help             list of the commands
+//   quit             close the connection
+//
+// The socket is only accessible to the user running the server.
+use dictrdlib::breaker::BreakerState;
+use dictrdlib::json;
+use dictrdlib::securitylog::SecurityEvent;
+use dictrdlib::server::{DictdServer, BREAKER_COOLDOWN};
+use log::{error, info};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::Instant;
+
+const ADMIN_HELP: &str = "snapshot         dump the state of the server as JSON
+stats            dump the counters of the server as JSON
+connections      list the active connections as JSON
+disconnect <id>  close the connection with the id
+reload           reload the databases that changed
+help             show this help
+quit             close the connection
+";
+
+// Most requested words listed by stats
+const POPULAR_WORDS: usize = 100;
+
+// Reloads the databases that changed
+pub type Reload = Arc<dyn Fn() + Send + Sync>;
+
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    // A socket left behind by a previous run
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+pub fn serve(listener: UnixListener, server: DictdServer<File>, reload: Reload) {
+    for stream in listener.incoming() {
+        match stream {
+            Err(e) => error!("Could not accept admin connection: {:?}", e),
+            Ok(stream) => {
+                let server = server.clone();
+                let reload = reload.clone();
+                spawn(move || {
+                    if let Err(e) = handle(stream, &server, &reload) {
+                        error!("Admin connection failed: {:?}", e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn handle(stream: UnixStream, server: &DictdServer<File>, reload: &Reload) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let command = line.trim();
+        if command.is_empty() {
+            continue;
+        }
+        info!("Admin command: {}", command);
+        server.record_security_event("admin-socket", &SecurityEvent::AdminCommand { command });
+        match command {
+            "snapshot" => writeln!(writer, "{}", snapshot(server))?,
+            "stats" => writeln!(writer, "{}", stats(server))?,
+            "connections" => writeln!(writer, "[{}]", connections(server).join(","))?,
+            "reload" => {
+                reload();
+                writeln!(writer, "ok")?
+            }
+            _ if command.starts_with("disconnect ") => {
+                let id = command["disconnect ".len()..].trim();
+                match server.disconnect(id) {
+                    Ok(true) => writeln!(writer, "ok")?,
+                    Ok(false) => writeln!(writer, "error: no connection {}", id)?,
+                    Err(e) => writeln!(writer, "error: {}", e)?,
+                }
+            }
+            "help" => write!(writer, "{}", ADMIN_HELP)?,
+            "quit" => break,
+            _ => writeln!(writer, "error: unknown command, see help")?,
+        }
+    }
+    Ok(())
+}
+
+// Databases with their counters, databases that failed to load and the
+// active connections
+fn snapshot(server: &DictdServer<File>) -> String {
+    let now = Instant::now();
+    let stats = server.statistics();
+    let stats = stats.lock().unwrap();
+    let mut databases = Vec::new();
+    for (name, database) in server.databases().read().unwrap().iter() {
+        let counters = stats.databases.get(name).cloned().unwrap_or_default();
+        let breaker = match database.breaker_state(now) {
+            BreakerState::Closed => "closed".to_string(),
+            BreakerState::HalfOpen => "half-open".to_string(),
+            BreakerState::Open(remaining) => format!("open for {}s", remaining.as_secs()),
+        };
+        databases.push(format!(
+            "{{\"name\":{},\"entries\":{},\"checksum\":{},\"defines\":{},\"matches\":{},\
+             \"misses\":{},\"breaker\":{}}}",
+            json::string(name),
+            database.indexreader().read().unwrap().entries().len(),
+            database.checksum().map_or("null".to_string(), json::string),
+            counters.defines,
+            counters.matches,
+            counters.misses,
+            json::string(&breaker)
+        ));
+    }
+    let unavailable: Vec<String> = server
+        .unavailable()
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, reason)| {
+            format!(
+                "{{\"name\":{},\"reason\":{}}}",
+                json::string(name),
+                json::string(reason)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"version\":{},\"uptime\":{},\"total_uptime\":{},\"breaker_cooldown\":{},\
+         \"databases\":[{}],\"unavailable\":[{}],\"connections\":[{}]}}",
+        json::string(env!("CARGO_PKG_VERSION")),
+        stats.uptime(),
+        stats.total_uptime(),
+        BREAKER_COOLDOWN.as_secs(),
+        databases.join(","),
+        unavailable.join(","),
+        connections(server).join(",")
+    )
+}
+
+// The counters of the server since it first started
+fn stats(server: &DictdServer<File>) -> String {
+    let stats = server.statistics();
+    let stats = stats.lock().unwrap();
+    let commands: Vec<String> = stats
+        .commands
+        .iter()
+        .map(|(command, count)| format!("{}:{}", json::string(command), count))
+        .collect();
+    let databases: Vec<String> = stats
+        .databases
+        .iter()
+        .map(|(name, counters)| {
+            format!(
+                "{}:{{\"defines\":{},\"matches\":{},\"misses\":{}}}",
+                json::string(name),
+                counters.defines,
+                counters.matches,
+                counters.misses
+            )
+        })
+        .collect();
+    // Seconds the latest commands took
+    let latencies: Vec<String> = stats
+        .latencies
+        .iter()
+        .map(|(command, latencies)| {
+            format!(
+                "{}:{{\"count\":{},\"p50\":{:.6},\"p90\":{:.6},\"p99\":{:.6},\"max\":{:.6}}}",
+                json::string(command),
+                latencies.count,
+                latencies.percentile(0.5),
+                latencies.percentile(0.9),
+                latencies.percentile(0.99),
+                latencies.max
+            )
+        })
+        .collect();
+    let words: Vec<String> = stats
+        .words
+        .top(POPULAR_WORDS)
+        .iter()
+        .map(|(word, count)| format!("{{\"word\":{},\"count\":{}}}", json::string(word), count))
+        .collect();
+    format!(
+        "{{\"uptime\":{},\"total_uptime\":{},\"connections\":{},\"commands\":{{{}}},\
+         \"cache_hits\":{},\"cache_misses\":{},\"databases\":{{{}}},\"latencies\":{{{}}},\
+         \"words\":[{}]}}",
+        stats.uptime(),
+        stats.total_uptime(),
+        stats.connections,
+        commands.join(","),
+        stats.cache_hits,
+        stats.cache_misses,
+        databases.join(","),
+        latencies.join(","),
+        words.join(",")
+    )
+}
+
+// The active connections, with the seconds since they connected and since
+// their last command
+fn connections(server: &DictdServer<File>) -> Vec<String> {
+    let now = Instant::now();
+    server
+        .connections()
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, info)| {
+            format!(
+                "{{\"id\":{},\"peer\":{},\"client\":{},\"connected\":{},\"idle\":{},\
+                 \"commands\":{}}}",
+                json::string(id),
+                json::string(&info.peer),
+                info.client
+                    .as_deref()
+                    .map_or("null".to_string(), json::string),
+                now.duration_since(info.connected).as_secs(),
+                now.duration_since(info.last_active).as_secs(),
+                info.commands
+            )
+        })
+        .collect()
+}
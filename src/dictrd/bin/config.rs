@@ -0,0 +1,904 @@
+// Configuration file of the server, /etc/dictrd.toml unless --config says
+// otherwise:
+//
+//   log_level = "info"
+//   # Words clients look up in the logs: "full", "hashed" with a key that
+//   # changes when the server starts, or "none"
+//   log_queries = "full"
+//   # Commands that are not valid UTF-8: "strict" answers them with 500,
+//   # "lossy" replaces the invalid bytes, any other name is a legacy charset
+//   # known to encoding_rs they are decoded from, e.g. "latin1"
+//   input_encoding = "strict"
+//   dict_dir = "/usr/share/dictd"
+//   # Text of the 220 greeting, the host name and operating system if left
+//   # out
+//   banner = "dict.example.org"
+//   # Strategy of MATCH with the strategy ".", lev if left out
+//   default_strategy = "prefix"
+//
+//   # Other names clients may use for strategies, "." for the default one
+//   [strategy_aliases]
+//   pre = "prefix"
+//   default = "."
+//
+//   [[listen]]
+//   address = "127.0.0.1:2628"
+//
+//   # IPv6 listeners only accept IPv6 clients
+//   [[listen]]
+//   address = "[::1]:2628"
+//   # Databases visible on this address, all if left out
+//   databases = ["jargon"]
+//
+//   # Implicit TLS, clients start the handshake right after connecting
+//   [[listen]]
+//   address = "0.0.0.0:2629"
+//   tls = true
+//   # Networks clients of this address may connect from, checked like
+//   # [access] and in addition to it
+//   allow = ["192.168.0.0/16"]
+//   deny = ["192.168.13.0/24"]
+//
+//   # PEM files of the certificate chain and private key of TLS listeners
+//   [tls]
+//   certificate = "/etc/dictrd/cert.pem"
+//   key = "/etc/dictrd/key.pem"
+//
+//   [[database]]
+//   name = "jargon"
+//   # Files without extension, relative to dict_dir. Defaults to the name.
+//   path = "jargon"
+//   # Replaces the description in the 00-database-short entry
+//   description = "The Jargon File"
+//   # The server does not start without a mandatory database
+//   mandatory = true
+//   # Networks the database is visible to, all if left out
+//   allow = ["10.0.0.0/8", "::1"]
+//   # Left out of SHOW DB and of searches in all databases (* and !), for
+//   # clients that know its name
+//   hidden = false
+//   # Only visible to clients that authenticated with AUTH or SASLAUTH
+//   require_auth = false
+//   # Matches sent from the database for a MATCH, the rest are cut off
+//   max_matches = 1000
+//   # Strategy of MATCH with the strategy "." for this database
+//   default_strategy = "exact"
+//   # Match prefixes and edit distances with a finite state transducer of
+//   # the headwords, which pays off for large databases
+//   fst = false
+//   # Phonetic strategies the keys of the headwords are computed for, out
+//   # of soundex, metaphone, caverphone and cologne. MATCH with the others
+//   # is answered with 551.
+//   phonetic = ["soundex", "metaphone", "caverphone"]
+//   # Language of the headwords for the stem strategy, by name or ISO 639-1
+//   # code. Without it MATCH with stem is answered with 551.
+//   stem_language = "english"
+//   # Binary search the index file instead of loading it, for indexes too
+//   # large to keep in memory. Only DEFINE and the exact, prefix and nprefix
+//   # strategies find its headwords.
+//   on_disk = false
+//   # Keep the sorted index and the indexes of the strategies in
+//   # <name>.cache and load them from there while the .index file is
+//   # unchanged, which starts the server faster
+//   index_cache = false
+//   # Sort and search the headwords in the order of a locale, e.g. "sv" for
+//   # Swedish with å, ä and ö after z, instead of byte order
+//   locale = "sv"
+//   # Encoding of the headwords and definitions, transcoded to UTF-8. By
+//   # default UTF-8, or Latin1 for 00-database-8bit-new databases.
+//   encoding = "koi8-r"
+//   # Scripts transliterated to Latin in lookups and matches, so that
+//   # "moskva" finds "Москва": "cyrillic", "greek" and "pinyin"
+//   transliterate = ["cyrillic"]
+//   # Format of the articles, announced to clients that enabled OPTION MIME:
+//   # "text/plain", "text/html" or "text/x-markdown"
+//   content_type = "text/html"
+//   # Applied to the articles in order before they are sent: strip-markup,
+//   # collapse-whitespace, drop-binary, and rewrite-references followed by
+//   # the delimiters of references and their replacements
+//   filters = ["strip-markup", "rewrite-references { } [[ ]]"]
+//   # Layout of the articles, with the fields {headword}, {pronunciation},
+//   # {body}, {database} and {source}, see dictrdlib::template
+//   template = "/etc/dictrd/article.template"
+//   # Seconds a query may take. Searches with XSEARCH give up after it, and
+//   # a database exceeding it 3 times in a row is left out of searches in
+//   # all databases for a minute.
+//   deadline = 1.0
+//
+//   # A database answered by an external program instead of files, run for
+//   # each lookup with "define -- <word>" or "match <strategy> -- <word>"
+//   # added to its arguments, see dictrdlib::plugin
+//   [[plugin]]
+//   name = "thesaurus"
+//   command = ["/usr/local/bin/thesaurus", "--lang", "en"]
+//   description = "Thesaurus"
+//   # Seconds a lookup may take before the program is killed
+//   timeout = 5
+//   # Like those of a database
+//   allow = ["10.0.0.0/8"]
+//   hidden = false
+//   require_auth = false
+//   max_matches = 100
+//
+//   # A database in a SQLite file, which can be updated while the server
+//   # runs, see dictrdlib::sqlite. Needs the sqlite feature.
+//   [[sqlite]]
+//   name = "slang"
+//   # Relative to dict_dir
+//   path = "slang.sqlite"
+//   description = "Slang"
+//   # allow, hidden, require_auth, max_matches and deadline like a
+//   # database, queries give up after the deadline
+//
+//   # Networks clients may connect from, all if left out, except for the
+//   # denied networks. Others are answered with 530 and disconnected.
+//   [access]
+//   allow = ["127.0.0.0/8", "192.168.0.0/16", "::1"]
+//   deny = ["192.168.13.0/24"]
+//
+//   # One line per command, as text or JSON lines. Rotated at a size in
+//   # bytes or an age in seconds, keeping a number of old files.
+//   [access_log]
+//   path = "/var/log/dictrd/access.log"
+//   format = "json"
+//   max_size = 10485760
+//   max_age = 86400
+//   keep = 5
+//
+//   # Users that can authenticate with SASLAUTH, keep the file private
+//   [[user]]
+//   name = "tim"
+//   secret = "tanstaaftanstaaf"
+//
+// Without a configuration file the server listens on 127.0.0.1:2628 and
+// serves the jargon and devils databases.
+use super::{BackendSettings, DatabaseSettings, ListenerSettings, Source};
+use dictrdlib::access::{AccessList, Network};
+use dictrdlib::accesslog::{AccessLog, AccessLogFormat, QueryLogging, Rotation};
+use dictrdlib::collation::Collation;
+use dictrdlib::filter;
+use dictrdlib::metadata::Encoding;
+use dictrdlib::mime::ContentType;
+use dictrdlib::phonetic::Phonetic;
+use dictrdlib::plugin::DEFAULT_TIMEOUT;
+use dictrdlib::server::{InputEncoding, DEFAULT_DEADLINE};
+use dictrdlib::strategy;
+use dictrdlib::template::Template;
+use dictrdlib::translit::Transliteration;
+use log::LevelFilter;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default = "default_log_level")]
+    log_level: String,
+    #[serde(default = "default_log_queries")]
+    log_queries: String,
+    #[serde(default = "default_input_encoding")]
+    input_encoding: String,
+    pub dict_dir: Option<PathBuf>,
+    pub banner: Option<String>,
+    pub default_strategy: Option<String>,
+    #[serde(default)]
+    pub strategy_aliases: HashMap<String, String>,
+    #[serde(default = "default_listen")]
+    listen: Vec<ListenConfig>,
+    #[serde(default = "default_databases")]
+    database: Vec<DatabaseConfig>,
+    #[serde(default)]
+    plugin: Vec<PluginConfig>,
+    #[serde(default)]
+    sqlite: Vec<SqliteConfig>,
+    #[serde(default)]
+    user: Vec<UserConfig>,
+    tls: Option<TlsConfig>,
+    #[serde(default)]
+    access: AccessConfig,
+    access_log: Option<AccessLogConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListenConfig {
+    address: String,
+    databases: Option<Vec<String>>,
+    #[serde(default)]
+    tls: bool,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DatabaseConfig {
+    name: String,
+    path: Option<PathBuf>,
+    description: Option<String>,
+    #[serde(default)]
+    mandatory: bool,
+    #[serde(default)]
+    fst: bool,
+    #[serde(default = "default_phonetic")]
+    phonetic: Vec<String>,
+    stem_language: Option<String>,
+    #[serde(default)]
+    on_disk: bool,
+    #[serde(default)]
+    index_cache: bool,
+    allow: Option<Vec<String>>,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    require_auth: bool,
+    max_matches: Option<usize>,
+    default_strategy: Option<String>,
+    locale: Option<String>,
+    encoding: Option<String>,
+    #[serde(default)]
+    transliterate: Vec<String>,
+    content_type: Option<String>,
+    #[serde(default)]
+    filters: Vec<String>,
+    template: Option<PathBuf>,
+    deadline: Option<f64>,
+    // Read from the template file when the configuration is loaded
+    #[serde(skip)]
+    layout: Option<Template>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PluginConfig {
+    name: String,
+    command: Vec<String>,
+    description: Option<String>,
+    timeout: Option<u64>,
+    allow: Option<Vec<String>>,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    require_auth: bool,
+    max_matches: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+// Refused without the sqlite feature
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+struct SqliteConfig {
+    name: String,
+    path: PathBuf,
+    description: Option<String>,
+    allow: Option<Vec<String>>,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    require_auth: bool,
+    max_matches: Option<usize>,
+    deadline: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserConfig {
+    name: String,
+    secret: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AccessConfig {
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AccessLogConfig {
+    path: PathBuf,
+    #[serde(default = "default_access_log_format")]
+    format: String,
+    max_size: Option<u64>,
+    max_age: Option<u64>,
+    #[serde(default = "default_keep")]
+    keep: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsConfig {
+    certificate: PathBuf,
+    key: PathBuf,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_log_queries() -> String {
+    "full".to_string()
+}
+
+fn default_input_encoding() -> String {
+    "strict".to_string()
+}
+
+fn default_phonetic() -> Vec<String> {
+    ["soundex", "metaphone", "caverphone"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn default_access_log_format() -> String {
+    "text".to_string()
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+fn default_listen() -> Vec<ListenConfig> {
+    vec![ListenConfig {
+        address: "127.0.0.1:2628".to_string(),
+        databases: None,
+        tls: false,
+        allow: Vec::new(),
+        deny: Vec::new(),
+    }]
+}
+
+fn default_databases() -> Vec<DatabaseConfig> {
+    ["jargon", "devils"]
+        .iter()
+        .map(|name| DatabaseConfig {
+            name: name.to_string(),
+            path: None,
+            description: None,
+            mandatory: false,
+            fst: false,
+            phonetic: default_phonetic(),
+            stem_language: None,
+            on_disk: false,
+            index_cache: false,
+            allow: None,
+            hidden: false,
+            require_auth: false,
+            max_matches: None,
+            default_strategy: None,
+            locale: None,
+            encoding: None,
+            transliterate: Vec::new(),
+            content_type: None,
+            filters: Vec::new(),
+            template: None,
+            deadline: None,
+            layout: None,
+        })
+        .collect()
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            log_level: default_log_level(),
+            log_queries: default_log_queries(),
+            input_encoding: default_input_encoding(),
+            dict_dir: None,
+            banner: None,
+            default_strategy: None,
+            strategy_aliases: HashMap::new(),
+            listen: default_listen(),
+            database: default_databases(),
+            plugin: Vec::new(),
+            sqlite: Vec::new(),
+            user: Vec::new(),
+            tls: None,
+            access: AccessConfig::default(),
+            access_log: None,
+        }
+    }
+}
+
+impl Config {
+    // Reads a configuration file. A missing file is only an error if
+    // required, otherwise the defaults apply.
+    pub fn load(path: &Path, required: bool) -> Result<Config, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        let mut config = Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        config.read_templates()?;
+        Ok(config)
+    }
+
+    fn parse(text: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+        config.check()?;
+        Ok(config)
+    }
+
+    // Reads the template files of the databases
+    fn read_templates(&mut self) -> Result<(), String> {
+        for database in self.database.iter_mut() {
+            if let Some(ref path) = database.template {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                let template =
+                    Template::new(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+                database.layout = Some(template);
+            }
+        }
+        Ok(())
+    }
+
+    // Catches mistakes that would otherwise only show once clients connect
+    fn check(&self) -> Result<(), String> {
+        self.log_level()?;
+        self.query_logging()?;
+        self.input_encoding()?;
+        self.listeners()?;
+        self.access()?;
+        self.access_log_format()?;
+        if self.listen.is_empty() {
+            return Err("no listen addresses".to_string());
+        }
+        for (i, database) in self.database.iter().enumerate() {
+            if self.database[..i].iter().any(|d| d.name == database.name) {
+                return Err(format!("database {} is declared twice", database.name));
+            }
+            if let Some(ref allow) = database.allow {
+                networks(allow)?;
+            }
+            check_deadline(&database.name, database.deadline)?;
+            if let Some(ref locale) = database.locale {
+                Collation::new(locale).map_err(|_| {
+                    format!("database {} has unknown locale {}", database.name, locale)
+                })?;
+            }
+            if let Some(ref encoding) = database.encoding {
+                if Encoding::for_label(encoding).is_none() {
+                    return Err(format!(
+                        "database {} has unknown encoding {}",
+                        database.name, encoding
+                    ));
+                }
+            }
+            for name in database.phonetic.iter() {
+                if Phonetic::from_name(name).is_none() {
+                    return Err(format!(
+                        "database {} has unknown phonetic algorithm {}",
+                        database.name, name
+                    ));
+                }
+            }
+            if let Some(ref language) = database.stem_language {
+                if strategy::stem_language(language).is_none() {
+                    return Err(format!(
+                        "database {} has unknown stemming language {}",
+                        database.name, language
+                    ));
+                }
+            }
+            for name in database.transliterate.iter() {
+                if Transliteration::from_name(name).is_none() {
+                    return Err(format!(
+                        "database {} has unknown transliteration {}",
+                        database.name, name
+                    ));
+                }
+            }
+            if let Some(ref content_type) = database.content_type {
+                if ContentType::from_mime(content_type).is_none() {
+                    return Err(format!(
+                        "database {} has unknown content type {}",
+                        database.name, content_type
+                    ));
+                }
+            }
+            for name in database.filters.iter() {
+                if filter::from_name(name, ContentType::Plain).is_none() {
+                    return Err(format!(
+                        "database {} has unknown filter {}",
+                        database.name, name
+                    ));
+                }
+            }
+            // The file is searched in byte order and as UTF-8
+            if database.on_disk && (database.locale.is_some() || database.encoding.is_some()) {
+                return Err(format!(
+                    "database {} cannot be searched on disk with a locale or encoding",
+                    database.name
+                ));
+            }
+            if database.on_disk && !database.transliterate.is_empty() {
+                return Err(format!(
+                    "database {} cannot be searched on disk with transliteration",
+                    database.name
+                ));
+            }
+            if database.on_disk && database.index_cache {
+                return Err(format!(
+                    "database {} cannot be searched on disk with an index cache",
+                    database.name
+                ));
+            }
+        }
+        let names: Vec<&String> = self.backend_names().collect();
+        for (i, name) in names.iter().enumerate() {
+            if self.database.iter().any(|d| &d.name == *name) || names[..i].contains(name) {
+                return Err(format!("database {} is declared twice", name));
+            }
+        }
+        for plugin in self.plugin.iter() {
+            if plugin.command.is_empty() {
+                return Err(format!("plugin {} has no command", plugin.name));
+            }
+            if let Some(ref allow) = plugin.allow {
+                networks(allow)?;
+            }
+        }
+        for sqlite in self.sqlite.iter() {
+            if !cfg!(feature = "sqlite") {
+                return Err(format!(
+                    "database {} needs dictrd built with the sqlite feature",
+                    sqlite.name
+                ));
+            }
+            if let Some(ref allow) = sqlite.allow {
+                networks(allow)?;
+            }
+            check_deadline(&sqlite.name, sqlite.deadline)?;
+        }
+        for (i, user) in self.user.iter().enumerate() {
+            if self.user[..i].iter().any(|u| u.name == user.name) {
+                return Err(format!("user {} is declared twice", user.name));
+            }
+        }
+        for listen in self.listen.iter() {
+            for name in listen.databases.iter().flatten() {
+                if !self.database.iter().any(|d| &d.name == name)
+                    && !self.backend_names().any(|n| n == name)
+                {
+                    return Err(format!(
+                        "listen address {} refers to unknown database {}",
+                        listen.address, name
+                    ));
+                }
+            }
+            if listen.tls && self.tls.is_none() {
+                return Err(format!(
+                    "listen address {} uses TLS but there is no [tls] section",
+                    listen.address
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn log_level(&self) -> Result<LevelFilter, String> {
+        LevelFilter::from_str(&self.log_level)
+            .map_err(|_| format!("invalid log level {}", self.log_level))
+    }
+
+    pub fn query_logging(&self) -> Result<QueryLogging, String> {
+        QueryLogging::from_name(&self.log_queries)
+            .ok_or_else(|| format!("invalid query logging {}", self.log_queries))
+    }
+
+    pub fn input_encoding(&self) -> Result<InputEncoding, String> {
+        InputEncoding::from_name(&self.input_encoding)
+            .ok_or_else(|| format!("invalid input encoding {}", self.input_encoding))
+    }
+
+    pub fn listeners(&self) -> Result<Vec<ListenerSettings>, String> {
+        self.listen
+            .iter()
+            .map(|listen| {
+                Ok(ListenerSettings {
+                    addr: SocketAddr::from_str(&listen.address)
+                        .map_err(|_| format!("invalid listen address {}", listen.address))?,
+                    databases: listen.databases.clone(),
+                    tls: listen.tls,
+                    access: AccessList {
+                        allow: networks(&listen.allow)?,
+                        deny: networks(&listen.deny)?,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    pub fn databases(&self) -> Vec<DatabaseSettings> {
+        self.database
+            .iter()
+            .map(|database| {
+                // Checked when the file was loaded
+                let content_type = database
+                    .content_type
+                    .as_ref()
+                    .map_or(ContentType::Plain, |mime| {
+                        ContentType::from_mime(mime).unwrap()
+                    });
+                let filters = database
+                    .filters
+                    .iter()
+                    .map(|name| filter::from_name(name, content_type).unwrap())
+                    .collect();
+                DatabaseSettings {
+                    name: database.name.clone(),
+                    path: database.path.clone(),
+                    description: database.description.clone(),
+                    mandatory: database.mandatory,
+                    content_type,
+                    filters,
+                    template: database.layout.clone(),
+                    deadline: deadline(database.deadline),
+                    fst: database.fst,
+                    // Checked when the file was loaded
+                    phonetic: database
+                        .phonetic
+                        .iter()
+                        .map(|name| Phonetic::from_name(name).unwrap())
+                        .collect(),
+                    // Checked when the file was loaded
+                    stem_language: database
+                        .stem_language
+                        .as_ref()
+                        .map(|language| strategy::stem_language(language).unwrap()),
+                    on_disk: database.on_disk,
+                    index_cache: database.index_cache,
+                    locale: database.locale.clone(),
+                    // Checked when the file was loaded
+                    encoding: database
+                        .encoding
+                        .as_ref()
+                        .map(|encoding| Encoding::for_label(encoding).unwrap()),
+                    // Checked when the file was loaded
+                    transliterations: database
+                        .transliterate
+                        .iter()
+                        .map(|name| Transliteration::from_name(name).unwrap())
+                        .collect(),
+                    // Checked when the file was loaded
+                    networks: database
+                        .allow
+                        .as_ref()
+                        .map(|allow| networks(allow).unwrap()),
+                    hidden: database.hidden,
+                    require_auth: database.require_auth,
+                    max_matches: database.max_matches,
+                    default_strategy: database.default_strategy.clone(),
+                }
+            })
+            .collect()
+    }
+
+    // Names of the plugin and SQLite databases
+    fn backend_names(&self) -> impl Iterator<Item = &String> {
+        let plugins = self.plugin.iter().map(|plugin| &plugin.name);
+        plugins.chain(self.sqlite.iter().map(|sqlite| &sqlite.name))
+    }
+
+    // The plugin and SQLite databases
+    pub fn backends(&self) -> Vec<BackendSettings> {
+        // Only extended with the sqlite feature
+        #[cfg_attr(not(feature = "sqlite"), allow(unused_mut))]
+        let mut backends: Vec<BackendSettings> = self
+            .plugin
+            .iter()
+            .map(|plugin| BackendSettings {
+                name: plugin.name.clone(),
+                source: Source::Plugin {
+                    command: plugin.command.clone(),
+                    timeout: plugin.timeout.map_or(DEFAULT_TIMEOUT, Duration::from_secs),
+                },
+                description: plugin.description.clone(),
+                // Checked when the file was loaded
+                networks: plugin.allow.as_ref().map(|allow| networks(allow).unwrap()),
+                hidden: plugin.hidden,
+                require_auth: plugin.require_auth,
+                max_matches: plugin.max_matches,
+            })
+            .collect();
+        #[cfg(feature = "sqlite")]
+        backends.extend(self.sqlite.iter().map(|sqlite| BackendSettings {
+            name: sqlite.name.clone(),
+            source: Source::Sqlite {
+                path: sqlite.path.clone(),
+                deadline: deadline(sqlite.deadline),
+            },
+            description: sqlite.description.clone(),
+            // Checked when the file was loaded
+            networks: sqlite.allow.as_ref().map(|allow| networks(allow).unwrap()),
+            hidden: sqlite.hidden,
+            require_auth: sqlite.require_auth,
+            max_matches: sqlite.max_matches,
+        }));
+        backends
+    }
+
+    pub fn access(&self) -> Result<AccessList, String> {
+        Ok(AccessList {
+            allow: networks(&self.access.allow)?,
+            deny: networks(&self.access.deny)?,
+        })
+    }
+
+    fn access_log_format(&self) -> Result<AccessLogFormat, String> {
+        match self.access_log.as_ref().map(|log| log.format.as_str()) {
+            Some("text") | None => Ok(AccessLogFormat::Text),
+            Some("json") => Ok(AccessLogFormat::Json),
+            Some(format) => Err(format!("invalid access log format {}", format)),
+        }
+    }
+
+    // Opens the access log. None without an [access_log] section.
+    pub fn access_log(&self) -> Result<Option<AccessLog>, String> {
+        let log = match self.access_log {
+            Some(ref log) => log,
+            None => return Ok(None),
+        };
+        let rotation = Rotation {
+            max_size: log.max_size,
+            max_age: log.max_age.map(Duration::from_secs),
+            keep: log.keep,
+        };
+        AccessLog::open(&log.path, self.access_log_format()?, rotation)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", log.path.display(), e))
+    }
+
+    // Reads the certificate and key of the TLS listeners. None without a
+    // [tls] section.
+    pub fn tls_acceptor(&self) -> Result<Option<TlsAcceptor>, String> {
+        let tls = match self.tls {
+            Some(ref tls) => tls,
+            None => return Ok(None),
+        };
+        let certificates = CertificateDer::pem_file_iter(&tls.certificate)
+            .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("{}: {}", tls.certificate.display(), e))?;
+        let key = PrivateKeyDer::from_pem_file(&tls.key)
+            .map_err(|e| format!("{}: {}", tls.key.display(), e))?;
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)
+            .map_err(|e| format!("{}: {}", tls.certificate.display(), e))?;
+        Ok(Some(TlsAcceptor::from(Arc::new(config))))
+    }
+
+    // Shared secrets by user name
+    pub fn users(&self) -> HashMap<String, String> {
+        self.user
+            .iter()
+            .map(|user| (user.name.clone(), user.secret.clone()))
+            .collect()
+    }
+}
+
+fn networks(list: &[String]) -> Result<Vec<Network>, String> {
+    list.iter().map(|network| network.parse()).collect()
+}
+
+fn check_deadline(name: &str, seconds: Option<f64>) -> Result<(), String> {
+    match seconds {
+        Some(seconds) if !(seconds > 0.0 && seconds.is_finite()) => Err(format!(
+            "database {} has invalid deadline {}",
+            name, seconds
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Checked when the file was loaded
+fn deadline(seconds: Option<f64>) -> Duration {
+    seconds.map_or(DEFAULT_DEADLINE, Duration::from_secs_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let config = Config::parse("").unwrap();
+        let listeners = config.listeners().unwrap();
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].addr.to_string(), "127.0.0.1:2628");
+        let names: Vec<String> = config.databases().into_iter().map(|d| d.name).collect();
+        assert_eq!(names, ["jargon", "devils"]);
+        assert!(config.backends().is_empty());
+        assert_eq!(config.log_level().unwrap(), LevelFilter::Info);
+        assert_eq!(config.query_logging().unwrap(), QueryLogging::Full);
+    }
+
+    #[test]
+    fn listeners_and_databases() {
+        let config = Config::parse(
+            r#"
+            [[listen]]
+            address = "[::1]:2628"
+            databases = ["jargon"]
+            deny = ["::1/128"]
+
+            [[database]]
+            name = "jargon"
+            path = "en/jargon"
+            phonetic = ["cologne"]
+            stem_language = "en"
+            deadline = 0.5
+
+            [[plugin]]
+            name = "thesaurus"
+            command = ["thesaurus"]
+            "#,
+        )
+        .unwrap();
+        let listeners = config.listeners().unwrap();
+        assert_eq!(listeners[0].addr.to_string(), "[::1]:2628");
+        assert_eq!(listeners[0].databases, Some(vec!["jargon".to_string()]));
+        assert!(!listeners[0].access.permits("::1".parse().unwrap()));
+        assert!(listeners[0].access.permits("::2".parse().unwrap()));
+        let databases = config.databases();
+        assert_eq!(databases.len(), 1);
+        assert_eq!(databases[0].path, Some(PathBuf::from("en/jargon")));
+        assert_eq!(databases[0].phonetic, [Phonetic::Cologne]);
+        assert!(databases[0].stem_language.is_some());
+        assert_eq!(databases[0].deadline, Duration::from_millis(500));
+        let backends = config.backends();
+        assert_eq!(backends[0].name, "thesaurus");
+        assert!(matches!(
+            backends[0].source,
+            Source::Plugin { timeout, .. } if timeout == DEFAULT_TIMEOUT
+        ));
+    }
+
+    #[test]
+    fn mistakes() {
+        let error = |text: &str| Config::parse(text).err().unwrap();
+        assert!(error("colour = \"red\"").contains("unknown field"));
+        assert!(error("log_level = \"loud\"").contains("invalid log level"));
+        assert!(error("input_encoding = \"klingon\"").contains("invalid input encoding"));
+        assert!(error("[[listen]]\naddress = \"localhost\"").contains("invalid listen"));
+        assert_eq!(
+            error("[[listen]]\naddress = \"127.0.0.1:2628\"\ndatabases = [\"zork\"]"),
+            "listen address 127.0.0.1:2628 refers to unknown database zork"
+        );
+        assert_eq!(
+            error("[[listen]]\naddress = \"127.0.0.1:2628\"\nallow = [\"10.0.0.0/33\"]"),
+            "invalid network 10.0.0.0/33"
+        );
+        assert_eq!(
+            error("[[database]]\nname = \"a\"\n[[database]]\nname = \"a\""),
+            "database a is declared twice"
+        );
+        assert_eq!(
+            error("[[database]]\nname = \"a\"\ndeadline = 0"),
+            "database a has invalid deadline 0"
+        );
+        assert_eq!(
+            error("[[database]]\nname = \"a\"\nphonetic = [\"nysiis\"]"),
+            "database a has unknown phonetic algorithm nysiis"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+// Running as a traditional daemon: started as root to bind the privileged
+// port, dictrd detaches from the terminal, writes its process id and
+// continues as an unprivileged user.
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+// User and group to continue as
+pub struct Credentials {
+    // Name of the user, for its supplementary groups
+    user: Option<CString>,
+    uid: Option<libc::uid_t>,
+    gid: libc::gid_t,
+}
+
+impl Credentials {
+    // Looks up the user and the group, names or numbers. Without a group
+    // the primary group of the user is taken.
+    pub fn lookup(user: Option<&str>, group: Option<&str>) -> io::Result<Credentials> {
+        let not_found = |kind: &str, name: &str| {
+            io::Error::new(io::ErrorKind::NotFound, format!("No {} {}", kind, name))
+        };
+        let (name, uid, user_gid) = match user {
+            None => (None, None, None),
+            Some(user) => {
+                let name = CString::new(user)?;
+                let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+                if !passwd.is_null() {
+                    let passwd = unsafe { &*passwd };
+                    (Some(name), Some(passwd.pw_uid), Some(passwd.pw_gid))
+                } else {
+                    match user.parse() {
+                        Ok(uid) => (None, Some(uid), None),
+                        Err(_) => return Err(not_found("user", user)),
+                    }
+                }
+            }
+        };
+        let gid = match group {
+            None => user_gid,
+            Some(group) => {
+                let name = CString::new(group)?;
+                let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+                if !entry.is_null() {
+                    Some(unsafe { (*entry).gr_gid })
+                } else {
+                    Some(group.parse().map_err(|_| not_found("group", group))?)
+                }
+            }
+        };
+        let gid = match gid {
+            Some(gid) => gid,
+            None => unsafe { libc::getgid() },
+        };
+        Ok(Credentials {
+            user: name,
+            uid,
+            gid,
+        })
+    }
+
+    // Gives up root, the group first as only root can change it
+    pub fn apply(&self) -> io::Result<()> {
+        let check = |result: libc::c_int| match result {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        };
+        unsafe {
+            match &self.user {
+                Some(user) => check(libc::initgroups(user.as_ptr(), self.gid))?,
+                None => check(libc::setgroups(1, &self.gid))?,
+            }
+            check(libc::setgid(self.gid))?;
+            if let Some(uid) = self.uid {
+                check(libc::setuid(uid))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Forks into the background and starts a new session without a controlling
+// terminal. Must be called before any threads are started. Standard input
+// and output are redirected to /dev/null, and so is standard error if it is
+// a terminal, so that its log can still be sent to a file.
+pub fn daemonize() -> io::Result<()> {
+    fork_and_exit_parent()?;
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // The session leader exits, so the daemon can never acquire a terminal
+    fork_and_exit_parent()?;
+    std::env::set_current_dir("/")?;
+    let null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    let mut fds = vec![libc::STDIN_FILENO, libc::STDOUT_FILENO];
+    if unsafe { libc::isatty(libc::STDERR_FILENO) } == 1 {
+        fds.push(libc::STDERR_FILENO);
+    }
+    for fd in fds {
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> io::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => std::process::exit(0),
+    }
+}
+
+// Writes the id of this process to a file
+pub fn write_pidfile(path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", std::process::id())
+}
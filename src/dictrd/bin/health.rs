@@ -0,0 +1,64 @@
+// A probe port for load balancers and liveness or readiness probes:
+//
+//   dictrd --health-address 127.0.0.1:2629
+//   curl http://127.0.0.1:2629/
+//
+// Each connection is answered right away, without waiting for a request,
+// with 200 if all databases were loaded and can be read and 503 naming the
+// failing ones otherwise, and closed. The answer is HTTP, so that it serves
+// HTTP probes as well as TCP checks expecting "200 OK".
+use dictrdlib::server::DictdServer;
+use log::{error, warn};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread::spawn;
+use std::time::Duration;
+
+// Time given to the client to finish sending its request, which is read so
+// that closing the connection does not reset it
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub fn serve(listener: TcpListener, server: DictdServer<File>) {
+    for stream in listener.incoming() {
+        match stream {
+            Err(e) => error!("Could not accept health connection: {:?}", e),
+            Ok(stream) => {
+                let server = server.clone();
+                spawn(move || {
+                    if let Err(e) = handle(stream, &server) {
+                        error!("Health connection failed: {:?}", e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn handle(mut stream: TcpStream, server: &DictdServer<File>) -> io::Result<()> {
+    let failing = server.check_databases();
+    let (status, body) = if failing.is_empty() {
+        ("200 OK", "ok\n".to_string())
+    } else {
+        for (name, reason) in failing.iter() {
+            warn!("{} failed the health check: {}", name, reason);
+        }
+        let names: Vec<&str> = failing.keys().map(String::as_str).collect();
+        (
+            "503 Service Unavailable",
+            format!("failing: {}\n", names.join(" ")),
+        )
+    };
+    write!(
+        stream,
+        "HTTP/1.0 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    stream.shutdown(Shutdown::Write)?;
+    stream.set_read_timeout(Some(DRAIN_TIMEOUT))?;
+    let _ = io::copy(&mut stream.take(64 * 1024), &mut io::sink());
+    Ok(())
+}
@@ -0,0 +1,299 @@
+// JSON gateway over HTTP for web frontends, with the http feature:
+//
+//   dictrd --http-address 127.0.0.1:8080
+//   curl http://127.0.0.1:8080/define/jargon/hack
+//   curl http://127.0.0.1:8080/match/*/prefix/hack
+//
+// GET /define/{database}/{word} answers
+// {"word":...,"definitions":[{"headword":...,"database":...,"description":...,"definition":...}]}
+// and GET /match/{database}/{strategy}/{word}
+// {"word":...,"matches":[{"database":...,"headword":...}],"truncated":false}.
+// The path segments are percent-encoded, the databases and strategies are
+// those of DEFINE and MATCH. Every request is served on its own connection,
+// except for WebSockets on /ws with the websocket feature.
+use dictrdlib::client::{Definition, Match};
+use dictrdlib::errors::DictError;
+use dictrdlib::json;
+use dictrdlib::percent::decode;
+use dictrdlib::securitylog::SecurityEvent;
+use dictrdlib::server::{DictdServer, Refusal};
+use dictrdlib::throttle::RateLimiter;
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+// Longest request line or header line, and most header lines of a request
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+// Time a client has to send its request, and to take a refusal
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Requests per second of each address, limited to the --command-rate of a
+// DICT connection since every request is a connection of its own
+struct RequestRate {
+    rate: Option<u64>,
+    limiters: HashMap<IpAddr, (RateLimiter, Instant)>,
+}
+
+impl RequestRate {
+    fn allows(&mut self, ip: IpAddr) -> bool {
+        let rate = match self.rate {
+            Some(rate) => rate,
+            None => return true,
+        };
+        let now = Instant::now();
+        // A bucket left alone for a second is full again, like a new one
+        if self.limiters.len() > 1024 {
+            self.limiters
+                .retain(|_, (_, last)| now.duration_since(*last) < Duration::from_secs(1));
+        }
+        let (limiter, last) = self
+            .limiters
+            .entry(ip)
+            .or_insert_with(|| (RateLimiter::new(rate, now), now));
+        *last = now;
+        limiter.reserve(1, now) == Duration::from_secs(0)
+    }
+}
+
+// Admits the clients like DICT connections, turning away those the access
+// list denies and those over --max-connections, the limit of their address
+// or the command rate
+pub fn serve(listener: TcpListener, server: DictdServer<File>) {
+    let mut rate = RequestRate {
+        rate: server.command_limiter().map(|limiter| limiter.rate()),
+        limiters: HashMap::new(),
+    };
+    for stream in listener.incoming() {
+        let (stream, peer) = match stream.and_then(|stream| {
+            let peer = stream.peer_addr()?;
+            Ok((stream, peer))
+        }) {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Could not accept HTTP connection: {:?}", e);
+                continue;
+            }
+        };
+        let admission = match server.admit_client(peer) {
+            Ok(admission) => admission,
+            Err(Refusal::Denied) => {
+                warn!("Denied HTTP access to {}", peer);
+                server.record_security_event(&peer.to_string(), &SecurityEvent::AccessDenied);
+                refuse(stream, "403 Forbidden", "Access denied");
+                continue;
+            }
+            Err(Refusal::Busy(reason)) => {
+                warn!("Turned away HTTP client {}, {}", peer, reason);
+                refuse(
+                    stream,
+                    "503 Service Unavailable",
+                    "Server temporarily unavailable",
+                );
+                continue;
+            }
+        };
+        if !rate.allows(peer.ip()) {
+            warn!("Turned away HTTP client {}, too many requests", peer);
+            refuse(stream, "429 Too Many Requests", "Too many requests");
+            continue;
+        }
+        let server = server.clone();
+        spawn(move || {
+            let _admission = admission;
+            match handle(stream, peer, server) {
+                // The read timeout
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    info!("HTTP client {} timed out", peer)
+                }
+                Err(e) => error!("HTTP connection failed: {:?}", e),
+                Ok(()) => (),
+            }
+        });
+    }
+}
+
+// Answers a client turned away without reading its request
+fn refuse(mut stream: TcpStream, status: &str, message: &str) {
+    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+    let _ = write_response(&mut stream, status, &error_body(message));
+}
+
+fn handle(stream: TcpStream, peer: SocketAddr, mut server: DictdServer<File>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let request = match read_line(&mut reader)? {
+        Some(request) => request,
+        None => {
+            info!("HTTP {}: request line too long", peer);
+            return write_response(&mut writer, "414 URI Too Long", &error_body("URI too long"));
+        }
+    };
+    // Header names in lower case
+    let mut headers = Vec::new();
+    loop {
+        let line = match read_line(&mut reader)? {
+            Some(line) if headers.len() < MAX_HEADERS => line,
+            _ => {
+                info!("HTTP {} {}: headers too large", peer, request.trim());
+                return write_response(
+                    &mut writer,
+                    "431 Request Header Fields Too Large",
+                    &error_body("Headers too large"),
+                );
+            }
+        };
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    let mut fields = request.split_whitespace();
+    let (status, body) = match (fields.next(), fields.next()) {
+        #[cfg(feature = "websocket")]
+        (Some("GET"), Some("/ws")) => match header(&headers, "sec-websocket-key") {
+            Some(key) => {
+                info!("HTTP {} {}: WebSocket", peer, request.trim());
+                // Idle like a DICT connection from now on
+                reader.get_ref().set_read_timeout(server.idle_timeout())?;
+                return crate::websocket::serve(reader, writer, key, peer, server);
+            }
+            None => ("400 Bad Request", error_body("Expected a WebSocket")),
+        },
+        (Some("GET"), Some(target)) => respond(&mut server, peer, target),
+        (Some(_), Some(_)) => ("405 Method Not Allowed", error_body("Only GET is allowed")),
+        _ => ("400 Bad Request", error_body("Bad request")),
+    };
+    info!("HTTP {} {}: {}", peer, request.trim(), status);
+    write_response(&mut writer, status, &body)
+}
+
+// Reads a line of at most MAX_LINE bytes, none if it is longer. The end of
+// the stream ends the line, like an empty line ends the headers.
+fn read_line(reader: &mut BufReader<TcpStream>) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.len() > MAX_LINE {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+// Writes the response at once, not a fragment per argument
+fn write_response(writer: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}\n",
+        status,
+        body.len() + 1,
+        body
+    );
+    writer.write_all(response.as_bytes())?;
+    writer.flush()
+}
+
+#[cfg(feature = "websocket")]
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn respond(
+    server: &mut DictdServer<File>,
+    peer: SocketAddr,
+    target: &str,
+) -> (&'static str, String) {
+    // The query string is not used
+    let path = target.split('?').next().unwrap_or("");
+    let segments: Vec<String> = path.split('/').skip(1).map(decode).collect();
+    match segments.as_slice() {
+        [command, database, word] if command == "define" => {
+            match server.define(peer, database, word) {
+                Ok(definitions) => ("200 OK", definitions_body(word, &definitions)),
+                Err(DictError::NoMatch(_)) => ("200 OK", definitions_body(word, &[])),
+                Err(e) => failure(e),
+            }
+        }
+        [command, database, strategy, word] if command == "match" => {
+            match server.match_words(peer, database, strategy, word) {
+                Ok((matches, truncated)) => ("200 OK", matches_body(word, &matches, truncated)),
+                Err(DictError::NoMatch(_)) => ("200 OK", matches_body(word, &[], false)),
+                Err(e) => failure(e),
+            }
+        }
+        _ => ("404 Not Found", error_body("Not found")),
+    }
+}
+
+// The HTTP status of a failed lookup, with the error
+pub fn failure(e: DictError) -> (&'static str, String) {
+    let status = match e.status_code() {
+        501 => "400 Bad Request",
+        550 | 551 => "404 Not Found",
+        _ => {
+            error!("HTTP lookup failed: {:?}", e);
+            "503 Service Unavailable"
+        }
+    };
+    (status, error_body(&e.to_string()))
+}
+
+pub fn definitions_body(word: &str, definitions: &[Definition]) -> String {
+    let items: Vec<String> = definitions
+        .iter()
+        .map(|definition| {
+            format!(
+                "{{\"headword\":{},\"database\":{},\"description\":{},\"definition\":{}}}",
+                json::string(&definition.headword),
+                json::string(&definition.database),
+                json::string(&definition.description),
+                json::string(&definition.text)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"word\":{},\"definitions\":[{}]}}",
+        json::string(word),
+        items.join(",")
+    )
+}
+
+pub fn matches_body(word: &str, matches: &[Match], truncated: bool) -> String {
+    let items: Vec<String> = matches
+        .iter()
+        .map(|m| {
+            format!(
+                "{{\"database\":{},\"headword\":{}}}",
+                json::string(&m.database),
+                json::string(&m.headword)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"word\":{},\"matches\":[{}],\"truncated\":{}}}",
+        json::string(word),
+        items.join(","),
+        truncated
+    )
+}
+
+pub fn error_body(message: &str) -> String {
+    format!("{{\"error\":{}}}", json::string(message))
+}
@@ -1,647 +1,1816 @@
-extern crate bufstream;
 extern crate dictrdlib;
 extern crate hostname;
 extern crate os_info;
 extern crate simple_logging;
 
-use bufstream::BufStream;
-use dictrdlib::parser::{Cmd, Command, Parser};
-use dictrdlib::{DictReader, IndexEntry, IndexReader};
-use log::LevelFilter;
-use log::{debug, error, info};
-use std::collections::HashMap;
-use std::fmt;
-use std::fmt::Display;
+use clap::{App, Arg, ArgMatches};
+use config::Config;
+#[cfg(unix)]
+use daemon::Credentials;
+use dictrdlib::access::{AccessList, Network};
+use dictrdlib::accesslog::QueryLogging;
+use dictrdlib::backend::Backend;
+#[cfg(feature = "sqlite")]
+use dictrdlib::backend::Deadline;
+use dictrdlib::clean::{check, Problem};
+use dictrdlib::collation::Collation;
+use dictrdlib::diskindex::DiskIndex;
+use dictrdlib::errors::DictError;
+use dictrdlib::filter::DefinitionFilter;
+use dictrdlib::indexcache;
+use dictrdlib::metadata::{is_metadata_headword, DatabaseMetadata, Encoding};
+use dictrdlib::mime::ContentType;
+use dictrdlib::normalize::Normalization;
+use dictrdlib::phonetic::Phonetic;
+use dictrdlib::plugin::Plugin;
+use dictrdlib::securitylog::{SecurityEvent, SecurityLog};
+use dictrdlib::server::{bind, DictdServer, WhenBusy};
+#[cfg(feature = "sqlite")]
+use dictrdlib::sqlite::SqliteDictionary;
+use dictrdlib::stats::Statistics;
+use dictrdlib::template::Template;
+use dictrdlib::translit::Transliteration;
+use dictrdlib::{DictReader, IndexEntry, IndexReader, SideIndexes, SidePlan};
+use log::{error, info, warn, LevelFilter};
+use rust_stemmers::Algorithm;
 use std::fs::File;
 use std::io::Write;
-use std::io::{BufRead, BufReader, Read, Seek};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom};
 use std::net::SocketAddr;
-use std::net::{TcpListener, TcpStream};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::{Arc, RwLock};
-use std::thread::spawn;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
 
-#[derive(Debug)]
-pub enum DictdError {
-    IoError(::std::io::Error),
-    EncodingError(::std::string::FromUtf8Error),
-    IllegalParameters,
+#[cfg(unix)]
+mod admin;
+mod config;
+#[cfg(unix)]
+mod daemon;
+mod health;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(windows)]
+mod service;
+#[cfg(target_os = "linux")]
+mod systemd;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+// Defaults of the paths that can be set on the command line or in the
+// environment
+#[cfg(not(windows))]
+const DEFAULT_CONFIG: &str = "/etc/dictrd.toml";
+#[cfg(not(windows))]
+const DEFAULT_DICT_DIR: &str = "/usr/share/dictd";
+#[cfg(not(windows))]
+const DEFAULT_STATE_FILE: &str = "/var/lib/dictrd/dictrd.state";
+// On Windows they are in %ProgramData%\dictrd
+#[cfg(windows)]
+const DEFAULT_CONFIG: &str = "dictrd.toml";
+#[cfg(windows)]
+const DEFAULT_DICT_DIR: &str = "dicts";
+#[cfg(windows)]
+const DEFAULT_STATE_FILE: &str = "dictrd.state";
+
+// How often the statistics are written to the state file
+const STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+// Largest edit distance of results of the lev strategy unless
+// --lev-distance says otherwise. Every step makes the index a lot larger.
+const DEFAULT_LEV_DISTANCE: u64 = 1;
+
+// Most lines at the start of an index --check reads
+const HEAD_LINES: usize = 100;
+
+#[cfg(not(windows))]
+fn default_path(path: &str) -> PathBuf {
+    PathBuf::from(path)
 }
 
-impl Display for DictdError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DictdError")
+#[cfg(windows)]
+fn default_path(name: &str) -> PathBuf {
+    let data = std::env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"));
+    data.join("dictrd").join(name)
+}
+
+// Resolves a path from the command line, the environment or the default, in
+// that order
+fn configured_path(matches: &ArgMatches, arg: &str, var: &str, default: &Path) -> PathBuf {
+    match matches.value_of_os(arg) {
+        Some(path) => PathBuf::from(path),
+        None => std::env::var_os(var).map_or_else(|| default.to_path_buf(), PathBuf::from),
     }
 }
 
-impl std::error::Error for DictdError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        match *self {
-            DictdError::IllegalParameters => None,
-            DictdError::EncodingError(ref e) => Some(e),
-            DictdError::IoError(ref e) => Some(e),
+// Resolves a positive number from the command line or the environment, None
+// if neither is given. Exits on anything else.
+fn configured_count(matches: &ArgMatches, arg: &str, var: &str) -> Option<u64> {
+    let value = matches
+        .value_of(arg)
+        .map(String::from)
+        .or_else(|| std::env::var(var).ok())?;
+    match value.parse::<u64>() {
+        Ok(count) if count > 0 => Some(count),
+        _ => {
+            error!("Invalid value for --{}: {}", arg, value);
+            std::process::exit(1)
         }
     }
 }
 
-impl From<::std::io::Error> for DictdError {
-    fn from(err: ::std::io::Error) -> DictdError {
-        DictdError::IoError(err)
+// Sets the strategy aliases and the default strategies of the server and of
+// the databases, --default-strategy taking precedence over the configuration
+// file. Exits if any of them names an unknown strategy.
+fn configure_strategies(
+    dictd_server: &mut DictdServer<File>,
+    matches: &ArgMatches,
+    config: &Config,
+    databases: &[DatabaseSettings],
+) {
+    if let Err(e) = dictd_server.set_strategy_aliases(config.strategy_aliases.clone()) {
+        error!("Invalid strategy alias: {}", e);
+        std::process::exit(1)
+    }
+    let default_strategy = matches
+        .value_of("default-strategy")
+        .map(String::from)
+        .or_else(|| std::env::var("DICTRD_DEFAULT_STRATEGY").ok())
+        .or_else(|| config.default_strategy.clone());
+    if let Some(strategy) = default_strategy {
+        if let Err(e) = dictd_server.set_default_strategy(&strategy) {
+            error!("Invalid default strategy: {}", e);
+            std::process::exit(1)
+        }
+    }
+    // Only checked here, the databases are not loaded yet
+    for settings in databases.iter() {
+        let strategy = settings.default_strategy.as_deref();
+        if let Err(e) = dictd_server.set_database_strategy(&settings.name, strategy) {
+            error!("Invalid default strategy of {}: {}", settings.name, e);
+            std::process::exit(1)
+        }
     }
 }
 
-impl From<::std::string::FromUtf8Error> for DictdError {
-    fn from(err: ::std::string::FromUtf8Error) -> DictdError {
-        DictdError::EncodingError(err)
+// Writes the statistics to the state file every STATS_INTERVAL
+fn checkpoint_statistics(stats: Arc<Mutex<Statistics>>, path: PathBuf) {
+    let tmp = path.with_extension("state.tmp");
+    // Only the first of repeated failures is logged
+    let mut failing = false;
+    loop {
+        sleep(STATS_INTERVAL);
+        let result = File::create(&tmp).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            stats.lock().unwrap().save(&mut out)?;
+            out.flush()?;
+            std::fs::rename(&tmp, &path)
+        });
+        match result {
+            Err(e) if !failing => {
+                error!("Could not write {}: {:?}", path.display(), e);
+                failing = true;
+            }
+            Err(_) => {}
+            Ok(()) => failing = false,
+        }
     }
 }
 
-pub struct Database<R: Read + Seek> {
-    shortname: String,
-    description: String,
-    info: String,
-    indexreader: Arc<RwLock<IndexReader>>,
-    dictreader: Arc<RwLock<DictReader<R>>>,
+// Path of one of the files of a database, e.g. "jargon.index"
+fn database_path(dir: &Path, filename: &str, extension: &str) -> PathBuf {
+    dir.join(format!("{}.{}", filename, extension))
 }
 
-pub struct DictdServer<R: Read + Seek> {
-    strategies: Arc<RwLock<HashMap<&'static str, &'static str>>>,
-    databases: Arc<RwLock<HashMap<String, Database<R>>>>,
+// Path of the dict file of a database, the dictzip compressed .dict.dz if
+// there is no .dict, or else a plain gzip compressed .dict.gz
+fn dict_path(dir: &Path, filename: &str) -> PathBuf {
+    let path = database_path(dir, filename, "dict");
+    if path.exists() {
+        return path;
+    }
+    let dictzip = database_path(dir, filename, "dict.dz");
+    let gzip = database_path(dir, filename, "dict.gz");
+    if !dictzip.exists() && gzip.exists() {
+        return gzip;
+    }
+    dictzip
 }
 
-impl<R: Read + Seek> Clone for DictdServer<R> {
-    fn clone(&self) -> DictdServer<R> {
-        let strategies = self.strategies.clone();
-        let databases = self.databases.clone();
-        DictdServer {
-            strategies,
-            databases,
+// Opens a file, naming it in the error
+fn open_with_path(path: &Path) -> std::io::Result<File> {
+    File::open(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+// Reads a database. Its files are transcoded from the encoding given, or
+// the one its metadata declares. An index and metadata read from the index
+// cache replace those of the .index file.
+fn add_database(
+    dir: &Path,
+    filename: String,
+    encoding: Option<Encoding>,
+    on_disk: bool,
+    cached: Option<(IndexReader, DatabaseMetadata)>,
+) -> Result<(IndexReader, DictReader<File>, DatabaseMetadata), DictError> {
+    let (mut di, cached_metadata) = match cached {
+        Some((di, metadata)) => (di, Some(metadata)),
+        None => (read_index(dir, &filename, encoding, on_disk)?, None),
+    };
+
+    let file = open_with_path(&dict_path(dir, &filename))?;
+    let file = BufReader::new(file);
+    let mut dr = DictReader::new(file)?;
+    if let Some(encoding) = encoding {
+        dr.set_encoding(encoding);
+    }
+
+    // foo.syn is read like foo.aliases, the name synonym tables go by
+    for extension in &["aliases", "syn"] {
+        if let Ok(file) = File::open(database_path(dir, &filename, extension)) {
+            let count = di.add_aliases(BufReader::new(file));
+            info!(
+                "Read {} aliases for {} from .{}",
+                count, filename, extension
+            );
         }
     }
+    if let Ok(file) = File::open(database_path(dir, &filename, "freq")) {
+        let count = di.add_frequencies(BufReader::new(file));
+        info!("Read {} word frequencies for {}", count, filename);
+    }
+
+    let metadata = match cached_metadata {
+        Some(metadata) => metadata,
+        None => {
+            let metadata = DatabaseMetadata::load(&di, &dr);
+            // SHOW INFO and SHOW DB use the parsed metadata from here on
+            di.remove_metadata_entries();
+            metadata
+        }
+    };
+    dr.set_encoding(encoding.unwrap_or_else(|| metadata.encoding()));
+    Ok((di, dr, metadata))
 }
 
-impl<R: Read + Seek> Default for DictdServer<R> {
-    fn default() -> Self {
-        DictdServer::new()
+// Reads the .index file of a database and normalizes its headwords
+fn read_index(
+    dir: &Path,
+    filename: &str,
+    encoding: Option<Encoding>,
+    on_disk: bool,
+) -> Result<IndexReader, DictError> {
+    let mut di = IndexReader::new();
+    let path = database_path(dir, filename, "index");
+    let mut file = open_with_path(&path)?;
+    match encoding {
+        _ if on_disk => di = IndexReader::on_disk(DiskIndex::open(&path)?),
+        Some(encoding) if encoding != Encoding::Utf8 => {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            di.try_parse_dict_index(encoding.decode(bytes)?.as_bytes())?;
+        }
+        _ => {
+            di.try_parse_dict_index(BufReader::new(file))?;
+        }
     }
+    // Lookups use the normalized headwords as keys, responses the original
+    // spelling
+    let normalization = di.normalization();
+    di.normalize_headwords(normalization);
+    Ok(di)
 }
 
-impl<R: Read + Seek> DictdServer<R> {
-    pub fn new() -> DictdServer<R> {
-        let strategies = Arc::new(RwLock::new(HashMap::new()));
-        strategies
-            .write()
-            .unwrap()
-            .insert("exact", "Match headwords exactly");
-        strategies
-            .write()
-            .unwrap()
-            .insert("prefix", "Match prefixes");
-        let databases = Arc::new(RwLock::new(HashMap::new()));
-        DictdServer {
-            strategies,
-            databases,
-        }
-    }
-
-    // Adds a database to the server
-    pub fn add_database(
-        &mut self,
-        shortname: String,
-        description: String,
-        info: String,
-        indexreader: Arc<RwLock<IndexReader>>,
-        dictreader: Arc<RwLock<DictReader<R>>>,
-    ) {
-        let database = Database {
-            shortname: shortname.clone(),
-            description,
-            info,
-            indexreader,
-            dictreader,
-        };
-        self.databases.write().unwrap().insert(shortname, database);
-    }
-
-    // Handles a connection from the client
-    // TODO: Should count commands and close connection after xx commands
-    pub fn handle_connection(
-        &mut self,
-        stream: &mut BufStream<TcpStream>,
-    ) -> Result<(), DictdError> {
-        let mut parser = Parser::new();
-        let info = os_info::get();
-        stream.write_all(
-            format!(
-                "220 {:?} {} on {} {}\n",
-                hostname::get()?,
-                env!("CARGO_PKG_NAME"),
-                info.os_type(),
-                info.version()
-            )
-            .as_bytes(),
-        )?;
-        if let Err(err) = stream.flush() {
-            return Err(DictdError::IoError(err));
-        }
+// FNV-1a checksum of the .index and .dict files of a database
+fn checksum(dir: &Path, filename: &str) -> std::io::Result<String> {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for path in [
+        database_path(dir, filename, "index"),
+        dict_path(dir, filename),
+    ]
+    .iter()
+    {
+        let mut file = BufReader::new(File::open(path)?);
         loop {
-            let mut reads = String::new();
-            stream.read_line(&mut reads).unwrap(); //TODO: non-blocking read
-            let query = reads.trim();
-            if !query.is_empty() {
-                info!(
-                    "{}: Received query: {}",
-                    stream.get_ref().peer_addr().unwrap(),
-                    query
-                );
-                let result = parser.parse(query);
-                let cmd = match result {
-                    Ok(cmd) => cmd,
-                    _ => {
-                        stream.write_all(b"500 I/O error\n").unwrap();
-                        stream.flush().unwrap();
-                        continue;
-                    }
-                };
-                match cmd.cmd {
-                    Cmd::Define => {
-                        self.command_define(&mut *stream, cmd)?
-                    }
-                    Cmd::Help => {
-                        self.command_help(&mut *stream)?
-                    }
-                    Cmd::Match => {
-                        self.command_match(&mut *stream, cmd)?
-                    }
-                    Cmd::Show => {
-                        self.command_show(&mut *stream, cmd)?
-                    }
-                    Cmd::Status => {
-                        self.command_status(&mut *stream, cmd)?
-                    }
-                    Cmd::Quit => {
-                        self.command_quit(&mut *stream, cmd)?;
-                        break;
-                    }
-                    Cmd::Option => {
-                        if let Err(e) = stream.write_all(b"502 OPTION not implemented\n") {
-                            return Err(DictdError::IoError(e));
-                        }
-                    }
-                    Cmd::Unknown => {
-                        if cmd.params.len() == 1 && cmd.params[0] == "XRANDOM" {
-                            self.command_random(&mut *stream, cmd)?
-                        } else if let Err(e) = stream.write_all(b"502 OPTION not implemented\n") {
-                            return Err(DictdError::IoError(e));
-                        }
-                    }
-
-                    _ => {
-                        if let Err(e) = stream.write_all(b"500 Unknown Command\n") {
-                            return Err(DictdError::IoError(e));
-                        }
-                    }
-                }
-                if let Err(e) = stream.flush() {
-                    return Err(DictdError::IoError(e));
-                }
-                //debug!("DEBUG: reads len =>>>>> {}", reads.len());
+            let buf = file.fill_buf()?;
+            if buf.is_empty() {
+                break;
             }
+            for &b in buf {
+                hash = (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+            }
+            let len = buf.len();
+            file.consume(len);
         }
-        Ok(())
-    }
-
-    // Helper function
-    fn database_exists(&self, database: &str) -> bool {
-        if self.databases.read().unwrap().contains_key(database) {
-            return true;
-        }
-        false
-    }
-
-    fn strategy_exists(&self, strategy: &str) -> bool {
-        if self.strategies.read().unwrap().contains_key(strategy) {
-            return true;
-        }
-        false
-    }
-
-    fn command_help(&self, stream: &mut BufStream<TcpStream>) -> Result<(), DictdError> {
-        stream.write_all(b"113 help text follows\n")?;
-        stream.write_all(b"DEFINE database word         -- look up word in database\n")?;
-        stream.write_all(
-            b"MATCH database strategy word -- match word in database using strategy\n",
-        )?;
-        stream.write_all(b"SHOW DB                      -- list all accessible databases\n")?;
-        stream.write_all(b"SHOW DATABASES               -- list all accessible databases\n")?;
-        stream
-            .write_all(b"SHOW STRAT                   -- list available matching strategies\n")?;
-        stream
-            .write_all(b"SHOW STRATEGIES              -- list available matching strategies\n")?;
-        stream.write_all(
-            b"SHOW INFO database           -- provide information about the database\n",
-        )?;
-        stream.write_all(b"SHOW SERVER                  -- provide site-specific information\n")?;
-        stream.write_all(b"OPTION MIME                  -- use MIME headers\n")?;
-        //stream.write_all(b"CLIENT info                  -- identify client to server\n")?;
-        //stream.write_all(b"AUTH user string             -- provide authentication information\n")?;
-        stream.write_all(b"STATUS                       -- display timing information\n")?;
-        stream.write_all(b"HELP                         -- display this help information\n")?;
-        stream.write_all(b"XRANDOM                      -- return a random definition\n")?;
-        stream.write_all(b"QUIT                         -- terminate connection\n.\n250 ok\n")?;
-        Ok(())
-    }
-
-    fn command_define(
-        &mut self,
-        stream: &mut BufStream<TcpStream>,
-        cmd: Command,
-    ) -> Result<(), DictdError> {
-        if cmd.params.len() < 3 {
-            stream.write_all(b"501 Syntax error, illegal parameters\n")?;
-            return Ok(());
-        }
-        let mut _match_all = false;
-        let mut _match_one = false;
-        let mut databases = Vec::<String>::new();
-        let database = cmd.params[1].clone();
-        match database.as_str() {
-            "*" | "!" => {
-                if database.as_str() == "*" {
-                    _match_all = true;
-                } else {
-                    _match_one = true;
-                }
-                for d in self.databases.read().unwrap().keys() {
-                    databases.push(d.clone());
-                }
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+// Logs a summary of a loaded database and warns about problems found in it
+fn report_database(
+    name: &str,
+    indexreader: &IndexReader,
+    metadata: &DatabaseMetadata,
+    dict_len: u64,
+    elapsed: Duration,
+) {
+    let entries = match indexreader.is_on_disk() {
+        true => "index on disk".to_string(),
+        false => format!("{} entries", indexreader.entries().len()),
+    };
+    info!(
+        "Loaded {}: {} in {:.2}s, {} bytes of definitions, {:?}, dictfmt {}",
+        name,
+        entries,
+        elapsed.as_secs_f64(),
+        dict_len,
+        metadata.encoding(),
+        metadata.dictfmt.as_deref().unwrap_or("unknown")
+    );
+    if metadata.short == "Unknown" {
+        warn!("{}: no 00-database-short entry", name);
+    }
+    let problems = check(indexreader, dict_len);
+    let dangling = problems
+        .iter()
+        .filter(|(_, problem)| *problem == Problem::Dangling)
+        .count();
+    if dangling > 0 {
+        warn!("{}: {} entries point beyond the dict file", name, dangling);
+    }
+    let duplicates = problems
+        .iter()
+        .filter(|(_, problem)| *problem == Problem::Duplicate)
+        .count();
+    if duplicates > 0 {
+        warn!("{}: {} duplicate entries", name, duplicates);
+    }
+}
+
+// Reads a database and applies its settings. The side indexes can be left
+// for later to start faster.
+fn load_database(
+    settings: &DatabaseSettings,
+    dir: &Path,
+    filename: &str,
+    lev_distance: usize,
+    side_indexes: bool,
+) -> Result<(IndexReader, DictReader<File>, DatabaseMetadata), DictError> {
+    let start = Instant::now();
+    let index_path = database_path(dir, filename, "index");
+    let cache_path = database_path(dir, filename, "cache");
+    // The cache is rebuilt when a setting shaping the cached indexes changes
+    let cache_settings = format!(
+        "{:?} {:?} {} {} {:?}",
+        settings.locale, settings.encoding, settings.fst, lev_distance, settings.phonetic
+    );
+    let cached = match settings.index_cache {
+        true => indexcache::load(&cache_path, &index_path, &cache_settings).unwrap_or_else(|e| {
+            warn!("Could not read index cache of {}: {:?}", settings.name, e);
+            None
+        }),
+        false => None,
+    };
+    if cached.is_some() {
+        info!("Read index of {} from its cache", settings.name);
+    }
+    let stale = settings.index_cache && cached.is_none();
+    let (mut di, dr, mut metadata) = add_database(
+        dir,
+        filename.to_string(),
+        settings.encoding,
+        settings.on_disk,
+        cached,
+    )?;
+    // Cached as it was read, not as configured
+    let read_metadata = match stale {
+        true => Some(metadata.clone()),
+        false => None,
+    };
+    if let Some(ref description) = settings.description {
+        metadata.short = description.clone();
+    }
+    if let Some(ref locale) = settings.locale {
+        di.set_collation(Collation::new(locale)?);
+    }
+    if !settings.transliterations.is_empty() {
+        di.set_transliterations(settings.transliterations.clone());
+    }
+    if !settings.on_disk {
+        let plan = side_plan(&di, settings, lev_distance);
+        match side_indexes {
+            true => {
+                let side = SideIndexes::build(di.entries(), &plan);
+                di.install_side_indexes(side);
             }
-            _ => {
-                if !database.is_empty() && !self.database_exists(&database) {
-                    stream.write_all(
-                        b"550 Invalid database, use \"SHOW DB\" for list of databases\n",
-                    )?;
-                    return Ok(());
-                }
-                databases.push(database);
+            // Built by warm_caches once the server runs
+            false => di.plan_side_indexes(plan),
+        }
+    }
+    if let Some(read_metadata) = read_metadata {
+        let saved = indexcache::save(
+            &cache_path,
+            &index_path,
+            &cache_settings,
+            &di,
+            &read_metadata,
+        );
+        if let Err(e) = saved {
+            warn!("Could not write index cache of {}: {:?}", settings.name, e);
+        }
+    }
+    report_database(&settings.name, &di, &metadata, dr.len(), start.elapsed());
+    Ok((di, dr, metadata))
+}
+
+type Loaded = Result<(IndexReader, DictReader<File>, DatabaseMetadata), DictError>;
+
+// Loads the databases side by side, on up to one thread per CPU. The
+// results are in the order of the settings.
+fn load_databases(
+    databases: &[DatabaseSettings],
+    dict_dir: &Path,
+    lev_distance: usize,
+    side_indexes: bool,
+) -> Vec<Loaded> {
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(databases.len());
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<Loaded>> = databases.iter().map(|_| None).collect();
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut loaded = Vec::new();
+                    loop {
+                        let pos = next.fetch_add(1, Ordering::Relaxed);
+                        let settings = match databases.get(pos) {
+                            Some(settings) => settings,
+                            None => return loaded,
+                        };
+                        let (dir, filename) = settings.location(dict_dir);
+                        let result =
+                            load_database(settings, &dir, &filename, lev_distance, side_indexes);
+                        loaded.push((pos, result));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            for (pos, result) in worker.join().expect("Loading databases panicked") {
+                results[pos] = Some(result);
             }
         }
-        let mut word = cmd.params[2].to_lowercase();
-        word.retain(|c| c.is_alphanumeric() || c.is_whitespace());
+    });
+    results.into_iter().flatten().collect()
+}
 
-        let database = &self.databases.read().unwrap()[&databases[0]];
+// Adds a loaded database to the server with its settings and auxiliary
+// indexes. Returns its index, which the server shares.
+fn serve_database(
+    dictd_server: &mut DictdServer<File>,
+    settings: &DatabaseSettings,
+    dir: &Path,
+    filename: &str,
+    (di, dr, metadata): (IndexReader, DictReader<File>, DatabaseMetadata),
+    checksum: Option<String>,
+) -> Arc<RwLock<IndexReader>> {
+    let aux_indexes = add_aux_indexes(dir, filename, &di);
+    let di = Arc::new(RwLock::new(di));
+    dictd_server.add_database(settings.name.clone(), metadata, di.clone(), dr);
+    dictd_server.set_content_type(&settings.name, settings.content_type);
+    dictd_server.set_filters(&settings.name, settings.filters.clone());
+    dictd_server.set_template(&settings.name, settings.template.clone());
+    dictd_server.set_deadline(&settings.name, settings.deadline);
+    dictd_server.set_database_max_matches(&settings.name, settings.max_matches);
+    // Checked at startup
+    dictd_server
+        .set_database_strategy(&settings.name, settings.default_strategy.as_deref())
+        .unwrap();
+    if let Some(checksum) = checksum {
+        dictd_server.set_checksum(&settings.name, checksum);
+    }
+    for (variant, di) in aux_indexes {
+        dictd_server.add_aux_index(&settings.name, variant, Arc::new(RwLock::new(di)));
+    }
+    di
+}
 
-        info!(
-            "DEFINE from {}: DEFINE {} {}",
-            stream.get_ref().peer_addr().unwrap(),
-            cmd.params[1],
-            word
-        );
-        // TODO: Loop over databases according to rules
-        if let Ok((offset, length)) = database
-            .indexreader
-            .write()
+// Loads the databases whose files changed since they were loaded again, and
+// those that could not be loaded before. A database that fails to load is
+// still served from its old files.
+fn reload_databases(
+    dictd_server: &mut DictdServer<File>,
+    databases: &[DatabaseSettings],
+    backends: &[BackendSettings],
+    dict_dir: &Path,
+    lev_distance: usize,
+) {
+    for settings in databases {
+        let (dir, filename) = settings.location(dict_dir);
+        let loaded = dictd_server
+            .databases()
+            .read()
             .unwrap()
-            .find_word(word.as_str())
+            .contains_key(&settings.name);
+        let checksum = match checksum(&dir, &filename) {
+            Ok(checksum) => checksum,
+            Err(e) => {
+                warn!("Could not checksum {}: {:?}", settings.name, e);
+                record_reload(dictd_server, &settings.name, false);
+                continue;
+            }
+        };
+        if loaded && dictd_server.checksum(&settings.name).as_ref() == Some(&checksum) {
+            continue;
+        }
+        let (di, dr, metadata) = match load_database(settings, &dir, &filename, lev_distance, true)
         {
-            debug!("offset = {}, length = {}", offset, length);
-            if let Ok(res) = database.dictreader.write().unwrap().find(offset, length) {
-                stream.write_all(b"150 1 definition retrieved\n")?;
-                stream.write_all(
-                    format!(
-                        "151 \"{}\" {} \"{}\"\n",
-                        word, database.shortname, database.description
-                    )
-                    .as_bytes(),
-                )?;
-                stream.write_all(res.as_bytes())?;
-                stream.write_all(b".\n")?;
-                stream.write_all(b"250 ok\n")?;
-            } else {
-                stream.write_all(b"XXX NOT FOUND\n")?;
+            Ok(database) => database,
+            Err(e) => {
+                error!("Could not reload database {}: {}", settings.name, e);
+                record_reload(dictd_server, &settings.name, false);
+                continue;
             }
+        };
+        if loaded {
+            let aux_indexes = add_aux_indexes(&dir, &filename, &di);
+            dictd_server.replace_database(
+                &settings.name,
+                metadata,
+                di,
+                dr,
+                aux_indexes,
+                Some(checksum),
+            );
+            info!("Reloaded {}", settings.name);
         } else {
-            stream.write_all(b"552 no match\n")?;
-        }
-        Ok(())
-    }
-
-    // MATCH database strategy word
-    fn command_match(
-        &mut self,
-        stream: &mut BufStream<TcpStream>,
-        cmd: Command,
-    ) -> Result<(), DictdError> {
-        if cmd.params.len() != 4 {
-            stream.write_all(b"501 Syntax error, illegal parameters\n")?;
-            return Ok(());
-        }
-        let strategy = &cmd.params[2];
-        if !self.strategy_exists(strategy) {
-            stream.write_all(
-                b"551 Invalid stragegy, use \"SHOW STRATS\" for a list of strategies\n",
-            )?;
-            return Ok(());
-        }
-        let word = &cmd.params[3];
-        let mut _match_all = false;
-        let mut _match_one = false;
-        let mut databases = Vec::<String>::new();
-        let database = cmd.params[1].clone();
-        match database.as_str() {
-            "*" | "!" => {
-                if database.as_str() == "*" {
-                    _match_all = true;
-                } else {
-                    _match_one = true;
-                }
-                for d in self.databases.read().unwrap().keys() {
-                    databases.push(d.clone());
-                }
+            serve_database(
+                dictd_server,
+                settings,
+                &dir,
+                &filename,
+                (di, dr, metadata),
+                Some(checksum),
+            );
+            info!("Loaded {}, which was unavailable", settings.name);
+        }
+        record_reload(dictd_server, &settings.name, true);
+    }
+    // Backends that could not be opened are tried again
+    for settings in backends {
+        if !dictd_server
+            .unavailable()
+            .read()
+            .unwrap()
+            .contains_key(&settings.name)
+        {
+            continue;
+        }
+        let ok = match serve_backend(dictd_server, settings, dict_dir) {
+            Ok(()) => {
+                info!("Opened {}, which was unavailable", settings.name);
+                true
             }
-            _ => {
-                if !database.is_empty() && !self.database_exists(&database) {
-                    stream.write_all(
-                        b"550 Invalid database, use \"SHOW DB\" for list of databases\n",
-                    )?;
-                    return Ok(());
-                }
-                databases.push(database);
+            Err(e) => {
+                error!("Could not open database {}: {}", settings.name, e);
+                dictd_server.set_unavailable(&settings.name, e);
+                false
+            }
+        };
+        record_reload(dictd_server, &settings.name, ok);
+    }
+}
+
+fn record_reload(dictd_server: &DictdServer<File>, database: &str, ok: bool) {
+    dictd_server.record_security_event("reload", &SecurityEvent::Reload { database, ok });
+}
+
+// Reloads the changed databases, one reload at a time
+#[cfg(unix)]
+fn reloader(
+    dictd_server: DictdServer<File>,
+    databases: Vec<DatabaseSettings>,
+    backends: Vec<BackendSettings>,
+    dict_dir: PathBuf,
+    lev_distance: usize,
+) -> admin::Reload {
+    let dictd_server = Mutex::new(dictd_server);
+    Arc::new(move || {
+        let mut dictd_server = dictd_server.lock().unwrap();
+        reload_databases(
+            &mut dictd_server,
+            &databases,
+            &backends,
+            &dict_dir,
+            lev_distance,
+        )
+    })
+}
+
+// Reloads changed databases whenever the server receives SIGHUP
+#[cfg(unix)]
+async fn reload_on_hangup(reload: admin::Reload) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("Could not handle SIGHUP: {:?}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        info!("Received SIGHUP, reloading changed databases");
+        let reload = reload.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || reload()).await {
+            error!("Reloading failed: {:?}", e);
+        }
+    }
+}
+
+// The indexes of the configured phonetic strategies and of the lev and stem
+// strategies, except those read from the index cache. With fst the prefix
+// and lev strategies use a finite state transducer instead of the sorted
+// index and the deletion index.
+fn side_plan(
+    indexreader: &IndexReader,
+    settings: &DatabaseSettings,
+    lev_distance: usize,
+) -> SidePlan {
+    let phonetic = settings
+        .phonetic
+        .iter()
+        .copied()
+        .filter(|phonetic| !indexreader.has_phonetic_index(*phonetic))
+        .collect();
+    let deletions = match settings.fst || indexreader.deletion_distance() == Some(lev_distance) {
+        true => None,
+        false => Some(lev_distance),
+    };
+    SidePlan {
+        phonetic,
+        deletions,
+        fst: settings.fst.then_some(lev_distance),
+        stem_language: settings.stem_language,
+    }
+}
+
+// Prepares the databases for their first queries once the server is running:
+// builds the side indexes, reads the dict files, so that the OS has them in
+// its page cache, and fills the definition cache with the articles of the
+// most frequent words
+fn warm_caches(
+    dictd_server: DictdServer<File>,
+    databases: Vec<(DatabaseSettings, PathBuf, Arc<RwLock<IndexReader>>)>,
+) {
+    for (settings, dict_path, indexreader) in databases {
+        let start = Instant::now();
+        // Built from a copy of the entries, so that lookups go on meanwhile,
+        // scanning the entries where they need the side indexes
+        let plan = indexreader.read().unwrap().side_plan().cloned();
+        if let Some(plan) = plan {
+            let entries = indexreader.read().unwrap().entries().to_vec();
+            let side = SideIndexes::build(&entries, &plan);
+            drop(entries);
+            if !indexreader.write().unwrap().install_side_indexes(side) {
+                warn!(
+                    "Index of {} changed while its side indexes were built",
+                    settings.name
+                );
             }
         }
-        let word = word.to_lowercase();
+        if let Ok(mut file) = File::open(dict_path) {
+            if let Err(e) = std::io::copy(&mut file, &mut std::io::sink()) {
+                error!("Could not read dict file of {}: {:?}", settings.name, e);
+            }
+        }
+        let primed = dictd_server.prime_cache(&settings.name);
+        if primed > 0 {
+            info!("Cached {} frequent articles of {}", primed, settings.name);
+        }
         info!(
-            "MATCH from {}: MATCH {:?} {} {}",
-            stream.get_ref().peer_addr().unwrap(),
-            cmd.params[1],
-            strategy,
-            word
+            "Warmed up {} in {:.2}s",
+            settings.name,
+            start.elapsed().as_secs_f64()
         );
+    }
+}
 
-        let mut results: Vec<(String, IndexEntry)> = Vec::<(String, IndexEntry)>::new();
-
-        for db in databases {
-            match strategy.as_str() {
-                "exact" => {
-                    if let Ok((offset, length)) = &self.databases.read().unwrap()[&db]
-                        .indexreader
-                        .write()
-                        .unwrap()
-                        .find_word(word.as_str())
-                    {
-                        let entry = IndexEntry {
-                            word: word.clone(),
-                            offset: *offset,
-                            length: *length,
-                        };
-                        results.push((db.clone(), entry));
-                    }
-                }
-                "prefix" => {
-                    if let Ok(res) = &self.databases.read().unwrap()[&db]
-                        .indexreader
-                        .write()
-                        .unwrap()
-                        .find_words_by_prefix(word.as_str())
-                    {
-                        for entry in res {
-                            results.push((db.clone(), entry.clone()));
-                        }
-                    }
+// Checks that a database can be loaded without loading it: its files have to
+// be readable, and the first and last lines of the index sorted and within
+// the dict file
+fn check_database(dir: &Path, filename: &str, locale: Option<&str>) -> Result<(), String> {
+    let index_path = database_path(dir, filename, "index");
+    let dict_path = dict_path(dir, filename);
+    // Offsets refer to the uncompressed data of dictzip files
+    let dict_len = File::open(&dict_path)
+        .and_then(|file| DictReader::new(BufReader::new(file)))
+        .map_err(|e| format!("{}: {}", dict_path.display(), e))?
+        .len();
+    let (head, last) =
+        head_and_last_line(&index_path).map_err(|e| format!("{}: {}", index_path.display(), e))?;
+    let mut entries = Vec::new();
+    for line in head.iter().chain(std::iter::once(&last)) {
+        match IndexEntry::from_line(line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => {
+                return Err(format!(
+                    "{}: malformed line {:?}",
+                    index_path.display(),
+                    line
+                ))
+            }
+        }
+    }
+    // The first and last headword have to be in the order lookups compare
+    // them in, normalized as the 00-database-* flags in the head say
+    let flag = |name: &str| {
+        entries.iter().any(|entry| {
+            entry.word == format!("00database{}", name.replace('-', ""))
+                || entry.word == format!("00-database-{}", name)
+        })
+    };
+    let normalization = Normalization::from_flags(flag("allchars"), flag("case-sensitive"));
+    let first = entries
+        .iter()
+        .find(|entry| !is_metadata_headword(&entry.word))
+        .unwrap_or(&entries[0]);
+    let last = &entries[entries.len() - 1];
+    let (first, last) = (
+        normalization.apply(&first.word),
+        normalization.apply(&last.word),
+    );
+    let order = match locale {
+        Some(locale) => Collation::new(locale)
+            .map_err(|e| e.to_string())?
+            .compare(&first, &last),
+        None => first.cmp(&last),
+    };
+    if order == std::cmp::Ordering::Greater {
+        return Err(format!("{}: index is not sorted", index_path.display()));
+    }
+    for entry in entries.iter() {
+        if entry.offset + entry.length > dict_len {
+            return Err(format!(
+                "{}: \"{}\" points beyond the end of {}",
+                index_path.display(),
+                entry.word,
+                dict_path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Reads the lines at the start of an index up to the first not starting with
+// 00, which include the 00-database-* ones, and its last line, without
+// reading all of it
+fn head_and_last_line(path: &Path) -> Result<(Vec<String>, String), std::io::Error> {
+    let mut file = File::open(path)?;
+    let mut head = Vec::new();
+    for line in BufReader::new(&file).lines().take(HEAD_LINES) {
+        let line = line?;
+        let more = line.starts_with("00");
+        head.push(line);
+        if !more {
+            break;
+        }
+    }
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(4096)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let tail = String::from_utf8_lossy(&tail);
+    let last = tail.trim_end().rsplit('\n').next().unwrap_or("");
+    Ok((head, last.to_string()))
+}
+
+// Loads the auxiliary indexes shipped next to a database's index, named
+// <database>.index.<variant>, and builds the "translit" index of the
+// transliterated headwords if transliteration is enabled
+fn add_aux_indexes(dir: &Path, filename: &str, di: &IndexReader) -> Vec<(String, IndexReader)> {
+    let prefix = format!("{}.index.", filename);
+    let mut indexes = Vec::new();
+    if let Some(translit) = di.transliterated_index() {
+        indexes.push(("translit".to_string(), translit));
+    }
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return indexes,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(variant) = name.strip_prefix(&prefix) {
+            let file = match File::open(entry.path()) {
+                Ok(file) => file,
+                Err(e) => {
+                    error!("Could not open {}: {:?}", name, e);
+                    continue;
                 }
-                _ => (),
+            };
+            let mut di = IndexReader::new();
+            if let Err(e) = di.try_parse_dict_index(BufReader::new(file)) {
+                error!("Could not read {}: {:?}", name, e);
+                continue;
             }
+            info!("Loaded {} index for {}", variant, filename);
+            indexes.push((variant.to_string(), di));
         }
+    }
+    indexes
+}
+
+// Settings of a single listening socket. All listeners share the loaded
+// databases, but each may restrict which of them its clients can see.
+pub struct ListenerSettings {
+    pub addr: SocketAddr,
+    pub databases: Option<Vec<String>>,
+    // Clients start a TLS handshake right after connecting
+    pub tls: bool,
+    // Checked in addition to the server's access list
+    pub access: AccessList,
+}
+
+// A database to load at startup. The server does not start if a mandatory
+// database cannot be loaded, other databases are skipped.
+#[derive(Clone)]
+pub struct DatabaseSettings {
+    pub name: String,
+    // Files of the database without extension, relative to the dict
+    // directory. The name if None.
+    pub path: Option<PathBuf>,
+    // Replaces the description from the database
+    pub description: Option<String>,
+    pub mandatory: bool,
+    pub content_type: ContentType,
+    pub filters: Vec<Arc<dyn DefinitionFilter>>,
+    pub template: Option<Template>,
+    // Time the database may take for a query
+    pub deadline: Duration,
+    // Match prefixes and edit distances with a finite state transducer, for
+    // large databases
+    pub fst: bool,
+    // Phonetic algorithms whose keys are built, the others are answered
+    // with 551
+    pub phonetic: Vec<Phonetic>,
+    // Language of the stem strategy, which the database is not searched
+    // with if None
+    pub stem_language: Option<Algorithm>,
+    // Search the index file instead of loading it
+    pub on_disk: bool,
+    // Load the index from <name>.cache while it is fresh
+    pub index_cache: bool,
+    // Locale the headwords are sorted and searched in, byte order if None
+    pub locale: Option<String>,
+    // Encoding of the files, declared by the database itself if None
+    pub encoding: Option<Encoding>,
+    // Scripts transliterated to Latin in lookups and matches, so that
+    // "moskva" finds "Москва"
+    pub transliterations: Vec<Transliteration>,
+    // Networks the database is visible to, None for all
+    pub networks: Option<Vec<Network>>,
+    // Left out of SHOW DB, * and !
+    pub hidden: bool,
+    // Visible to authenticated clients only
+    pub require_auth: bool,
+    // Most matches sent from the database for a MATCH, None for no limit
+    pub max_matches: Option<usize>,
+    // Strategy of MATCH with the strategy ".", the server's if None
+    pub default_strategy: Option<String>,
+}
+
+// A database answered by a backend instead of files
+pub struct BackendSettings {
+    pub name: String,
+    pub source: Source,
+    pub description: Option<String>,
+    pub networks: Option<Vec<Network>>,
+    pub hidden: bool,
+    pub require_auth: bool,
+    pub max_matches: Option<usize>,
+}
+
+pub enum Source {
+    // An external program and its first arguments, killed after the
+    // timeout, see dictrdlib::plugin
+    Plugin {
+        command: Vec<String>,
+        timeout: Duration,
+    },
+    // A SQLite database, relative to the dict directory, whose queries fail
+    // after the deadline, see dictrdlib::sqlite
+    #[cfg(feature = "sqlite")]
+    Sqlite { path: PathBuf, deadline: Duration },
+}
+
+impl DatabaseSettings {
+    // Directory and base name of the files of the database, e.g.
+    // ("/usr/share/dictd", "jargon") for jargon.index and jargon.dict
+    fn location(&self, dict_dir: &Path) -> (PathBuf, String) {
+        let path = dict_dir.join(
+            self.path
+                .as_deref()
+                .unwrap_or_else(|| Path::new(&self.name)),
+        );
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.name.clone());
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        (dir, filename)
+    }
+}
 
-        // Collect results
-        if !results.is_empty() {
-            stream.write_all(
-                format!("152 {} matche(s) found: list follows\n", results.len()).as_bytes(),
-            )?;
-            for (database, entry) in results {
-                stream.write_all(format!("{} \"{}\"\n", database, entry.word).as_bytes())?;
+// Checks that the database of a backend can be answered: that the program
+// of a plugin is found and that an SQLite file opens
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+fn check_backend(settings: &BackendSettings, dict_dir: &Path) -> Result<(), String> {
+    match &settings.source {
+        Source::Plugin { command, timeout } => Plugin::new(command.clone(), *timeout)
+            .check()
+            .map_err(|e| e.to_string()),
+        #[cfg(feature = "sqlite")]
+        Source::Sqlite { path, .. } => SqliteDictionary::open(&dict_dir.join(path))
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+// Opens the database answered by a backend and adds it to the server
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+fn serve_backend(
+    dictd_server: &mut DictdServer<File>,
+    settings: &BackendSettings,
+    dict_dir: &Path,
+) -> Result<(), String> {
+    let (backend, deadline): (Arc<dyn Backend>, _) = match &settings.source {
+        Source::Plugin { command, timeout } => {
+            info!("Answering {} with {}", settings.name, command[0]);
+            (Arc::new(Plugin::new(command.clone(), *timeout)), *timeout)
+        }
+        #[cfg(feature = "sqlite")]
+        Source::Sqlite { path, deadline } => {
+            let dictionary =
+                SqliteDictionary::open(&dict_dir.join(path)).map_err(|e| e.to_string())?;
+            info!("Opened {} in {}", settings.name, path.display());
+            // Queries waiting for a locked file give up at the deadline
+            let dictionary = Deadline::new(Arc::new(dictionary), *deadline);
+            (Arc::new(dictionary), *deadline)
+        }
+    };
+    let metadata = DatabaseMetadata {
+        short: settings
+            .description
+            .clone()
+            .unwrap_or_else(|| settings.name.clone()),
+        ..DatabaseMetadata::default()
+    };
+    dictd_server.add_backend(settings.name.clone(), metadata, backend);
+    dictd_server.set_deadline(&settings.name, deadline);
+    dictd_server.set_database_max_matches(&settings.name, settings.max_matches);
+    dictd_server.set_networks(&settings.name, settings.networks.clone());
+    dictd_server.set_hidden(&settings.name, settings.hidden);
+    dictd_server.set_auth_required(&settings.name, settings.require_auth);
+    Ok(())
+}
+
+// Takes over a socket passed by systemd and returns its address
+fn adopt(listener: &std::net::TcpListener) -> std::io::Result<SocketAddr> {
+    listener.set_nonblocking(true)?;
+    listener.local_addr()
+}
+
+// Addresses from --listen or DICTRD_LISTEN, which replace the configured
+// listeners. Exits on an invalid address.
+fn configured_listeners(matches: &ArgMatches) -> Option<Vec<ListenerSettings>> {
+    let addresses: Vec<String> = match matches.values_of("listen") {
+        Some(values) => values.map(String::from).collect(),
+        None => std::env::var("DICTRD_LISTEN")
+            .ok()?
+            .split(',')
+            .map(|address| address.trim().to_string())
+            .filter(|address| !address.is_empty())
+            .collect(),
+    };
+    if addresses.is_empty() {
+        return None;
+    }
+    let listeners = addresses
+        .iter()
+        .map(|address| match address.parse() {
+            Ok(addr) => ListenerSettings {
+                addr,
+                databases: None,
+                tls: false,
+                access: AccessList::default(),
+            },
+            Err(_) => {
+                error!("Invalid value for --listen: {}", address);
+                std::process::exit(1)
             }
-            stream.write_all(b".\n")?;
-            stream.write_all(b"250 ok\n")?;
+        })
+        .collect();
+    Some(listeners)
+}
+
+// Resolves the log level: --verbose wins over --log-level or
+// DICTRD_LOG_LEVEL, which win over the configuration file
+fn log_level(matches: &ArgMatches, config: &Config) -> LevelFilter {
+    match matches.occurrences_of("verbose") {
+        0 => (),
+        1 => return LevelFilter::Debug,
+        _ => return LevelFilter::Trace,
+    }
+    let level = matches
+        .value_of("log-level")
+        .map(String::from)
+        .or_else(|| std::env::var("DICTRD_LOG_LEVEL").ok());
+    match level {
+        Some(level) => LevelFilter::from_str(&level).unwrap_or_else(|_| {
+            eprintln!("Invalid value for --log-level: {}", level);
+            std::process::exit(1)
+        }),
+        None => config.log_level().unwrap(),
+    }
+}
+
+fn main() {
+    // Started by the service control manager
+    #[cfg(windows)]
+    if std::env::args_os().any(|arg| arg == "--service") {
+        if let Err(e) = service::dispatch(serve) {
+            eprintln!("Could not run as a service: {}", e);
+            std::process::exit(1)
+        }
+        return;
+    }
+    serve()
+}
+
+fn serve() {
+    let app = App::new("dictrd")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Jan Schreiber <jan@mecinus.com>")
+        .about("Dictionary server")
+        .arg(
+            Arg::with_name("admin-socket")
+                .long("admin-socket")
+                .value_name("path")
+                .help("serve admin commands on a Unix socket [env: DICTRD_ADMIN_SOCKET]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache-size")
+                .long("cache-size")
+                .value_name("count")
+                .help("articles kept in the definition cache, 0 disables it [env: DICTRD_CACHE_SIZE] [default: 1024]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .visible_alias("test-config")
+                .help("check the configuration and databases, then exit"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("file")
+                .help("configuration file [env: DICTRD_CONFIG] [default: /etc/dictrd.toml]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("command-rate")
+                .long("command-rate")
+                .value_name("count")
+                .help("commands per second of each client, faster clients are disconnected [env: DICTRD_COMMAND_RATE]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("daemon")
+                .long("daemon")
+                .help("detach from the terminal and run in the background"),
+        )
+        .arg(
+            Arg::with_name("dedup-matches")
+                .long("dedup-matches")
+                .help("list headwords found in several databases once in MATCH"),
+        )
+        .arg(
+            Arg::with_name("default-strategy")
+                .long("default-strategy")
+                .value_name("strategy")
+                .help("strategy of MATCH with the strategy . [env: DICTRD_DEFAULT_STRATEGY]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dict-dir")
+                .long("dict-dir")
+                .value_name("dir")
+                .help("directory of the databases [env: DICTRD_DICT_DIR]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("global-rate-limit")
+                .long("global-rate-limit")
+                .value_name("bytes")
+                .help(
+                    "bytes per second sent to all clients together [env: DICTRD_GLOBAL_RATE_LIMIT]",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("group")
+                .long("group")
+                .value_name("group")
+                .help("continue as this group once the listeners are bound [env: DICTRD_GROUP]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("health-address")
+                .long("health-address")
+                .value_name("address")
+                .help("answer health probes on a port of its own [env: DICTRD_HEALTH_ADDRESS]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("idle-timeout")
+                .long("idle-timeout")
+                .value_name("seconds")
+                .help("close connections idle for longer [env: DICTRD_IDLE_TIMEOUT]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("lev-distance")
+                .long("lev-distance")
+                .value_name("count")
+                .help("largest edit distance of the lev strategy [env: DICTRD_LEV_DISTANCE]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .value_name("address")
+                .help("listen on address instead of the configured ones, can be repeated [env: DICTRD_LISTEN]")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .value_name("level")
+                .help("log level, one of off, error, warn, info, debug, trace [env: DICTRD_LOG_LEVEL]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("match-page-size")
+                .long("match-page-size")
+                .value_name("count")
+                .help("send longer lists of matches in pages [env: DICTRD_MATCH_PAGE_SIZE]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-commands")
+                .long("max-commands")
+                .value_name("count")
+                .help("close connections after this many commands [env: DICTRD_MAX_COMMANDS]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-connections")
+                .long("max-connections")
+                .value_name("count")
+                .help("clients served at the same time [env: DICTRD_MAX_CONNECTIONS]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-connections-per-ip")
+                .long("max-connections-per-ip")
+                .value_name("count")
+                .help("clients served at the same time from one address [env: DICTRD_MAX_CONNECTIONS_PER_IP]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-matches")
+                .long("max-matches")
+                .value_name("count")
+                .help("send at most this many matches for a MATCH [env: DICTRD_MAX_MATCHES]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("pidfile")
+                .long("pidfile")
+                .value_name("file")
+                .help("write the process id to file [env: DICTRD_PIDFILE]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("popular-words")
+                .long("popular-words")
+                .value_name("count")
+                .help("count the words looked up, keeping the most requested [env: DICTRD_POPULAR_WORDS]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("port")
+                .long("port")
+                .value_name("port")
+                .help("port of the listen addresses [env: DICTRD_PORT]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rate-limit")
+                .long("rate-limit")
+                .value_name("bytes")
+                .help("bytes per second sent to each client [env: DICTRD_RATE_LIMIT]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("security-log")
+                .long("security-log")
+                .value_name("file")
+                .help("append authentication and admin events to file [env: DICTRD_SECURITY_LOG]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("slow-query")
+                .long("slow-query")
+                .value_name("milliseconds")
+                .help("log commands taking longer as slow [env: DICTRD_SLOW_QUERY]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("state-file")
+                .long("state-file")
+                .value_name("file")
+                .help("file the statistics are kept in [env: DICTRD_STATE_FILE]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("user")
+                .long("user")
+                .value_name("user")
+                .help("continue as this user once the listeners are bound [env: DICTRD_USER]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .help("log at debug level, at trace level if given twice"),
+        )
+        .arg(
+            Arg::with_name("warm")
+                .long("warm")
+                .help("serve before the side indexes are built, answering lev, phonetic and stem matches by slower scans until they are"),
+        )
+        .arg(
+            Arg::with_name("when-busy")
+                .long("when-busy")
+                .value_name("policy")
+                .help("queue or reject clients over --max-connections [env: DICTRD_WHEN_BUSY] [default: reject]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("workers")
+                .long("workers")
+                .value_name("count")
+                .help("threads serving the clients [env: DICTRD_WORKERS] [default: one per CPU]")
+                .takes_value(true),
+        );
+    #[cfg(feature = "http")]
+    let app = app.arg(
+        Arg::with_name("http-address")
+            .long("http-address")
+            .value_name("address")
+            .help("serve DEFINE and MATCH as JSON over HTTP [env: DICTRD_HTTP_ADDRESS]")
+            .takes_value(true),
+    );
+    #[cfg(feature = "metrics")]
+    let app = app.arg(
+        Arg::with_name("metrics-address")
+            .long("metrics-address")
+            .value_name("address")
+            .help("serve Prometheus metrics over HTTP [env: DICTRD_METRICS_ADDRESS]")
+            .takes_value(true),
+    );
+    #[cfg(windows)]
+    let app = app
+        .arg(
+            Arg::with_name("install-service")
+                .long("install-service")
+                .help("register dictrd as a Windows service and exit"),
+        )
+        .arg(
+            Arg::with_name("service")
+                .long("service")
+                .help("run as a Windows service, as the service control manager does"),
+        )
+        .arg(
+            Arg::with_name("uninstall-service")
+                .long("uninstall-service")
+                .help("remove the Windows service and exit"),
+        );
+    let matches = app.get_matches();
+    #[cfg(windows)]
+    {
+        let (result, done) = if matches.is_present("install-service") {
+            (service::install(), "Installed service dictrd")
+        } else if matches.is_present("uninstall-service") {
+            (service::uninstall(), "Removed service dictrd")
         } else {
-            stream.write_all(b"552 no match\n")?;
-        }
-        Ok(())
-    }
-
-    fn command_random(
-        &self,
-        stream: &mut BufStream<TcpStream>,
-        _cmd: Command,
-    ) -> Result<(), DictdError> {
-        if let Some(database) = self.databases.read().unwrap().get("jargon") {
-            if let Ok((word, offset, length)) = database.indexreader.write().unwrap().find_random()
-            {
-                debug!("offset = {}, length = {}", offset, length);
-                if let Ok(res) = database.dictreader.write().unwrap().find(offset, length) {
-                    stream.write_all(b"150 1 definition retrieved\n")?;
-                    stream.write_all(
-                        format!(
-                            "151 \"{}\" {} \"{}\"\n",
-                            word, database.shortname, database.description
-                        )
-                        .as_bytes(),
-                    )?;
-                    stream.write_all(res.as_bytes())?;
-                    stream.write_all(b".\n")?;
-                    stream.write_all(b"250 ok\n")?;
-                } else {
-                    stream.write_all(b"552 no match\n")?;
-                }
-            } else {
-                stream.write_all(b"552 no match\n")?;
+            (Ok(()), "")
+        };
+        match result {
+            Ok(()) if done.is_empty() => (),
+            Ok(()) => {
+                println!("{}", done);
+                std::process::exit(0)
+            }
+            Err(e) => {
+                eprintln!("Could not change service dictrd: {}", e);
+                std::process::exit(1)
+            }
+        }
+        // Without a terminal there are no daemons, users or Unix sockets
+        for unix in ["admin-socket", "daemon", "group", "pidfile", "user"] {
+            if matches.is_present(unix) {
+                eprintln!("--{} is not supported on Windows", unix);
+                std::process::exit(1)
             }
         }
-        Ok(())
     }
+    let config_path = configured_path(
+        &matches,
+        "config",
+        "DICTRD_CONFIG",
+        &default_path(DEFAULT_CONFIG),
+    );
+    // Only the default configuration file is optional
+    let required = matches.is_present("config") || std::env::var_os("DICTRD_CONFIG").is_some();
+    let config = Config::load(&config_path, required).unwrap_or_else(|e| {
+        eprintln!("Could not read configuration {}", e);
+        std::process::exit(1)
+    });
+    let level = log_level(&matches, &config);
+    #[cfg(windows)]
+    if service::is_service() {
+        if let Err(e) = service::log_to_event_log(level) {
+            eprintln!("Could not log to the event log: {}", e);
+        }
+    } else {
+        simple_logging::log_to_stderr(level);
+    }
+    #[cfg(not(windows))]
+    simple_logging::log_to_stderr(level);
+    let default_dict_dir = default_path(DEFAULT_DICT_DIR);
+    let dict_dir = configured_path(
+        &matches,
+        "dict-dir",
+        "DICTRD_DICT_DIR",
+        config.dict_dir.as_deref().unwrap_or(&default_dict_dir),
+    );
+    let state_file = configured_path(
+        &matches,
+        "state-file",
+        "DICTRD_STATE_FILE",
+        &default_path(DEFAULT_STATE_FILE),
+    );
 
-    fn command_quit(
-        &self,
-        stream: &mut BufStream<TcpStream>,
-        cmd: Command,
-    ) -> Result<(), DictdError> {
-        if cmd.params.len() != 1 {
-            stream.write_all(b"501 Syntax error, illegal parameters\n")?;
-            return Ok(());
+    let mut listeners =
+        configured_listeners(&matches).unwrap_or_else(|| config.listeners().unwrap());
+    if let Some(port) = configured_count(&matches, "port", "DICTRD_PORT") {
+        if port > u64::from(u16::MAX) {
+            error!("Invalid value for --port: {}", port);
+            std::process::exit(1)
+        }
+        for settings in listeners.iter_mut() {
+            settings.addr.set_port(port as u16);
         }
-        stream.write_all(b"221 Closing connection. kthxb.\n")?;
-        stream.flush()?;
-        Ok(())
     }
+    let tls = config.tls_acceptor().unwrap_or_else(|e| {
+        error!("Could not set up TLS: {}", e);
+        std::process::exit(1)
+    });
+    let users = config.users();
+    let databases = config.databases();
 
-    fn command_show(
-        &self,
-        stream: &mut BufStream<TcpStream>,
-        cmd: Command,
-    ) -> Result<(), DictdError> {
-        if !cmd.params.len() == 2
-            && !(cmd.params.len() == 3 && cmd.params[1].to_uppercase() == "INFO")
-        {
-            stream.write_all(b"501 Syntax error, illegal parameters\n")?;
-            return Ok(());
-        }
-        match cmd.params[1].to_uppercase().as_str() {
-            "DB" | "DATABASES" => {
-                stream.write_all(
-                    format!(
-                        "110 {} database(s) present\n",
-                        self.databases.read().unwrap().len()
-                    )
-                    .as_bytes(),
-                )?;
-                let databases = &*self.databases.read().unwrap();
-                for (shortname, database) in databases {
-                    stream.write_all(
-                        format!("{} \"{}\"\n", shortname, database.description).as_bytes(),
-                    )?;
+    if matches.is_present("check") {
+        let mut failed = false;
+        for settings in databases.iter() {
+            let (dir, filename) = settings.location(&dict_dir);
+            match check_database(&dir, &filename, settings.locale.as_deref()) {
+                Ok(()) => println!("ok      {}", settings.name),
+                Err(e) => {
+                    println!("FAILED  {}: {}", settings.name, e);
+                    failed = true;
                 }
-                stream.write_all(b".\n")?;
-                stream.write_all(b"250 ok\n")?;
             }
-            "STRAT" | "STRATEGIES" => {
-                stream.write_all(
-                    format!(
-                        "111 {} strategies present\n",
-                        self.strategies.read().unwrap().len()
-                    )
-                    .as_bytes(),
-                )?;
-                let strategies = &*self.strategies.read().unwrap();
-                for (strat, descr) in strategies {
-                    stream.write_all(format!("{} \"{}\"\n", strat, descr).as_bytes())?;
+        }
+        for settings in config.backends().iter() {
+            match check_backend(settings, &dict_dir) {
+                Ok(()) => println!("ok      {}", settings.name),
+                Err(e) => {
+                    println!("FAILED  {}: {}", settings.name, e);
+                    failed = true;
                 }
-                stream.write_all(b".\n")?;
-                stream.write_all(b"250 ok\n")?;
             }
-            "SERVER" => {
-                stream.write_all(b"114 server information\n")?;
-                stream.write_all(b"\n.\n")?;
+        }
+        for settings in listeners.iter() {
+            println!("listen  {}", settings.addr);
+        }
+        std::process::exit(if failed { 1 } else { 0 });
+    }
+
+    #[cfg(unix)]
+    let user = matches
+        .value_of("user")
+        .map(String::from)
+        .or_else(|| std::env::var("DICTRD_USER").ok());
+    #[cfg(unix)]
+    let group = matches
+        .value_of("group")
+        .map(String::from)
+        .or_else(|| std::env::var("DICTRD_GROUP").ok());
+    #[cfg(unix)]
+    let credentials = if user.is_some() || group.is_some() {
+        Some(
+            Credentials::lookup(user.as_deref(), group.as_deref()).unwrap_or_else(|e| {
+                error!("Could not change user: {}", e);
+                std::process::exit(1)
+            }),
+        )
+    } else {
+        None
+    };
+
+    // Listeners are only bound once all databases are loaded, so clients
+    // cannot connect to a server that is not ready
+    let mut dictd_server = DictdServer::<File>::new();
+    configure_strategies(&mut dictd_server, &matches, &config, &databases);
+    // Databases are listed and searched in the order of the configuration
+    let order = databases
+        .iter()
+        .map(|settings| settings.name.clone())
+        .chain(config.backends().into_iter().map(|settings| settings.name))
+        .collect();
+    dictd_server.set_database_order(order);
+    dictd_server.set_dedup_matches(matches.is_present("dedup-matches"));
+    let warm = matches.is_present("warm");
+    let lev_distance = configured_count(&matches, "lev-distance", "DICTRD_LEV_DISTANCE")
+        .unwrap_or(DEFAULT_LEV_DISTANCE) as usize;
+    let mut to_warm = Vec::new();
+    // With --warm the side indexes are built after startup
+    let all_loaded = load_databases(&databases, &dict_dir, lev_distance, !warm);
+    for (settings, loaded) in databases.iter().zip(all_loaded) {
+        let (dir, filename) = settings.location(&dict_dir);
+        let loaded = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) if settings.mandatory => {
+                error!("Could not load database {}: {}", settings.name, e);
+                std::process::exit(1)
+            }
+            Err(e) => {
+                error!(
+                    "Could not load database {}, serving the others: {}",
+                    settings.name, e
+                );
+                dictd_server.set_unavailable(&settings.name, e.to_string());
+                continue;
             }
-            "INFO" => {
-                if cmd.params.len() != 3 {
-                    stream.write_all(b"501 Syntax error, illegal parameters\n")?;
-                } else {
-                    let database = &cmd.params[2];
-                    if !self.database_exists(database) {
-                        stream.write_all(
-                            b"550 Invalid database, use \"SHOW DB\" for list of databases\n",
-                        )?;
-                    } else {
-                        let database = &self.databases.read().unwrap()[database];
-                        stream.write_all(b"112 database information follows\n")?;
-                        stream.write_all(database.description.as_bytes())?;
-                        stream.write_all(b".\n")?;
-                        stream.write_all(database.info.as_bytes())?;
-                        stream.write_all(b".\n")?;
-                        stream.write_all(b"250 ok\n")?;
-                    }
-                }
+        };
+        let checksum = match checksum(&dir, &filename) {
+            Ok(checksum) => Some(checksum),
+            Err(e) => {
+                warn!("Could not checksum {}: {:?}", settings.name, e);
+                None
             }
-            _ => {
-                stream.write_all(b"501 Syntax error, illegal parameters\n")?;
+        };
+        let di = serve_database(
+            &mut dictd_server,
+            settings,
+            &dir,
+            &filename,
+            loaded,
+            checksum,
+        );
+        to_warm.push((settings.clone(), dict_path(&dir, &filename), di));
+    }
+
+    let backends = config.backends();
+    for settings in backends.iter() {
+        if let Err(e) = serve_backend(&mut dictd_server, settings, &dict_dir) {
+            error!(
+                "Could not open database {}, serving the others: {}",
+                settings.name, e
+            );
+            dictd_server.set_unavailable(&settings.name, e);
+        }
+    }
+
+    dictd_server.set_users(users);
+    dictd_server.set_banner(config.banner.clone());
+    dictd_server.set_access(config.access().unwrap());
+    for settings in databases.iter() {
+        dictd_server.set_networks(&settings.name, settings.networks.clone());
+        dictd_server.set_hidden(&settings.name, settings.hidden);
+        dictd_server.set_auth_required(&settings.name, settings.require_auth);
+    }
+
+    let security_log = matches
+        .value_of_os("security-log")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("DICTRD_SECURITY_LOG").map(PathBuf::from));
+    if let Some(path) = security_log {
+        match SecurityLog::open(&path) {
+            Ok(security_log) => dictd_server.set_security_log(security_log),
+            Err(e) => {
+                error!("Could not open security log {}: {:?}", path.display(), e);
+                std::process::exit(1)
             }
         }
-        Ok(())
     }
 
-    fn command_status(
-        &self,
-        stream: &mut BufStream<TcpStream>,
-        cmd: Command,
-    ) -> Result<(), DictdError> {
-        if cmd.params.len() != 1 {
-            return Ok(());
+    // Checked when the file was loaded
+    dictd_server.set_query_logging(config.query_logging().unwrap());
+    dictd_server.set_input_encoding(config.input_encoding().unwrap());
+    match config.access_log() {
+        Ok(Some(access_log)) => dictd_server.set_access_log(access_log),
+        Ok(None) => (),
+        Err(e) => {
+            error!("Could not open access log {}", e);
+            std::process::exit(1)
         }
-        stream.write_all(b"210 status all good\n")?;
-        Ok(())
     }
-}
 
-fn add_database(filename: String) -> (IndexReader, DictReader<File>, String, String) {
-    let mut di = IndexReader::new();
-    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    path.push("dicts");
-    path.push(format!("{}.index", filename));
-    let file = File::open(path).unwrap();
-    let file = BufReader::new(file);
-    di.parse_dict_index(file);
+    if let Ok(file) = File::open(&state_file) {
+        dictd_server.set_statistics(Statistics::load(BufReader::new(file)));
+    }
+    if let Some(count) = configured_count(&matches, "popular-words", "DICTRD_POPULAR_WORDS") {
+        // The words are kept in the state file, whatever the logs leave out
+        if !matches!(config.query_logging().unwrap(), QueryLogging::Full) {
+            error!("--popular-words needs log_queries = \"full\"");
+            std::process::exit(1)
+        }
+        dictd_server
+            .statistics()
+            .lock()
+            .unwrap()
+            .count_words(count as usize);
+    }
 
-    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    path.push("dicts");
-    path.push(format!("{}.dict", filename));
-    let file = File::open(path).unwrap();
-    let file = BufReader::new(file);
-    let mut dr = DictReader::new(file).unwrap();
-
-    let mut description = "Unknown".to_string();
-    if let Ok((offset, length)) = di.find_word("00databaseshort") {
-        if let Ok(res) = dr.find(offset, length) {
-            let lines: Vec<&str> = res.split('\n').collect();
-            if lines.len() >= 2 {
-                description = lines[1].trim().to_string();
+    // Sockets passed by systemd replace the configured addresses, but keep
+    // the settings of the address they are bound to. The others are bound
+    // while still privileged, before forking, so errors reach the terminal.
+    let mut bound = Vec::new();
+    #[cfg(target_os = "linux")]
+    let activated = systemd::listeners();
+    #[cfg(not(target_os = "linux"))]
+    let activated: Vec<std::net::TcpListener> = Vec::new();
+    if activated.is_empty() {
+        for settings in listeners {
+            let listener = bind(settings.addr).unwrap_or_else(|e| {
+                error!("Could not bind to {}: {:?}", settings.addr, e);
+                std::process::exit(1)
+            });
+            bound.push((listener, settings));
+        }
+    } else {
+        for listener in activated {
+            let addr = adopt(&listener).unwrap_or_else(|e| {
+                error!("Could not use socket passed by systemd: {:?}", e);
+                std::process::exit(1)
+            });
+            let settings = match listeners.iter().position(|s| s.addr == addr) {
+                Some(i) => listeners.swap_remove(i),
+                None => ListenerSettings {
+                    addr,
+                    databases: None,
+                    tls: false,
+                    access: AccessList::default(),
+                },
+            };
+            bound.push((listener, settings));
+        }
+    }
+    // No threads may run yet
+    #[cfg(unix)]
+    {
+        if matches.is_present("daemon") {
+            if let Err(e) = daemon::daemonize() {
+                error!("Could not run in the background: {:?}", e);
+                std::process::exit(1)
             }
         }
+        let pidfile = matches
+            .value_of_os("pidfile")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("DICTRD_PIDFILE").map(PathBuf::from));
+        if let Some(path) = pidfile {
+            if let Err(e) = daemon::write_pidfile(&path) {
+                error!("Could not write pidfile {}: {:?}", path.display(), e);
+                std::process::exit(1)
+            }
+        }
+    }
+    let stats = dictd_server.statistics();
+    spawn(move || checkpoint_statistics(stats, state_file));
+
+    dictd_server.set_match_page_size(
+        configured_count(&matches, "match-page-size", "DICTRD_MATCH_PAGE_SIZE")
+            .map(|size| size as usize),
+    );
+    dictd_server.set_max_matches(
+        configured_count(&matches, "max-matches", "DICTRD_MAX_MATCHES").map(|max| max as usize),
+    );
+    dictd_server.set_rate_limits(
+        configured_count(&matches, "rate-limit", "DICTRD_RATE_LIMIT"),
+        configured_count(&matches, "global-rate-limit", "DICTRD_GLOBAL_RATE_LIMIT"),
+    );
+    if let Some(size) = configured_count(&matches, "cache-size", "DICTRD_CACHE_SIZE") {
+        dictd_server.set_cache_size(size as usize);
+    }
+    if let Some(max) = configured_count(&matches, "max-connections", "DICTRD_MAX_CONNECTIONS") {
+        dictd_server.set_max_connections(max as usize);
     }
-    let mut info = "Unknown".to_string();
-    if let Ok((offset, length)) = di.find_word("00databaseinfo") {
-        if let Ok(res) = dr.find(offset, length) {
-            let lines: Vec<&str> = res.split('\n').collect();
-            if lines.len() >= 2 {
-                info = lines[1].trim().to_string();
+    let when_busy = matches
+        .value_of("when-busy")
+        .map(String::from)
+        .or_else(|| std::env::var("DICTRD_WHEN_BUSY").ok());
+    if let Some(name) = when_busy {
+        match WhenBusy::from_name(&name) {
+            Some(when_busy) => dictd_server.set_when_busy(when_busy),
+            None => {
+                error!("Invalid value for --when-busy: {}", name);
+                std::process::exit(1)
             }
         }
     }
-    (di, dr, description, info)
-}
+    dictd_server.set_max_connections_per_ip(
+        configured_count(
+            &matches,
+            "max-connections-per-ip",
+            "DICTRD_MAX_CONNECTIONS_PER_IP",
+        )
+        .map(|max| max as usize),
+    );
+    dictd_server.set_connection_limits(
+        configured_count(&matches, "idle-timeout", "DICTRD_IDLE_TIMEOUT").map(Duration::from_secs),
+        configured_count(&matches, "max-commands", "DICTRD_MAX_COMMANDS"),
+    );
+    dictd_server.set_command_rate(configured_count(
+        &matches,
+        "command-rate",
+        "DICTRD_COMMAND_RATE",
+    ));
+    dictd_server.set_slow_query(
+        configured_count(&matches, "slow-query", "DICTRD_SLOW_QUERY").map(Duration::from_millis),
+    );
 
-fn main() {
-    simple_logging::log_to_stderr(LevelFilter::Info);
+    #[cfg(unix)]
+    let reload = reloader(
+        dictd_server.clone(),
+        databases,
+        backends,
+        dict_dir,
+        lev_distance,
+    );
+    #[cfg(unix)]
+    {
+        let admin_socket = matches
+            .value_of_os("admin-socket")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("DICTRD_ADMIN_SOCKET").map(PathBuf::from));
+        if let Some(path) = admin_socket {
+            let listener = admin::bind(&path).unwrap_or_else(|e| {
+                error!("Could not bind admin socket {}: {:?}", path.display(), e);
+                std::process::exit(1)
+            });
+            info!("Admin commands on {}", path.display());
+            let dictd_server = dictd_server.clone();
+            let reload = reload.clone();
+            spawn(move || admin::serve(listener, dictd_server, reload));
+        }
+    }
 
-    let port = 2628;
-    let addr: SocketAddr = SocketAddr::from_str(format!("127.0.0.1:{}", port).as_str()).unwrap();
-    let listener = TcpListener::bind(addr).unwrap_or_else(|e| {
-        error!("Could not bind to port {}: {:?}", port, e);
+    #[cfg(feature = "metrics")]
+    {
+        let metrics_address = matches
+            .value_of("metrics-address")
+            .map(String::from)
+            .or_else(|| std::env::var("DICTRD_METRICS_ADDRESS").ok());
+        if let Some(address) = metrics_address {
+            let listener = std::net::TcpListener::bind(&address).unwrap_or_else(|e| {
+                error!("Could not bind metrics address {}: {:?}", address, e);
+                std::process::exit(1)
+            });
+            info!("Metrics on http://{}/metrics", address);
+            let dictd_server = dictd_server.clone();
+            spawn(move || metrics::serve(listener, dictd_server));
+        }
+    }
+
+    #[cfg(feature = "http")]
+    {
+        let http_address = matches
+            .value_of("http-address")
+            .map(String::from)
+            .or_else(|| std::env::var("DICTRD_HTTP_ADDRESS").ok());
+        if let Some(address) = http_address {
+            let listener = std::net::TcpListener::bind(&address).unwrap_or_else(|e| {
+                error!("Could not bind HTTP address {}: {:?}", address, e);
+                std::process::exit(1)
+            });
+            info!("HTTP gateway on http://{}/", address);
+            let dictd_server = dictd_server.clone();
+            spawn(move || http::serve(listener, dictd_server));
+        }
+    }
+
+    let health_address = matches
+        .value_of("health-address")
+        .map(String::from)
+        .or_else(|| std::env::var("DICTRD_HEALTH_ADDRESS").ok());
+    if let Some(address) = health_address {
+        let listener = std::net::TcpListener::bind(&address).unwrap_or_else(|e| {
+            error!("Could not bind health address {}: {:?}", address, e);
+            std::process::exit(1)
+        });
+        info!("Health probes on {}", address);
+        let dictd_server = dictd_server.clone();
+        spawn(move || health::serve(listener, dictd_server));
+    }
+
+    #[cfg(unix)]
+    if let Some(credentials) = credentials {
+        if let Err(e) = credentials.apply() {
+            error!("Could not change user: {:?}", e);
+            std::process::exit(1)
+        }
+    }
+
+    // Commands block the worker running them, which hands its other clients
+    // to a thread of the blocking pool, so both are limited
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if let Some(workers) = configured_count(&matches, "workers", "DICTRD_WORKERS") {
+        builder
+            .worker_threads(workers as usize)
+            .max_blocking_threads(workers as usize);
+    }
+    let runtime = builder.enable_all().build().unwrap_or_else(|e| {
+        error!("Could not start runtime: {:?}", e);
         std::process::exit(1)
     });
-
-    let mut dictd_server = DictdServer::<File>::new();
-    let (di, dr, description, info) = add_database("jargon".to_string());
-    dictd_server.add_database(
-        "jargon".to_string(),
-        description,
-        info,
-        Arc::new(RwLock::new(di)),
-        Arc::new(RwLock::new(dr)),
-    );
-    let (di, dr, description, info) = add_database("devils".to_string());
-    dictd_server.add_database(
-        "devils".to_string(),
-        description,
-        info,
-        Arc::new(RwLock::new(di)),
-        Arc::new(RwLock::new(dr)),
-    );
-    for stream in listener.incoming() {
-        match stream {
-            Err(e) => error!("Could not listen to port: {:?}", e),
-            Ok(stream) => {
-                info!(
-                    "New client connection from {} to {}",
-                    stream.peer_addr().unwrap(),
-                    stream.local_addr().unwrap()
-                );
-                let mut dictd_server = dictd_server.clone();
-                spawn(move || {
-                    let mut stream = BufStream::new(stream);
-                    dictd_server.handle_connection(&mut stream).expect("Could not handle connection");
-                });
+    runtime.block_on(async {
+        let mut handles = Vec::new();
+        let mut addrs = Vec::new();
+        for (listener, settings) in bound {
+            let listener = TcpListener::from_std(listener).unwrap_or_else(|e| {
+                error!("Could not listen on {}: {:?}", settings.addr, e);
+                std::process::exit(1)
+            });
+            let tls = if settings.tls { tls.clone() } else { None };
+            if tls.is_some() {
+                info!("Listening on {} (TLS)", settings.addr);
+            } else {
+                info!("Listening on {}", settings.addr);
             }
+            addrs.push(settings.addr.to_string());
+            let dictd_server = dictd_server
+                .with_view(settings.databases)
+                .with_listener_access(settings.access);
+            handles.push(tokio::spawn(dictd_server.serve(listener, tls)));
         }
-    }
+        info!(
+            "Ready, serving {} databases on {}",
+            dictd_server.databases().read().unwrap().len(),
+            addrs.join(", ")
+        );
+        #[cfg(target_os = "linux")]
+        if let Err(e) = systemd::notify("READY=1") {
+            warn!("Could not notify systemd: {:?}", e);
+        }
+        #[cfg(windows)]
+        service::running();
+        if warm {
+            let dictd_server = dictd_server.clone();
+            spawn(move || warm_caches(dictd_server, to_warm));
+        }
+        #[cfg(unix)]
+        tokio::spawn(reload_on_hangup(reload));
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    });
 }
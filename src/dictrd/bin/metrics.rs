@@ -0,0 +1,169 @@
+// Prometheus metrics over HTTP, with the metrics feature:
+//
+//   dictrd --metrics-address 127.0.0.1:9628
+//   curl http://127.0.0.1:9628/metrics
+//
+// Only GET /metrics is answered, every request on its own connection.
+use dictrdlib::metrics::Exposition;
+use dictrdlib::server::DictdServer;
+use log::error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::spawn;
+
+pub fn serve(listener: TcpListener, server: DictdServer<File>) {
+    for stream in listener.incoming() {
+        match stream {
+            Err(e) => error!("Could not accept metrics connection: {:?}", e),
+            Ok(stream) => {
+                let server = server.clone();
+                spawn(move || {
+                    if let Err(e) = handle(stream, &server) {
+                        error!("Metrics connection failed: {:?}", e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn handle(stream: TcpStream, server: &DictdServer<File>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // The headers are not needed
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+        line.clear();
+    }
+    let mut fields = request.split_whitespace();
+    let (status, body) = match (fields.next(), fields.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render(server)),
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+fn render(server: &DictdServer<File>) -> String {
+    let mut exposition = Exposition::new();
+    let open = server.connections().lock().unwrap().len();
+    let headwords: Vec<(String, f64)> = server
+        .databases()
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, database)| {
+            let entries = database.indexreader().read().unwrap().entries().len();
+            (name.clone(), entries as f64)
+        })
+        .collect();
+    {
+        let stats = server.statistics();
+        let stats = stats.lock().unwrap();
+        exposition.metric(
+            "dictrd_uptime_seconds",
+            "gauge",
+            "Seconds since the server started",
+            stats.uptime() as f64,
+        );
+        exposition.metric(
+            "dictrd_connections_total",
+            "counter",
+            "Connections accepted",
+            stats.connections as f64,
+        );
+        exposition.metric(
+            "dictrd_connections_open",
+            "gauge",
+            "Connections being served",
+            open as f64,
+        );
+        exposition.metric(
+            "dictrd_cache_hits_total",
+            "counter",
+            "Articles found in the definition cache",
+            stats.cache_hits as f64,
+        );
+        exposition.metric(
+            "dictrd_cache_misses_total",
+            "counter",
+            "Articles read from the dict files",
+            stats.cache_misses as f64,
+        );
+        exposition.labeled(
+            "dictrd_commands_total",
+            "counter",
+            "Commands received by command",
+            "command",
+            stats
+                .commands
+                .iter()
+                .map(|(command, count)| (command.as_str(), *count as f64)),
+        );
+        exposition.labeled(
+            "dictrd_defines_total",
+            "counter",
+            "DEFINE lookups by database",
+            "database",
+            stats
+                .databases
+                .iter()
+                .map(|(name, database)| (name.as_str(), database.defines as f64)),
+        );
+        exposition.labeled(
+            "dictrd_matches_total",
+            "counter",
+            "MATCH lookups by database",
+            "database",
+            stats
+                .databases
+                .iter()
+                .map(|(name, database)| (name.as_str(), database.matches as f64)),
+        );
+        exposition.labeled(
+            "dictrd_misses_total",
+            "counter",
+            "Lookups without result by database",
+            "database",
+            stats
+                .databases
+                .iter()
+                .map(|(name, database)| (name.as_str(), database.misses as f64)),
+        );
+    }
+    let metrics = server.metrics().lock().unwrap();
+    exposition.labeled(
+        "dictrd_definitions_total",
+        "counter",
+        "Definitions sent by database",
+        "database",
+        metrics
+            .definitions
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count as f64)),
+    );
+    exposition.histogram(
+        "dictrd_match_duration_seconds",
+        "Time MATCH commands took",
+        &metrics.match_latency,
+    );
+    exposition.labeled(
+        "dictrd_index_headwords",
+        "gauge",
+        "Headwords in the index by database",
+        "database",
+        headwords
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count)),
+    );
+    exposition.finish()
+}
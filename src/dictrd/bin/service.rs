@@ -0,0 +1,263 @@
+// Running as a Windows service: the service control manager starts
+// "dictrd.exe --service", whose main thread is handed to the dispatcher
+// while the server runs in the main function of the service. Messages go to
+// the Application event log instead of stderr.
+//
+//   dictrd --install-service
+//   sc start dictrd
+//   dictrd --uninstall-service
+use log::{info, Level, LevelFilter, Log, Metadata, Record};
+use std::ffi::{c_void, OsStr};
+use std::io;
+use std::iter::once;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr::{null, null_mut};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::OnceLock;
+use windows_sys::core::PWSTR;
+use windows_sys::Win32::Foundation::{ERROR_CALL_NOT_IMPLEMENTED, HANDLE, NO_ERROR};
+use windows_sys::Win32::System::EventLog::{
+    RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+    EVENTLOG_WARNING_TYPE,
+};
+use windows_sys::Win32::System::Services::{
+    ChangeServiceConfig2W, CloseServiceHandle, CreateServiceW, DeleteService, OpenSCManagerW,
+    OpenServiceW, RegisterServiceCtrlHandlerExW, SetServiceStatus, StartServiceCtrlDispatcherW,
+    SC_HANDLE, SC_MANAGER_CONNECT, SC_MANAGER_CREATE_SERVICE, SERVICE_ACCEPT_SHUTDOWN,
+    SERVICE_ACCEPT_STOP, SERVICE_ALL_ACCESS, SERVICE_AUTO_START, SERVICE_CONFIG_DESCRIPTION,
+    SERVICE_CONTROL_INTERROGATE, SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP,
+    SERVICE_DESCRIPTIONW, SERVICE_ERROR_NORMAL, SERVICE_RUNNING, SERVICE_START_PENDING,
+    SERVICE_STATUS, SERVICE_STOPPED, SERVICE_TABLE_ENTRYW, SERVICE_WIN32_OWN_PROCESS,
+};
+
+// Name of the service and of the event source
+const NAME: &str = "dictrd";
+const DISPLAY_NAME: &str = "dictrd dictionary server";
+const DESCRIPTION: &str = "Answers DICT (RFC 2229) lookups";
+// Runs without the privileges of LocalSystem
+const ACCOUNT: &str = "NT AUTHORITY\\LocalService";
+// Loading large databases takes a while
+const START_WAIT_HINT: u32 = 300_000;
+// Standard access right to delete an object
+const DELETE: u32 = 0x0001_0000;
+
+// The server, run by the main function of the service
+static SERVE: OnceLock<fn()> = OnceLock::new();
+// Handle to report the status with, null when not running as a service
+static STATUS: AtomicPtr<c_void> = AtomicPtr::new(null_mut());
+
+// Hands the main thread to the service control manager, which runs serve
+// in a thread of its own. Returns when the service stopped.
+pub fn dispatch(serve: fn()) -> io::Result<()> {
+    let _ = SERVE.set(serve);
+    let mut name = wide(NAME);
+    let table = [
+        SERVICE_TABLE_ENTRYW {
+            lpServiceName: name.as_mut_ptr(),
+            lpServiceProc: Some(service_main),
+        },
+        SERVICE_TABLE_ENTRYW {
+            lpServiceName: null_mut(),
+            lpServiceProc: None,
+        },
+    ];
+    if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+unsafe extern "system" fn service_main(_argc: u32, _argv: *mut PWSTR) {
+    let name = wide(NAME);
+    let handle = RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(control), null());
+    if handle.is_null() {
+        return;
+    }
+    STATUS.store(handle, Ordering::SeqCst);
+    report(SERVICE_START_PENDING);
+    if let Some(serve) = SERVE.get() {
+        serve();
+    }
+    report(SERVICE_STOPPED);
+}
+
+unsafe extern "system" fn control(
+    control: u32,
+    _event_type: u32,
+    _event_data: *mut c_void,
+    _context: *mut c_void,
+) -> u32 {
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            info!("Stopping service");
+            report(SERVICE_STOPPED);
+            std::process::exit(0)
+        }
+        SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+        _ => ERROR_CALL_NOT_IMPLEMENTED,
+    }
+}
+
+fn report(state: u32) {
+    let handle = STATUS.load(Ordering::SeqCst);
+    if handle.is_null() {
+        return;
+    }
+    let status = SERVICE_STATUS {
+        dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+        dwCurrentState: state,
+        dwControlsAccepted: if state == SERVICE_RUNNING {
+            SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN
+        } else {
+            0
+        },
+        dwWin32ExitCode: NO_ERROR,
+        dwServiceSpecificExitCode: 0,
+        dwCheckPoint: 0,
+        dwWaitHint: if state == SERVICE_START_PENDING {
+            START_WAIT_HINT
+        } else {
+            0
+        },
+    };
+    unsafe { SetServiceStatus(handle, &status) };
+}
+
+// Whether the server runs as a service, once dispatched
+pub fn is_service() -> bool {
+    !STATUS.load(Ordering::SeqCst).is_null()
+}
+
+// Tells the service control manager the server is ready
+pub fn running() {
+    report(SERVICE_RUNNING);
+}
+
+// Logs to the Application event log. Without a message file registered for
+// the source the event viewer prefixes the message with a note that the
+// description of the event is missing.
+struct EventLog {
+    source: HANDLE,
+    level: LevelFilter,
+}
+
+// Event sources may be reported to from any thread
+unsafe impl Send for EventLog {}
+unsafe impl Sync for EventLog {}
+
+impl Log for EventLog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let kind = match record.level() {
+            Level::Error => EVENTLOG_ERROR_TYPE,
+            Level::Warn => EVENTLOG_WARNING_TYPE,
+            _ => EVENTLOG_INFORMATION_TYPE,
+        };
+        let message = wide(&record.args().to_string());
+        let strings = [message.as_ptr()];
+        unsafe {
+            ReportEventW(
+                self.source,
+                kind,
+                0,
+                0,
+                null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                null(),
+            )
+        };
+    }
+
+    fn flush(&self) {}
+}
+
+pub fn log_to_event_log(level: LevelFilter) -> io::Result<()> {
+    let name = wide(NAME);
+    let source = unsafe { RegisterEventSourceW(null(), name.as_ptr()) };
+    if source.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let logger = Box::leak(Box::new(EventLog { source, level }));
+    log::set_logger(logger).map_err(|e| io::Error::other(e.to_string()))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+// Closes a handle of the service control manager when dropped
+struct ServiceHandle(SC_HANDLE);
+
+impl ServiceHandle {
+    fn new(handle: SC_HANDLE) -> io::Result<ServiceHandle> {
+        if handle.is_null() {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ServiceHandle(handle))
+        }
+    }
+}
+
+impl Drop for ServiceHandle {
+    fn drop(&mut self) {
+        unsafe { CloseServiceHandle(self.0) };
+    }
+}
+
+// Registers the service, started automatically with this executable. The
+// configuration is read from its default path or DICTRD_CONFIG.
+pub fn install() -> io::Result<()> {
+    let exe = std::env::current_exe()?;
+    let command = wide(&format!("\"{}\" --service", exe.display()));
+    let manager =
+        ServiceHandle::new(unsafe { OpenSCManagerW(null(), null(), SC_MANAGER_CREATE_SERVICE) })?;
+    let service = ServiceHandle::new(unsafe {
+        CreateServiceW(
+            manager.0,
+            wide(NAME).as_ptr(),
+            wide(DISPLAY_NAME).as_ptr(),
+            SERVICE_ALL_ACCESS,
+            SERVICE_WIN32_OWN_PROCESS,
+            SERVICE_AUTO_START,
+            SERVICE_ERROR_NORMAL,
+            command.as_ptr(),
+            null(),
+            null_mut(),
+            null(),
+            wide(ACCOUNT).as_ptr(),
+            null(),
+        )
+    })?;
+    let mut description = wide(DESCRIPTION);
+    let info = SERVICE_DESCRIPTIONW {
+        lpDescription: description.as_mut_ptr(),
+    };
+    let info: *const SERVICE_DESCRIPTIONW = &info;
+    if unsafe { ChangeServiceConfig2W(service.0, SERVICE_CONFIG_DESCRIPTION, info.cast()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Removes the service, once it is stopped if it is running
+pub fn uninstall() -> io::Result<()> {
+    let manager =
+        ServiceHandle::new(unsafe { OpenSCManagerW(null(), null(), SC_MANAGER_CONNECT) })?;
+    let service =
+        ServiceHandle::new(unsafe { OpenServiceW(manager.0, wide(NAME).as_ptr(), DELETE) })?;
+    if unsafe { DeleteService(service.0) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Nul-terminated UTF-16 for the Windows API
+fn wide(text: &str) -> Vec<u16> {
+    OsStr::new(text).encode_wide().chain(once(0)).collect()
+}
@@ -0,0 +1,47 @@
+// systemd integration: sockets passed by socket activation (sd_listen_fds(3))
+// and readiness notification (sd_notify(3)). Both do nothing when the server
+// is not started by systemd.
+use std::io;
+use std::net::TcpListener;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+// File descriptor of the first passed socket
+const LISTEN_FDS_START: i32 = 3;
+
+// Sockets passed by systemd, in the order of the ListenStream= lines
+pub fn listeners() -> Vec<TcpListener> {
+    // The sockets are meant for this process, not a parent that passed the
+    // environment on
+    let pid = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok());
+    if pid != Some(std::process::id()) {
+        return Vec::new();
+    }
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<i32>().ok())
+        .unwrap_or(0);
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| unsafe { TcpListener::from_raw_fd(fd) })
+        .collect()
+}
+
+// Sends a state like "READY=1" to the service manager
+pub fn notify(state: &str) -> io::Result<()> {
+    let path = match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let socket = UnixDatagram::unbound()?;
+    // Sockets in the abstract namespace start with @
+    let addr = match path.as_bytes() {
+        [b'@', name @ ..] => SocketAddr::from_abstract_name(name)?,
+        _ => SocketAddr::from_pathname(&path)?,
+    };
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
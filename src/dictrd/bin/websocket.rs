@@ -0,0 +1,142 @@
+// Lookups on a WebSocket, with the websocket feature, for interactive
+// clients such as a dictionary in a browser matching as the user types:
+//
+//   ws://127.0.0.1:8080/ws on the --http-address of the HTTP gateway
+//
+// Every text message is a request, answered in order with a message like
+// the HTTP gateway's, carrying the id of the request if it had one:
+//
+//   {"id":1,"command":"match","database":"*","strategy":"prefix","word":"hac"}
+//   {"id":1,"word":"hac","matches":[...],"truncated":false}
+//   {"id":2,"command":"define","database":"!","word":"hack"}
+//   {"id":2,"word":"hack","definitions":[...]}
+//   {"command":"client","text":"frontend 1.0"}
+//
+// The database defaults to "*" and the strategy to ".". Like a DICT
+// connection the WebSocket has a session and is listed with the
+// connections, is closed when idle and limited to the command rate.
+use crate::http::{definitions_body, error_body, failure, matches_body};
+use dictrdlib::errors::DictError;
+use dictrdlib::server::DictdServer;
+use dictrdlib::websocket::{accept_key, write_message, Message, Receiver};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use socket2::Socket;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+// Longest request accepted
+const MAX_MESSAGE: usize = 64 * 1024;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<serde_json::Value>,
+    command: String,
+    #[serde(default = "all_databases")]
+    database: String,
+    #[serde(default = "default_strategy")]
+    strategy: String,
+    #[serde(default)]
+    word: String,
+    #[serde(default)]
+    text: String,
+}
+
+fn all_databases() -> String {
+    "*".to_string()
+}
+
+fn default_strategy() -> String {
+    ".".to_string()
+}
+
+// Answers the opening handshake and the requests until the client closes
+// the WebSocket
+pub fn serve(
+    reader: BufReader<TcpStream>,
+    mut writer: TcpStream,
+    key: &str,
+    peer: SocketAddr,
+    mut server: DictdServer<File>,
+) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    writer.flush()?;
+    server.set_socket(Socket::from(writer.try_clone()?));
+    let _guard = server.register_connection(peer);
+    let mut receiver = Receiver::new(reader, MAX_MESSAGE);
+    let mut command_limiter = server.command_limiter();
+    loop {
+        let reply = match receiver.next_message()? {
+            // Like a DICT client sending commands too fast
+            Some(Message::Text(_))
+                if command_limiter.as_mut().is_some_and(|limiter| {
+                    limiter.reserve(1, Instant::now()) > Duration::from_secs(0)
+                }) =>
+            {
+                warn!("Closing WebSocket of {}, too many requests", peer);
+                return close(&mut writer, Some(1008));
+            }
+            Some(Message::Text(text)) => match serde_json::from_str::<Request>(&text) {
+                Ok(request) => answer(&mut server, peer, request),
+                Err(e) => Some(error_body(&format!("Invalid request: {}", e))),
+            },
+            Some(Message::Ping(data)) => {
+                write_message(&mut writer, &Message::Pong(data))?;
+                None
+            }
+            Some(Message::Pong(_)) => None,
+            // Requests are text
+            Some(Message::Binary(_)) => return close(&mut writer, Some(1003)),
+            Some(Message::Close(code)) => {
+                debug!("WebSocket of {} closed with {:?}", peer, code);
+                return close(&mut writer, code.map(|_| 1000));
+            }
+            None => return Ok(()),
+        };
+        if let Some(reply) = reply {
+            write_message(&mut writer, &Message::Text(reply))?;
+        }
+    }
+}
+
+// The answer to a request, none for CLIENT
+fn answer(server: &mut DictdServer<File>, peer: SocketAddr, request: Request) -> Option<String> {
+    let body = match request.command.to_lowercase().as_str() {
+        "define" => match server.define(peer, &request.database, &request.word) {
+            Ok(definitions) => definitions_body(&request.word, &definitions),
+            Err(DictError::NoMatch(_)) => definitions_body(&request.word, &[]),
+            Err(e) => failure(e).1,
+        },
+        "match" => {
+            match server.match_words(peer, &request.database, &request.strategy, &request.word) {
+                Ok((matches, truncated)) => matches_body(&request.word, &matches, truncated),
+                Err(DictError::NoMatch(_)) => matches_body(&request.word, &[], false),
+                Err(e) => failure(e).1,
+            }
+        }
+        "client" => {
+            server.identify(&request.text);
+            return None;
+        }
+        command => {
+            info!("Unknown WebSocket command {:?} from {}", command, peer);
+            error_body(&format!("Unknown command {}", command))
+        }
+    };
+    // The id goes first: {"id":...,"word":...}
+    Some(match request.id {
+        Some(id) => format!("{{\"id\":{},{}", id, &body[1..]),
+        None => body,
+    })
+}
+
+fn close(writer: &mut TcpStream, code: Option<u16>) -> io::Result<()> {
+    write_message(writer, &Message::Close(code))
+}
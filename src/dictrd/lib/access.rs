@@ -0,0 +1,95 @@
+// Access control by client address. Networks are written in CIDR notation,
+// e.g. 192.168.0.0/16 or 2001:db8::/32; a plain address stands for itself.
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Network {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Network, String> {
+        let invalid = || format!("invalid network {}", text);
+        let (addr, prefix) = match text.find('/') {
+            Some(pos) => (&text[..pos], Some(&text[pos + 1..])),
+            None => (text, None),
+        };
+        let addr = IpAddr::from_str(addr).map_err(|_| invalid())?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| invalid())?,
+            None => bits,
+        };
+        if prefix > bits {
+            return Err(invalid());
+        }
+        Ok(Network { addr, prefix })
+    }
+}
+
+impl Network {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of IPv6 sockets show up as ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip,
+        };
+        let (network, ip, bits) = match (self.addr, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                (u32::from(network) as u128, u32::from(ip) as u128, 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+        self.prefix == 0 || (network ^ ip) >> (bits - self.prefix) == 0
+    }
+}
+
+// Clients in a denied network are refused, the others if they are in none
+// of the allowed networks. An empty allow list allows everyone.
+#[derive(Clone, Debug, Default)]
+pub struct AccessList {
+    pub allow: Vec<Network>,
+    pub deny: Vec<Network>,
+}
+
+impl AccessList {
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|network| network.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|network| network.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn networks() {
+        let ip = |text: &str| IpAddr::from_str(text).unwrap();
+        let lan = Network::from_str("192.168.0.0/16").unwrap();
+        assert!(lan.contains(ip("192.168.13.1")));
+        assert!(lan.contains(ip("::ffff:192.168.13.1")));
+        assert!(!lan.contains(ip("192.169.0.1")));
+        assert!(Network::from_str("::1").unwrap().contains(ip("::1")));
+        assert!(Network::from_str("0.0.0.0/0")
+            .unwrap()
+            .contains(ip("8.8.8.8")));
+        assert!(Network::from_str("10.0.0.0/33").is_err());
+
+        let access = AccessList {
+            allow: vec![lan],
+            deny: vec![Network::from_str("192.168.13.0/24").unwrap()],
+        };
+        assert!(access.permits(ip("192.168.1.1")));
+        assert!(!access.permits(ip("192.168.13.1")));
+        assert!(!access.permits(ip("10.0.0.1")));
+        assert!(AccessList::default().permits(ip("10.0.0.1")));
+    }
+}
@@ -0,0 +1,257 @@
+// Access log with one line per command: time, peer, client, command,
+// database, word, status code and duration, as text or as JSON lines. The
+// file is rotated once it reaches a size or an age: access.log becomes
+// access.log.1, access.log.1 becomes access.log.2 and so on, keeping a given
+// number of old files.
+use crate::json;
+use crate::securitylog::timestamp;
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+pub struct AccessRecord<'a> {
+    pub peer: &'a str,
+    pub client: Option<&'a str>,
+    pub command: &'a str,
+    pub database: Option<&'a str>,
+    pub word: Option<&'a str>,
+    // Code of the first status line of the response
+    pub status: u16,
+    pub duration: Duration,
+}
+
+// Values from clients are quoted and escaped, so they cannot forge lines
+impl<'a> fmt::Display for AccessRecord<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let quoted = |value: Option<&str>| value.map_or("-".to_string(), |v| format!("{:?}", v));
+        write!(
+            f,
+            "{} client={} command={} database={} word={} status={} duration={:.6}",
+            self.peer,
+            quoted(self.client),
+            self.command,
+            quoted(self.database),
+            quoted(self.word),
+            self.status,
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+impl<'a> AccessRecord<'a> {
+    pub fn to_json(&self, time: SystemTime) -> String {
+        let value = |value: Option<&str>| value.map_or("null".to_string(), json::string);
+        format!(
+            "{{\"time\":{},\"peer\":{},\"client\":{},\"command\":{},\"database\":{},\"word\":{},\"status\":{},\"duration\":{:.6}}}",
+            json::string(&timestamp(time)),
+            json::string(self.peer),
+            value(self.client),
+            json::string(self.command),
+            value(self.database),
+            value(self.word),
+            self.status,
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+// Whether the words clients look up appear in the logs. Public servers may
+// not want to keep them: hashed, lookups of the same word can still be told
+// apart, but with a key chosen when the server starts, so the words cannot be
+// found by hashing a word list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryLogging {
+    Full,
+    Hashed([u8; 16]),
+    Omitted,
+}
+
+impl QueryLogging {
+    // The policies by their names in the configuration: full, hashed or none
+    pub fn from_name(name: &str) -> Option<QueryLogging> {
+        match name {
+            "full" => Some(QueryLogging::Full),
+            "hashed" => Some(QueryLogging::Hashed(rand::random())),
+            "none" => Some(QueryLogging::Omitted),
+            _ => None,
+        }
+    }
+
+    // The word as it may be logged, None if it may not
+    pub fn word<'a>(&self, word: &'a str) -> Option<Cow<'a, str>> {
+        match self {
+            QueryLogging::Full => Some(Cow::Borrowed(word)),
+            QueryLogging::Hashed(key) => {
+                let mut context = md5::Context::new();
+                context.consume(key);
+                context.consume(word.as_bytes());
+                let digest = format!("{:x}", context.compute());
+                Some(Cow::Owned(format!("#{}", &digest[..16])))
+            }
+            QueryLogging::Omitted => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessLogFormat {
+    Text,
+    Json,
+}
+
+// When the log is rotated, never if neither limit is set
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rotation {
+    pub max_size: Option<u64>,
+    pub max_age: Option<Duration>,
+    // Old files kept
+    pub keep: usize,
+}
+
+pub struct AccessLog {
+    path: PathBuf,
+    format: AccessLogFormat,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+    opened: SystemTime,
+}
+
+impl AccessLog {
+    pub fn open(path: &Path, format: AccessLogFormat, rotation: Rotation) -> io::Result<AccessLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        Ok(AccessLog {
+            path: path.to_path_buf(),
+            format,
+            rotation,
+            file,
+            size: metadata.len(),
+            opened: metadata.created().unwrap_or_else(|_| SystemTime::now()),
+        })
+    }
+
+    pub fn record(&mut self, record: &AccessRecord) -> io::Result<()> {
+        self.record_at(record, SystemTime::now())
+    }
+
+    fn record_at(&mut self, record: &AccessRecord, now: SystemTime) -> io::Result<()> {
+        if self.rotation_due(now) {
+            self.rotate(now)?;
+        }
+        let line = match self.format {
+            AccessLogFormat::Text => format!("{} {}\n", timestamp(now), record),
+            AccessLogFormat::Json => format!("{}\n", record.to_json(now)),
+        };
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotation_due(&self, now: SystemTime) -> bool {
+        let age = now.duration_since(self.opened).unwrap_or_default();
+        self.size > 0
+            && (self.rotation.max_size.is_some_and(|max| self.size >= max)
+                || self.rotation.max_age.is_some_and(|max| age >= max))
+    }
+
+    fn rotate(&mut self, now: SystemTime) -> io::Result<()> {
+        // The oldest file first, so that none is overwritten before it moved
+        for i in (1..self.rotation.keep).rev() {
+            let from = rotated(&self.path, i);
+            if from.exists() {
+                fs::rename(from, rotated(&self.path, i + 1))?;
+            }
+        }
+        if self.rotation.keep > 0 {
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.opened = now;
+        Ok(())
+    }
+}
+
+// access.log.1 for access.log
+fn rotated(path: &Path, i: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", i));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn records_and_rotation() {
+        let record = AccessRecord {
+            peer: "127.0.0.1:4711",
+            client: Some("dictr \"1\""),
+            command: "DEFINE",
+            database: Some("jargon"),
+            word: Some("hack"),
+            status: 150,
+            duration: Duration::from_millis(12),
+        };
+        assert_eq!(
+            record.to_string(),
+            "127.0.0.1:4711 client=\"dictr \\\"1\\\"\" command=DEFINE database=\"jargon\" word=\"hack\" status=150 duration=0.012000"
+        );
+        assert_eq!(
+            record.to_json(UNIX_EPOCH),
+            "{\"time\":\"1970-01-01T00:00:00Z\",\"peer\":\"127.0.0.1:4711\",\"client\":\"dictr \\\"1\\\"\",\"command\":\"DEFINE\",\"database\":\"jargon\",\"word\":\"hack\",\"status\":150,\"duration\":0.012000}"
+        );
+
+        let dir = std::env::temp_dir().join(format!("dictr-accesslog-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        let rotation = Rotation {
+            max_size: Some(1),
+            max_age: None,
+            keep: 2,
+        };
+        let mut log = AccessLog::open(&path, AccessLogFormat::Json, rotation).unwrap();
+        for _ in 0..4 {
+            log.record_at(&record, UNIX_EPOCH).unwrap();
+        }
+        let lines = |path: &Path| fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!(lines(&path), 1);
+        assert_eq!(lines(&rotated(&path, 1)), 1);
+        assert_eq!(lines(&rotated(&path, 2)), 1);
+        assert!(!rotated(&path, 3).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn query_logging() {
+        assert_eq!(
+            QueryLogging::from_name("full").unwrap().word("hack"),
+            Some(Cow::Borrowed("hack"))
+        );
+        assert_eq!(QueryLogging::from_name("none").unwrap().word("hack"), None);
+        assert_eq!(QueryLogging::from_name("all"), None);
+
+        let hashed = QueryLogging::from_name("hashed").unwrap();
+        let word = hashed.word("hack").unwrap();
+        assert_eq!(word.len(), 17);
+        assert!(word.starts_with('#'));
+        assert_eq!(hashed.word("hack").unwrap(), word);
+        assert_ne!(hashed.word("hacker").unwrap(), word);
+        assert_ne!(
+            QueryLogging::Hashed([0; 16]).word("hack"),
+            QueryLogging::Hashed([1; 16]).word("hack")
+        );
+    }
+}
@@ -0,0 +1,90 @@
+// Checks that the articles of a database are valid UTF-8, and repairs
+// databases with articles in a legacy 8 bit encoding.
+use crate::errors::DictError;
+use crate::metadata::Encoding;
+use crate::{DictReader, IndexReader, ReadAt};
+use std::collections::HashMap;
+use std::io::Write;
+
+// Returns the positions in IndexReader::entries of the entries whose article
+// is not valid UTF-8
+pub fn invalid_entries<R: ReadAt>(
+    indexreader: &IndexReader,
+    dictreader: &DictReader<R>,
+) -> Result<Vec<usize>, DictError> {
+    let mut invalid = Vec::new();
+    for (pos, entry) in indexreader.iter().enumerate() {
+        let bytes = dictreader.find_bytes(entry.offset, entry.length)?;
+        if std::str::from_utf8(&bytes).is_err() {
+            invalid.push(pos);
+        }
+    }
+    Ok(invalid)
+}
+
+// Writes a copy of the database with every article that is not valid UTF-8
+// transcoded from its guessed legacy encoding. Transcoding changes the
+// article lengths, so the index is rewritten with the new ranges. Returns the
+// number of articles transcoded.
+pub fn transcode<R: ReadAt, D: Write, I: Write>(
+    indexreader: &IndexReader,
+    dictreader: &DictReader<R>,
+    dict_out: &mut D,
+    index_out: &mut I,
+) -> Result<usize, DictError> {
+    let mut ranges: Vec<(u64, u64)> = indexreader
+        .entries()
+        .iter()
+        .map(|entry| (entry.offset, entry.length))
+        .collect();
+    ranges.sort_unstable();
+    ranges.dedup();
+    let mut moved = HashMap::new();
+    let mut offset = 0;
+    let mut transcoded = 0;
+    for (old_offset, old_length) in ranges {
+        let mut bytes = dictreader.find_bytes(old_offset, old_length)?;
+        if std::str::from_utf8(&bytes).is_err() {
+            let encoding = Encoding::guess_legacy(&bytes);
+            bytes = encoding.decode(bytes)?.into_bytes();
+            transcoded += 1;
+        }
+        dict_out.write_all(&bytes)?;
+        let length = bytes.len() as u64;
+        moved.insert((old_offset, old_length), (offset, length));
+        offset += length;
+    }
+    for entry in indexreader.entries() {
+        let mut entry = entry.clone();
+        let (offset, length) = moved[&(entry.offset, entry.length)];
+        entry.offset = offset;
+        entry.length = length;
+        writeln!(index_out, "{}", entry.to_line())?;
+    }
+    Ok(transcoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn transcode_latin1() {
+        let dict = b"valid\ngr\xfc\xdfe\n".to_vec();
+        let mut di = IndexReader::new();
+        di.parse_dict_index("gruesse\tG\tG\nvalid\tA\tG\n".as_bytes());
+        let dr = DictReader::new(BufReader::new(Cursor::new(dict))).unwrap();
+        assert_eq!(invalid_entries(&di, &dr).unwrap(), [0]);
+        let (mut dict_out, mut index_out) = (Vec::new(), Vec::new());
+        assert_eq!(
+            transcode(&di, &dr, &mut dict_out, &mut index_out).unwrap(),
+            1
+        );
+        assert_eq!(String::from_utf8(dict_out).unwrap(), "valid\ngrüße\n");
+        assert_eq!(
+            String::from_utf8(index_out).unwrap(),
+            "gruesse\tG\tI\nvalid\tA\tG\n"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// Databases whose lookups are answered by something other than an index and
+// a dict file, e.g. an external program. The server passes words on as the
+// client sent them and renders the definitions found with the filters and
+// template of the database.
+use crate::dictionary::Definition;
+use std::io;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub trait Backend: Send + Sync {
+    // The definitions of a word, none if it is not found
+    fn define(&self, word: &str) -> io::Result<Vec<Definition>>;
+
+    // The headwords matching a word with a strategy. Strategies the backend
+    // does not support fail with Unsupported, which is answered with 551
+    // like a strategy whose index a database lacks.
+    fn match_words(&self, strategy: &str, word: &str) -> io::Result<Vec<String>>;
+}
+
+// Runs a query on a thread of its own and stops waiting for it after the
+// deadline, failing with TimedOut. The query still runs to its end, its
+// result is dropped.
+pub fn with_deadline<T, F>(deadline: Duration, query: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(query());
+    });
+    receiver.recv_timeout(deadline).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("No answer within {:?}", deadline),
+        )
+    })
+}
+
+// A backend whose lookups fail with TimedOut after a deadline, for backends
+// that may block for longer, e.g. on a locked SQLite file
+pub struct Deadline {
+    backend: Arc<dyn Backend>,
+    deadline: Duration,
+}
+
+impl Deadline {
+    pub fn new(backend: Arc<dyn Backend>, deadline: Duration) -> Deadline {
+        Deadline { backend, deadline }
+    }
+}
+
+impl Backend for Deadline {
+    fn define(&self, word: &str) -> io::Result<Vec<Definition>> {
+        let (backend, word) = (self.backend.clone(), word.to_string());
+        with_deadline(self.deadline, move || backend.define(&word))?
+    }
+
+    fn match_words(&self, strategy: &str, word: &str) -> io::Result<Vec<String>> {
+        let backend = self.backend.clone();
+        let (strategy, word) = (strategy.to_string(), word.to_string());
+        with_deadline(self.deadline, move || backend.match_words(&strategy, &word))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers after a pause
+    struct Slow(Duration);
+
+    impl Backend for Slow {
+        fn define(&self, _word: &str) -> io::Result<Vec<Definition>> {
+            thread::sleep(self.0);
+            Ok(Vec::new())
+        }
+
+        fn match_words(&self, _strategy: &str, word: &str) -> io::Result<Vec<String>> {
+            thread::sleep(self.0);
+            Ok(vec![word.to_string()])
+        }
+    }
+
+    #[test]
+    fn deadline() {
+        let slow = Deadline::new(
+            Arc::new(Slow(Duration::from_millis(500))),
+            Duration::from_millis(20),
+        );
+        let e = slow.match_words("exact", "word").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        let fast = Deadline::new(
+            Arc::new(Slow(Duration::from_millis(0))),
+            Duration::from_secs(5),
+        );
+        assert_eq!(fast.match_words("exact", "word").unwrap(), ["word"]);
+    }
+}
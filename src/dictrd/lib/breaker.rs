@@ -0,0 +1,96 @@
+// Circuit breaker for databases that are too slow to answer. A query taking
+// longer than the deadline counts as a failure. After a number of failures
+// in a row the breaker opens and the database is left out of searches in
+// all databases for a while, then it is tried again.
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakerState {
+    Closed,
+    // Excluded for the remaining time
+    Open(Duration),
+    // Cooled down, the next query decides
+    HalfOpen,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    deadline: Duration,
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    opened: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(deadline: Duration, threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            deadline,
+            threshold,
+            cooldown,
+            failures: 0,
+            opened: None,
+        }
+    }
+
+    pub fn deadline(&self) -> Duration {
+        self.deadline
+    }
+
+    pub fn state(&self, now: Instant) -> BreakerState {
+        match self.opened {
+            None => BreakerState::Closed,
+            Some(opened) => match self.cooldown.checked_sub(now.duration_since(opened)) {
+                Some(remaining) if remaining > Duration::from_secs(0) => {
+                    BreakerState::Open(remaining)
+                }
+                _ => BreakerState::HalfOpen,
+            },
+        }
+    }
+
+    pub fn allows(&self, now: Instant) -> bool {
+        !matches!(self.state(now), BreakerState::Open(_))
+    }
+
+    // Records how long a query took. Returns true if this opened the breaker.
+    pub fn record(&mut self, elapsed: Duration, now: Instant) -> bool {
+        if elapsed <= self.deadline {
+            self.failures = 0;
+            self.opened = None;
+            return false;
+        }
+        self.failures += 1;
+        let half_open = self.state(now) == BreakerState::HalfOpen;
+        if half_open || (self.opened.is_none() && self.failures >= self.threshold) {
+            self.opened = Some(now);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_and_recover() {
+        let (fast, slow) = (Duration::from_millis(1), Duration::from_millis(20));
+        let mut breaker =
+            CircuitBreaker::new(Duration::from_millis(10), 2, Duration::from_secs(60));
+        let now = Instant::now();
+        assert!(!breaker.record(slow, now));
+        assert!(!breaker.record(fast, now));
+        assert!(!breaker.record(slow, now));
+        assert!(breaker.record(slow, now));
+        assert!(!breaker.allows(now + Duration::from_secs(30)));
+        let later = now + Duration::from_secs(61);
+        assert_eq!(breaker.state(later), BreakerState::HalfOpen);
+        // A single slow query after the cooldown opens it again
+        assert!(breaker.record(slow, later));
+        assert!(!breaker.allows(later));
+        breaker.record(fast, later);
+        assert_eq!(breaker.state(later), BreakerState::Closed);
+    }
+}
@@ -0,0 +1,90 @@
+// Cache of a bounded number of values that drops the least recently used
+// value first
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+pub struct LruCache<K, V> {
+    capacity: usize,
+    // Values with the time of their last use
+    values: HashMap<K, (V, u64)>,
+    // Keys by the time of their last use, the oldest first
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache {
+            capacity,
+            values: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.clock += 1;
+        let (value, used) = self.values.get_mut(key)?;
+        self.recency.remove(used);
+        *used = self.clock;
+        self.recency.insert(self.clock, key.clone());
+        Some(value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.clock += 1;
+        if let Some((_, used)) = self.values.insert(key.clone(), (value, self.clock)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.clock, key);
+        while self.values.len() > self.capacity {
+            match self.recency.pop_first() {
+                Some((_, oldest)) => self.values.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+
+    // Drops the values whose key does not satisfy keep
+    pub fn retain<F: Fn(&K) -> bool>(&mut self, keep: F) {
+        self.values.retain(|key, _| keep(key));
+        self.recency.retain(|_, key| keep(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        // b is older than a now
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.get(&"c"), Some(&3));
+        cache.insert("c", 4);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"c"), Some(&4));
+        cache.retain(|key| *key != "a");
+        assert_eq!(cache.len(), 1);
+        cache.insert("d", 5);
+        assert_eq!(cache.get(&"c"), Some(&4));
+    }
+}
@@ -0,0 +1,86 @@
+// Consistency checks of an index against its .dict file, used to rewrite a
+// clean index.
+use crate::IndexReader;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    // Same headword and range as an earlier entry
+    Duplicate,
+    // Same range as the earlier entry of another headword
+    SharedRange(String),
+    // The range exceeds the .dict file
+    Dangling,
+}
+
+// Returns the positions of the problematic entries in IndexReader::entries
+pub fn check(indexreader: &IndexReader, dict_len: u64) -> Vec<(usize, Problem)> {
+    let mut problems = Vec::new();
+    let mut seen = HashSet::new();
+    let mut ranges: HashMap<(u64, u64), &str> = HashMap::new();
+    for (pos, entry) in indexreader.iter().enumerate() {
+        let range = (entry.offset, entry.length);
+        if entry.offset + entry.length > dict_len {
+            problems.push((pos, Problem::Dangling));
+        } else if !seen.insert((entry.word.as_str(), range)) {
+            problems.push((pos, Problem::Duplicate));
+        } else if let Some(word) = ranges.get(&range) {
+            problems.push((pos, Problem::SharedRange(word.to_string())));
+        } else {
+            ranges.insert(range, &entry.word);
+        }
+    }
+    problems
+}
+
+// Writes the index without duplicate and dangling entries and returns the
+// number of entries removed. Headwords sharing a range are synonyms in
+// dictfmt output and only removed if shared_ranges is set.
+pub fn write_clean_index<W: Write>(
+    indexreader: &IndexReader,
+    problems: &[(usize, Problem)],
+    shared_ranges: bool,
+    out: &mut W,
+) -> std::io::Result<usize> {
+    let removed: HashSet<usize> = problems
+        .iter()
+        .filter(|(_, problem)| shared_ranges || !matches!(problem, Problem::SharedRange(_)))
+        .map(|(pos, _)| *pos)
+        .collect();
+    for (pos, entry) in indexreader.iter().enumerate() {
+        if !removed.contains(&pos) {
+            writeln!(out, "{}", entry.to_line())?;
+        }
+    }
+    Ok(removed.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_index() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("a\tA\tE\na\tA\tE\nb\tA\tE\nc\tE\tE\nd\tE\tZ\n".as_bytes());
+        let problems = check(&di, 10);
+        assert_eq!(
+            problems,
+            [
+                (1, Problem::Duplicate),
+                (2, Problem::SharedRange("a".to_string())),
+                (4, Problem::Dangling)
+            ]
+        );
+        let mut out = Vec::new();
+        assert_eq!(
+            write_clean_index(&di, &problems, false, &mut out).unwrap(),
+            2
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a\tA\tE\nb\tA\tE\nc\tE\tE\n"
+        );
+    }
+}
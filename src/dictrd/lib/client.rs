@@ -0,0 +1,735 @@
+// Client side of the DICT protocol (RFC 2229), for programs that look up
+// words on a server:
+//
+//   let mut client = DictClient::connect("localhost", 2628)?;
+//   for definition in client.define("*", "hacker")? {
+//       println!("{}", definition.text);
+//   }
+//   client.quit()?;
+//
+// Errors the server answers with are io::Errors wrapping their Status.
+//
+// A client connected with connect_traced writes every line it sends and
+// receives to a trace, for debugging servers:
+//
+//     0.000 <- 220 dictd 1.13 <auth.mime> <123@host>
+//     0.001 -> CLIENT dictr 0.1.0
+//
+// with the seconds since connecting.
+use crate::compress::inflate_block;
+use crate::mime::ContentType;
+use crate::sasl::cram_md5_response;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+// Status codes of the responses the client reads
+pub const DATABASES_PRESENT: u16 = 110;
+pub const STRATEGIES_AVAILABLE: u16 = 111;
+pub const DATABASE_INFORMATION: u16 = 112;
+pub const HELP_TEXT: u16 = 113;
+pub const SERVER_INFORMATION: u16 = 114;
+pub const DEFINITIONS_RETRIEVED: u16 = 150;
+pub const DEFINITION: u16 = 151;
+pub const MATCHES_FOUND: u16 = 152;
+pub const BANNER: u16 = 220;
+pub const AUTHENTICATED: u16 = 230;
+pub const OK: u16 = 250;
+pub const SASL_CHALLENGE: u16 = 330;
+pub const ILLEGAL_PARAMETERS: u16 = 501;
+pub const INVALID_DATABASE: u16 = 550;
+pub const INVALID_STRATEGY: u16 = 551;
+pub const NO_MATCH: u16 = 552;
+pub const NO_DATABASES: u16 = 554;
+pub const NO_STRATEGIES: u16 = 555;
+
+// Servers not answering within this time are given up
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Status line of a response, e.g. 552 no match
+#[derive(Clone, Debug, PartialEq)]
+pub struct Status {
+    pub code: u16,
+    pub text: String,
+}
+
+impl Status {
+    // The status as an error, which callers can get back with
+    // io::Error::get_ref and downcast_ref
+    pub fn into_error(self) -> io::Error {
+        io::Error::other(self)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.text)
+    }
+}
+
+impl std::error::Error for Status {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+    pub headword: String,
+    pub database: String,
+    pub description: String,
+    pub text: String,
+    // Format of the text, declared in the MIME headers, see mime
+    pub content_type: ContentType,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub database: String,
+    pub headword: String,
+}
+
+// A database of SHOW DB or a strategy of SHOW STRAT
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    pub name: String,
+    pub description: String,
+}
+
+pub struct DictClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    // Capabilities announced in the banner, e.g. "mime" and "sasl-cram-md5"
+    capabilities: Vec<String>,
+    // Whether the server compresses text blocks, see compress
+    compressed: bool,
+    // Whether definitions start with MIME headers, see mime
+    mime: bool,
+    // Where the lines sent and received are written, see connect_traced
+    trace: Option<Trace>,
+}
+
+struct Trace {
+    start: Instant,
+    out: Box<dyn Write + Send>,
+}
+
+impl Trace {
+    // Errors writing the trace do not fail the lookup
+    fn line(&mut self, direction: &str, line: &str) {
+        let seconds = self.start.elapsed().as_secs_f64();
+        let _ = writeln!(self.out, "{:8.3} {} {}", seconds, direction, line);
+    }
+}
+
+impl DictClient {
+    // Connects, reads the banner and identifies the client
+    pub fn connect(host: &str, port: u16) -> io::Result<DictClient> {
+        let client = DictClient::open(host, port, CONNECT_TIMEOUT, None)?;
+        client.writer.set_read_timeout(None)?;
+        Ok(client)
+    }
+
+    // Connects like connect, but gives up on connecting and on every read
+    // and write after timeout, with an error of kind TimedOut or
+    // WouldBlock, instead of waiting for a server that stopped answering
+    pub fn connect_with_timeout(
+        host: &str,
+        port: u16,
+        timeout: Duration,
+    ) -> io::Result<DictClient> {
+        let client = DictClient::open(host, port, timeout, None)?;
+        client.writer.set_write_timeout(Some(timeout))?;
+        Ok(client)
+    }
+
+    // Connects like connect, or like connect_with_timeout with a timeout,
+    // and writes the lines sent and received to trace, starting with the
+    // banner
+    pub fn connect_traced(
+        host: &str,
+        port: u16,
+        timeout: Option<Duration>,
+        trace: Box<dyn Write + Send>,
+    ) -> io::Result<DictClient> {
+        let trace = Trace {
+            start: Instant::now(),
+            out: trace,
+        };
+        let client = DictClient::open(host, port, timeout.unwrap_or(CONNECT_TIMEOUT), Some(trace))?;
+        match timeout {
+            Some(timeout) => client.writer.set_write_timeout(Some(timeout))?,
+            None => client.writer.set_read_timeout(None)?,
+        }
+        Ok(client)
+    }
+
+    // Connects and reads the banner within timeout, leaving the read timeout
+    // set
+    fn open(
+        host: &str,
+        port: u16,
+        timeout: Duration,
+        trace: Option<Trace>,
+    ) -> io::Result<DictClient> {
+        let stream = connect_timeout(host, port, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        let mut client = DictClient {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            capabilities: Vec::new(),
+            compressed: false,
+            mime: false,
+            trace,
+        };
+        let banner = client.read_status()?;
+        if banner.code != BANNER {
+            return Err(banner.into_error());
+        }
+        // 220 text <capabilities> <msg-id>
+        if let Some(start) = banner.text.find('<') {
+            if let Some(end) = banner.text[start..].find('>') {
+                client.capabilities = banner.text[start + 1..start + end]
+                    .split('.')
+                    .filter(|capability| !capability.is_empty())
+                    .map(str::to_lowercase)
+                    .collect();
+            }
+        }
+        client.client(&format!(
+            "{} {}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ))?;
+        Ok(client)
+    }
+
+    // Capabilities the server announced, in lower case
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    // Tells the server which client is connected. Servers not knowing
+    // CLIENT answer with an error, which does not matter.
+    pub fn client(&mut self, text: &str) -> io::Result<()> {
+        self.command(&format!("CLIENT {}", text))?;
+        Ok(())
+    }
+
+    // Sends a command line and reads the status of the response. Any text
+    // following it is left for the caller.
+    pub fn command(&mut self, line: &str) -> io::Result<Status> {
+        if let Some(trace) = &mut self.trace {
+            trace.line("->", line);
+        }
+        self.writer.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.read_status()
+    }
+
+    // Asks the server to compress the text blocks of its responses with
+    // deflate, for slow links. Returns false if the server does not offer
+    // compression, which leaves the responses as they are.
+    pub fn compress(&mut self) -> io::Result<bool> {
+        if !self.capabilities.iter().any(|c| c == "xcompress") {
+            return Ok(false);
+        }
+        let status = self.command("OPTION XCOMPRESS deflate")?;
+        if status.code != OK {
+            return Err(status.into_error());
+        }
+        self.compressed = true;
+        Ok(true)
+    }
+
+    // Asks the server to declare the content type of every definition in
+    // MIME headers, so that HTML and Markdown articles can be told apart.
+    // Returns false if the server does not offer them, which leaves all
+    // definitions plain text.
+    pub fn mime(&mut self) -> io::Result<bool> {
+        if !self.capabilities.iter().any(|c| c == "mime") {
+            return Ok(false);
+        }
+        let status = self.command("OPTION MIME")?;
+        if status.code != OK {
+            return Err(status.into_error());
+        }
+        self.mime = true;
+        Ok(true)
+    }
+
+    // Authenticates with SASL CRAM-MD5, which keeps the key off the network
+    pub fn authenticate(&mut self, user: &str, key: &str) -> io::Result<()> {
+        if !self.capabilities.iter().any(|c| c == "sasl-cram-md5") {
+            return Err(io::Error::other(
+                "Server does not offer CRAM-MD5 authentication",
+            ));
+        }
+        let status = self.command("SASLAUTH CRAM-MD5")?;
+        if status.code != SASL_CHALLENGE {
+            return Err(status.into_error());
+        }
+        let challenge = base64::decode(status.text.trim())
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "Invalid CRAM-MD5 challenge"))?;
+        let response = cram_md5_response(user, key, &challenge);
+        let status = self.command(&format!("SASLRESP {}", base64::encode(response)))?;
+        match status.code {
+            AUTHENTICATED => Ok(()),
+            _ => Err(status.into_error()),
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            if let Some(trace) = &mut self.trace {
+                trace.line("<-", "(connection closed)");
+            }
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "Connection closed by server",
+            ));
+        }
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        if let Some(trace) = &mut self.trace {
+            trace.line("<-", line);
+        }
+        Ok(line.to_string())
+    }
+
+    pub fn read_status(&mut self) -> io::Result<Status> {
+        let line = self.read_line()?;
+        match line.get(..3).and_then(|code| code.parse().ok()) {
+            Some(code) => Ok(Status {
+                code,
+                text: line[3..].trim_start().to_string(),
+            }),
+            None => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid status line: {}", line),
+            )),
+        }
+    }
+
+    // Reads a text block up to the line with a single dot, removing the dot
+    // doubled at the start of lines and the MIME headers of the text
+    pub fn read_text(&mut self) -> io::Result<String> {
+        Ok(self.read_typed_text()?.1)
+    }
+
+    // Reads a text block like read_text, returning the content type the
+    // MIME headers declare, plain text without them
+    fn read_typed_text(&mut self) -> io::Result<(ContentType, String)> {
+        let text = self.read_block()?;
+        Ok(match self.mime {
+            true => split_mime_headers(&text),
+            false => (ContentType::Plain, text),
+        })
+    }
+
+    fn read_block(&mut self) -> io::Result<String> {
+        let mut text = String::new();
+        loop {
+            let line = self.read_line()?;
+            if line == "." && self.compressed {
+                let inflated = inflate_block(&text).map_err(|e| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Invalid compressed text: {}", e),
+                    )
+                })?;
+                // Lines end like those of uncompressed text
+                return Ok(inflated.lines().map(|line| format!("{}\n", line)).collect());
+            }
+            if line == "." {
+                return Ok(text);
+            }
+            text.push_str(line.strip_prefix('.').unwrap_or(&line));
+            text.push('\n');
+        }
+    }
+
+    // Reads the text block following a status with the expected code, and
+    // the status ending the response
+    fn read_text_response(&mut self, status: Status, code: u16) -> io::Result<String> {
+        if status.code != code {
+            return Err(status.into_error());
+        }
+        let text = self.read_text()?;
+        let status = self.read_status()?;
+        if status.code != OK {
+            return Err(status.into_error());
+        }
+        Ok(text)
+    }
+
+    // Looks up a word, no definitions is not an error
+    pub fn define(&mut self, database: &str, word: &str) -> io::Result<Vec<Definition>> {
+        let status = self.command(&format!("DEFINE {} {}", database, quote(word)))?;
+        match status.code {
+            DEFINITIONS_RETRIEVED => (),
+            NO_MATCH => return Ok(Vec::new()),
+            _ => return Err(status.into_error()),
+        }
+        let mut definitions = Vec::new();
+        loop {
+            let status = self.read_status()?;
+            match status.code {
+                DEFINITION => {
+                    // "word" database "description"
+                    let mut params = split_quoted(&status.text)?.into_iter();
+                    let (content_type, text) = self.read_typed_text()?;
+                    definitions.push(Definition {
+                        headword: params.next().unwrap_or_default(),
+                        database: params.next().unwrap_or_default(),
+                        description: params.next().unwrap_or_default(),
+                        text,
+                        content_type,
+                    });
+                }
+                OK => return Ok(definitions),
+                _ => return Err(status.into_error()),
+            }
+        }
+    }
+
+    // Matches a word with a strategy, "." being the server's default. Lists
+    // the server sends in pages are requested page by page with XNEXT.
+    pub fn match_(&mut self, database: &str, strategy: &str, word: &str) -> io::Result<Vec<Match>> {
+        let mut status =
+            self.command(&format!("MATCH {} {} {}", database, strategy, quote(word)))?;
+        let mut matches = Vec::new();
+        loop {
+            match status.code {
+                MATCHES_FOUND => (),
+                NO_MATCH => return Ok(matches),
+                _ => return Err(status.into_error()),
+            }
+            for line in self.read_text()?.lines() {
+                let mut params = split_quoted(line)?.into_iter();
+                if let (Some(database), Some(headword)) = (params.next(), params.next()) {
+                    matches.push(Match { database, headword });
+                }
+            }
+            let end = self.read_status()?;
+            if end.code != OK {
+                return Err(end.into_error());
+            }
+            // 250 ok - 1234 more matches, XNEXT token
+            match end
+                .text
+                .split_whitespace()
+                .skip_while(|&w| w != "XNEXT")
+                .nth(1)
+            {
+                Some(token) => status = self.command(&format!("XNEXT {}", token))?,
+                None => return Ok(matches),
+            }
+        }
+    }
+
+    pub fn show_databases(&mut self) -> io::Result<Vec<Item>> {
+        let status = self.command("SHOW DB")?;
+        if status.code == NO_DATABASES {
+            return Ok(Vec::new());
+        }
+        item_list(&self.read_text_response(status, DATABASES_PRESENT)?)
+    }
+
+    pub fn show_strategies(&mut self) -> io::Result<Vec<Item>> {
+        let status = self.command("SHOW STRAT")?;
+        if status.code == NO_STRATEGIES {
+            return Ok(Vec::new());
+        }
+        item_list(&self.read_text_response(status, STRATEGIES_AVAILABLE)?)
+    }
+
+    // Information about a database, None if the database is unknown
+    pub fn show_info(&mut self, database: &str) -> io::Result<Option<String>> {
+        let status = self.command(&format!("SHOW INFO {}", quote(database)))?;
+        if status.code == INVALID_DATABASE {
+            return Ok(None);
+        }
+        self.read_text_response(status, DATABASE_INFORMATION)
+            .map(Some)
+    }
+
+    // Information about the server
+    pub fn show_server(&mut self) -> io::Result<String> {
+        let status = self.command("SHOW SERVER")?;
+        self.read_text_response(status, SERVER_INFORMATION)
+    }
+
+    // The commands the server understands
+    pub fn help(&mut self) -> io::Result<String> {
+        let status = self.command("HELP")?;
+        self.read_text_response(status, HELP_TEXT)
+    }
+
+    pub fn quit(&mut self) -> io::Result<()> {
+        self.command("QUIT")?;
+        Ok(())
+    }
+}
+
+// Tries the addresses of the host in turn
+fn connect_timeout(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(ErrorKind::NotFound, "Host has no address");
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+// Parses the lines of SHOW DB and SHOW STRAT, a name and a quoted
+// description each
+fn item_list(text: &str) -> io::Result<Vec<Item>> {
+    let mut list = Vec::new();
+    for line in text.lines() {
+        let mut params = split_quoted(line)?.into_iter();
+        if let (Some(name), Some(description)) = (params.next(), params.next()) {
+            list.push(Item { name, description });
+        }
+    }
+    Ok(list)
+}
+
+// Splits the MIME headers off a definition, up to the first empty line, and
+// returns the declared content type and the text. Unknown types are taken
+// for plain text.
+fn split_mime_headers(text: &str) -> (ContentType, String) {
+    let (headers, body) = match text.find("\n\n") {
+        Some(end) => (&text[..end], &text[end + 2..]),
+        // Only an empty line, no headers
+        None if text.starts_with('\n') => ("", &text[1..]),
+        None => return (ContentType::Plain, text.to_string()),
+    };
+    let content_type = headers
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-type"))
+        .and_then(|(_, value)| ContentType::from_mime(value.split(';').next()?.trim()))
+        .unwrap_or(ContentType::Plain);
+    (content_type, body.to_string())
+}
+
+fn quote(word: &str) -> String {
+    format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Splits a line into words, which may be quoted with single or double quotes
+fn split_quoted(line: &str) -> io::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let quote = match chars.peek() {
+            None => return Ok(words),
+            Some(&c) if c == '"' || c == '\'' => chars.next(),
+            Some(_) => None,
+        };
+        let mut word = String::new();
+        loop {
+            match chars.next() {
+                Some('\\') => word.extend(chars.next()),
+                Some(c) if Some(c) == quote => break,
+                Some(c) if quote.is_none() && c.is_whitespace() => break,
+                Some(c) => word.push(c),
+                None if quote.is_some() => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Unterminated quote: {}", line),
+                    ))
+                }
+                None => break,
+            }
+        }
+        words.push(word);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // A server answering each command line with the next response
+    fn scripted_server(responses: Vec<&'static str>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            writer
+                .write_all(b"220 test <mime.xnext> <1.2@test>\r\n")
+                .unwrap();
+            for response in responses {
+                if lines.next().is_none() {
+                    break;
+                }
+                writer.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn protocol() {
+        let port = scripted_server(vec![
+            "250 ok\r\n",
+            "150 1 definitions retrieved\r\n\
+             151 \"hacker\" jargon \"Jargon File\"\r\n\
+             hacker\r\n..dotted\r\n.\r\n\
+             250 ok\r\n",
+            "552 no match\r\n",
+            "152 1 matches found\r\n\
+             jargon \"hack\"\r\n.\r\n\
+             250 ok - 1 more matches, XNEXT 7\r\n",
+            "152 1 matches found\r\n\
+             jargon \"hacker\"\r\n.\r\n\
+             250 ok\r\n",
+            "110 1 databases present\r\n\
+             jargon \"Jargon File\"\r\n.\r\n\
+             250 ok\r\n",
+            "550 invalid database\r\n",
+            "501 syntax error, illegal parameters\r\n",
+            "221 bye\r\n",
+        ]);
+        let mut client = DictClient::connect("127.0.0.1", port).unwrap();
+        assert_eq!(client.capabilities(), ["mime", "xnext"]);
+        assert_eq!(
+            client.define("*", "hacker").unwrap(),
+            vec![Definition {
+                headword: "hacker".to_string(),
+                database: "jargon".to_string(),
+                description: "Jargon File".to_string(),
+                text: "hacker\n.dotted\n".to_string(),
+                content_type: ContentType::Plain,
+            }]
+        );
+        assert!(client.define("*", "zzqx").unwrap().is_empty());
+        let headwords: Vec<String> = client
+            .match_("*", "prefix", "hack")
+            .unwrap()
+            .into_iter()
+            .map(|m| m.headword)
+            .collect();
+        assert_eq!(headwords, ["hack", "hacker"]);
+        assert_eq!(
+            client.show_databases().unwrap(),
+            vec![Item {
+                name: "jargon".to_string(),
+                description: "Jargon File".to_string(),
+            }]
+        );
+        assert_eq!(client.show_info("nope").unwrap(), None);
+        let e = client.match_("*", "nope", "hack").unwrap_err();
+        let status = e.get_ref().and_then(|e| e.downcast_ref::<Status>());
+        assert_eq!(status.map(|status| status.code), Some(501));
+        client.quit().unwrap();
+    }
+
+    #[test]
+    fn mime_headers() {
+        let port = scripted_server(vec![
+            "250 ok\r\n",
+            "250 ok - mime headers added\r\n",
+            "150 1 definitions retrieved\r\n\
+             151 \"cat\" wiki \"Wiki\"\r\n\
+             Content-type: text/html; charset=utf-8\r\n\
+             Content-transfer-encoding: 8bit\r\n\r\n\
+             <p>A cat</p>\r\n.\r\n\
+             250 ok\r\n",
+            "152 1 matches found\r\n\
+             \r\nwiki \"cat\"\r\n.\r\n\
+             250 ok\r\n",
+        ]);
+        let mut client = DictClient::connect("127.0.0.1", port).unwrap();
+        assert!(client.mime().unwrap());
+        let definitions = client.define("*", "cat").unwrap();
+        assert_eq!(definitions[0].content_type, ContentType::Html);
+        assert_eq!(definitions[0].text, "<p>A cat</p>\n");
+        let matches = client.match_("*", "exact", "cat").unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].headword, "cat");
+    }
+
+    // A trace the test can read back
+    #[derive(Clone, Default)]
+    struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace() {
+        let port = scripted_server(vec!["250 ok\r\n", "552 no match\r\n"]);
+        let shared = Shared::default();
+        let mut client =
+            DictClient::connect_traced("127.0.0.1", port, None, Box::new(shared.clone())).unwrap();
+        assert!(client.define("*", "zork").unwrap().is_empty());
+        let trace = String::from_utf8(shared.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = trace
+            .lines()
+            .map(|line| line.trim_start().split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "<- 220 test <mime.xnext> <1.2@test>",
+                &format!(
+                    "-> CLIENT {} {}",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                ),
+                "<- 250 ok",
+                "-> DEFINE * \"zork\"",
+                "<- 552 no match",
+            ]
+        );
+    }
+
+    #[test]
+    fn timeout() {
+        // Answers CLIENT, then stops answering
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"220 test <> <1.2@test>\r\n").unwrap();
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            stream.write_all(b"250 ok\r\n").unwrap();
+            thread::sleep(Duration::from_secs(2));
+        });
+        let timeout = Duration::from_millis(100);
+        let mut client = DictClient::connect_with_timeout("127.0.0.1", port, timeout).unwrap();
+        let e = client.define("*", "hacker").unwrap_err();
+        assert!(matches!(
+            e.kind(),
+            ErrorKind::WouldBlock | ErrorKind::TimedOut
+        ));
+    }
+
+    #[test]
+    fn quoted_words() {
+        assert_eq!(
+            split_quoted(r#"jargon "a \"b\" c" 'd'"#).unwrap(),
+            ["jargon", "a \"b\" c", "d"]
+        );
+        assert!(split_quoted("\"open").is_err());
+        assert_eq!(quote(r#"say "hi""#), r#""say \"hi\"""#);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::errors::DictError;
+use icu_collator::{Collator, CollatorOptions};
+use icu_locid::Locale;
+use std::cmp::Ordering;
+
+// Locale specific ordering of headwords, using the ICU collation rules of
+// the locale (e.g. "sv" sorts å, ä, ö after z, "de" sorts ä with a). An index
+// with a collation is sorted and binary searched with it instead of by bytes.
+pub struct Collation {
+    locale: String,
+    collator: Collator,
+}
+
+impl Collation {
+    pub fn new(locale: &str) -> Result<Collation, DictError> {
+        let parsed: Locale = match locale.parse() {
+            Ok(parsed) => parsed,
+            Err(_) => return Err(DictError::UnknownLocale(locale.to_string())),
+        };
+        let collator = match Collator::try_new(&(&parsed).into(), CollatorOptions::new()) {
+            Ok(collator) => collator,
+            Err(_) => return Err(DictError::UnknownLocale(locale.to_string())),
+        };
+        Ok(Collation {
+            locale: locale.to_string(),
+            collator,
+        })
+    }
+
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    pub fn compare(&self, left: &str, right: &str) -> Ordering {
+        self.collator.compare(left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_order() {
+        let sv = Collation::new("sv").unwrap();
+        assert_eq!(sv.compare("zebra", "ål"), Ordering::Less);
+        assert_eq!(sv.compare("ål", "ärm"), Ordering::Less);
+        let de = Collation::new("de").unwrap();
+        assert_eq!(de.compare("ärger", "zebra"), Ordering::Less);
+        assert!(Collation::new("not a locale").is_err());
+    }
+}
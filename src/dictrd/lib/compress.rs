@@ -0,0 +1,104 @@
+use crate::errors::DictError;
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+use flate2::write::{DeflateEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::{Read, Write};
+
+// Maximum length of a line in a compressed text block
+const LINE_LENGTH: usize = 76;
+
+// How the text blocks of a connection are compressed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockCompression {
+    // Raw deflate data, OPTION XCOMPRESS deflate
+    Deflate,
+    // A zlib stream, the deflate data with a header and an Adler-32
+    // checksum, OPTION DEFLATE
+    Zlib,
+}
+
+impl BlockCompression {
+    pub fn compress(self, text: &str) -> Result<String, DictError> {
+        match self {
+            BlockCompression::Deflate => deflate_block(text),
+            BlockCompression::Zlib => zlib_block(text),
+        }
+    }
+}
+
+// Compresses a text block for the XCOMPRESS extension. The deflated data is
+// base64 encoded and split into lines, so the result can be sent like any
+// other text block and still be terminated by a single "." line.
+pub fn deflate_block(text: &str) -> Result<String, DictError> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    Ok(encoded_lines(&encoder.finish()?))
+}
+
+// Compresses a text block for the DEFLATE extension, like deflate_block but
+// in a zlib stream
+pub fn zlib_block(text: &str) -> Result<String, DictError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    Ok(encoded_lines(&encoder.finish()?))
+}
+
+fn encoded_lines(data: &[u8]) -> String {
+    let encoded = base64::encode(data);
+    let mut result = String::with_capacity(encoded.len() + encoded.len() / LINE_LENGTH + 1);
+    for line in encoded.as_bytes().chunks(LINE_LENGTH) {
+        result.push_str(std::str::from_utf8(line).unwrap());
+        result.push('\n');
+    }
+    result
+}
+
+// Reverses deflate_block. Takes the lines of the text block without the
+// terminating "." line.
+pub fn inflate_block(block: &str) -> Result<String, DictError> {
+    let encoded: String = block.split_whitespace().collect();
+    let data = base64::decode(encoded).map_err(|_| DictError::InvalidBase64)?;
+    let mut decoder = DeflateDecoder::new(&data[..]);
+    let mut text = String::new();
+    decoder.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+// Reverses zlib_block, failing on a wrong checksum
+pub fn inflate_zlib_block(block: &str) -> Result<String, DictError> {
+    let encoded: String = block.split_whitespace().collect();
+    let data = base64::decode(encoded).map_err(|_| DictError::InvalidBase64)?;
+    let mut decoder = ZlibDecoder::new(&data[..]);
+    let mut text = String::new();
+    decoder.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deflate_roundtrip() {
+        let text = "ack\n  /ak/ /interj./\n.leading dot\n".repeat(50);
+        let block = deflate_block(&text).unwrap();
+        assert!(block.lines().all(|l| l.len() <= LINE_LENGTH && l != "."));
+        assert!(block.len() < text.len());
+        assert_eq!(inflate_block(&block).unwrap(), text);
+        assert!(matches!(
+            inflate_block("not base64!"),
+            Err(DictError::InvalidBase64)
+        ));
+    }
+
+    #[test]
+    fn zlib_roundtrip() {
+        let text = "ack\n  /ak/ /interj./\n".repeat(50);
+        let block = zlib_block(&text).unwrap();
+        let data = base64::decode(block.split_whitespace().collect::<String>()).unwrap();
+        // The zlib header of the default compression level
+        assert_eq!(data[..2], [0x78, 0x9c]);
+        assert_eq!(inflate_zlib_block(&block).unwrap(), text);
+        assert!(inflate_block(&block).is_err());
+    }
+}
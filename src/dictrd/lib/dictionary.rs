@@ -0,0 +1,377 @@
+// A database opened for lookups: its index together with its dict file.
+// This is the API for programs that look up words without running a server:
+//
+//   let mut jargon = Dictionary::open("jargon.index", "jargon.dict")?;
+//   for definition in jargon.define("hacker")? {
+//       println!("{}", definition.text);
+//   }
+//   let words = jargon.match_(&strategy::Prefix, "hack")?;
+//   for definition in jargon.iter() {
+//       let definition = definition?;
+//   }
+//
+// Without files, e.g. in WebAssembly, a database is opened from the bytes of
+// its files with Dictionary::from_bytes.
+use crate::errors::DictError;
+use crate::filter::DefinitionFilter;
+use crate::metadata::DatabaseMetadata;
+use crate::mime::ContentType;
+use crate::normalize::Normalization;
+use crate::strategy::{Indexes, MatchStrategy};
+use crate::template::{self, Template};
+use crate::{DictReader, IndexEntry, IndexReader, ReadAt};
+use rand::seq::SliceRandom;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::BufReader;
+use std::io::Cursor;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+pub struct Dictionary<R: ReadAt> {
+    pub index: IndexReader,
+    pub dict: DictReader<R>,
+    metadata: DatabaseMetadata,
+    // How words are looked up, see IndexReader::normalization
+    normalization: Normalization,
+    // Applied to the articles looked up, see set_filters and set_template
+    filters: Vec<Arc<dyn DefinitionFilter>>,
+    template: Option<Template>,
+}
+
+// An article with the headword it was found under
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+    pub headword: String,
+    pub text: String,
+}
+
+// The articles of a dictionary with their headwords, see Dictionary::iter
+pub struct Definitions<'a, R: ReadAt> {
+    entries: std::slice::Iter<'a, IndexEntry>,
+    dict: &'a DictReader<R>,
+}
+
+impl<R: ReadAt> Iterator for Definitions<'_, R> {
+    type Item = Result<Definition, DictError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.next()?;
+        Some(
+            self.dict
+                .find(entry.offset, entry.length)
+                .map(|text| Definition {
+                    headword: entry.headword().to_string(),
+                    text,
+                }),
+        )
+    }
+}
+
+pub struct LookupResult {
+    pub word: String,
+    // The entry found and its definition, NoMatch if the word is not in the
+    // index
+    pub definition: Result<(IndexEntry, String), DictError>,
+}
+
+#[cfg(feature = "fs")]
+impl Dictionary<File> {
+    // Opens the .index and .dict (or .dict.dz) file of a database. The
+    // definitions are decoded as its 00-database-* entries declare.
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(
+        index_path: P,
+        dict_path: Q,
+    ) -> Result<Dictionary<File>, DictError> {
+        let mut index = IndexReader::new();
+        index.try_parse_dict_index(BufReader::new(File::open(index_path)?))?;
+        let dict = DictReader::new(BufReader::new(File::open(dict_path)?))?;
+        let mut dictionary = Dictionary::new(index, dict);
+        dictionary.dict.set_encoding(dictionary.metadata.encoding());
+        Ok(dictionary)
+    }
+}
+
+impl<T: AsRef<[u8]>> Dictionary<Cursor<T>> {
+    // Opens a database from the contents of its .index and .dict (or
+    // .dict.dz) file
+    pub fn from_bytes(index: &[u8], dict: T) -> Result<Dictionary<Cursor<T>>, DictError> {
+        let mut reader = IndexReader::new();
+        reader.try_parse_dict_index(index)?;
+        let dict = DictReader::from_bytes(dict)?;
+        let mut dictionary = Dictionary::new(reader, dict);
+        dictionary.dict.set_encoding(dictionary.metadata.encoding());
+        Ok(dictionary)
+    }
+}
+
+impl<R: ReadAt> Dictionary<R> {
+    // Prepares an index the way the server does: headwords are normalized
+    // and the 00-database-* entries are moved into the metadata
+    pub fn new(mut index: IndexReader, dict: DictReader<R>) -> Dictionary<R> {
+        let normalization = index.normalization();
+        index.normalize_headwords(normalization);
+        let metadata = DatabaseMetadata::load(&index, &dict);
+        index.remove_metadata_entries();
+        Dictionary {
+            index,
+            dict,
+            metadata,
+            normalization,
+            filters: Vec::new(),
+            template: None,
+        }
+    }
+
+    // Sets the filters applied to the articles of define, define_many and
+    // random, like the filters of a database of the server
+    pub fn set_filters(&mut self, filters: Vec<Arc<dyn DefinitionFilter>>) {
+        self.filters = filters;
+    }
+
+    // Sets the template the articles of define, define_many and random are
+    // laid out with
+    pub fn set_template(&mut self, template: Option<Template>) {
+        self.template = template;
+    }
+
+    // Prepares an article with the filters and the template
+    fn render(&self, headword: &str, text: String) -> String {
+        if self.filters.is_empty() && self.template.is_none() {
+            return text;
+        }
+        let (_, text) = template::render(
+            &self.filters,
+            self.template.as_ref(),
+            ContentType::Plain,
+            headword,
+            &text,
+            "",
+            &self.metadata.short,
+        );
+        text
+    }
+
+    pub fn metadata(&self) -> &DatabaseMetadata {
+        &self.metadata
+    }
+
+    // Returns the articles of a word, none if it is not in the index. Like
+    // DEFINE, aliases and transliterations are tried when the word is not
+    // found.
+    pub fn define(&self, word: &str) -> Result<Vec<Definition>, DictError> {
+        let word = self.normalization.apply(word);
+        let mut entries: Vec<IndexEntry> = self
+            .index
+            .range(word.as_str()..=word.as_str())
+            .cloned()
+            .collect();
+        if entries.is_empty() {
+            match self.index.find_entry(&word) {
+                Ok(entry) => entries.push(entry),
+                Err(DictError::NoMatch(_)) => return Ok(Vec::new()),
+                Err(e) => return Err(e),
+            }
+        }
+        let ranges: Vec<(u64, u64)> = entries.iter().map(|e| (e.offset, e.length)).collect();
+        entries
+            .iter()
+            .zip(self.dict.find_many(&ranges))
+            .map(|(entry, text)| {
+                Ok(Definition {
+                    headword: entry.headword().to_string(),
+                    text: self.render(entry.headword(), text?),
+                })
+            })
+            .collect()
+    }
+
+    // Returns the headwords matching a word with a strategy, e.g.
+    // strategy::Prefix, best matches first and each headword once
+    pub fn match_(
+        &mut self,
+        strategy: &dyn MatchStrategy,
+        word: &str,
+    ) -> Result<Vec<String>, DictError> {
+        let word = self.normalization.apply(word);
+        // Strategies search indexes the server shares between connections,
+        // so the index is lent to them behind a lock
+        let index = RwLock::new(std::mem::take(&mut self.index));
+        let aux = HashMap::new();
+        let result = strategy.search(
+            &Indexes {
+                index: &index,
+                aux: &aux,
+            },
+            &word,
+        );
+        self.index = index.into_inner().unwrap();
+        let mut headwords: Vec<String> = Vec::new();
+        for entry in result? {
+            if !headwords
+                .iter()
+                .any(|headword| headword == entry.headword())
+            {
+                headwords.push(entry.headword().to_string());
+            }
+        }
+        Ok(headwords)
+    }
+
+    // Returns every headword with its article as in the dict file, in the
+    // order of the index, for exporting the database. An article shared by
+    // several headwords is returned with each of them.
+    // Articles that cannot be read are errors, after which the iteration
+    // goes on.
+    pub fn iter(&self) -> Definitions<'_, R> {
+        Definitions {
+            entries: self.index.iter(),
+            dict: &self.dict,
+        }
+    }
+
+    // Returns the article of a random headword
+    pub fn random(&self) -> Result<Definition, DictError> {
+        let entry = match self.index.entries().choose(&mut rand::thread_rng()) {
+            Some(entry) => entry.clone(),
+            None => return Err(DictError::NoMatch(String::new())),
+        };
+        Ok(Definition {
+            headword: entry.headword().to_string(),
+            text: self.render(
+                entry.headword(),
+                self.dict.find(entry.offset, entry.length)?,
+            ),
+        })
+    }
+
+    // Looks up many words at once and returns their results in the order of
+    // the words. The words are sorted and looked up in a single pass over the
+    // index, and the articles are read in the order of the dict file, grouping
+    // neighbouring articles into one read. Words not found are looked up with
+    // the same fallbacks as IndexReader::find_entry (aliases,
+    // transliteration).
+    pub fn define_many(&self, words: &[&str]) -> Vec<LookupResult> {
+        let normalized: Vec<String> = words
+            .iter()
+            .map(|word| self.normalization.apply(word))
+            .collect();
+        let index = &self.index;
+        let mut order: Vec<usize> = (0..words.len()).collect();
+        order.sort_by(|&a, &b| index.compare_headwords(&normalized[a], &normalized[b]));
+
+        let mut entries: Vec<Option<IndexEntry>> = words.iter().map(|_| None).collect();
+        let mut rest = index.entries();
+        for pos in order {
+            let word = normalized[pos].as_str();
+            let start =
+                rest.partition_point(|e| index.compare_headwords(&e.word, word) == Ordering::Less);
+            rest = &rest[start..];
+            if let Some(entry) = rest.first() {
+                if index.compare_headwords(&entry.word, word) == Ordering::Equal {
+                    entries[pos] = Some(entry.clone());
+                }
+            }
+        }
+        for (pos, entry) in entries.iter_mut().enumerate() {
+            if entry.is_none() {
+                *entry = self.index.find_entry(&normalized[pos]).ok();
+            }
+        }
+
+        let ranges: Vec<(u64, u64)> = entries
+            .iter()
+            .flatten()
+            .map(|entry| (entry.offset, entry.length))
+            .collect();
+        let mut articles = self.dict.find_many(&ranges).into_iter();
+        words
+            .iter()
+            .zip(entries)
+            .map(|(word, entry)| LookupResult {
+                word: word.to_string(),
+                definition: match entry {
+                    Some(entry) => articles.next().unwrap().map(|article| {
+                        let article = self.render(entry.headword(), article);
+                        (entry, article)
+                    }),
+                    None => Err(DictError::NoMatch(word.to_string())),
+                },
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "fs")]
+    use crate::strategy;
+    #[cfg(feature = "fs")]
+    use std::path::PathBuf;
+
+    #[test]
+    fn batch_lookup() {
+        let dict = b"apple\nbanana\ncherry\n".to_vec();
+        let index = b"apple\tA\tG\nbanana\tG\tH\ncherry\tN\tH\n";
+        let dictionary = Dictionary::from_bytes(index, dict).unwrap();
+        let results = dictionary.define_many(&["cherry", "durian", "apple", "cherry"]);
+        let definitions: Vec<Option<&str>> = results
+            .iter()
+            .map(|result| {
+                result
+                    .definition
+                    .as_ref()
+                    .ok()
+                    .map(|(_, text)| text.as_str())
+            })
+            .collect();
+        assert_eq!(
+            definitions,
+            vec![Some("cherry\n"), None, Some("apple\n"), Some("cherry\n")]
+        );
+        assert_eq!(results[1].word, "durian");
+        let headwords: Vec<String> = dictionary
+            .iter()
+            .map(|definition| definition.unwrap().headword)
+            .collect();
+        assert_eq!(headwords, ["apple", "banana", "cherry"]);
+        assert_eq!(
+            dictionary.define("Banana").unwrap(),
+            vec![Definition {
+                headword: "banana".to_string(),
+                text: "banana\n".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn rendered_articles() {
+        let mut dictionary = Dictionary::from_bytes(b"cat\tA\tM\n", b"cat\nA  pet.\n").unwrap();
+        dictionary.set_filters(vec![Arc::new(crate::filter::CollapseWhitespace)]);
+        dictionary.set_template(Some(Template::new("{headword} - {body}").unwrap()));
+        assert_eq!(dictionary.define("cat").unwrap()[0].text, "cat - A pet.\n");
+        assert_eq!(dictionary.random().unwrap().text, "cat - A pet.\n");
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn open_database() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("dicts");
+        let mut jargon =
+            Dictionary::open(dir.join("jargon.index"), dir.join("jargon.dict")).unwrap();
+        assert!(jargon.metadata().short.contains("Jargon"));
+        let definitions = jargon.define("Hacker").unwrap();
+        assert_eq!(definitions.len(), 1);
+        assert!(definitions[0].text.contains("hacker"));
+        assert!(jargon.define("no such word").unwrap().is_empty());
+        let words = jargon.match_(&strategy::Prefix, "hacker").unwrap();
+        assert!(words.len() > 1);
+        assert!(words.iter().all(|word| word.starts_with("hacker")));
+        assert!(!jargon.random().unwrap().text.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+// Random access to dictzip files (.dict.dz). A dictzip file is a gzip file
+// whose data is compressed in chunks of a fixed size, each flushed so that it
+// can be inflated on its own. The "RA" field in the extra field of the gzip
+// header lists the compressed sizes of the chunks, so an article is read by
+// inflating only the chunks it spans.
+use crate::errors::DictError;
+use crate::ReadAt;
+use flate2::read::MultiGzDecoder;
+use flate2::{Compress, Compression, Crc, Decompress, FlushCompress, FlushDecompress};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const FHCRC: u8 = 2;
+const FEXTRA: u8 = 4;
+const FNAME: u8 = 8;
+const FCOMMENT: u8 = 16;
+
+// Error for gzip files that are not dictzip files
+pub const NO_CHUNK_TABLE: &str = "gzip file without dictzip chunk table";
+
+// Uncompressed size of the chunks dictzip writes, whose compressed size
+// always fits the 16 bits of the chunk table
+pub const CHUNK_LEN: usize = 58315;
+
+#[derive(Debug)]
+pub struct Dictzip {
+    // Uncompressed size of a chunk, all but the last chunk have this size
+    chunk_len: u64,
+    // Position of each chunk in the file, followed by the end of the last
+    offsets: Vec<u64>,
+    // Uncompressed size of the data
+    len: u64,
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, DictError> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+// Skips a zero terminated string
+fn skip_string<R: Read>(reader: &mut R) -> Result<(), DictError> {
+    let mut byte = [0; 1];
+    loop {
+        reader.read_exact(&mut byte)?;
+        if byte[0] == 0 {
+            return Ok(());
+        }
+    }
+}
+
+impl Dictzip {
+    // Reads the chunk table from the gzip header. Returns None if the file is
+    // not gzip compressed, and fails for gzip files without chunk table.
+    pub fn open<R: Read + Seek + ReadAt>(reader: &mut R) -> Result<Option<Dictzip>, DictError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut header = [0; 10];
+        let mut read = 0;
+        while read < header.len() {
+            match reader.read(&mut header[read..])? {
+                0 => return Ok(None),
+                n => read += n,
+            }
+        }
+        if header[..2] != GZIP_MAGIC {
+            return Ok(None);
+        }
+        let flags = header[3];
+        if header[2] != 8 || flags & FEXTRA == 0 {
+            return Err(DictError::SyntaxError(NO_CHUNK_TABLE));
+        }
+        let mut extra = vec![0; read_u16(reader)? as usize];
+        reader.read_exact(&mut extra)?;
+        let mut sizes = None;
+        let mut chunk_len = 0;
+        let mut fields = &extra[..];
+        while fields.len() >= 4 {
+            let len = u16::from_le_bytes([fields[2], fields[3]]) as usize;
+            let data = fields
+                .get(4..4 + len)
+                .ok_or(DictError::SyntaxError("truncated gzip extra field"))?;
+            // Version 1: chunk length, chunk count, compressed chunk sizes
+            if fields[..2] == *b"RA" && data.len() >= 6 {
+                let words: Vec<u64> = data
+                    .chunks_exact(2)
+                    .map(|word| u64::from(u16::from_le_bytes([word[0], word[1]])))
+                    .collect();
+                chunk_len = words[1];
+                let count = words[2] as usize;
+                if words[0] != 1 {
+                    return Err(DictError::SyntaxError("unsupported dictzip chunk table"));
+                }
+                if chunk_len == 0 {
+                    return Err(DictError::SyntaxError("dictzip chunk length 0"));
+                }
+                if data.len() != 6 + 2 * count {
+                    return Err(DictError::SyntaxError(
+                        "dictzip chunk count does not match its table",
+                    ));
+                }
+                sizes = Some(words[3..3 + count].to_vec());
+            }
+            fields = &fields[4 + len..];
+        }
+        let sizes = sizes.ok_or(DictError::SyntaxError(NO_CHUNK_TABLE))?;
+        if flags & FNAME != 0 {
+            skip_string(reader)?;
+        }
+        if flags & FCOMMENT != 0 {
+            skip_string(reader)?;
+        }
+        if flags & FHCRC != 0 {
+            read_u16(reader)?;
+        }
+        let mut offsets = vec![reader.stream_position()?];
+        for size in sizes {
+            offsets.push(offsets[offsets.len() - 1] + size);
+        }
+        let mut dictzip = Dictzip {
+            chunk_len,
+            offsets,
+            len: 0,
+        };
+        // Only the last chunk may be shorter
+        let chunks = dictzip.offsets.len() as u64 - 1;
+        if chunks > 0 {
+            let last = dictzip.inflate_chunk(&*reader, chunks as usize - 1)?;
+            dictzip.len = (chunks - 1) * chunk_len + last.len() as u64;
+        }
+        Ok(Some(dictzip))
+    }
+
+    // Inflates all of a gzip file, for gzip files without chunk table, which
+    // cannot be read at random
+    pub fn inflate_all<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, DictError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut data = Vec::new();
+        MultiGzDecoder::new(reader).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    // Uncompressed size of the data
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn inflate_chunk<R: ReadAt>(&self, reader: &R, chunk: usize) -> Result<Vec<u8>, DictError> {
+        let start = self.offsets[chunk];
+        let mut compressed = vec![0; (self.offsets[chunk + 1] - start) as usize];
+        reader.read_exact_at(&mut compressed, start)?;
+        let mut data = Vec::with_capacity(self.chunk_len as usize);
+        Decompress::new(false)
+            .decompress_vec(&compressed, &mut data, FlushDecompress::Sync)
+            .map_err(|_| DictError::SyntaxError("corrupt dictzip chunk"))?;
+        Ok(data)
+    }
+
+    // Reads len bytes of the uncompressed data starting at offset
+    pub fn read<R: ReadAt>(&self, reader: &R, offset: u64, len: u64) -> Result<Vec<u8>, DictError> {
+        let mut result = Vec::with_capacity(len as usize);
+        if len == 0 {
+            return Ok(result);
+        }
+        let first = (offset / self.chunk_len) as usize;
+        let last = ((offset + len - 1) / self.chunk_len) as usize;
+        for chunk in first..=last {
+            let data = self.inflate_chunk(reader, chunk)?;
+            let chunk_start = chunk as u64 * self.chunk_len;
+            let from = offset.saturating_sub(chunk_start) as usize;
+            let to = ((offset + len - chunk_start) as usize).min(data.len());
+            if from > to {
+                break;
+            }
+            result.extend_from_slice(&data[from..to]);
+        }
+        Ok(result)
+    }
+}
+
+// Compresses data like dictzip, in chunks of chunk_len bytes (CHUNK_LEN
+// for dictzip's own). Fails for data with more chunks than the gzip header
+// has room for, about 1.9 GB in chunks of CHUNK_LEN.
+pub fn compress<W: Write>(data: &[u8], chunk_len: usize, mut out: W) -> io::Result<()> {
+    let mut compress = Compress::new(Compression::best(), false);
+    let mut chunks = Vec::new();
+    let count = data.chunks(chunk_len).count();
+    for (i, chunk) in data.chunks(chunk_len).enumerate() {
+        // Room for incompressible chunks, which deflate stores in blocks
+        let mut compressed = Vec::with_capacity(chunk.len() + chunk.len() / 8 + 64);
+        let flush = if i + 1 == count {
+            FlushCompress::Finish
+        } else {
+            FlushCompress::Full
+        };
+        let before = compress.total_in();
+        compress
+            .compress_vec(chunk, &mut compressed, flush)
+            .map_err(io::Error::other)?;
+        if compress.total_in() - before != chunk.len() as u64 || compressed.len() > 0xffff {
+            return Err(io::Error::other("dictzip chunk too large"));
+        }
+        chunks.push(compressed);
+    }
+    // Version 1, chunk length, chunk count and the compressed chunk sizes
+    let mut ra = Vec::new();
+    for word in [1, chunk_len, chunks.len()].iter() {
+        ra.extend_from_slice(&(*word as u16).to_le_bytes());
+    }
+    for chunk in chunks.iter() {
+        ra.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+    }
+    if chunk_len > 0xffff || ra.len() + 4 > 0xffff {
+        return Err(io::Error::other("too much data for a dictzip file"));
+    }
+    out.write_all(&[0x1f, 0x8b, 8, FEXTRA, 0, 0, 0, 0, 2, 3])?;
+    out.write_all(&(ra.len() as u16 + 4).to_le_bytes())?;
+    out.write_all(b"RA")?;
+    out.write_all(&(ra.len() as u16).to_le_bytes())?;
+    out.write_all(&ra)?;
+    for chunk in chunks {
+        out.write_all(&chunk)?;
+    }
+    let mut crc = Crc::new();
+    crc.update(data);
+    out.write_all(&crc.sum().to_le_bytes())?;
+    out.write_all(&(data.len() as u32).to_le_bytes())?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn dictzip(data: &[u8], chunk_len: usize) -> Vec<u8> {
+        let mut file = Vec::new();
+        compress(data, chunk_len, &mut file).unwrap();
+        file
+    }
+
+    #[test]
+    fn random_access() {
+        let data: Vec<u8> = (0..1000)
+            .map(|i| b"abcdefghijklmnopqrstuvwxyz\n"[i % 27])
+            .collect();
+        let mut file = Cursor::new(dictzip(&data, 64));
+        let dictzip = Dictzip::open(&mut file).unwrap().unwrap();
+        assert_eq!(dictzip.len(), 1000);
+        assert_eq!(dictzip.read(&file, 0, 10).unwrap(), &data[..10]);
+        // Spanning several chunks
+        assert_eq!(dictzip.read(&file, 60, 200).unwrap(), &data[60..260]);
+        assert_eq!(dictzip.read(&file, 990, 10).unwrap(), &data[990..]);
+        // A valid gzip file as well
+        assert_eq!(Dictzip::inflate_all(&mut file).unwrap(), data);
+    }
+
+    #[test]
+    fn plain_files() {
+        assert!(Dictzip::open(&mut Cursor::new(b"plain text\n".to_vec()))
+            .unwrap()
+            .is_none());
+        assert!(Dictzip::open(&mut Cursor::new(Vec::new()))
+            .unwrap()
+            .is_none());
+        let gzip = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];
+        assert!(Dictzip::open(&mut Cursor::new(gzip)).is_err());
+    }
+
+    #[test]
+    fn corrupt_chunk_tables() {
+        let file = dictzip(&[b'a'; 200], 64);
+        // The chunk length and count follow the version in the RA field
+        let corrupt = |at: usize, word: u16| {
+            let mut file = file.clone();
+            file[at..at + 2].copy_from_slice(&word.to_le_bytes());
+            Dictzip::open(&mut Cursor::new(file))
+        };
+        assert!(corrupt(18, 64).is_ok());
+        assert!(matches!(corrupt(18, 0), Err(DictError::SyntaxError(_))));
+        assert!(matches!(corrupt(20, 3), Err(DictError::SyntaxError(_))));
+        assert!(matches!(corrupt(20, 5), Err(DictError::SyntaxError(_))));
+    }
+}
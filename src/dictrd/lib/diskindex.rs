@@ -0,0 +1,194 @@
+// Index searched in its file instead of memory, for databases whose index
+// is too large to load. A lookup binary searches the file: it reads the
+// first line after the middle of the range, compares its headword and halves
+// the range, so a lookup takes a few dozen small reads.
+//
+// The file has to be sorted in byte order of the normalized headwords, as
+// dictfmt sorts it. Only exact and prefix lookups are possible this way.
+use crate::errors::DictError;
+use crate::normalize::Normalization;
+use crate::{IndexEntry, ReadAt};
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+// Bytes read at a time while looking for the end of a line
+const CHUNK: u64 = 256;
+
+pub struct DiskIndex {
+    file: Box<dyn ReadAt + Send + Sync>,
+    len: u64,
+    normalization: Normalization,
+}
+
+impl DiskIndex {
+    pub fn new<R: ReadAt + Send + Sync + 'static>(file: R, len: u64) -> DiskIndex {
+        DiskIndex {
+            file: Box::new(file),
+            len,
+            normalization: Normalization::Default,
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn open(path: &Path) -> Result<DiskIndex, DictError> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(DiskIndex::new(file, len))
+    }
+
+    // Size of the file in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Sets how the headwords and queries are compared
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+    }
+
+    // Entries whose normalized headword equals the normalized word
+    pub fn find(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        let key = self.normalization.apply(word);
+        self.scan(&key, |headword| headword == key)
+    }
+
+    // Entries whose normalized headword starts with the normalized prefix
+    pub fn find_prefix(&self, prefix: &str) -> Result<Vec<IndexEntry>, DictError> {
+        let key = self.normalization.apply(prefix);
+        self.scan(&key, |headword| headword.starts_with(&key))
+    }
+
+    // Collects the entries from the first one not sorting before key, for
+    // as long as their headwords are accepted. The entries carry the
+    // normalized headword, and the headword of the file as original.
+    fn scan<F: Fn(&str) -> bool>(
+        &self,
+        key: &str,
+        accept: F,
+    ) -> Result<Vec<IndexEntry>, DictError> {
+        let mut entries = Vec::new();
+        let mut pos = self.lower_bound(key)?;
+        while pos < self.len {
+            let (line, next) = self.read_line(pos)?;
+            let mut entry = IndexEntry::from_line(&line)?;
+            let normalized = self.normalization.apply(&entry.word);
+            if !accept(&normalized) {
+                break;
+            }
+            if normalized != entry.word {
+                let original = std::mem::replace(&mut entry.word, normalized);
+                entry.original.get_or_insert(original);
+            }
+            entries.push(entry);
+            pos = next;
+        }
+        Ok(entries)
+    }
+
+    // Start of the first line whose headword does not sort before key. All
+    // lines before lo sort before it, the line at hi does not.
+    fn lower_bound(&self, key: &str) -> Result<u64, DictError> {
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let start = self.line_start(lo + (hi - lo) / 2)?;
+            if start >= hi {
+                break;
+            }
+            let (line, next) = self.read_line(start)?;
+            if self.key(&line).as_str() < key {
+                lo = next;
+            } else {
+                hi = start;
+            }
+        }
+        // No line starts in the upper half of what is left, a line or two
+        while lo < hi {
+            let (line, next) = self.read_line(lo)?;
+            if self.key(&line).as_str() >= key {
+                return Ok(lo);
+            }
+            lo = next;
+        }
+        Ok(hi)
+    }
+
+    fn key(&self, line: &str) -> String {
+        self.normalization
+            .apply(line.split('\t').next().unwrap_or(""))
+    }
+
+    // Start of the first line at or after pos
+    fn line_start(&self, pos: u64) -> Result<u64, DictError> {
+        if pos == 0 {
+            return Ok(0);
+        }
+        Ok(self.line_end(pos - 1)?.map_or(self.len, |end| end + 1))
+    }
+
+    // Position of the first line break at or after pos
+    fn line_end(&self, mut pos: u64) -> Result<Option<u64>, DictError> {
+        let mut buf = [0; CHUNK as usize];
+        while pos < self.len {
+            let n = CHUNK.min(self.len - pos) as usize;
+            self.file.read_exact_at(&mut buf[..n], pos)?;
+            if let Some(i) = buf[..n].iter().position(|&b| b == b'\n') {
+                return Ok(Some(pos + i as u64));
+            }
+            pos += n as u64;
+        }
+        Ok(None)
+    }
+
+    // The line starting at start, and the start of the next line
+    fn read_line(&self, start: u64) -> Result<(String, u64), DictError> {
+        let end = self.line_end(start)?.unwrap_or(self.len);
+        let mut buf = vec![0; (end - start) as usize];
+        self.file.read_exact_at(&mut buf, start)?;
+        let line = String::from_utf8_lossy(&buf);
+        Ok((line.trim_end_matches('\r').to_string(), end + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn binary_search() {
+        let mut text = String::from("00databaseshort\tA\tB\n");
+        for word in ["a", "aa", "ab", "abc", "b", "hack", "hack attack", "hacker"].iter() {
+            text.push_str(&format!("{}\tB\tC\n", word));
+        }
+        text.push_str("Hacker\tC\tD\nhackers\tD\tE\nzork\tE\tF");
+        let len = text.len() as u64;
+        let index = DiskIndex::new(Cursor::new(text.into_bytes()), len);
+        let words = |entries: Vec<IndexEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.word).collect()
+        };
+        assert_eq!(words(index.find("a").unwrap()), vec!["a"]);
+        assert_eq!(words(index.find("zork").unwrap()), vec!["zork"]);
+        assert_eq!(
+            words(index.find("00-database-short").unwrap()),
+            vec!["00databaseshort"]
+        );
+        assert!(index.find("hackish").unwrap().is_empty());
+        assert!(index.find("zzz").unwrap().is_empty());
+
+        let hackers = index.find("HACKER").unwrap();
+        assert_eq!(words(hackers.clone()), vec!["hacker", "hacker"]);
+        assert_eq!(hackers[1].headword(), "Hacker");
+        assert_eq!(hackers[1].offset, 2);
+        assert_eq!(
+            words(index.find_prefix("hack").unwrap()),
+            vec!["hack", "hack attack", "hacker", "hacker", "hackers"]
+        );
+        assert_eq!(words(index.find_prefix("ab").unwrap()), vec!["ab", "abc"]);
+    }
+}
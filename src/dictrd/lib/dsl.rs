@@ -0,0 +1,290 @@
+// Reads dictionaries in the DSL format of ABBYY Lingvo. A card is one or
+// more headword lines followed by the lines of its body, which start with
+// whitespace. Header lines like #NAME "..." precede the first card, {{...}}
+// are comments. The files are UTF-16 or UTF-8 text.
+//
+// Headwords may have optional parts in parentheses, "colo(u)r" stands for
+// color and colour, and parts in braces that are shown but not indexed. In
+// the body ~ stands for the headword, [m1] to [m9] give the indentation of a
+// line and [ref]word[/ref] or <<word>> refer to another card. The other tags
+// only format the text and are dropped unless the markup is kept.
+use crate::errors::DictError;
+use crate::metadata::DatabaseMetadata;
+use crate::writer::Article;
+use std::io::Read;
+
+pub struct Dsl {
+    pub metadata: DatabaseMetadata,
+    pub articles: Vec<Article>,
+}
+
+pub fn read_dsl<R: Read>(mut input: R, keep_markup: bool) -> Result<Dsl, DictError> {
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let text = decode(bytes)?;
+    let text = strip_comments(&text);
+
+    let mut metadata = DatabaseMetadata {
+        utf8: true,
+        ..DatabaseMetadata::default()
+    };
+    let (mut from, mut to) = (None, None);
+    let mut articles = Vec::new();
+    let mut headwords: Vec<String> = Vec::new();
+    let mut body = String::new();
+    let mut in_body = false;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            in_body = true;
+            let line = line.trim();
+            if keep_markup {
+                body.push_str(line);
+            } else {
+                let (level, line) = indentation(line);
+                body.push_str(&"  ".repeat(level.saturating_sub(1)));
+                body.push_str(&to_text(line, headwords.first().map_or("", |h| h)));
+            }
+            body.push('\n');
+            continue;
+        }
+        if headwords.is_empty() && line.starts_with('#') {
+            let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim().trim_matches('"').to_string();
+            match name {
+                "#NAME" => metadata.short = value,
+                "#INDEX_LANGUAGE" => from = Some(value),
+                "#CONTENTS_LANGUAGE" => to = Some(value),
+                _ => {}
+            }
+            continue;
+        }
+        // A headword line after a body starts the next card
+        if in_body {
+            articles.push(card(&headwords, &body));
+            headwords.clear();
+            body.clear();
+            in_body = false;
+        }
+        headwords.push(line.trim().to_string());
+    }
+    if !headwords.is_empty() {
+        articles.push(card(&headwords, &body));
+    }
+    if let (Some(from), Some(to)) = (from, to) {
+        metadata.info = format!("{}, {} to {}", metadata.short, from, to);
+    }
+    Ok(Dsl { metadata, articles })
+}
+
+// The article of a card, found by every variant of its headwords but showing
+// them as written without the markers
+fn card(headwords: &[String], body: &str) -> Article {
+    let mut variants = Vec::new();
+    for headword in headwords.iter() {
+        for variant in expand(headword) {
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+    }
+    let shown = headwords.iter().map(|headword| display(headword)).collect();
+    Article {
+        headwords: variants,
+        definition: Article::new(shown, body).definition,
+    }
+}
+
+// UTF-16 with or without byte order mark, otherwise UTF-8
+fn decode(bytes: Vec<u8>) -> Result<String, DictError> {
+    let utf16 = |bytes: &[u8], le: bool| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| {
+                if le {
+                    u16::from_le_bytes([pair[0], pair[1]])
+                } else {
+                    u16::from_be_bytes([pair[0], pair[1]])
+                }
+            })
+            .collect();
+        String::from_utf16(&units).map_err(|_| DictError::SyntaxError("Invalid UTF-16"))
+    };
+    match bytes.as_slice() {
+        [0xff, 0xfe, rest @ ..] => utf16(rest, true),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, false),
+        [0xef, 0xbb, 0xbf, rest @ ..] => Ok(String::from_utf8(rest.to_vec())?),
+        // Text starting with an ASCII character
+        [_, 0, ..] => utf16(&bytes, true),
+        [0, _, ..] => utf16(&bytes, false),
+        _ => Ok(String::from_utf8(bytes)?),
+    }
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        rest = match rest[start..].find("}}") {
+            Some(end) => &rest[start + end + 2..],
+            None => "",
+        };
+    }
+    result.push_str(rest);
+    result
+}
+
+// The level of a leading [mN] tag and the rest of the line
+fn indentation(line: &str) -> (usize, &str) {
+    match line.as_bytes() {
+        [b'[', b'm', level @ b'0'..=b'9', b']', ..] => ((level - b'0') as usize, &line[4..]),
+        _ => (0, line),
+    }
+}
+
+// Removes the formatting tags of a body line, writing references as {word}
+// like dictfmt does
+fn to_text(line: &str, headword: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    // Text of sound and picture files is left out
+    let mut in_media = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    if !in_media {
+                        result.push(c);
+                    }
+                }
+            }
+            '[' => {
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let name = tag.split_whitespace().next().unwrap_or("");
+                match name {
+                    "ref" => result.push('{'),
+                    "/ref" => result.push('}'),
+                    "s" => in_media = true,
+                    "/s" => in_media = false,
+                    _ => {}
+                }
+            }
+            _ if in_media => {}
+            '<' if chars.peek() == Some(&'<') => {
+                chars.next();
+                result.push('{');
+            }
+            '>' if chars.peek() == Some(&'>') => {
+                chars.next();
+                result.push('}');
+            }
+            '~' => result.push_str(&display(headword)),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+// A headword as shown: without the braces of unindexed parts, the
+// parentheses of optional parts and escapes
+fn display(headword: &str) -> String {
+    let mut result = String::new();
+    let mut chars = headword.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            '{' | '}' | '(' | ')' => {}
+            c => result.push(c),
+        }
+    }
+    result.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// The headwords a headword is indexed by: without the unindexed parts in
+// braces, and with and without each optional part in parentheses
+fn expand(headword: &str) -> Vec<String> {
+    let mut variants = vec![String::new()];
+    let mut chars = headword.chars();
+    let mut unindexed = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    if !unindexed {
+                        variants.iter_mut().for_each(|v| v.push(c));
+                    }
+                }
+            }
+            '{' => unindexed = true,
+            '}' => unindexed = false,
+            _ if unindexed => {}
+            '(' => {
+                let optional: String = chars.by_ref().take_while(|&c| c != ')').collect();
+                let with: Vec<String> = variants
+                    .iter()
+                    .map(|v| format!("{}{}", v, optional))
+                    .collect();
+                variants.extend(with);
+            }
+            c => variants.iter_mut().for_each(|v| v.push(c)),
+        }
+    }
+    variants
+        .iter()
+        .map(|v| v.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DSL: &str = "#NAME \"English-Norwegian\"\n\
+                       #INDEX_LANGUAGE \"English\"\n\
+                       #CONTENTS_LANGUAGE \"Norwegian\"\n\
+                       \n\
+                       colo(u)r\n\
+                       \t[m1][trn]farge[/trn] {{a comment}}\n\
+                       \t[m2][ex]the ~ red[/ex], see [ref]paint[/ref]\n\
+                       \n\
+                       {to }paint\n\
+                       painting\n\
+                       \t[m1]male <<colour>> \\[sic\\] [s]paint.wav[/s]\n";
+
+    #[test]
+    fn cards() {
+        let dsl = read_dsl(DSL.as_bytes(), false).unwrap();
+        assert_eq!(dsl.metadata.short, "English-Norwegian");
+        assert_eq!(dsl.metadata.info, "English-Norwegian, English to Norwegian");
+        assert_eq!(dsl.articles.len(), 2);
+        assert_eq!(dsl.articles[0].headwords, vec!["color", "colour"]);
+        assert_eq!(
+            dsl.articles[0].definition,
+            "colour\n   farge\n     the colour red, see {paint}\n"
+        );
+        assert_eq!(dsl.articles[1].headwords, vec!["paint", "painting"]);
+        assert_eq!(
+            dsl.articles[1].definition,
+            "to paint, painting\n   male {colour} [sic]\n"
+        );
+
+        let kept = read_dsl(DSL.as_bytes(), true).unwrap();
+        assert!(kept.articles[0]
+            .definition
+            .contains("[m2][ex]the ~ red[/ex]"));
+    }
+
+    #[test]
+    fn utf16() {
+        let mut bytes = vec![0xff, 0xfe];
+        for unit in DSL.encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        let dsl = read_dsl(bytes.as_slice(), false).unwrap();
+        assert_eq!(dsl.articles.len(), 2);
+    }
+}
@@ -1,18 +1,62 @@
 use std::fmt;
 use std::fmt::Display;
 
+// Errors of reading databases and of answering queries. Those a client
+// caused map to the status line the server answers with.
 #[derive(Debug)]
 pub enum DictError {
     IoError(::std::io::Error),
     EncodingError(::std::string::FromUtf8Error),
     InvalidBase64,
     SyntaxError(&'static str),
-    NoMatch(&'static str),
+    // The word looked up, empty for random lookups
+    NoMatch(String),
+    UnknownLocale(String),
+    IllegalParameters,
+    // The database or strategy named in the query
+    InvalidDatabase(String),
+    InvalidStrategy(String),
+}
+
+impl DictError {
+    // Status code of the response to a query failing with the error. Errors
+    // that are not the client's are reported as a temporary problem.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            DictError::IllegalParameters => 501,
+            DictError::InvalidDatabase(_) => 550,
+            DictError::InvalidStrategy(_) => 551,
+            DictError::NoMatch(_) => 552,
+            _ => 420,
+        }
+    }
+
+    // Status line of the response, as dictd words it
+    pub fn protocol_line(&self) -> &'static str {
+        match self.status_code() {
+            501 => "501 Syntax error, illegal parameters",
+            550 => "550 Invalid database, use \"SHOW DB\" for list of databases",
+            551 => "551 Invalid strategy, use \"SHOW STRAT\" for a list of strategies",
+            552 => "552 no match",
+            _ => "420 Server temporarily unavailable",
+        }
+    }
 }
 
 impl Display for DictError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DictError")
+        match self {
+            DictError::IoError(e) => write!(f, "{}", e),
+            DictError::EncodingError(e) => write!(f, "{}", e),
+            DictError::InvalidBase64 => write!(f, "Invalid base64 number"),
+            DictError::SyntaxError(e) => write!(f, "{}", e),
+            DictError::NoMatch(word) if word.is_empty() => write!(f, "No match"),
+            DictError::NoMatch(word) => write!(f, "No match for {}", word),
+            DictError::UnknownLocale(locale) => write!(f, "Unknown locale {}", locale),
+            DictError::IllegalParameters => write!(f, "Illegal parameters"),
+            DictError::InvalidDatabase(database) => write!(f, "Invalid database {}", database),
+            DictError::InvalidStrategy(strategy) => write!(f, "Invalid strategy {}", strategy),
+        }
     }
 }
 
@@ -21,9 +65,7 @@ impl std::error::Error for DictError {
         match *self {
             DictError::IoError(ref e) => Some(e),
             DictError::EncodingError(ref e) => Some(e),
-            DictError::InvalidBase64 => None,
-            DictError::SyntaxError(_e) => None,
-            DictError::NoMatch(_e) => None,
+            _ => None,
         }
     }
 }
@@ -39,3 +81,28 @@ impl From<::std::string::FromUtf8Error> for DictError {
         DictError::EncodingError(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_lines() {
+        let e = DictError::NoMatch("hack".to_string());
+        assert_eq!((e.status_code(), e.protocol_line()), (552, "552 no match"));
+        assert_eq!(e.to_string(), "No match for hack");
+        assert_eq!(
+            DictError::InvalidDatabase("x".to_string()).status_code(),
+            550
+        );
+        assert_eq!(
+            DictError::InvalidStrategy("x".to_string()).status_code(),
+            551
+        );
+        assert_eq!(DictError::IllegalParameters.status_code(), 501);
+        // Failures of the server are not the client's
+        let e = DictError::from(std::io::Error::other("disk on fire"));
+        assert_eq!(e.protocol_line(), "420 Server temporarily unavailable");
+        assert_eq!(e.to_string(), "disk on fire");
+    }
+}
@@ -0,0 +1,108 @@
+// Writes the articles of a database for other programs, e.g. to migrate or
+// diff dictionaries:
+//
+//   JSON Lines   one {"headword": ..., "definition": ...} object per line
+//   CSV          a headword,definition header and a line per article, quoted
+//                as RFC 4180 says; definitions keep their line breaks
+//
+// The source format of dictfmt is written by unformat.
+use crate::dictionary::Definition;
+use crate::errors::DictError;
+use crate::json;
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    JsonLines,
+    Csv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "jsonl" => Some(Format::JsonLines),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+// Writes the definitions in a format and returns how many were written.
+// The first one that cannot be read ends the export.
+pub fn export<I, W>(definitions: I, format: Format, out: &mut W) -> Result<usize, DictError>
+where
+    I: IntoIterator<Item = Result<Definition, DictError>>,
+    W: Write,
+{
+    if format == Format::Csv {
+        writeln!(out, "headword,definition")?;
+    }
+    let mut count = 0;
+    for definition in definitions {
+        let definition = definition?;
+        match format {
+            Format::JsonLines => writeln!(
+                out,
+                "{{\"headword\":{},\"definition\":{}}}",
+                json::string(&definition.headword),
+                json::string(&definition.text)
+            )?,
+            Format::Csv => writeln!(
+                out,
+                "{},{}",
+                csv_field(&definition.headword),
+                csv_field(&definition.text)
+            )?,
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+// A field quoted if it contains a separator, quote or line break, with its
+// quotes doubled
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definitions() -> Vec<Result<Definition, DictError>> {
+        vec![
+            Ok(Definition {
+                headword: "hack".to_string(),
+                text: "hack n. 1. A \"quick\" job.\n".to_string(),
+            }),
+            Ok(Definition {
+                headword: "kluge".to_string(),
+                text: "clever".to_string(),
+            }),
+        ]
+    }
+
+    #[test]
+    fn formats() {
+        let mut out = Vec::new();
+        let count = export(definitions(), Format::JsonLines, &mut out).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"headword\":\"hack\",\"definition\":\"hack n. 1. A \\\"quick\\\" job.\\n\"}\n\
+             {\"headword\":\"kluge\",\"definition\":\"clever\"}\n"
+        );
+        let mut out = Vec::new();
+        export(definitions(), Format::Csv, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "headword,definition\nhack,\"hack n. 1. A \"\"quick\"\" job.\n\"\nkluge,clever\n"
+        );
+        let failing = vec![Err(DictError::NoMatch(String::new()))];
+        assert!(export(failing, Format::Csv, &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+// Filters applied to articles before they are sent, configured per database
+// as a chain of DefinitionFilters.
+use crate::mime::ContentType;
+use std::sync::Arc;
+
+pub trait DefinitionFilter: Send + Sync {
+    fn apply(&self, text: &str) -> String;
+
+    // Content type of the filtered text
+    fn content_type(&self, content_type: ContentType) -> ContentType {
+        content_type
+    }
+}
+
+// Applies the filters in order
+pub fn apply_filters(
+    filters: &[Arc<dyn DefinitionFilter>],
+    content_type: ContentType,
+    text: &str,
+) -> (ContentType, String) {
+    let mut text = text.to_string();
+    let mut content_type = content_type;
+    for filter in filters {
+        text = filter.apply(&text);
+        content_type = filter.content_type(content_type);
+    }
+    (content_type, text)
+}
+
+// Makes a filter from its name in the configuration: strip-markup, which
+// converts articles of the content type to plain text, collapse-whitespace,
+// drop-binary, or rewrite-references with the delimiters to replace and
+// their replacements, e.g. "rewrite-references { } [[ ]]"
+pub fn from_name(name: &str, content_type: ContentType) -> Option<Arc<dyn DefinitionFilter>> {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    match words.as_slice() {
+        ["strip-markup"] => Some(Arc::new(StripMarkup(content_type))),
+        ["collapse-whitespace"] => Some(Arc::new(CollapseWhitespace)),
+        ["drop-binary"] => Some(Arc::new(DropBinary)),
+        ["rewrite-references", open, close, new_open, new_close] => {
+            Some(Arc::new(RewriteReferences {
+                open: open.to_string(),
+                close: close.to_string(),
+                new_open: new_open.to_string(),
+                new_close: new_close.to_string(),
+            }))
+        }
+        _ => None,
+    }
+}
+
+// Converts HTML or Markdown articles to plain text
+pub struct StripMarkup(pub ContentType);
+
+impl DefinitionFilter for StripMarkup {
+    fn apply(&self, text: &str) -> String {
+        self.0.to_plain_text(text)
+    }
+
+    fn content_type(&self, _content_type: ContentType) -> ContentType {
+        ContentType::Plain
+    }
+}
+
+// Removes leading empty lines and trailing whitespace, collapses runs of
+// spaces after the indentation and runs of empty lines
+pub struct CollapseWhitespace;
+
+impl DefinitionFilter for CollapseWhitespace {
+    fn apply(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut empty = true;
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                if !empty {
+                    result.push('\n');
+                }
+                empty = true;
+                continue;
+            }
+            empty = false;
+            let content = line.trim_start();
+            result.push_str(&line[..line.len() - content.len()]);
+            result.push_str(&content.split_whitespace().collect::<Vec<&str>>().join(" "));
+            result.push('\n');
+        }
+        result
+    }
+}
+
+// Rewrites cross-references from one syntax to another, e.g. the {word}
+// of dictfmt to [[word]]
+pub struct RewriteReferences {
+    pub open: String,
+    pub close: String,
+    pub new_open: String,
+    pub new_close: String,
+}
+
+impl DefinitionFilter for RewriteReferences {
+    fn apply(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(&self.open) {
+            let inner = &rest[start + self.open.len()..];
+            match inner.find(&self.close) {
+                Some(end) => {
+                    result.push_str(&rest[..start]);
+                    result.push_str(&self.new_open);
+                    result.push_str(&inner[..end]);
+                    result.push_str(&self.new_close);
+                    rest = &inner[end + self.close.len()..];
+                }
+                None => break,
+            }
+        }
+        result.push_str(rest);
+        result
+    }
+}
+
+// Drops binary data embedded in articles, like pronunciation recordings as
+// data: URIs or stray control characters
+pub struct DropBinary;
+
+impl DefinitionFilter for DropBinary {
+    fn apply(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("data:") {
+            result.push_str(&rest[..start]);
+            let uri = &rest[start..];
+            let end = uri
+                .find(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == ')')
+                .unwrap_or(uri.len());
+            // Only base64 payloads, "data:" may also be plain text
+            if !uri[..end].contains(";base64,") {
+                result.push_str("data:");
+                rest = &uri[5..];
+                continue;
+            }
+            rest = &uri[end..];
+        }
+        result.push_str(rest);
+        result.retain(|c| !c.is_control() || c == '\n' || c == '\t');
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_chain() {
+        let filters: Vec<Arc<dyn DefinitionFilter>> = vec![
+            Arc::new(StripMarkup(ContentType::Html)),
+            Arc::new(DropBinary),
+            Arc::new(RewriteReferences {
+                open: "{".to_string(),
+                close: "}".to_string(),
+                new_open: "[[".to_string(),
+                new_close: "]]".to_string(),
+            }),
+            Arc::new(CollapseWhitespace),
+        ];
+        let text = "<p>A  {cat}\u{7}  data:audio/mp3;base64,AAAA sound</p>\n\n\n  see {dog} \n";
+        let (content_type, text) = apply_filters(&filters, ContentType::Html, text);
+        assert_eq!(content_type, ContentType::Plain);
+        assert_eq!(text, "A [[cat]] sound\n\n  see [[dog]]\n");
+
+        let filters: Vec<Arc<dyn DefinitionFilter>> =
+            ["strip-markup", "rewrite-references { } [[ ]]"]
+                .iter()
+                .map(|name| from_name(name, ContentType::Html).unwrap())
+                .collect();
+        let (_, text) = apply_filters(&filters, ContentType::Html, "<b>{cat}</b>");
+        assert_eq!(text, "[[cat]]");
+        assert!(from_name("rewrite-references { }", ContentType::Html).is_none());
+        assert!(from_name("uppercase", ContentType::Plain).is_none());
+    }
+}
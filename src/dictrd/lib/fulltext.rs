@@ -0,0 +1,154 @@
+// Inverted index of the words in the articles of a database, for searches
+// in the definitions rather than the headwords. Words are runs of
+// alphanumeric characters, compared in lowercase.
+use crate::{DictReader, IndexEntry, IndexReader, ReadAt};
+use std::collections::HashMap;
+
+pub struct FullTextIndex {
+    // Maps each word to the articles containing it and how often
+    postings: HashMap<String, Vec<(usize, u32)>>,
+    // Entries of each article, articles with several headwords have several
+    articles: Vec<Vec<IndexEntry>>,
+}
+
+impl FullTextIndex {
+    // Reads all articles of a database. Articles that cannot be read are
+    // left out.
+    pub fn build<R: ReadAt>(
+        indexreader: &IndexReader,
+        dictreader: &DictReader<R>,
+    ) -> FullTextIndex {
+        let mut entries: Vec<&IndexEntry> = indexreader.iter().collect();
+        entries.sort_by_key(|entry| (entry.offset, entry.length));
+        let mut articles: Vec<Vec<IndexEntry>> = Vec::new();
+        let mut ranges = Vec::new();
+        for entry in entries {
+            match articles.last_mut() {
+                Some(article)
+                    if article[0].offset == entry.offset && article[0].length == entry.length =>
+                {
+                    article.push(entry.clone())
+                }
+                _ => {
+                    articles.push(vec![entry.clone()]);
+                    ranges.push((entry.offset, entry.length));
+                }
+            }
+        }
+        let mut postings: HashMap<String, Vec<(usize, u32)>> = HashMap::new();
+        for (pos, text) in dictreader.find_many(&ranges).into_iter().enumerate() {
+            let text = match text {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for word in words(&text) {
+                *counts.entry(word).or_default() += 1;
+            }
+            for (word, count) in counts {
+                postings.entry(word).or_default().push((pos, count));
+            }
+        }
+        FullTextIndex { postings, articles }
+    }
+
+    // Number of distinct words
+    pub fn len(&self) -> usize {
+        self.postings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.postings.is_empty()
+    }
+
+    // Returns the entries of the articles containing all words of the query,
+    // the articles with the most occurrences first
+    pub fn search(&self, query: &str) -> Vec<&IndexEntry> {
+        let mut terms = words(query);
+        terms.sort();
+        terms.dedup();
+        let mut scores: Option<HashMap<usize, u32>> = None;
+        for term in terms {
+            let postings = match self.postings.get(&term) {
+                Some(postings) => postings,
+                None => return Vec::new(),
+            };
+            scores = Some(match scores {
+                None => postings.iter().copied().collect(),
+                Some(scores) => postings
+                    .iter()
+                    .filter_map(|(pos, count)| scores.get(pos).map(|score| (*pos, score + count)))
+                    .collect(),
+            });
+        }
+        let mut ranked: Vec<(usize, u32)> = scores.unwrap_or_default().into_iter().collect();
+        ranked.sort_by(|(pos1, score1), (pos2, score2)| {
+            score2.cmp(score1).then_with(|| {
+                self.articles[*pos1][0]
+                    .word
+                    .cmp(&self.articles[*pos2][0].word)
+            })
+        });
+        ranked
+            .iter()
+            .flat_map(|(pos, _)| self.articles[*pos].iter())
+            .collect()
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::DatabaseMetadata;
+    use crate::normalize::Normalization;
+    use crate::writer::write_database;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn search() {
+        let entries = [
+            (
+                "big-endian",
+                "big-endian\n   Most significant byte first, see endianness.\n",
+            ),
+            (
+                "endianness",
+                "endianness\n   Order of bytes. Endianness matters, endianness!\n",
+            ),
+            ("byte", "byte\n   Eight bits.\n"),
+        ];
+        let (mut index, mut dict) = (Vec::new(), Vec::new());
+        write_database(
+            entries,
+            &DatabaseMetadata::default(),
+            Normalization::AllChars,
+            &mut index,
+            &mut dict,
+        )
+        .unwrap();
+        let mut di = IndexReader::new();
+        di.parse_dict_index(Cursor::new(index));
+        di.remove_metadata_entries();
+        let dr = DictReader::new(BufReader::new(Cursor::new(dict))).unwrap();
+        let fulltext = FullTextIndex::build(&di, &dr);
+
+        let found = |query: &str| -> Vec<String> {
+            fulltext
+                .search(query)
+                .iter()
+                .map(|entry| entry.word.clone())
+                .collect()
+        };
+        assert_eq!(found("Endianness"), vec!["endianness", "big-endian"]);
+        assert_eq!(found("byte endianness"), vec!["big-endian"]);
+        assert_eq!(found("bits"), vec!["byte"]);
+        assert!(found("nibble").is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+// Cache of a loaded index: the sorted, normalized entries, the metadata
+// read from the 00-database-* entries and the side indexes built so far,
+// written to a binary file next to the .index file. Loading it saves
+// parsing, sorting and indexing the headwords again, as long as the .index
+// file has the size and modification time it had when the cache was
+// written, and the settings the index was loaded with are the same. The
+// settings are a string of the caller's choosing, e.g. the locale the
+// entries are sorted in.
+use crate::metadata::DatabaseMetadata;
+use crate::normalize::Normalization;
+use crate::phonetic::Phonetic;
+use crate::{IndexEntry, IndexReader, KeyIndex};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"DICTRIDX";
+// Raised whenever the layout changes, older caches are rebuilt
+const VERSION: u32 = 2;
+
+// Writes the cache of an index to path, through a temporary file so that a
+// server starting meanwhile never reads half a cache
+pub fn save(
+    path: &Path,
+    index_path: &Path,
+    settings: &str,
+    indexreader: &IndexReader,
+    metadata: &DatabaseMetadata,
+) -> io::Result<()> {
+    let tmp = path.with_extension("cache.tmp");
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    out.write_all(MAGIC)?;
+    write_u32(&mut out, VERSION)?;
+    write_source(&mut out, index_path, settings)?;
+    out.write_all(&[match indexreader.flags {
+        Some(normalization) => flag_bits(normalization),
+        None => 0xff,
+    }])?;
+
+    write_str(&mut out, &metadata.short)?;
+    write_str(&mut out, &metadata.info)?;
+    write_option(&mut out, metadata.url.as_deref())?;
+    out.write_all(&[metadata.utf8 as u8, metadata.eightbit_new as u8])?;
+    write_option(&mut out, metadata.dictfmt.as_deref())?;
+
+    write_u64(&mut out, indexreader.idx.len() as u64)?;
+    for entry in indexreader.idx.iter() {
+        write_str(&mut out, &entry.word)?;
+        write_u64(&mut out, entry.offset)?;
+        write_u64(&mut out, entry.length)?;
+        write_option(&mut out, entry.original.as_deref())?;
+    }
+    write_key_index(&mut out, &indexreader.trigrams)?;
+    write_key_index(&mut out, &indexreader.tokens)?;
+    write_key_index(&mut out, &indexreader.first_tokens)?;
+    write_key_index(&mut out, &indexreader.last_tokens)?;
+    write_u32(&mut out, indexreader.phonetic.len() as u32)?;
+    for (phonetic, index) in indexreader.phonetic.iter() {
+        write_str(&mut out, phonetic.name())?;
+        write_key_index(&mut out, index)?;
+    }
+    match indexreader.deletions {
+        Some((max, ref index)) => {
+            out.write_all(&[1])?;
+            write_u64(&mut out, max as u64)?;
+            write_key_index(&mut out, index)?;
+        }
+        None => out.write_all(&[0])?,
+    }
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+// Reads the cache of an index. None if there is none, or if it was written
+// for another version of the .index file, with other settings or by another
+// version of the server.
+pub fn load(
+    path: &Path,
+    index_path: &Path,
+    settings: &str,
+) -> io::Result<Option<(IndexReader, DatabaseMetadata)>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut input = BufReader::new(file);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC || read_u32(&mut input)? != VERSION {
+        return Ok(None);
+    }
+    let mut source = Vec::new();
+    write_source(&mut source, index_path, settings)?;
+    let mut cached = vec![0; source.len()];
+    input.read_exact(&mut cached)?;
+    if cached != source {
+        return Ok(None);
+    }
+
+    let mut indexreader = IndexReader::new();
+    indexreader.flags = match read_u8(&mut input)? {
+        0xff => None,
+        bits => Some(Normalization::from_flags(bits & 1 != 0, bits & 2 != 0)),
+    };
+    let metadata = DatabaseMetadata {
+        short: read_str(&mut input)?,
+        info: read_str(&mut input)?,
+        url: read_option(&mut input)?,
+        utf8: read_u8(&mut input)? != 0,
+        eightbit_new: read_u8(&mut input)? != 0,
+        dictfmt: read_option(&mut input)?,
+    };
+
+    let count = read_u64(&mut input)?;
+    for _ in 0..count {
+        indexreader.idx.push(IndexEntry {
+            word: read_str(&mut input)?,
+            offset: read_u64(&mut input)?,
+            length: read_u64(&mut input)?,
+            original: read_option(&mut input)?,
+        });
+    }
+    indexreader.trigrams = read_key_index(&mut input)?;
+    indexreader.tokens = read_key_index(&mut input)?;
+    indexreader.first_tokens = read_key_index(&mut input)?;
+    indexreader.last_tokens = read_key_index(&mut input)?;
+    for _ in 0..read_u32(&mut input)? {
+        let name = read_str(&mut input)?;
+        let phonetic = Phonetic::from_name(&name).ok_or_else(|| invalid("unknown phonetic"))?;
+        indexreader
+            .phonetic
+            .insert(phonetic, read_key_index(&mut input)?);
+    }
+    if read_u8(&mut input)? == 1 {
+        let max = read_u64(&mut input)? as usize;
+        indexreader.deletions = Some((max, read_key_index(&mut input)?));
+    }
+    Ok(Some((indexreader, metadata)))
+}
+
+fn flag_bits(normalization: Normalization) -> u8 {
+    normalization.allchars() as u8 | (normalization.case_sensitive() as u8) << 1
+}
+
+// Size and modification time of the .index file, and the settings
+fn write_source<W: Write>(out: &mut W, index_path: &Path, settings: &str) -> io::Result<()> {
+    let meta = fs::metadata(index_path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    write_u64(out, meta.len())?;
+    write_u64(out, modified.as_secs())?;
+    write_u32(out, modified.subsec_nanos())?;
+    write_str(out, settings)
+}
+
+fn write_key_index<W: Write>(out: &mut W, index: &KeyIndex) -> io::Result<()> {
+    write_u64(out, index.len() as u64)?;
+    for (key, positions) in index.iter() {
+        write_str(out, key)?;
+        write_u32(out, positions.len() as u32)?;
+        for &pos in positions {
+            write_u32(out, pos as u32)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_key_index<R: Read>(input: &mut R) -> io::Result<KeyIndex> {
+    let count = read_u64(input)?;
+    let mut index = KeyIndex::with_capacity(count as usize);
+    for _ in 0..count {
+        let key = read_str(input)?;
+        let len = read_u32(input)?;
+        let mut positions = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            positions.push(read_u32(input)? as usize);
+        }
+        index.insert(key, positions);
+    }
+    Ok(index)
+}
+
+fn write_u32<W: Write>(out: &mut W, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(out: &mut W, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_str<W: Write>(out: &mut W, value: &str) -> io::Result<()> {
+    write_u32(out, value.len() as u32)?;
+    out.write_all(value.as_bytes())
+}
+
+fn write_option<W: Write>(out: &mut W, value: Option<&str>) -> io::Result<()> {
+    match value {
+        Some(value) => {
+            out.write_all(&[1])?;
+            write_str(out, value)
+        }
+        None => out.write_all(&[0]),
+    }
+}
+
+fn read_u8<R: Read>(input: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_str<R: Read>(input: &mut R) -> io::Result<String> {
+    let len = read_u32(input)?;
+    let mut buf = vec![0; len as usize];
+    input.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|_| invalid("headword is not UTF-8"))
+}
+
+fn read_option<R: Read>(input: &mut R) -> io::Result<Option<String>> {
+    match read_u8(input)? {
+        0 => Ok(None),
+        _ => read_str(input).map(Some),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_and_staleness() {
+        let dir = std::env::temp_dir().join(format!("dictr-indexcache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let index_path = dir.join("test.index");
+        let text = "00databaseshort\tA\tB\nHacker\tB\tC\nhack\tC\tD\nkluge\tD\tE\n";
+        fs::write(&index_path, text).unwrap();
+
+        let mut di = IndexReader::new();
+        di.parse_dict_index(text.as_bytes());
+        di.normalize_headwords(di.normalization());
+        di.remove_metadata_entries();
+        di.build_phonetic_index(Phonetic::Soundex);
+        di.build_deletion_index(1);
+        let metadata = DatabaseMetadata {
+            short: "Test".to_string(),
+            dictfmt: Some("1.13.0".to_string()),
+            ..DatabaseMetadata::default()
+        };
+        let path = dir.join("test.cache");
+        save(&path, &index_path, "sv", &di, &metadata).unwrap();
+
+        let (cached, cached_metadata) = load(&path, &index_path, "sv").unwrap().unwrap();
+        assert_eq!(cached_metadata.short, "Test");
+        assert_eq!(cached_metadata.dictfmt.as_deref(), Some("1.13.0"));
+        let words = |di: &IndexReader| -> Vec<String> {
+            di.iter()
+                .map(|entry| entry.headword().to_string())
+                .collect()
+        };
+        assert_eq!(words(&cached), vec!["hack", "Hacker", "kluge"]);
+        assert_eq!(cached.normalization(), Normalization::Default);
+        assert_eq!(cached.find_entry("hacker").unwrap().headword(), "Hacker");
+        let hak = cached.find_words_by_phonetic(Phonetic::Soundex, "hak");
+        assert_eq!(hak.unwrap().len(), 1);
+        assert_eq!(cached.find_words_by_distance("kluje").unwrap().len(), 1);
+        assert_eq!(cached.find_words_by_substring("ack").unwrap().len(), 2);
+
+        assert!(cached.has_phonetic_index(Phonetic::Soundex));
+        assert!(!cached.has_phonetic_index(Phonetic::Metaphone));
+
+        // Other settings or a changed .index file make the cache stale
+        assert!(load(&path, &index_path, "").unwrap().is_none());
+        fs::write(&index_path, format!("{}zork\tE\tF\n", text)).unwrap();
+        assert!(load(&path, &index_path, "sv").unwrap().is_none());
+        assert!(load(&dir.join("missing"), &index_path, "sv")
+            .unwrap()
+            .is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+// Edit distance for the lev match strategy. Instead of comparing the query
+// with every headword, IndexReader::build_deletion_index maps all variants of
+// the headwords with up to n characters deleted to their entries. Two words
+// within distance n share such a variant, so only the entries found under
+// the variants of the query have to be compared.
+use std::collections::HashSet;
+
+// The word itself and all variants with up to max characters deleted
+pub fn deletions(word: &str, max: usize) -> HashSet<String> {
+    let mut result = HashSet::new();
+    let mut current: Vec<Vec<char>> = vec![word.chars().collect()];
+    result.insert(word.to_string());
+    for _ in 0..max {
+        let mut next = Vec::new();
+        for chars in current.iter() {
+            for i in 0..chars.len() {
+                let mut variant = chars.clone();
+                variant.remove(i);
+                if result.insert(variant.iter().collect()) {
+                    next.push(variant);
+                }
+            }
+        }
+        current = next;
+    }
+    result
+}
+
+// Levenshtein distance: insertions, deletions and substitutions of single
+// characters
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(distance("hacker", "hacker"), 0);
+        assert_eq!(distance("hacker", "hackr"), 1);
+        assert_eq!(distance("hacker", "hackers"), 1);
+        assert_eq!(distance("hacker", "hocker"), 1);
+        assert_eq!(distance("hacker", "ahcker"), 2);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("Größe", "Grösse"), 2);
+
+        let variants = deletions("abc", 1);
+        let mut variants: Vec<&str> = variants.iter().map(String::as_str).collect();
+        variants.sort_unstable();
+        assert_eq!(variants, vec!["ab", "abc", "ac", "bc"]);
+        assert_eq!(deletions("aab", 2).len(), 5);
+    }
+}
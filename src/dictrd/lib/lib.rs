@@ -1,19 +1,212 @@
+use self::collation::Collation;
+use self::dictzip::{Dictzip, NO_CHUNK_TABLE};
+use self::diskindex::DiskIndex;
 use self::errors::DictError;
-use log::info;
+use self::metadata::{is_metadata_headword, Encoding};
+use self::normalize::Normalization;
+use self::phonetic::Phonetic;
+use self::translit::{transliterate, Transliteration};
+use fst::automaton::{Levenshtein, Str};
+use fst::{Automaton, IntoStreamer, Map, MapBuilder, Streamer};
+use log::{info, warn};
 use rand::seq::SliceRandom;
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use rust_stemmers::{Algorithm, Stemmer};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::ops::{Bound, RangeBounds};
+pub mod access;
+#[cfg(feature = "fs")]
+pub mod accesslog;
+pub mod audit;
+pub mod backend;
+pub mod breaker;
+pub mod cache;
+pub mod clean;
+#[cfg(feature = "net")]
+pub mod client;
+pub mod collation;
+pub mod compress;
+pub mod dictionary;
+pub mod dictzip;
+pub mod diskindex;
+pub mod dsl;
 pub mod errors;
+pub mod export;
+pub mod filter;
+pub mod fulltext;
+#[cfg(feature = "fs")]
+pub mod indexcache;
+pub mod json;
+pub mod lev;
+pub mod metadata;
+pub mod metrics;
+pub mod mime;
+pub mod normalize;
 pub mod parser;
+pub mod percent;
+pub mod phonetic;
+#[cfg(feature = "net")]
+pub mod plugin;
+pub mod receive;
+pub mod response;
+pub mod sasl;
+#[cfg(feature = "fs")]
+pub mod securitylog;
+#[cfg(feature = "net")]
+pub mod server;
+pub mod source;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod strategy;
+pub mod template;
+pub mod throttle;
+pub mod translit;
+pub mod unformat;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+pub mod writer;
+pub mod xdxf;
+pub mod xref;
 
 #[derive(Clone)]
 pub struct IndexEntry {
     pub word: String,
     pub offset: u64,
     pub length: u64,
+    // Unnormalized headword from an optional fourth column
+    pub original: Option<String>,
+}
+
+impl IndexEntry {
+    // Parses an index line: headword, offset, length and optionally the
+    // original headword, separated by tabs
+    pub fn from_line(line: &str) -> Result<IndexEntry, DictError> {
+        let mut split = line.split('\t');
+        let (word, offset, length) = match (split.next(), split.next(), split.next()) {
+            (Some(word), Some(offset), Some(length)) => (word, offset, length),
+            _ => return Err(DictError::SyntaxError("Malformed index line")),
+        };
+        let original = split.next().map(|original| original.to_owned());
+        Ok(IndexEntry {
+            word: word.to_owned(),
+            offset: decode_base64(offset)?,
+            length: decode_base64(length)?,
+            original,
+        })
+    }
+
+    // Headword to show to clients
+    pub fn headword(&self) -> &str {
+        self.original.as_deref().unwrap_or(&self.word)
+    }
+
+    // Formats the entry as an index line, the inverse of parsing it
+    pub fn to_line(&self) -> String {
+        let mut line = format!(
+            "{}\t{}\t{}",
+            self.word,
+            encode_base64(self.offset),
+            encode_base64(self.length)
+        );
+        if let Some(original) = &self.original {
+            line.push('\t');
+            line.push_str(original);
+        }
+        line
+    }
+}
+
+// Maps a key derived from the headword to the positions of its entries
+type KeyIndex = HashMap<String, Vec<usize>>;
+
+// Side indexes of an index still to be built, see
+// IndexReader::plan_side_indexes
+#[derive(Clone, Debug)]
+pub struct SidePlan {
+    pub phonetic: Vec<Phonetic>,
+    // Largest edit distance of a deletion index or transducer for the lev
+    // strategy
+    pub deletions: Option<usize>,
+    pub fst: Option<usize>,
+    pub stem_language: Option<Algorithm>,
+}
+
+// Side indexes built from a copy of the entries of an index, so that its
+// lock is only held to install them, see IndexReader::install_side_indexes
+pub struct SideIndexes {
+    // Number of entries they were built for
+    entries: usize,
+    phonetic: HashMap<Phonetic, KeyIndex>,
+    deletions: Option<(usize, KeyIndex)>,
+    fst: Option<(usize, Map<Vec<u8>>)>,
+    stems: Option<(Algorithm, KeyIndex)>,
+}
+
+impl SideIndexes {
+    pub fn build(entries: &[IndexEntry], plan: &SidePlan) -> SideIndexes {
+        SideIndexes {
+            entries: entries.len(),
+            phonetic: plan
+                .phonetic
+                .iter()
+                .map(|phonetic| (*phonetic, key_index(entries, |word| phonetic.key(word))))
+                .collect(),
+            deletions: plan
+                .deletions
+                .map(|max| (max, deletion_index(entries, max))),
+            fst: plan.fst.map(|max| (max, fst_map(entries))),
+            stems: plan
+                .stem_language
+                .map(|language| (language, stem_index(entries, language))),
+        }
+    }
 }
 
 pub struct IndexReader {
     idx: Vec<IndexEntry>,
+    // Positions of the entries sorted by the range of their article
+    offsets: Vec<usize>,
+    // Maps character trigrams to the positions of the entries containing them
+    trigrams: KeyIndex,
+    // Maps the lowercased words of the headwords, split at whitespace and
+    // hyphens, to the positions of their entries
+    tokens: KeyIndex,
+    // The same for only the first and only the last word of the headwords
+    first_tokens: KeyIndex,
+    last_tokens: KeyIndex,
+    // Precomputed phonetic keys of the enabled algorithms, mapping each key
+    // to the positions of its entries
+    phonetic: HashMap<Phonetic, KeyIndex>,
+    // Largest edit distance of the lev strategy and the lowercased headwords
+    // with up to that many characters deleted, if enabled
+    deletions: Option<(usize, KeyIndex)>,
+    // Largest edit distance of the lev strategy and a finite state transducer
+    // mapping the headwords to the position of their first entry, if built
+    fst: Option<(usize, Map<Vec<u8>>)>,
+    // Language of the headwords and their stems, if stemming is enabled
+    stems: Option<(Algorithm, KeyIndex)>,
+    // Enabled transliterations and the transliterated headwords
+    translit: Option<(Vec<Transliteration>, KeyIndex)>,
+    // Locale collation the entries are sorted by, byte order if None
+    collation: Option<Collation>,
+    // Alternate headwords (inflections, misspellings, ...) mapped to the
+    // canonical headword
+    aliases: HashMap<String, String>,
+    // Occurrence counts of headwords from a frequency list
+    frequencies: HashMap<String, u64>,
+    // Normalization of the 00-database-allchars and
+    // 00-database-case-sensitive flags seen before the metadata entries were
+    // removed
+    flags: Option<Normalization>,
+    // Index searched in its file instead of the entries, which stay empty
+    disk: Option<DiskIndex>,
+    // Side indexes being built apart, whose lookups scan the entries
+    // until they are installed
+    plan: Option<SidePlan>,
 }
 
 impl Default for IndexReader {
@@ -24,113 +217,1183 @@ impl Default for IndexReader {
 
 impl IndexReader {
     pub fn new() -> IndexReader {
-        IndexReader { idx: Vec::new() }
-    }
-
-    fn decode_base64(&mut self, word: &str) -> Result<u64, DictError> {
-        let mut index = 0u64;
-        for (i, ch) in word.chars().rev().enumerate() {
-            let base64 = match ch {
-                '0'..='9' => (ch as u64) + 4,
-                'A'..='Z' => (ch as u64) - 65,
-                'a'..='z' => (ch as u64) - 71,
-                '+' => 62,
-                '/' => 63,
-                _ => return Err(DictError::InvalidBase64),
-            };
-            index += base64 * 64u64.pow(i as u32);
+        IndexReader {
+            idx: Vec::new(),
+            offsets: Vec::new(),
+            trigrams: KeyIndex::new(),
+            tokens: KeyIndex::new(),
+            first_tokens: KeyIndex::new(),
+            last_tokens: KeyIndex::new(),
+            phonetic: HashMap::new(),
+            deletions: None,
+            fst: None,
+            stems: None,
+            translit: None,
+            collation: None,
+            aliases: HashMap::new(),
+            frequencies: HashMap::new(),
+            flags: None,
+            disk: None,
+            plan: None,
+        }
+    }
+
+    // An index searched in its file, see DiskIndex. Only exact and prefix
+    // lookups find its headwords.
+    pub fn on_disk(disk: DiskIndex) -> IndexReader {
+        IndexReader {
+            disk: Some(disk),
+            ..IndexReader::new()
         }
-        Ok(index)
     }
 
-    pub fn find_word(&mut self, word: &str) -> Result<(u64, u64), DictError> {
+    pub fn is_on_disk(&self) -> bool {
+        self.disk.is_some()
+    }
+
+    pub fn find_word(&self, word: &str) -> Result<(u64, u64), DictError> {
+        if let Some(disk) = &self.disk {
+            return match disk.find(word)?.first() {
+                Some(entry) => Ok((entry.offset, entry.length)),
+                None => Err(DictError::NoMatch(word.to_string())),
+            };
+        }
         let word = word.to_string();
-        match self.idx.binary_search_by(|entry| entry.word.cmp(&word)) {
+        match self
+            .idx
+            .binary_search_by(|entry| compare_words(&self.collation, &entry.word, &word))
+        {
             Ok(idx) => {
                 let entry = &self.idx[idx];
                 //debug!("{}: {}", entry.offset, entry.length);
                 Ok((entry.offset, entry.length))
             }
-            Err(_e) => Err(DictError::NoMatch("552 no match")),
+            Err(_e) => Err(DictError::NoMatch(word.to_string())),
         }
     }
 
-    pub fn find_words_by_prefix(&mut self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
-        let word = word.to_string();
-        let mut res: Vec<IndexEntry> = Vec::new();
-        for entry in self.idx.iter() {
-            if entry.word.starts_with(word.as_str()) {
-                res.push(entry.clone());
+    // Looks up a word exactly, returning the first entry with the headword,
+    // see find_entries
+    pub fn find_entry(&self, word: &str) -> Result<IndexEntry, DictError> {
+        Ok(self.find_entries(word)?.remove(0))
+    }
+
+    // Looks up a word exactly, returning every entry with the headword, e.g.
+    // both "C" and "C++" normalized to "c". If the word is not found,
+    // aliases are resolved and, if transliteration is enabled, the entries
+    // with the same transliteration are returned. The entries carry the
+    // canonical headword.
+    pub fn find_entries(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        let entries = self.find_exact(word);
+        if !entries.is_empty() {
+            return Ok(entries);
+        }
+        if let Some(canonical) = self.resolve_alias(word) {
+            let entries = self.find_exact(canonical);
+            if !entries.is_empty() {
+                return Ok(entries);
+            }
+        }
+        match self.find_words_by_translit(word)? {
+            entries if entries.is_empty() => Err(DictError::NoMatch(word.to_string())),
+            entries => Ok(entries),
+        }
+    }
+
+    fn find_exact(&self, word: &str) -> Vec<IndexEntry> {
+        if let Some(disk) = &self.disk {
+            let mut entries = disk.find(word).unwrap_or_default();
+            entries.retain(|entry| self.flags.is_none() || !is_metadata_headword(&entry.word));
+            return entries;
+        }
+        self.range(word..=word).cloned().collect()
+    }
+
+    // Returns the canonical headword of an alias
+    pub fn resolve_alias(&self, word: &str) -> Option<&str> {
+        self.aliases.get(word).map(|canonical| canonical.as_str())
+    }
+
+    // Reads aliases, one tab separated "alias canonical" pair per line, and
+    // returns the number of aliases read. Both are normalized like the
+    // headwords, queries find "Colour\tColor" as colour.
+    pub fn add_aliases<B: BufRead>(&mut self, buf: B) -> usize {
+        let normalization = self.normalization();
+        let mut count = 0;
+        for line in buf.lines().map_while(Result::ok) {
+            let mut split = line.splitn(2, '\t');
+            if let (Some(alias), Some(canonical)) = (split.next(), split.next()) {
+                let (alias, canonical) = (alias.trim(), canonical.trim());
+                if alias.is_empty() || canonical.is_empty() {
+                    continue;
+                }
+                self.aliases
+                    .insert(normalization.apply(alias), normalization.apply(canonical));
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // Returns entries whose transliterated headword equals the transliterated
+    // word. Fails if transliteration is not enabled for this index.
+    pub fn find_words_by_translit(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        let (transliterations, index) = match &self.translit {
+            Some(translit) => translit,
+            None => return Err(DictError::NoMatch(word.to_string())),
+        };
+        match index.get(&transliterate(word, transliterations)) {
+            Some(positions) => Ok(positions.iter().map(|&pos| self.idx[pos].clone()).collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // Enables transliteration of headwords and queries to Latin script
+    pub fn set_transliterations(&mut self, transliterations: Vec<Transliteration>) {
+        let index = key_index(&self.idx, |word| transliterate(word, &transliterations));
+        self.translit = Some((transliterations, index));
+    }
+
+    // Transliterates a query like the headwords, None if transliteration is
+    // not enabled for this index
+    pub fn transliterate(&self, word: &str) -> Option<String> {
+        let (transliterations, _) = self.translit.as_ref()?;
+        Some(transliterate(word, transliterations))
+    }
+
+    // Returns an index of the transliterated headwords for the match
+    // strategies, whose entries carry the headword they were transliterated
+    // from. None if transliteration is not enabled for this index.
+    pub fn transliterated_index(&self) -> Option<IndexReader> {
+        let (transliterations, _) = self.translit.as_ref()?;
+        let mut index = IndexReader::new();
+        index.idx = self
+            .idx
+            .iter()
+            .map(|entry| IndexEntry {
+                word: transliterate(&entry.word, transliterations),
+                original: Some(entry.headword().to_string()),
+                ..entry.clone()
+            })
+            .collect();
+        index.sort_and_reindex();
+        Some(index)
+    }
+
+    // Returns how queries have to be normalized for this index, depending on
+    // the 00-database-allchars and 00-database-case-sensitive flags written
+    // by dictfmt
+    pub fn normalization(&self) -> Normalization {
+        if let Some(flags) = self.flags {
+            return flags;
+        }
+        let flag = |name: &str| {
+            self.find_word(&format!("00database{}", name.replace('-', "")))
+                .is_ok()
+                || self.find_word(&format!("00-database-{}", name)).is_ok()
+        };
+        Normalization::from_flags(flag("allchars"), flag("case-sensitive"))
+    }
+
+    pub fn find_words_by_prefix(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        if let Some(disk) = &self.disk {
+            let mut res = disk.find_prefix(word)?;
+            res.retain(|entry| self.flags.is_none() || !is_metadata_headword(&entry.word));
+            self.rank_by_frequency(&mut res);
+            return Ok(res);
+        }
+        let mut res: Vec<IndexEntry> = match &self.fst {
+            Some((_, map)) => {
+                let positions = self.fst_positions(map.search(Str::new(word).starts_with()));
+                positions.iter().map(|&pos| self.idx[pos].clone()).collect()
+            }
+            None => self.entries_with_prefix(word).cloned().collect(),
+        };
+        self.rank_by_frequency(&mut res);
+        Ok(res)
+    }
+
+    // Returns entries whose headword ends with word. This scans the whole
+    // index, an index of reversed headwords answers faster, see
+    // find_words_by_reversed_suffix.
+    pub fn find_words_by_suffix(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        let mut res: Vec<IndexEntry> = self
+            .idx
+            .iter()
+            .filter(|entry| entry.word.ends_with(word))
+            .cloned()
+            .collect();
+        self.rank_by_frequency(&mut res);
+        Ok(res)
+    }
+
+    // Returns entries whose headword contains word. For words of three or
+    // more characters only the entries having all their trigrams are
+    // compared.
+    pub fn find_words_by_substring(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        let chars: Vec<char> = word.chars().collect();
+        let mut candidates: Option<Vec<usize>> = None;
+        for window in chars.windows(3) {
+            let trigram: String = window.iter().collect();
+            let positions = self.trigrams.get(&trigram).map_or(&[][..], |p| &p[..]);
+            // Positions are in ascending order
+            candidates = Some(match candidates {
+                None => positions.to_vec(),
+                Some(candidates) => candidates
+                    .into_iter()
+                    .filter(|pos| positions.binary_search(pos).is_ok())
+                    .collect(),
+            });
+        }
+        let mut res: Vec<IndexEntry> = match candidates {
+            Some(candidates) => candidates
+                .into_iter()
+                .map(|pos| &self.idx[pos])
+                .filter(|entry| entry.word.contains(word))
+                .cloned()
+                .collect(),
+            None => self
+                .idx
+                .iter()
+                .filter(|entry| entry.word.contains(word))
+                .cloned()
+                .collect(),
+        };
+        self.rank_by_frequency(&mut res);
+        Ok(res)
+    }
+
+    // For an index of reversed headwords (a .index.suffix file), returns the
+    // entries whose headword ends with word, with the headwords restored
+    pub fn find_words_by_reversed_suffix(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        let reversed: String = word.chars().rev().collect();
+        let mut res = self.find_words_by_prefix(&reversed)?;
+        for entry in res.iter_mut() {
+            entry.word = entry.word.chars().rev().collect();
+            if let Some(original) = entry.original.as_mut() {
+                *original = original.chars().rev().collect();
+            }
+        }
+        Ok(res)
+    }
+
+    // Returns entries with the word as one of the words of their headword,
+    // ignoring case, e.g. "positive ack" for "ack"
+    pub fn find_words_by_word(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        Ok(self.find_by_token(&self.tokens, word))
+    }
+
+    // Returns entries whose headword starts with the word, ignoring case,
+    // e.g. "give up" and "give" for "give"
+    pub fn find_words_by_first_word(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        Ok(self.find_by_token(&self.first_tokens, word))
+    }
+
+    // Returns entries whose headword ends with the word, ignoring case, e.g.
+    // "give up" and "make up" for "up"
+    pub fn find_words_by_last_word(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        Ok(self.find_by_token(&self.last_tokens, word))
+    }
+
+    fn find_by_token(&self, tokens: &KeyIndex, word: &str) -> Vec<IndexEntry> {
+        let positions = match tokens.get(&word.to_lowercase()) {
+            Some(positions) => positions,
+            None => return Vec::new(),
+        };
+        let mut res: Vec<IndexEntry> = positions.iter().map(|&pos| self.idx[pos].clone()).collect();
+        self.rank_by_frequency(&mut res);
+        res
+    }
+
+    // Returns entries sharing at least min_similarity (Dice coefficient of
+    // the character trigrams) with the word, best matches first
+    pub fn find_words_by_ngram(
+        &self,
+        word: &str,
+        min_similarity: f64,
+    ) -> Result<Vec<IndexEntry>, DictError> {
+        let query = trigrams(word);
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for trigram in query.iter() {
+            if let Some(positions) = self.trigrams.get(trigram) {
+                for &pos in positions {
+                    *shared.entry(pos).or_insert(0) += 1;
+                }
+            }
+        }
+        let mut scored: Vec<(f64, &IndexEntry)> = shared
+            .into_iter()
+            .map(|(pos, count)| {
+                let entry = &self.idx[pos];
+                let total = query.len() + trigrams(&entry.word).len();
+                (2.0 * count as f64 / total as f64, entry)
+            })
+            .filter(|(score, _)| *score >= min_similarity)
+            .collect();
+        let frequencies = &self.frequencies;
+        let frequency = |entry: &IndexEntry| frequencies.get(&entry.word).copied().unwrap_or(0);
+        scored.sort_by(|(s1, e1), (s2, e2)| {
+            s2.partial_cmp(s1)
+                .unwrap()
+                .then(frequency(e2).cmp(&frequency(e1)))
+                .then(e1.word.cmp(&e2.word))
+        });
+        Ok(scored.into_iter().map(|(_, entry)| entry.clone()).collect())
+    }
+
+    // Returns entries with the same phonetic key as the word. Fails with
+    // InvalidStrategy if the keys for the algorithm have not been built or
+    // planned for this index.
+    pub fn find_words_by_phonetic(
+        &self,
+        phonetic: Phonetic,
+        word: &str,
+    ) -> Result<Vec<IndexEntry>, DictError> {
+        let key = phonetic.key(word);
+        let positions = match (self.phonetic.get(&phonetic), &self.plan) {
+            (Some(index), _) => index.get(&key).cloned().unwrap_or_default(),
+            (None, Some(plan)) if plan.phonetic.contains(&phonetic) => {
+                self.scan(|headword| phonetic.key(headword), &key)
+            }
+            (None, _) => return Err(DictError::InvalidStrategy(phonetic.name().to_string())),
+        };
+        let mut res: Vec<IndexEntry> = positions.iter().map(|&pos| self.idx[pos].clone()).collect();
+        self.rank_by_frequency(&mut res);
+        Ok(res)
+    }
+
+    pub fn has_phonetic_index(&self, phonetic: Phonetic) -> bool {
+        self.phonetic.contains_key(&phonetic)
+    }
+
+    // Precomputes the keys of all entries for a phonetic algorithm, which
+    // enables find_words_by_phonetic for it
+    pub fn build_phonetic_index(&mut self, phonetic: Phonetic) {
+        let index = key_index(&self.idx, |word| phonetic.key(word));
+        self.phonetic.insert(phonetic, index);
+    }
+
+    // Returns entries within the edit distance of the deletion index of the
+    // word, closest first, ignoring case. Fails if the deletion index has
+    // neither been built nor planned.
+    pub fn find_words_by_distance(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        let word = word.to_lowercase();
+        let (max, candidates) = match (&self.deletions, &self.fst) {
+            (Some((max, index)), _) => {
+                let mut candidates: Vec<usize> = lev::deletions(&word, *max)
+                    .iter()
+                    .filter_map(|variant| index.get(variant))
+                    .flatten()
+                    .copied()
+                    .collect();
+                candidates.sort_unstable();
+                candidates.dedup();
+                (*max, candidates)
+            }
+            (None, Some((max, map))) => {
+                let automaton = Levenshtein::new(&word, *max as u32)
+                    .map_err(|_| DictError::NoMatch(word.to_string()))?;
+                (*max, self.fst_positions(map.search(automaton)))
             }
+            (None, None) => match self
+                .plan
+                .as_ref()
+                .and_then(|plan| plan.deletions.or(plan.fst))
+            {
+                // Every headword is a candidate until the index is built
+                Some(max) => (max, (0..self.idx.len()).collect()),
+                None => return Err(DictError::NoMatch(word.to_string())),
+            },
+        };
+        let mut scored: Vec<(usize, usize)> = candidates
+            .into_iter()
+            .map(|pos| {
+                (
+                    lev::distance(&word, &self.idx[pos].word.to_lowercase()),
+                    pos,
+                )
+            })
+            .filter(|(distance, _)| *distance <= max)
+            .collect();
+        scored.sort_unstable();
+        Ok(scored
+            .into_iter()
+            .map(|(_, pos)| self.idx[pos].clone())
+            .collect())
+    }
+
+    // Precomputes the deletion variants of all headwords, which enables
+    // find_words_by_distance up to the given distance
+    pub fn build_deletion_index(&mut self, max: usize) {
+        self.deletions = Some((max, deletion_index(&self.idx, max)));
+    }
+
+    // Builds a finite state transducer of the headwords, which answers
+    // find_words_by_prefix and, without a deletion index,
+    // find_words_by_distance up to the given distance. It takes far less
+    // memory than the deletion index on large dictionaries.
+    pub fn build_fst_index(&mut self, max: usize) {
+        self.fst = Some((max, fst_map(&self.idx)));
+    }
+
+    // Largest edit distance of the deletion index, if built
+    pub fn deletion_distance(&self) -> Option<usize> {
+        self.deletions.as_ref().map(|(max, _)| *max)
+    }
+
+    pub fn has_fst_index(&self) -> bool {
+        self.fst.is_some()
+    }
+
+    // Positions of all entries with the headwords found in the transducer, in
+    // index order
+    fn fst_positions<A: Automaton>(&self, stream: fst::map::StreamBuilder<'_, A>) -> Vec<usize> {
+        let mut positions = Vec::new();
+        let mut stream = stream.into_stream();
+        while let Some((key, first)) = stream.next() {
+            // Entries with the same headword follow the first one
+            positions.extend(
+                (first as usize..self.idx.len())
+                    .take_while(|&pos| self.idx[pos].word.as_bytes() == key),
+            );
         }
+        positions.sort_unstable();
+        positions
+    }
+
+    // Returns entries whose headword has the same stem as the word. Fails
+    // with InvalidStrategy if no stemming language has been set or planned
+    // for this index.
+    pub fn find_words_by_stem(&self, word: &str) -> Result<Vec<IndexEntry>, DictError> {
+        let language = match (&self.stems, &self.plan) {
+            (Some((language, _)), _) => *language,
+            (
+                None,
+                Some(SidePlan {
+                    stem_language: Some(language),
+                    ..
+                }),
+            ) => *language,
+            _ => return Err(DictError::InvalidStrategy("stem".to_string())),
+        };
+        let stemmer = Stemmer::create(language);
+        let stem = |word: &str| stemmer.stem(&word.to_lowercase()).into_owned();
+        let key = stem(word);
+        let positions = match &self.stems {
+            Some((_, index)) => index.get(&key).cloned().unwrap_or_default(),
+            None => self.scan(stem, &key),
+        };
+        let mut res: Vec<IndexEntry> = positions.iter().map(|&pos| self.idx[pos].clone()).collect();
+        self.rank_by_frequency(&mut res);
         Ok(res)
     }
 
-    pub fn find_random(&mut self) -> Result<(String, u64, u64), DictError> {
+    // Sets the language of the headwords and precomputes their stems, which
+    // enables find_words_by_stem
+    pub fn set_stem_language(&mut self, language: Algorithm) {
+        self.stems = Some((language, stem_index(&self.idx, language)));
+    }
+
+    // Positions of the entries whose headword has the key, found by deriving
+    // the key of every headword, for lookups whose side index is planned
+    fn scan<F: Fn(&str) -> String>(&self, key: F, wanted: &str) -> Vec<usize> {
+        if wanted.is_empty() {
+            return Vec::new();
+        }
+        self.idx
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| key(&entry.word) == wanted)
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    // Records side indexes that are built apart with SideIndexes::build.
+    // Until they are installed, the lookups needing them scan all entries.
+    pub fn plan_side_indexes(&mut self, plan: SidePlan) {
+        self.plan = Some(plan);
+    }
+
+    pub fn side_plan(&self) -> Option<&SidePlan> {
+        self.plan.as_ref()
+    }
+
+    // Installs side indexes built apart and ends their plan. Returns false,
+    // installing nothing, if the entries changed since they were copied.
+    pub fn install_side_indexes(&mut self, side: SideIndexes) -> bool {
+        if side.entries != self.idx.len() {
+            return false;
+        }
+        self.phonetic.extend(side.phonetic);
+        if side.deletions.is_some() {
+            self.deletions = side.deletions;
+        }
+        if side.fst.is_some() {
+            self.fst = side.fst;
+        }
+        if side.stems.is_some() {
+            self.stems = side.stems;
+        }
+        self.plan = None;
+        true
+    }
+
+    // All entries in index order
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.idx
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, IndexEntry> {
+        self.idx.iter()
+    }
+
+    // Entries whose headword falls in the range, e.g. di.range("a".."b"),
+    // compared in the order of this index
+    pub fn range<'a, R: RangeBounds<&'a str>>(&self, range: R) -> std::slice::Iter<'_, IndexEntry> {
+        let collation = &self.collation;
+        let start = match range.start_bound() {
+            Bound::Included(word) => self
+                .idx
+                .partition_point(|e| compare_words(collation, &e.word, word) == Ordering::Less),
+            Bound::Excluded(word) => self
+                .idx
+                .partition_point(|e| compare_words(collation, &e.word, word) != Ordering::Greater),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(word) => self
+                .idx
+                .partition_point(|e| compare_words(collation, &e.word, word) != Ordering::Greater),
+            Bound::Excluded(word) => self
+                .idx
+                .partition_point(|e| compare_words(collation, &e.word, word) == Ordering::Less),
+            Bound::Unbounded => self.idx.len(),
+        };
+        self.idx[start..end.max(start)].iter()
+    }
+
+    // Entries whose headword starts with prefix, in index order
+    pub fn entries_with_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = &'a IndexEntry> + 'a {
+        // In byte order these entries are adjacent, a collation may put
+        // other headwords between them
+        let entries = match self.collation {
+            Some(_) => &self.idx[..],
+            None => {
+                let start = self.idx.partition_point(|e| e.word.as_str() < prefix);
+                let len = self.idx[start..].partition_point(|e| e.word.starts_with(prefix));
+                &self.idx[start..start + len]
+            }
+        };
+        entries
+            .iter()
+            .filter(move |entry| entry.word.starts_with(prefix))
+    }
+
+    // Returns the entry of the article at the given range of the .dict file
+    pub fn find_entry_at(&self, offset: u64, length: u64) -> Option<IndexEntry> {
+        let range = |pos: &usize| (self.idx[*pos].offset, self.idx[*pos].length);
+        let start = self
+            .offsets
+            .partition_point(|pos| range(pos) < (offset, length));
+        self.offsets
+            .get(start)
+            .filter(|pos| range(pos) == (offset, length))
+            .map(|pos| self.idx[*pos].clone())
+    }
+
+    pub fn find_random(&self) -> Result<(String, u64, u64), DictError> {
         if let Some(res) = self.idx.choose(&mut rand::thread_rng()) {
             return Ok((res.word.clone(), res.offset, res.length));
         }
-        Err(DictError::NoMatch("552 no match"))
+        Err(DictError::NoMatch(String::new()))
+    }
+
+    // Picks a random entry with a probability proportional to its frequency,
+    // or inversely proportional if rare is set. Without a frequency list the
+    // choice is uniform.
+    pub fn find_random_weighted(&self, rare: bool) -> Result<(String, u64, u64), DictError> {
+        if self.frequencies.is_empty() {
+            return self.find_random();
+        }
+        let frequencies = &self.frequencies;
+        let res = self.idx.choose_weighted(&mut rand::thread_rng(), |entry| {
+            let frequency = frequencies.get(&entry.word).copied().unwrap_or(0) as f64;
+            if rare {
+                1.0 / (frequency + 1.0)
+            } else {
+                frequency
+            }
+        });
+        match res {
+            Ok(res) => Ok((res.word.clone(), res.offset, res.length)),
+            // All weights are zero, none of the headwords is in the list
+            Err(_) => self.find_random(),
+        }
+    }
+
+    // Reads a frequency list with a headword and its count per line,
+    // separated by whitespace, and returns the number of words read
+    pub fn add_frequencies<B: BufRead>(&mut self, buf: B) -> usize {
+        let mut count = 0;
+        for line in buf.lines().map_while(Result::ok) {
+            let line = line.trim();
+            if let Some(pos) = line.rfind(char::is_whitespace) {
+                if let Ok(frequency) = line[pos..].trim().parse::<u64>() {
+                    let word = line[..pos].trim().to_string();
+                    *self.frequencies.entry(word).or_default() += frequency;
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    // Returns the frequency of a headword, 0 if it is not in the list
+    pub fn frequency(&self, word: &str) -> u64 {
+        self.frequencies.get(word).copied().unwrap_or(0)
+    }
+
+    // Returns the entries of the count most frequent headwords of the
+    // frequency list that are in the index, the most frequent first
+    pub fn most_frequent(&self, count: usize) -> Vec<IndexEntry> {
+        let mut words: Vec<(&String, &u64)> = self.frequencies.iter().collect();
+        words.sort_by(|(w1, f1), (w2, f2)| f2.cmp(f1).then(w1.cmp(w2)));
+        words
+            .into_iter()
+            .map(|(word, _)| self.find_exact(word))
+            .filter(|entries| !entries.is_empty())
+            .take(count)
+            .flatten()
+            .collect()
+    }
+
+    // Moves common words to the front. The sort is stable, so entries of the
+    // same frequency (all of them without a frequency list) keep their order.
+    fn rank_by_frequency(&self, entries: &mut [IndexEntry]) {
+        if !self.frequencies.is_empty() {
+            entries.sort_by_key(|entry| std::cmp::Reverse(self.frequency(&entry.word)));
+        }
     }
 
     pub fn parse_dict_index<B: BufRead>(&mut self, buf: B) {
-        let mut line_number = 0;
-        for l in buf.lines() {
-            let line = l.unwrap();
-            let entry = self.parse_line(&line);
-            self.idx.push(entry);
-            line_number += 1;
+        self.try_parse_dict_index(buf)
+            .expect("Could not parse index");
+    }
+
+    // Like parse_dict_index, but returns an error instead of panicking on
+    // unreadable or malformed lines. Nothing is added to the index then.
+    // Large indexes are parsed in chunks, one per CPU.
+    pub fn try_parse_dict_index<B: BufRead>(&mut self, mut buf: B) -> Result<usize, DictError> {
+        let mut bytes = Vec::new();
+        buf.read_to_end(&mut bytes)?;
+        let chunks = match bytes.len() < PARALLEL_PARSE_BYTES {
+            true => 1,
+            false => parse_threads(),
+        };
+        let mut entries = parse_lines(&bytes, chunks)?;
+        let count = entries.len();
+        self.idx.append(&mut entries);
+        self.sort_and_reindex();
+        info!("Read {} lines from index", count);
+        Ok(count)
+    }
+
+    // Sorts the headwords according to the locale's collation rules instead
+    // of byte order
+    pub fn set_collation(&mut self, collation: Collation) {
+        self.collation = Some(collation);
+        // Entries from an index cache are sorted already
+        let collation = &self.collation;
+        let sorted = self.idx.windows(2).all(|pair| {
+            compare_words(collation, &pair[0].word, &pair[1].word) != Ordering::Greater
+        });
+        if !sorted {
+            self.sort_and_reindex();
         }
-        self.idx.sort_by(|e1, e2| e1.word.cmp(&e2.word));
-        info!("Read {} lines from index", line_number);
     }
 
-    fn parse_line(&mut self, line: &str) -> IndexEntry {
-        let mut split = line.split('\t');
-        let word = split.next().unwrap();
-        let offset = split.next().unwrap();
-        let offset = self.decode_base64(offset).unwrap();
-        let length = split.next().unwrap();
-        let length = self.decode_base64(length).unwrap();
-        IndexEntry {
-            word: word.to_owned(),
-            offset,
-            length,
+    pub fn collation(&self) -> Option<&Collation> {
+        self.collation.as_ref()
+    }
+
+    // Compares two headwords in the order of this index
+    pub fn compare_headwords(&self, left: &str, right: &str) -> Ordering {
+        compare_words(&self.collation, left, right)
+    }
+
+    // Replaces headwords by the normalized form queries are looked up with,
+    // keeping the original spelling as display headword. Returns the number
+    // of headwords changed.
+    pub fn normalize_headwords(&mut self, normalization: Normalization) -> usize {
+        // The headwords of the file are normalized as they are read
+        if let Some(disk) = &mut self.disk {
+            disk.set_normalization(normalization);
+            return 0;
+        }
+        let mut changed = 0;
+        for entry in self.idx.iter_mut() {
+            // The 00-database-* entries have to keep their names
+            if is_metadata_headword(&entry.word) {
+                continue;
+            }
+            let normalized = normalization.apply(&entry.word);
+            if normalized != entry.word {
+                let original = std::mem::replace(&mut entry.word, normalized);
+                entry.original.get_or_insert(original);
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            self.sort_and_reindex();
+        }
+        changed
+    }
+
+    // Drops the 00-database-* entries once DatabaseMetadata has been loaded
+    // from them, so that they do not show up in lookups. Returns the number
+    // of entries removed; those of an index on disk are skipped from here on.
+    pub fn remove_metadata_entries(&mut self) -> usize {
+        self.flags = Some(self.normalization());
+        let before = self.idx.len();
+        self.idx.retain(|entry| !is_metadata_headword(&entry.word));
+        let removed = before - self.idx.len();
+        if removed > 0 {
+            self.sort_and_reindex();
+        }
+        removed
+    }
+
+    // Sorts the entries and rebuilds all indexes referring to their positions
+    fn sort_and_reindex(&mut self) {
+        let collation = &self.collation;
+        self.idx
+            .sort_by(|e1, e2| compare_words(collation, &e1.word, &e2.word));
+        self.build_offset_index();
+        self.build_trigram_index();
+        self.build_token_index();
+        let phonetics: Vec<Phonetic> = self.phonetic.keys().copied().collect();
+        for phonetic in phonetics {
+            self.build_phonetic_index(phonetic);
+        }
+        if let Some((max, _)) = self.deletions {
+            self.build_deletion_index(max);
+        }
+        if let Some((max, _)) = self.fst {
+            self.build_fst_index(max);
+        }
+        if let Some((language, _)) = self.stems {
+            self.set_stem_language(language);
+        }
+        if let Some((transliterations, _)) = self.translit.take() {
+            self.set_transliterations(transliterations);
+        }
+    }
+
+    fn build_token_index(&mut self) {
+        self.tokens.clear();
+        self.first_tokens.clear();
+        self.last_tokens.clear();
+        for (pos, entry) in self.idx.iter().enumerate() {
+            let mut tokens: Vec<String> = entry
+                .word
+                .split(|c: char| c.is_whitespace() || c == '-')
+                .filter(|token| !token.is_empty())
+                .map(str::to_lowercase)
+                .collect();
+            if let (Some(first), Some(last)) = (tokens.first(), tokens.last()) {
+                self.first_tokens
+                    .entry(first.clone())
+                    .or_default()
+                    .push(pos);
+                self.last_tokens.entry(last.clone()).or_default().push(pos);
+            }
+            tokens.sort_unstable();
+            tokens.dedup();
+            for token in tokens {
+                self.tokens.entry(token).or_default().push(pos);
+            }
+        }
+    }
+
+    fn build_offset_index(&mut self) {
+        let idx = &self.idx;
+        self.offsets = (0..idx.len()).collect();
+        // Stable, so the first entry of an article comes first
+        self.offsets
+            .sort_by_key(|pos| (idx[*pos].offset, idx[*pos].length));
+    }
+
+    fn build_trigram_index(&mut self) {
+        self.trigrams.clear();
+        for (pos, entry) in self.idx.iter().enumerate() {
+            for trigram in trigrams(&entry.word) {
+                self.trigrams.entry(trigram).or_default().push(pos);
+            }
+        }
+    }
+}
+
+fn decode_base64(word: &str) -> Result<u64, DictError> {
+    let mut index = 0u64;
+    for (i, ch) in word.chars().rev().enumerate() {
+        let base64 = match ch {
+            '0'..='9' => (ch as u64) + 4,
+            'A'..='Z' => (ch as u64) - 65,
+            'a'..='z' => (ch as u64) - 71,
+            '+' => 62,
+            '/' => 63,
+            _ => return Err(DictError::InvalidBase64),
+        };
+        index += base64 * 64u64.pow(i as u32);
+    }
+    Ok(index)
+}
+
+// Encodes a number in the base64 digits of dictd indexes
+fn encode_base64(mut value: u64) -> String {
+    const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut digits = vec![DIGITS[(value % 64) as usize]];
+    value /= 64;
+    while value > 0 {
+        digits.push(DIGITS[(value % 64) as usize]);
+        value /= 64;
+    }
+    digits.iter().rev().map(|&d| d as char).collect()
+}
+
+fn compare_words(collation: &Option<Collation>, left: &str, right: &str) -> Ordering {
+    match collation {
+        Some(collation) => collation.compare(left, right),
+        None => left.cmp(right),
+    }
+}
+
+// Groups the positions of all entries by a key derived from the headword
+fn key_index<F: Fn(&str) -> String>(entries: &[IndexEntry], key: F) -> KeyIndex {
+    let mut index = KeyIndex::new();
+    for (pos, entry) in entries.iter().enumerate() {
+        let key = key(&entry.word);
+        if !key.is_empty() {
+            index.entry(key).or_default().push(pos);
+        }
+    }
+    index
+}
+
+fn stem_index(entries: &[IndexEntry], language: Algorithm) -> KeyIndex {
+    let stemmer = Stemmer::create(language);
+    key_index(entries, |word| {
+        stemmer.stem(&word.to_lowercase()).into_owned()
+    })
+}
+
+fn deletion_index(entries: &[IndexEntry], max: usize) -> KeyIndex {
+    let mut index = KeyIndex::new();
+    for (pos, entry) in entries.iter().enumerate() {
+        for variant in lev::deletions(&entry.word.to_lowercase(), max) {
+            index.entry(variant).or_default().push(pos);
+        }
+    }
+    index
+}
+
+fn fst_map(entries: &[IndexEntry]) -> Map<Vec<u8>> {
+    let mut keys: Vec<(&[u8], usize)> = entries
+        .iter()
+        .enumerate()
+        .map(|(pos, entry)| (entry.word.as_bytes(), pos))
+        .collect();
+    // The transducer needs its keys in byte order, once each
+    keys.sort_unstable();
+    keys.dedup_by_key(|(key, _)| *key);
+    let mut builder = MapBuilder::memory();
+    for (key, pos) in keys {
+        builder
+            .insert(key, pos as u64)
+            .expect("Keys are sorted and unique");
+    }
+    builder.into_map()
+}
+
+// Indexes smaller than this are parsed on one thread
+const PARALLEL_PARSE_BYTES: usize = 1 << 20;
+
+// Threads parsing a large index, one per CPU
+#[cfg(feature = "threads")]
+fn parse_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Without threads, e.g. in WebAssembly, the caller's thread parses it all
+#[cfg(not(feature = "threads"))]
+fn parse_threads() -> usize {
+    1
+}
+
+// Parses index lines split into about as many chunks of whole lines, each on
+// its own thread. The entries keep the order of the lines.
+fn parse_lines(bytes: &[u8], chunks: usize) -> Result<Vec<IndexEntry>, DictError> {
+    let mut bounds = vec![0];
+    for i in 1..chunks {
+        let start = (bytes.len() * i / chunks).max(*bounds.last().unwrap());
+        match bytes[start..].iter().position(|&b| b == b'\n') {
+            Some(pos) => bounds.push(start + pos + 1),
+            None => break,
+        }
+    }
+    bounds.push(bytes.len());
+    bounds.dedup();
+    if bounds.len() <= 2 {
+        return parse_chunk(bytes);
+    }
+    let parsed = std::thread::scope(|scope| {
+        let threads: Vec<_> = bounds
+            .windows(2)
+            .map(|pair| scope.spawn(move || parse_chunk(&bytes[pair[0]..pair[1]])))
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().expect("Index parser panicked"))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    Ok(parsed.into_iter().flatten().collect())
+}
+
+// Parses whole lines, as BufRead::lines splits them
+fn parse_chunk(bytes: &[u8]) -> Result<Vec<IndexEntry>, DictError> {
+    if bytes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let text = std::str::from_utf8(bytes)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    text.strip_suffix('\n')
+        .unwrap_or(text)
+        .split('\n')
+        .map(|line| IndexEntry::from_line(line.strip_suffix('\r').unwrap_or(line)))
+        .collect()
+}
+
+// Returns the distinct character trigrams of a word, padded so that the
+// beginning and end of the word carry extra weight
+fn trigrams(word: &str) -> Vec<String> {
+    let chars: Vec<char> = format!("  {} ", word).chars().collect();
+    let mut result: Vec<String> = chars.windows(3).map(|w| w.iter().collect()).collect();
+    result.sort();
+    result.dedup();
+    result
+}
+
+// Largest gap between two articles that DictReader::find_many reads over
+// rather than seeking
+const MAX_READ_GAP: u64 = 4096;
+
+// Files articles are read from. A read at an offset leaves the position of
+// the file alone, so that many lookups can read the same file at once.
+pub trait ReadAt {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()>;
+}
+
+#[cfg(all(feature = "fs", unix))]
+impl ReadAt for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+}
+
+// Reads on Windows move the cursor of the file, which is only used for reads
+// at an offset
+#[cfg(all(feature = "fs", windows))]
+impl ReadAt for File {
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.seek_read(buf, offset) {
+                Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
         }
+        Ok(())
+    }
+}
+
+// Reads at an offset bypass the buffer
+impl<R: ReadAt> ReadAt for BufReader<R> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        self.get_ref().read_exact_at(buf, offset)
+    }
+}
+
+// Dict files held in memory
+impl<T: AsRef<[u8]>> ReadAt for Cursor<T> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        let data = self.get_ref().as_ref();
+        let bytes = data
+            .get(offset as usize..)
+            .and_then(|rest| rest.get(..buf.len()))
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+        buf.copy_from_slice(bytes);
+        Ok(())
     }
 }
 
-pub struct DictReader<R: Read + Seek> {
-    buf: BufReader<R>,
+pub struct DictReader<R> {
+    file: R,
     len: u64,
+    encoding: Encoding,
+    // Chunk table of a dictzip compressed file
+    dictzip: Option<Dictzip>,
+    // Data of a gzip file without chunk table, held in memory
+    inflated: Option<Vec<u8>>,
 }
 
-impl<R: Read + Seek> DictReader<R> {
+impl<R: Read + Seek + ReadAt> DictReader<R> {
+    // Reads plain and dictzip compressed (.dict.dz) files, offsets and
+    // lengths always refer to the uncompressed data. Only the header is read
+    // through the buffer, articles are read at their offset. Plain gzip
+    // files (.dict.gz) are decompressed into memory.
     pub fn new(mut buf: BufReader<R>) -> Result<DictReader<R>, std::io::Error> {
-        let len = buf.seek(SeekFrom::End(0))?;
-        Ok(DictReader { buf, len })
+        let invalid = |e| match e {
+            DictError::IoError(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)),
+        };
+        let (dictzip, inflated) = match Dictzip::open(&mut buf) {
+            Ok(dictzip) => (dictzip, None),
+            Err(DictError::SyntaxError(NO_CHUNK_TABLE)) => {
+                let data = Dictzip::inflate_all(&mut buf).map_err(invalid)?;
+                warn!(
+                    "Dict file is gzip compressed without dictzip chunk table, holding all {} bytes in memory; compress it with dictzip to avoid this",
+                    data.len()
+                );
+                (None, Some(data))
+            }
+            Err(e) => return Err(invalid(e)),
+        };
+        let len = match (&dictzip, &inflated) {
+            (Some(dictzip), _) => dictzip.len(),
+            (None, Some(data)) => data.len() as u64,
+            (None, None) => buf.seek(SeekFrom::End(0))?,
+        };
+        Ok(DictReader {
+            file: buf.into_inner(),
+            len,
+            encoding: Encoding::Utf8,
+            dictzip,
+            inflated,
+        })
+    }
+}
+
+impl<T: AsRef<[u8]>> DictReader<Cursor<T>> {
+    // Reads a dict file held in memory, e.g. one handed to WebAssembly
+    pub fn from_bytes(data: T) -> Result<DictReader<Cursor<T>>, std::io::Error> {
+        DictReader::new(BufReader::new(Cursor::new(data)))
+    }
+}
+
+// Lookups only read, so a reader can be shared by many threads
+impl<R: ReadAt> DictReader<R> {
+    // Size of the uncompressed .dict file in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Sets the encoding definitions are transcoded from
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn find(&self, offset: u64, len: u64) -> Result<String, DictError> {
+        let buffer = self.find_bytes(offset, len)?;
+        let result = self.encoding.decode(buffer)?;
+        //debug!("RESULT = {}", result);
+        Ok(result)
     }
 
-    pub fn find(&mut self, offset: u64, len: u64) -> Result<String, DictError> {
+    // Returns the raw bytes of an article
+    pub fn find_bytes(&self, offset: u64, len: u64) -> Result<Vec<u8>, DictError> {
         if offset >= self.len || offset + len > self.len {
             return Err(DictError::SyntaxError(
                 "501 Syntax error, illegal parameters",
             ));
         }
-        self.buf.seek(SeekFrom::Start(offset))?;
+        if let Some(ref dictzip) = self.dictzip {
+            return dictzip.read(&self.file, offset, len);
+        }
+        if let Some(ref data) = self.inflated {
+            return Ok(data[offset as usize..(offset + len) as usize].to_vec());
+        }
         let mut buffer = vec![0; len as usize];
-        self.buf.read_exact(&mut buffer)?;
+        self.file.read_exact_at(&mut buffer, offset)?;
+        Ok(buffer)
+    }
 
-        let result = String::from_utf8(buffer)?;
-        //debug!("RESULT = {}", result);
-        Ok(result)
+    // Reads the articles at many ranges, in the order given. Ranges less than
+    // MAX_READ_GAP bytes apart are read together, so a batch of lookups
+    // seeks and reads once per cluster of articles instead of once per
+    // article.
+    pub fn find_many(&self, ranges: &[(u64, u64)]) -> Vec<Result<String, DictError>> {
+        let mut order: Vec<usize> = (0..ranges.len()).collect();
+        order.sort_by_key(|&pos| ranges[pos]);
+        let mut results: Vec<Option<Result<String, DictError>>> =
+            ranges.iter().map(|_| None).collect();
+        let mut group_start = 0;
+        while group_start < order.len() {
+            let (start, first_len) = ranges[order[group_start]];
+            let mut end = start + first_len;
+            let mut group_end = group_start + 1;
+            while group_end < order.len() && ranges[order[group_end]].0 <= end + MAX_READ_GAP {
+                let (offset, len) = ranges[order[group_end]];
+                end = end.max(offset + len);
+                group_end += 1;
+            }
+            let group = &order[group_start..group_end];
+            match self.find_bytes(start, end - start) {
+                Ok(bytes) => {
+                    for &pos in group {
+                        let (offset, len) = ranges[pos];
+                        let article = bytes
+                            [(offset - start) as usize..(offset - start + len) as usize]
+                            .to_vec();
+                        results[pos] = Some(self.encoding.decode(article));
+                    }
+                }
+                // Read the articles one by one, to tell which ranges are bad
+                Err(_) => {
+                    for &pos in group {
+                        let (offset, len) = ranges[pos];
+                        results[pos] = Some(self.find(offset, len));
+                    }
+                }
+            }
+            group_start = group_end;
+        }
+        results.into_iter().map(Option::unwrap).collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
     use std::fs::File;
+    use std::io::Write;
     use std::path::PathBuf;
 
     #[test]
@@ -144,6 +1407,7 @@ mod tests {
         di.parse_dict_index(file);
     }
 
+    #[cfg(feature = "fs")]
     #[test]
     fn dict_read() {
         let mut di = IndexReader::new();
@@ -159,8 +1423,422 @@ mod tests {
             path.push("db.expect.dict");
             let file = File::open(path).unwrap();
             let file = BufReader::new(file);
-            let mut dr = DictReader::new(file).unwrap();
-            dr.find(offset, length);
+            let dr = DictReader::new(file).unwrap();
+            let _ = dr.find(offset, length);
+        }
+    }
+
+    #[test]
+    fn shared_reads() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("apple\tA\tG\nbanana\tG\tH\ncherry\tN\tH\n".as_bytes());
+        let dr = DictReader::new(BufReader::new(Cursor::new(
+            b"apple\nbanana\ncherry\n".to_vec(),
+        )))
+        .unwrap();
+        // Lookups borrow the readers immutably and run side by side
+        std::thread::scope(|scope| {
+            for word in ["apple", "banana", "cherry"].iter() {
+                let (di, dr) = (&di, &dr);
+                scope.spawn(move || {
+                    let (offset, length) = di.find_word(word).unwrap();
+                    assert_eq!(dr.find(offset, length).unwrap(), format!("{}\n", word));
+                });
+            }
+        });
+        assert!(dr.find(18, 10).is_err());
+    }
+
+    #[test]
+    fn plain_gzip() {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(b"apple\nbanana\ncherry\n").unwrap();
+        let dr = DictReader::new(BufReader::new(Cursor::new(gzip.finish().unwrap()))).unwrap();
+        assert_eq!(dr.len(), 20);
+        assert_eq!(dr.find(6, 7).unwrap(), "banana\n");
+        assert!(dr.find(14, 7).is_err());
+    }
+
+    #[test]
+    fn original_headword() {
+        let mut di = IndexReader::new();
+        let lines = "bar\tA\tB\nnew york\tCG/S\tZ6\tNew York\n";
+        di.parse_dict_index(lines.as_bytes());
+        let entry = di.find_entry("new york").unwrap();
+        assert_eq!(entry.headword(), "New York");
+        assert_eq!(di.find_entry("bar").unwrap().headword(), "bar");
+        let round_trip: Vec<String> = di.idx.iter().map(|entry| entry.to_line()).collect();
+        assert_eq!(round_trip.join("\n") + "\n", lines);
+    }
+
+    #[test]
+    fn entry_at_range() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("mouse\tB\tC\nrat\tD\tC\nvole\tA\tC\nzebu\tB\tC\n".as_bytes());
+        assert_eq!(di.find_entry_at(1, 2).unwrap().word, "mouse");
+        assert_eq!(di.find_entry_at(0, 2).unwrap().word, "vole");
+        assert_eq!(di.find_entry_at(3, 2).unwrap().word, "rat");
+        assert!(di.find_entry_at(1, 3).is_none());
+        assert!(di.find_entry_at(4, 2).is_none());
+    }
+
+    #[test]
+    fn weighted_random() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("common\tA\tB\nrare\tB\tB\n".as_bytes());
+        assert_eq!(
+            di.add_frequencies("common 1000\nrare\t0\nbroken\n".as_bytes()),
+            2
+        );
+        assert_eq!(di.frequency("common"), 1000);
+        assert_eq!(di.frequency("missing"), 0);
+        // "rare" has a weight of zero
+        for _ in 0..20 {
+            assert_eq!(di.find_random_weighted(false).unwrap().0, "common");
+        }
+        assert!(di.find_random_weighted(true).is_ok());
+    }
+
+    #[test]
+    fn frequency_ranking() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("coma\tA\tB\ncome\tB\tB\ncommon\tC\tB\ncomputer\tD\tB\n".as_bytes());
+        di.add_frequencies("computer 10\ncome 50\ncommon 20\n".as_bytes());
+        let words: Vec<String> = di
+            .find_words_by_prefix("com")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.word)
+            .collect();
+        assert_eq!(words, ["come", "common", "computer", "coma"]);
+        let words: Vec<String> = di
+            .most_frequent(2)
+            .into_iter()
+            .map(|entry| entry.word)
+            .collect();
+        assert_eq!(words, ["come", "common"]);
+    }
+
+    #[test]
+    fn headwords_starting_with_00() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("00-database-url\tA\tB\n007 Bond\tB\tB\n00-Flag\tC\tB\n".as_bytes());
+        // Only the 00-database-* entries keep their names
+        assert_eq!(di.normalize_headwords(Normalization::Default), 2);
+        assert_eq!(di.find_entry("007 bond").unwrap().headword(), "007 Bond");
+        assert_eq!(di.find_entry("00flag").unwrap().headword(), "00-Flag");
+        assert_eq!(di.find_word("00-database-url").unwrap(), (0, 1));
+    }
+
+    #[test]
+    fn normalized_headwords() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("AT&T\tA\tB\nBar\tB\tB\nfoo\tC\tB\n".as_bytes());
+        assert_eq!(di.normalize_headwords(Normalization::Default), 2);
+        let lines: Vec<String> = di.entries().iter().map(|entry| entry.to_line()).collect();
+        assert_eq!(lines, ["att\tA\tB\tAT&T", "bar\tB\tB\tBar", "foo\tC\tB"]);
+        assert_eq!(di.compare_headwords("bar", "att"), Ordering::Greater);
+
+        // Queries are normalized the same way and find the original spelling
+        let mut di = IndexReader::new();
+        di.parse_dict_index("C++\tA\tB\nNaïve\tB\tB\n".as_bytes());
+        di.normalize_headwords(Normalization::Default);
+        let entry = di.find_entry(&Normalization::Default.apply("c++")).unwrap();
+        assert_eq!(entry.headword(), "C++");
+        let entry = di
+            .find_entry(&Normalization::Default.apply("NAÏVE"))
+            .unwrap();
+        assert_eq!(entry.headword(), "Naïve");
+        let words: Vec<String> = di
+            .find_words_by_prefix(&Normalization::Default.apply("Na"))
+            .unwrap()
+            .iter()
+            .map(|entry| entry.headword().to_string())
+            .collect();
+        assert_eq!(words, ["Naïve"]);
+    }
+
+    #[test]
+    fn entries_sharing_a_key() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("C\tA\tB\nC++\tB\tB\nCobol\tC\tB\n".as_bytes());
+        di.normalize_headwords(Normalization::Default);
+        let headwords: Vec<String> = di
+            .find_entries("c")
+            .unwrap()
+            .iter()
+            .map(|entry| entry.headword().to_string())
+            .collect();
+        assert_eq!(headwords, ["C", "C++"]);
+        assert_eq!(di.find_entry("c").unwrap().headword(), "C");
+        assert!(di.find_entries("co").is_err());
+    }
+
+    #[test]
+    fn alias_lookup() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("color\tA\tB\nmouse\tB\tB\n".as_bytes());
+        assert_eq!(
+            di.add_aliases("Colour\tColor\nmice\tmouse\nbroken\n\tmouse\n".as_bytes()),
+            2
+        );
+        assert_eq!(di.find_entry("colour").unwrap().word, "color");
+        assert_eq!(di.find_entry("mice").unwrap().offset, 1);
+        assert_eq!(di.resolve_alias("color"), None);
+        assert!(di.find_word("colour").is_err());
+    }
+
+    #[test]
+    fn reversed_suffix_match() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("kcah\tA\tB\nkca\tB\tB\ngnikcah\tC\tB\n".as_bytes());
+        let res = di.find_words_by_reversed_suffix("ck").unwrap();
+        let words: Vec<&str> = res.iter().map(|e| e.word.as_str()).collect();
+        assert_eq!(words, vec!["ack", "hack"]);
+    }
+
+    #[test]
+    fn substring_and_suffix_match() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index(&b"ack\tA\tB\nhack\tB\tB\nhacker\tC\tB\nnak\tD\tB\n"[..]);
+        let words =
+            |res: Vec<IndexEntry>| -> Vec<String> { res.into_iter().map(|e| e.word).collect() };
+        assert_eq!(
+            words(di.find_words_by_substring("ack").unwrap()),
+            vec!["ack", "hack", "hacker"]
+        );
+        assert_eq!(
+            words(di.find_words_by_substring("ke").unwrap()),
+            vec!["hacker"]
+        );
+        assert!(di.find_words_by_substring("acker!").unwrap().is_empty());
+        assert_eq!(
+            words(di.find_words_by_suffix("ack").unwrap()),
+            vec!["ack", "hack"]
+        );
+    }
+
+    #[test]
+    fn word_match() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index(&b"ACK\tA\tB\npositive ack\tB\tB\nack-ack ack\tC\tB\nhack\tD\tB\n"[..]);
+        let res = di.find_words_by_word("Ack").unwrap();
+        let words: Vec<&str> = res.iter().map(|e| e.word.as_str()).collect();
+        assert_eq!(words, vec!["ACK", "ack-ack ack", "positive ack"]);
+        assert!(di.find_words_by_word("ac").unwrap().is_empty());
+    }
+
+    #[test]
+    fn first_and_last_word_match() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index(&b"give up\tA\tB\nmake up\tB\tB\ngive\tC\tB\nup to date\tD\tB\n"[..]);
+        let words =
+            |res: Vec<IndexEntry>| -> Vec<String> { res.into_iter().map(|e| e.word).collect() };
+        assert_eq!(
+            words(di.find_words_by_first_word("Give").unwrap()),
+            vec!["give", "give up"]
+        );
+        assert_eq!(
+            words(di.find_words_by_last_word("up").unwrap()),
+            vec!["give up", "make up"]
+        );
+        assert!(di.find_words_by_last_word("to").unwrap().is_empty());
+    }
+
+    #[test]
+    fn ngram_match() {
+        let mut di = IndexReader::new();
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests");
+        path.push("db.expect.index");
+        let file = File::open(path).unwrap();
+        let file = BufReader::new(file);
+        di.parse_dict_index(file);
+        let res = di.find_words_by_ngram("headwrod4", 0.4).unwrap();
+        assert_eq!(res[0].word, "headword4");
+        assert!(res.iter().all(|e| e.word.starts_with("headword")));
+        assert!(di.find_words_by_ngram("xyzzy", 0.4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn phonetic_match() {
+        let mut di = IndexReader::new();
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests");
+        path.push("db.expect.index");
+        let file = File::open(path).unwrap();
+        let file = BufReader::new(file);
+        di.parse_dict_index(file);
+        assert!(di.find_words_by_phonetic(Phonetic::Cologne, "at").is_err());
+        di.build_phonetic_index(Phonetic::Cologne);
+        let res = di.find_words_by_phonetic(Phonetic::Cologne, "ad").unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].word, "att");
+    }
+
+    #[test]
+    fn lev_match() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index(&b"Hacker\tA\tB\nhack\tB\tB\nhacked\tC\tB\nhackers\tD\tB\n"[..]);
+        assert!(di.find_words_by_distance("hacker").is_err());
+        di.build_deletion_index(1);
+        let res = di.find_words_by_distance("hacker").unwrap();
+        let words: Vec<&str> = res.iter().map(|e| e.word.as_str()).collect();
+        assert_eq!(words, vec!["Hacker", "hacked", "hackers"]);
+        di.build_deletion_index(2);
+        assert_eq!(di.find_words_by_distance("hacker").unwrap().len(), 4);
+    }
+
+    #[test]
+    fn fst_match() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index(
+            &b"hack\tA\tB\nhacked\tB\tB\nhacker\tC\tB\nhacker\tD\tB\nhackers\tE\tB\nzap\tF\tB\n"[..],
+        );
+        let lines = |res: Vec<IndexEntry>| -> Vec<String> {
+            res.iter().map(|entry| entry.to_line()).collect()
+        };
+        let linear = lines(di.find_words_by_prefix("hacke").unwrap());
+        di.build_fst_index(1);
+        assert!(di.has_fst_index());
+        assert_eq!(lines(di.find_words_by_prefix("hacke").unwrap()), linear);
+        assert_eq!(linear.len(), 4);
+        let res = di.find_words_by_distance("hacker").unwrap();
+        let offsets: Vec<u64> = res.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![2, 3, 1, 4]);
+    }
+
+    #[test]
+    fn stem_match() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index(&b"run\tA\tB\nrunner\tB\tB\nruns\tC\tB\n"[..]);
+        assert!(di.find_words_by_stem("running").is_err());
+        di.set_stem_language(Algorithm::English);
+        let res = di.find_words_by_stem("running").unwrap();
+        let words: Vec<&str> = res.iter().map(|e| e.word.as_str()).collect();
+        assert_eq!(words, vec!["run", "runs"]);
+    }
+
+    #[test]
+    fn planned_side_indexes() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index(&b"hack\tA\tB\nhacker\tB\tB\nrun\tC\tB\nruns\tD\tB\n"[..]);
+        let plan = SidePlan {
+            phonetic: vec![Phonetic::Soundex],
+            deletions: Some(1),
+            fst: None,
+            stem_language: Some(Algorithm::English),
+        };
+        di.plan_side_indexes(plan.clone());
+        let words = |res: Result<Vec<IndexEntry>, DictError>| -> Vec<String> {
+            res.unwrap().into_iter().map(|entry| entry.word).collect()
+        };
+        // Answered by scanning the entries until the indexes are installed
+        let scanned = (
+            words(di.find_words_by_distance("hacke")),
+            words(di.find_words_by_phonetic(Phonetic::Soundex, "hak")),
+            words(di.find_words_by_stem("running")),
+        );
+        assert_eq!(scanned.0, ["hack", "hacker"]);
+        assert!(di
+            .find_words_by_phonetic(Phonetic::Metaphone, "hak")
+            .is_err());
+        let side = SideIndexes::build(di.entries(), &plan);
+        assert!(di.install_side_indexes(side));
+        assert!(di.side_plan().is_none());
+        assert!(di.has_phonetic_index(Phonetic::Soundex));
+        let indexed = (
+            words(di.find_words_by_distance("hacke")),
+            words(di.find_words_by_phonetic(Phonetic::Soundex, "hak")),
+            words(di.find_words_by_stem("running")),
+        );
+        assert_eq!(scanned, indexed);
+        let stale = SideIndexes::build(&di.entries()[1..], &plan);
+        assert!(!di.install_side_indexes(stale));
+    }
+
+    #[test]
+    fn translit_lookup() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("москва\tA\tB\nмосковский\tB\tB\n".as_bytes());
+        assert!(di.find_entry("moskva").is_err());
+        di.set_transliterations(vec![Transliteration::Cyrillic]);
+        assert_eq!(di.find_entry("moskva").unwrap().word, "москва");
+        assert_eq!(di.find_entry("москва").unwrap().offset, 0);
+        assert!(di.find_entry("moskov").is_err());
+    }
+
+    #[test]
+    fn collated_lookup() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("apfel\tA\tB\närger\tB\tB\nzebra\tC\tB\n".as_bytes());
+        di.set_collation(Collation::new("de").unwrap());
+        let words: Vec<String> = di.idx.iter().map(|e| e.word.clone()).collect();
+        assert_eq!(words, vec!["apfel", "ärger", "zebra"]);
+        for word in &words {
+            assert!(di.find_word(word).is_ok());
+        }
+        assert!(di.find_word("bär").is_err());
+    }
+
+    #[test]
+    fn index_ranges() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("bar\tA\tB\nbaz\tB\tB\nfoo\tC\tB\nbazaar\tD\tB\n".as_bytes());
+        let words = |entries: Vec<&IndexEntry>| -> Vec<String> {
+            entries.iter().map(|e| e.word.clone()).collect()
+        };
+        assert_eq!(di.iter().count(), 4);
+        assert_eq!(
+            words(di.range("baz".."foo").collect()),
+            vec!["baz", "bazaar"]
+        );
+        assert_eq!(
+            words(di.range("baz"..="foo").collect()),
+            vec!["baz", "bazaar", "foo"]
+        );
+        assert_eq!(words(di.range(.."bas").collect()), vec!["bar"]);
+        assert_eq!(di.range("z".."a").count(), 0);
+        assert_eq!(
+            words(di.entries_with_prefix("baz").collect()),
+            vec!["baz", "bazaar"]
+        );
+        assert_eq!(di.entries_with_prefix("qux").count(), 0);
+        di.set_collation(Collation::new("de").unwrap());
+        assert_eq!(
+            words(di.entries_with_prefix("ba").collect()),
+            vec!["bar", "baz", "bazaar"]
+        );
+    }
+
+    #[test]
+    fn malformed_index() {
+        let mut di = IndexReader::new();
+        assert!(di
+            .try_parse_dict_index("a\tA\tB\nb\tB\n".as_bytes())
+            .is_err());
+        assert!(di.entries().is_empty());
+        assert_eq!(di.try_parse_dict_index("a\tA\tB\n".as_bytes()).unwrap(), 1);
+    }
+
+    #[test]
+    fn chunked_parse() {
+        let mut text = String::new();
+        for i in 0..100 {
+            text.push_str(&format!("word{}\tA\t{}\r\n", i, encode_base64(i)));
+        }
+        let words = |entries: Vec<IndexEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.word).collect()
+        };
+        let whole = words(parse_lines(text.as_bytes(), 1).unwrap());
+        assert_eq!(whole.len(), 100);
+        assert_eq!(whole[99], "word99");
+        for chunks in [2, 7, 100, 1000].iter() {
+            assert_eq!(words(parse_lines(text.as_bytes(), *chunks).unwrap()), whole);
         }
+        // A malformed line in any chunk fails the whole index
+        text.push_str("broken\n");
+        assert!(parse_lines(text.as_bytes(), 7).is_err());
+        assert!(parse_lines(b"", 7).unwrap().is_empty());
     }
 }
@@ -0,0 +1,250 @@
+use crate::errors::DictError;
+use crate::{DictReader, IndexReader, ReadAt};
+
+// Character encoding of the definitions in a .dict file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    // 8 bit data (00-database-8bit-new), read as ISO-8859-1
+    Latin1,
+    // Latin1 with printable characters in 0x80-0x9f, common in legacy data
+    Windows1252,
+    // Any other encoding known to encoding_rs, e.g. KOI8-R, declared in the
+    // configuration of the database
+    Legacy(&'static encoding_rs::Encoding),
+}
+
+impl Encoding {
+    pub fn decode(self, bytes: Vec<u8>) -> Result<String, DictError> {
+        Ok(match self {
+            Encoding::Utf8 => String::from_utf8(bytes)?,
+            Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+            Encoding::Windows1252 => bytes.iter().map(|&b| windows1252(b)).collect(),
+            Encoding::Legacy(encoding) => {
+                encoding.decode_without_bom_handling(&bytes).0.into_owned()
+            }
+        })
+    }
+
+    // Looks up an encoding by a name like "utf-8", "latin1" or "koi8-r"
+    pub fn for_label(label: &str) -> Option<Encoding> {
+        match label.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            // encoding_rs treats these as Windows-1252, like web browsers
+            "latin1" | "iso-8859-1" | "iso8859-1" => Some(Encoding::Latin1),
+            "windows-1252" | "cp1252" => Some(Encoding::Windows1252),
+            label => encoding_rs::Encoding::for_label(label.as_bytes()).map(Encoding::Legacy),
+        }
+    }
+
+    // Guesses the 8 bit encoding of text that is not valid UTF-8. The range
+    // 0x80-0x9f holds control characters in Latin1, so text using it is most
+    // likely Windows-1252.
+    pub fn guess_legacy(bytes: &[u8]) -> Encoding {
+        if bytes.iter().any(|b| (0x80..=0x9f).contains(b)) {
+            Encoding::Windows1252
+        } else {
+            Encoding::Latin1
+        }
+    }
+}
+
+fn windows1252(b: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž',
+        '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}',
+        'ž', 'Ÿ',
+    ];
+    match b {
+        0x80..=0x9f => HIGH[(b - 0x80) as usize],
+        _ => b as char,
+    }
+}
+
+// The 00-database-* entries dictfmt adds to every database
+#[derive(Clone, Debug)]
+pub struct DatabaseMetadata {
+    pub short: String,
+    // All of the info entry, often several paragraphs on the source and
+    // license of the database
+    pub info: String,
+    pub url: Option<String>,
+    pub utf8: bool,
+    pub eightbit_new: bool,
+    // Version of dictfmt that built the database (00-database-dictfmt-*)
+    pub dictfmt: Option<String>,
+}
+
+impl Default for DatabaseMetadata {
+    fn default() -> Self {
+        DatabaseMetadata {
+            short: "Unknown".to_string(),
+            info: "Unknown".to_string(),
+            url: None,
+            utf8: false,
+            eightbit_new: false,
+            dictfmt: None,
+        }
+    }
+}
+
+impl DatabaseMetadata {
+    pub fn load<R: ReadAt>(
+        indexreader: &IndexReader,
+        dictreader: &DictReader<R>,
+    ) -> DatabaseMetadata {
+        let mut metadata = DatabaseMetadata::default();
+        if let Some(short) = first_line(indexreader, dictreader, "short") {
+            metadata.short = short;
+        }
+        if let Some(info) = full_text(indexreader, dictreader, "info") {
+            metadata.info = info;
+        }
+        metadata.url = first_line(indexreader, dictreader, "url");
+        metadata.utf8 = has_entry(indexreader, "utf8");
+        metadata.eightbit_new = has_entry(indexreader, "8bit-new");
+        for prefix in &["00databasedictfmt", "00-database-dictfmt-"] {
+            if let Ok(entries) = indexreader.find_words_by_prefix(prefix) {
+                if let Some(entry) = entries.first() {
+                    // The entry text carries the dotted version number
+                    let version = match dictreader.find(entry.offset, entry.length) {
+                        Ok(res) => res
+                            .trim()
+                            .trim_start_matches("00-database-dictfmt-")
+                            .to_string(),
+                        Err(_) => entry.word[prefix.len()..].to_string(),
+                    };
+                    metadata.dictfmt = Some(version);
+                }
+            }
+        }
+        metadata
+    }
+
+    // Encoding of the definitions, as declared by the flags
+    pub fn encoding(&self) -> Encoding {
+        if self.eightbit_new && !self.utf8 {
+            Encoding::Latin1
+        } else {
+            Encoding::Utf8
+        }
+    }
+}
+
+// Whether a headword is one of the 00-database-* entries, which are not
+// meant to be found by lookups
+pub fn is_metadata_headword(word: &str) -> bool {
+    word.starts_with("00database") || word.starts_with("00-database-")
+}
+
+// Headwords of a metadata entry, with and without dictfmt --allchars
+fn headwords(name: &str) -> [String; 2] {
+    [
+        format!("00database{}", name.replace('-', "")),
+        format!("00-database-{}", name),
+    ]
+}
+
+fn has_entry(indexreader: &IndexReader, name: &str) -> bool {
+    headwords(name)
+        .iter()
+        .any(|headword| indexreader.find_word(headword).is_ok())
+}
+
+// Returns the first line of an entry's value. Depending on the dictfmt
+// version the value may be preceded by a line repeating the headword.
+fn first_line<R: ReadAt>(
+    indexreader: &IndexReader,
+    dictreader: &DictReader<R>,
+    name: &str,
+) -> Option<String> {
+    for headword in headwords(name).iter() {
+        if let Ok((offset, length)) = indexreader.find_word(headword) {
+            if let Ok(res) = dictreader.find(offset, length) {
+                return res
+                    .lines()
+                    .filter(|line| !line.starts_with("00-database-"))
+                    .map(|line| line.trim())
+                    .find(|line| !line.is_empty())
+                    .map(|line| line.to_string());
+            }
+        }
+    }
+    None
+}
+
+// Returns the value of an entry without the line repeating the headword and
+// without blank lines around it, keeping its layout
+fn full_text<R: ReadAt>(
+    indexreader: &IndexReader,
+    dictreader: &DictReader<R>,
+    name: &str,
+) -> Option<String> {
+    for headword in headwords(name).iter() {
+        if let Ok((offset, length)) = indexreader.find_word(headword) {
+            if let Ok(res) = dictreader.find(offset, length) {
+                let lines: Vec<&str> = res
+                    .lines()
+                    .skip_while(|line| line.starts_with("00-database-") || line.trim().is_empty())
+                    .map(|line| line.trim_end())
+                    .collect();
+                let text = lines.join("\n").trim_end().to_string();
+                return if text.is_empty() { None } else { Some(text) };
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::PathBuf;
+
+    #[test]
+    fn load_metadata() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("dicts");
+        path.push("nno-nob.index");
+        let mut di = IndexReader::new();
+        di.parse_dict_index(BufReader::new(File::open(&path).unwrap()));
+        path.set_extension("dict");
+        let dr = DictReader::from_bytes(std::fs::read(&path).unwrap()).unwrap();
+        let metadata = DatabaseMetadata::load(&di, &dr);
+        assert!(metadata.utf8);
+        assert!(!metadata.eightbit_new);
+        assert_eq!(metadata.encoding(), Encoding::Utf8);
+        assert_eq!(metadata.dictfmt.as_deref(), Some("1.13.0"));
+        assert!(metadata.url.is_some());
+        assert_ne!(metadata.short, "Unknown");
+        assert!(metadata
+            .info
+            .starts_with("Norwegian Nynorsk-Norwegian Bokmål FreeDict Dictionary\n\nMaintainer"));
+        assert!(metadata.info.lines().count() > 20);
+
+        assert!(di.remove_metadata_entries() > 0);
+        assert!(di.find_word("00databaseinfo").is_err());
+        assert!(!di.entries().iter().any(|e| is_metadata_headword(&e.word)));
+    }
+
+    #[test]
+    fn legacy_encodings() {
+        let latin1 = b"Gr\xfc\xdfe".to_vec();
+        assert_eq!(Encoding::guess_legacy(&latin1), Encoding::Latin1);
+        assert_eq!(Encoding::Latin1.decode(latin1).unwrap(), "Grüße");
+        let cp1252 = b"\x93quoted\x94 \x80".to_vec();
+        assert_eq!(Encoding::guess_legacy(&cp1252), Encoding::Windows1252);
+        assert_eq!(Encoding::Windows1252.decode(cp1252).unwrap(), "“quoted” €");
+        assert!(Encoding::Utf8.decode(b"\xfc".to_vec()).is_err());
+
+        let koi8r = Encoding::for_label("KOI8-R").unwrap();
+        assert_eq!(
+            koi8r.decode(b"\xd3\xcc\xcf\xd7\xcf".to_vec()).unwrap(),
+            "слово"
+        );
+        assert_eq!(Encoding::for_label("latin1"), Some(Encoding::Latin1));
+        assert!(Encoding::for_label("klingon").is_none());
+    }
+}
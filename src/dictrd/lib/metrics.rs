@@ -0,0 +1,165 @@
+// Metrics in the Prometheus text exposition format, written without a
+// client library. Counters that are not kept in Statistics live in Metrics
+// and start from zero with every run.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// Upper bounds of the buckets of latency histograms in seconds
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    // Observations up to each bound, cumulative like in the exposition
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Histogram {
+        Histogram {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+pub struct Metrics {
+    // Time MATCH commands took
+    pub match_latency: Histogram,
+    // Definitions sent by database
+    pub definitions: BTreeMap<String, u64>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            match_latency: Histogram::new(&LATENCY_BUCKETS),
+            definitions: BTreeMap::new(),
+        }
+    }
+
+    pub fn record_definition(&mut self, database: &str) {
+        *self.definitions.entry(database.to_string()).or_default() += 1;
+    }
+}
+
+// Text of a scrape, one metric family after the other
+#[derive(Default)]
+pub struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    pub fn new() -> Exposition {
+        Exposition::default()
+    }
+
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    // A counter or gauge without labels
+    pub fn metric(&mut self, name: &str, kind: &str, help: &str, value: f64) {
+        self.header(name, kind, help);
+        let _ = writeln!(self.text, "{} {}", name, value);
+    }
+
+    // A counter or gauge with a value per label value, e.g. per database
+    pub fn labeled<'a, I>(&mut self, name: &str, kind: &str, help: &str, label: &str, values: I)
+    where
+        I: IntoIterator<Item = (&'a str, f64)>,
+    {
+        self.header(name, kind, help);
+        for (label_value, value) in values {
+            let _ = writeln!(
+                self.text,
+                "{}{{{}=\"{}\"}} {}",
+                name,
+                label,
+                escape(label_value),
+                value
+            );
+        }
+    }
+
+    pub fn histogram(&mut self, name: &str, help: &str, histogram: &Histogram) {
+        self.header(name, "histogram", help);
+        for (bound, count) in histogram.bounds.iter().zip(histogram.counts.iter()) {
+            let _ = writeln!(self.text, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(
+            self.text,
+            "{}_bucket{{le=\"+Inf\"}} {}",
+            name, histogram.count
+        );
+        let _ = writeln!(self.text, "{}_sum {}", name, histogram.sum);
+        let _ = writeln!(self.text, "{}_count {}", name, histogram.count);
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposition() {
+        let mut histogram = Histogram::new(&[0.1, 1.0]);
+        histogram.observe(0.05);
+        histogram.observe(0.5);
+        histogram.observe(2.0);
+        let mut exposition = Exposition::new();
+        exposition.metric("up_seconds", "gauge", "Uptime", 12.0);
+        exposition.labeled(
+            "defines_total",
+            "counter",
+            "DEFINEs",
+            "database",
+            vec![("a\"b", 3.0)],
+        );
+        exposition.histogram("latency_seconds", "Latency", &histogram);
+        assert_eq!(
+            exposition.finish(),
+            "# HELP up_seconds Uptime\n# TYPE up_seconds gauge\nup_seconds 12\n\
+             # HELP defines_total DEFINEs\n# TYPE defines_total counter\n\
+             defines_total{database=\"a\\\"b\"} 3\n\
+             # HELP latency_seconds Latency\n# TYPE latency_seconds histogram\n\
+             latency_seconds_bucket{le=\"0.1\"} 1\nlatency_seconds_bucket{le=\"1\"} 2\n\
+             latency_seconds_bucket{le=\"+Inf\"} 3\nlatency_seconds_sum 2.55\n\
+             latency_seconds_count 3\n"
+        );
+    }
+}
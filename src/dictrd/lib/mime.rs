@@ -0,0 +1,169 @@
+// Content types of articles. Most databases are plain text, but converted
+// dictionaries (StarDict, MDX, ...) may contain HTML or Markdown, which the
+// server announces in the MIME headers of OPTION MIME and clients can strip.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContentType {
+    Plain,
+    Html,
+    Markdown,
+}
+
+impl ContentType {
+    pub fn from_mime(mime: &str) -> Option<ContentType> {
+        match mime.to_lowercase().as_str() {
+            "text/plain" => Some(ContentType::Plain),
+            "text/html" => Some(ContentType::Html),
+            "text/x-markdown" | "text/markdown" => Some(ContentType::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            ContentType::Plain => "text/plain",
+            ContentType::Html => "text/html",
+            ContentType::Markdown => "text/x-markdown",
+        }
+    }
+
+    // Header preceding text responses when MIME headers are enabled
+    pub fn header(self) -> String {
+        format!(
+            "Content-type: {}; charset=utf-8\nContent-transfer-encoding: 8bit\n\n",
+            self.mime()
+        )
+    }
+
+    // Converts an article to plain text for display in a terminal
+    pub fn to_plain_text(self, text: &str) -> String {
+        match self {
+            ContentType::Plain => text.to_string(),
+            ContentType::Html => strip_html(text),
+            ContentType::Markdown => strip_markdown(text),
+        }
+    }
+}
+
+fn strip_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        result.push_str(&decode_entities(&rest[..start]));
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let tag = rest[start + 1..end].trim().to_lowercase();
+        let name = tag
+            .trim_start_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or("");
+        // Block elements end a line
+        if matches!(name, "br" | "br/" | "p" | "div" | "li" | "tr") && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(&decode_entities(rest));
+    result
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) if end <= 8 => end,
+            _ => {
+                result.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let decoded = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            entity => entity
+                .strip_prefix('#')
+                .and_then(|code| match code.strip_prefix('x') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => code.parse().ok(),
+                })
+                .and_then(std::char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn strip_markdown(text: &str) -> String {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_start_matches('#').trim_start_matches(' ');
+        let mut plain = String::with_capacity(line.len());
+        let mut rest = line;
+        // Links [text](url) keep their text
+        while let Some(start) = rest.find('[') {
+            let link = rest[start..].find("](").and_then(|mid| {
+                let close = rest[start + mid..].find(')')?;
+                Some((start + mid, start + mid + close))
+            });
+            match link {
+                Some((mid, close)) => {
+                    plain.push_str(&rest[..start]);
+                    plain.push_str(&rest[start + 1..mid]);
+                    rest = &rest[close + 1..];
+                }
+                None => {
+                    plain.push_str(&rest[..=start]);
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        plain.push_str(rest);
+        lines.push(plain.replace("**", "").replace("__", "").replace('`', ""));
+    }
+    let mut result = lines.join("\n");
+    if text.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text() {
+        let html = "<b>cat</b> &amp; dog<br>see <a href=\"x\">mouse</a> &#233;&lt;";
+        assert_eq!(
+            ContentType::Html.to_plain_text(html),
+            "cat & dog\nsee mouse é<"
+        );
+        let markdown = "# cat\n**noun** see [mouse](dict://mouse) or `dog`\n";
+        assert_eq!(
+            ContentType::Markdown.to_plain_text(markdown),
+            "cat\nnoun see mouse or dog\n"
+        );
+        assert_eq!(ContentType::from_mime("text/HTML"), Some(ContentType::Html));
+    }
+}
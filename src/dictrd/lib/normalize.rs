@@ -0,0 +1,118 @@
+// Normalization of queries before they are looked up in an index. The mode
+// of a database follows the flags dictfmt stored in its index.
+//
+// Unless the database is case sensitive, case is folded fully, so that
+// "Füße" and "FÜSSE" meet. The text is composed, so that precomposed and
+// decomposed accents meet.
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+    // Fold case, strip accents and everything but alphanumerics and
+    // whitespace
+    Default,
+    // Fold case only, the index was built with dictfmt --allchars
+    AllChars,
+    // Like Default but keeping case, the index was built with dictfmt
+    // --case-sensitive
+    CaseSensitive,
+    // Compose only, the index was built with both options
+    AllCharsCaseSensitive,
+}
+
+impl Normalization {
+    // The mode for the 00-database-allchars and 00-database-case-sensitive
+    // flags of an index
+    pub fn from_flags(allchars: bool, case_sensitive: bool) -> Normalization {
+        match (allchars, case_sensitive) {
+            (false, false) => Normalization::Default,
+            (true, false) => Normalization::AllChars,
+            (false, true) => Normalization::CaseSensitive,
+            (true, true) => Normalization::AllCharsCaseSensitive,
+        }
+    }
+
+    pub fn allchars(self) -> bool {
+        matches!(
+            self,
+            Normalization::AllChars | Normalization::AllCharsCaseSensitive
+        )
+    }
+
+    pub fn case_sensitive(self) -> bool {
+        matches!(
+            self,
+            Normalization::CaseSensitive | Normalization::AllCharsCaseSensitive
+        )
+    }
+
+    pub fn apply(self, word: &str) -> String {
+        let fold = |c: char| match self.case_sensitive() {
+            true => vec![c],
+            false => fold_case(c),
+        };
+        match self.allchars() {
+            false => word
+                .nfkd()
+                .filter(|&c| !is_combining_mark(c))
+                .flat_map(fold)
+                .filter(|&c| c.is_alphanumeric() || c.is_whitespace())
+                .collect(),
+            true => word.chars().flat_map(fold).nfc().collect(),
+        }
+    }
+}
+
+// Lowercasing plus the full case foldings of Unicode that lowercasing does
+// not cover
+fn fold_case(c: char) -> Vec<char> {
+    match c {
+        'ß' | 'ẞ' => vec!['s', 's'],
+        'ς' => vec!['σ'],
+        'ﬀ' => vec!['f', 'f'],
+        'ﬁ' => vec!['f', 'i'],
+        'ﬂ' => vec!['f', 'l'],
+        // Dotted capital I lowercases to i with a combining dot above
+        'İ' => vec!['i'],
+        c => c.to_lowercase().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_query() {
+        assert_eq!(Normalization::Default.apply("AT&T"), "att");
+        assert_eq!(Normalization::AllChars.apply("AT&T"), "at&t");
+        assert_eq!(Normalization::CaseSensitive.apply("AT&T"), "ATT");
+        assert_eq!(Normalization::AllCharsCaseSensitive.apply("AT&T"), "AT&T");
+        assert_eq!(
+            Normalization::from_flags(false, true),
+            Normalization::CaseSensitive
+        );
+    }
+
+    #[test]
+    fn unicode() {
+        let default = Normalization::Default;
+        assert_eq!(default.apply("Füße"), "fusse");
+        assert_eq!(default.apply("FUSSE"), "fusse");
+        // Precomposed and decomposed ü
+        assert_eq!(default.apply("\u{fc}"), default.apply("u\u{308}"));
+        assert_eq!(default.apply("İstanbul"), "istanbul");
+        assert_eq!(default.apply("ﬁne"), "fine");
+        assert_eq!(default.apply("ΣΟΦΟΣ"), default.apply("σοφος"));
+
+        let allchars = Normalization::AllChars;
+        assert_eq!(allchars.apply("Füße"), "füsse");
+        assert_eq!(allchars.apply("FU\u{308}SSE"), "füsse");
+        assert_eq!(Normalization::CaseSensitive.apply("Füße"), "Fuße");
+        assert_eq!(
+            Normalization::AllCharsCaseSensitive.apply("FU\u{308}SSE"),
+            "FÜSSE"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Cmd {
     Unknown, // 3.2
     Define,  // 3.2
@@ -16,6 +16,7 @@ pub enum Cmd {
     Auth,     // 3.11
     SaslAuth, // 3.12
     SaslResp, // 3.12
+    Random,   // XRANDOM, an extension
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -36,10 +37,66 @@ pub struct Command {
     pub params: Vec<String>,
 }
 
+// A command of RFC 2229 with the number of parameters it takes after the
+// command word
 pub struct CommandDesc {
-    pub cmd_str: String,
+    pub cmd_str: &'static str,
     pub cmd: Cmd,
-    pub min_params: i8,
+    pub min_params: usize,
+    // None for any number
+    pub max_params: Option<usize>,
+}
+
+const fn desc(
+    cmd_str: &'static str,
+    cmd: Cmd,
+    min_params: usize,
+    max_params: Option<usize>,
+) -> CommandDesc {
+    CommandDesc {
+        cmd_str,
+        cmd,
+        min_params,
+        max_params,
+    }
+}
+
+pub const COMMANDS: [CommandDesc; 12] = [
+    desc("DEFINE", Cmd::Define, 2, Some(2)),
+    desc("MATCH", Cmd::Match, 3, Some(3)),
+    desc("SHOW", Cmd::Show, 1, Some(2)),
+    desc("CLIENT", Cmd::Client, 1, None),
+    desc("STATUS", Cmd::Status, 0, Some(0)),
+    desc("HELP", Cmd::Help, 0, Some(0)),
+    desc("QUIT", Cmd::Quit, 0, Some(0)),
+    desc("OPTION", Cmd::Option, 1, Some(2)),
+    desc("AUTH", Cmd::Auth, 2, Some(2)),
+    desc("SASLAUTH", Cmd::SaslAuth, 1, Some(2)),
+    desc("SASLRESP", Cmd::SaslResp, 1, Some(1)),
+    desc("XRANDOM", Cmd::Random, 0, Some(2)),
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    // The line holds no command
+    Empty,
+    // Too few or too many parameters, or an unknown subcommand
+    IllegalParameters,
+    // An unterminated string, a backslash at the end of the line or a
+    // control character
+    Malformed,
+}
+
+impl ParseError {
+    // The status line the server answers with
+    pub fn response(&self) -> &'static str {
+        match self {
+            ParseError::Empty => "500 Syntax error, command not recognized",
+            ParseError::IllegalParameters | ParseError::Malformed => {
+                "501 Syntax error, illegal parameters"
+            }
+        }
+    }
 }
 
 pub struct Parser {
@@ -64,97 +121,112 @@ impl Parser {
         Parser { result: command }
     }
 
-    pub fn parse(&mut self, string: &str) -> Result<Command, std::io::Error> {
-        let iter = string.chars();
-        let mut arg = Vec::<char>::with_capacity(20);
-
-        // True if arg parsed and whitespace found
-        let mut skip_whitespace = false;
-        let mut in_arg = false; // True if in an argument
-        let mut in_dblquote = false;
-        let mut args = Vec::<String>::new();
-        let mut quote = false;
-        // TODO: Implement single quotes
-        for ch in iter {
-            if quote {
-                if in_dblquote && ch == '\"' {
-                    arg.push(ch);
-                }
-                quote = false;
-                continue;
-            }
-            if ch == '\\' {
-                quote = true;
-                continue;
-            }
-            if ch == '"' {
-                if in_dblquote {
-                    args.push(arg.clone().into_iter().collect::<String>());
-                    arg.clear();
-                    in_arg = false;
-                    in_dblquote = false;
-                    skip_whitespace = true;
-                    arg.push(ch);
-                } else {
-                    in_dblquote = true;
-                }
+    // Splits a command line into its words and checks the parameters of the
+    // commands of RFC 2229. Other commands, like the extensions of the
+    // server, are returned as Cmd::Unknown for the caller to check.
+    pub fn parse(&mut self, string: &str) -> Result<Command, ParseError> {
+        let args = tokenize(string)?;
+        if args.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        let mut command = Command {
+            cmd: Cmd::Unknown,
+            subcmd: SubCmd::Unknown,
+            strategy: String::new(),
+            database: String::new(),
+            params: args,
+        };
+        let name = command.params[0].to_uppercase();
+        let desc = match COMMANDS.iter().find(|desc| desc.cmd_str == name) {
+            Some(desc) => desc,
+            None => return Ok(command),
+        };
+        let count = command.params.len() - 1;
+        if count < desc.min_params || desc.max_params.is_some_and(|max| count > max) {
+            return Err(ParseError::IllegalParameters);
+        }
+        match desc.cmd {
+            Cmd::Define => command.database = command.params[1].clone(),
+            Cmd::Match => {
+                command.database = command.params[1].clone();
+                command.strategy = command.params[2].clone();
             }
-            if ch.is_whitespace() {
-                if in_dblquote {
-                    arg.push(ch);
-                } else {
-                    if skip_whitespace {
-                        continue;
+            Cmd::Show => {
+                command.subcmd = match (command.params[1].to_uppercase().as_str(), count) {
+                    ("DB" | "DATABASES", 1) => SubCmd::Database,
+                    ("STRAT" | "STRATEGIES", 1) => SubCmd::Strategies,
+                    ("SERVER", 1) => SubCmd::Server,
+                    ("INFO", 2) => {
+                        command.database = command.params[2].clone();
+                        SubCmd::Info
                     }
-                    args.push(arg.clone().into_iter().collect::<String>());
-                    arg.clear();
-                    skip_whitespace = true;
+                    _ => return Err(ParseError::IllegalParameters),
                 }
             }
-            if ch.is_alphanumeric() || ch.is_ascii_punctuation() && ch != '\"' {
-                in_arg = true;
-                if skip_whitespace {
-                    skip_whitespace = false;
+            // XRANDOM [database] [RARE], all databases by default
+            Cmd::Random => {
+                let rare = |param: &String| param.eq_ignore_ascii_case("RARE");
+                command.database = match &command.params[1..] {
+                    [] => "*".to_string(),
+                    [flag] if rare(flag) => "*".to_string(),
+                    [database] => database.clone(),
+                    [database, flag] if rare(flag) => database.clone(),
+                    _ => return Err(ParseError::IllegalParameters),
                 }
-                arg.push(ch);
             }
+            _ => {}
         }
-        if in_arg {
-            args.push(arg.into_iter().collect::<String>());
-        }
+        command.cmd = desc.cmd;
+        Ok(command)
+    }
+}
 
-        //debug!("Found {} args: {:?}", args.len(), args);
-        let argc = args.len();
-        if argc == 0 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "501 Syntax error, illegal parameters",
-            ));
+// Splits a command line into words as RFC 2229 describes them: atoms
+// separated by spaces or tabs, and strings in double or single quotes. A
+// backslash takes the next character literally, in strings and atoms alike.
+// Quoted and unquoted parts without space between them form one word, so
+// "foo"'bar' is foobar.
+fn tokenize(line: &str) -> Result<Vec<String>, ParseError> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // Whether a word has begun, it may be an empty string
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => match chars.next() {
+                Some(c) if !c.is_control() => {
+                    word.push(c);
+                    in_word = true;
+                }
+                _ => return Err(ParseError::Malformed),
+            },
+            (' ' | '\t', None) => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (c, _) if c.is_control() => return Err(ParseError::Malformed),
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, _) => {
+                word.push(c);
+                in_word = true;
+            }
         }
-        let command = String::from_utf8_lossy(args[0].as_bytes());
-        let cmd = match command.to_uppercase().as_str() {
-            "DEFINE" => Cmd::Define,
-            "MATCH" => Cmd::Match,
-            "SHOW" => Cmd::Show,
-            "CLIENT" => Cmd::Client,
-            "STATUS" => Cmd::Status,
-            "HELP" => Cmd::Help,
-            "QUIT" => Cmd::Quit,
-            "OPTION" => Cmd::Option,
-            "AUTH" => Cmd::Auth,
-            "SASLAUTH" => Cmd::SaslAuth,
-            "SASLRESP" => Cmd::SaslResp,
-            _ => Cmd::Unknown,
-        };
-        //println!("COMMAND={}, arg[1] = {}, cmd={:?}", command, args[1], cmd);
-        Ok(Command {
-            cmd,
-            subcmd: SubCmd::Unknown,
-            strategy: String::new(),
-            database: String::new(),
-            params: args,
-        })
     }
+    if quote.is_some() {
+        return Err(ParseError::Malformed);
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
 }
 
 #[cfg(test)]
@@ -164,7 +236,7 @@ mod tests {
     #[test]
     fn parser_show() {
         let mut parser = Parser::new();
-        let result = parser.parse("SHOW    DATABASE \"foo b\\\"ar\"").unwrap();
+        let result = parser.parse("SHOW    INFO \"foo b\\\"ar\"").unwrap();
         println!("{:?}", result);
         assert_eq!(result.cmd, Cmd::Show);
     }
@@ -194,5 +266,87 @@ mod tests {
         let result = parser.parse("DEFINE * shortcake").unwrap();
         println!("{:?}", result);
         assert_eq!(result.cmd, Cmd::Define);
+        assert_eq!(result.database, "*");
+    }
+
+    #[test]
+    fn parser_arity() {
+        let mut parser = Parser::new();
+        let result = parser.parse("show info jargon").unwrap();
+        assert_eq!(result.subcmd, SubCmd::Info);
+        assert_eq!(result.database, "jargon");
+        let result = parser.parse("MATCH foldoc prefix lisp").unwrap();
+        assert_eq!(result.strategy, "prefix");
+        assert_eq!(
+            parser.parse("SHOW STRAT").unwrap().subcmd,
+            SubCmd::Strategies
+        );
+        assert_eq!(parser.parse("CLIENT a b c").unwrap().cmd, Cmd::Client);
+        assert_eq!(
+            parser.parse("XDEFINEMATCH * . x").unwrap().cmd,
+            Cmd::Unknown
+        );
+        assert_eq!(parser.parse("XRANDOM").unwrap().database, "*");
+        assert_eq!(parser.parse("xrandom rare").unwrap().database, "*");
+        assert_eq!(parser.parse("XRANDOM jargon").unwrap().database, "jargon");
+        let result = parser.parse("XRANDOM devils RARE").unwrap();
+        assert_eq!(result.cmd, Cmd::Random);
+        assert_eq!(result.database, "devils");
+
+        let illegal = Err(ParseError::IllegalParameters);
+        assert_eq!(parser.parse("DEFINE jargon"), illegal);
+        assert_eq!(parser.parse("MATCH jargon exact foo bar"), illegal);
+        assert_eq!(parser.parse("SHOW INFO"), illegal);
+        assert_eq!(parser.parse("SHOW DB jargon"), illegal);
+        assert_eq!(parser.parse("SHOW NOTHING"), illegal);
+        assert_eq!(parser.parse("QUIT now"), illegal);
+        assert_eq!(parser.parse("XRANDOM jargon often"), illegal);
+        assert_eq!(parser.parse("   "), Err(ParseError::Empty));
+        assert!(ParseError::Empty.response().starts_with("500 "));
+    }
+
+    #[test]
+    fn quoting() {
+        let words = |line: &str| tokenize(line).unwrap();
+        assert_eq!(words("DEFINE  *\tfoo"), vec!["DEFINE", "*", "foo"]);
+        assert_eq!(
+            words(r#"DEFINE * "ice cream""#),
+            vec!["DEFINE", "*", "ice cream"]
+        );
+        assert_eq!(
+            words("DEFINE * 'ice cream'"),
+            vec!["DEFINE", "*", "ice cream"]
+        );
+        // The other kind of quote is an ordinary character in a string
+        assert_eq!(words(r#"DEFINE * "don't""#)[2], "don't");
+        assert_eq!(words(r#"DEFINE * 'say "hi"'"#)[2], r#"say "hi""#);
+        // Backslash escapes in strings and atoms
+        assert_eq!(words(r#"DEFINE * "a \"b\" c""#)[2], r#"a "b" c"#);
+        assert_eq!(words(r"DEFINE * 'it\'s'")[2], "it's");
+        assert_eq!(words(r#"DEFINE * "back\\slash""#)[2], r"back\slash");
+        assert_eq!(words(r"DEFINE * ice\ cream")[2], "ice cream");
+        assert_eq!(words(r"DEFINE * \x")[2], "x");
+        // Empty strings are words, adjacent parts form one word
+        assert_eq!(words(r#"DEFINE "" foo"#), vec!["DEFINE", "", "foo"]);
+        assert_eq!(words(r#"DEFINE * "foo"'bar'baz"#)[2], "foobarbaz");
+        assert_eq!(
+            words(r#"MATCH "a b" exact c"#),
+            vec!["MATCH", "a b", "exact", "c"]
+        );
+        assert_eq!(words("DEFINE * Füße")[2], "Füße");
+        assert!(words("  ").is_empty());
+
+        let malformed = Err(ParseError::Malformed);
+        assert_eq!(tokenize(r#"DEFINE * "foo"#), malformed);
+        assert_eq!(tokenize("DEFINE * 'foo"), malformed);
+        assert_eq!(tokenize(r"DEFINE * foo\"), malformed);
+        assert_eq!(tokenize("DEFINE * fo\u{7}o"), malformed);
+        assert_eq!(tokenize("DEFINE * \"a\tb\""), malformed);
+        assert_eq!(tokenize("DEFINE * a\\\u{1}"), malformed);
+        assert_eq!(tokenize("DEFINE * \u{7f}"), malformed);
+
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(r#"DEFINE * "foo"#), Err(ParseError::Malformed));
+        assert!(ParseError::Malformed.response().starts_with("501 "));
     }
 }
@@ -0,0 +1,41 @@
+// Percent-encoding of URL parts, as in the paths of dict:// URLs and of the
+// HTTP gateway
+
+// Decodes %XX escapes, keeping a % that starts none
+pub fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match (bytes[i], text.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) if hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                u8::from_str_radix(hex, 16).ok()
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(b) => {
+                result.push(b);
+                i += 3;
+            }
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_escapes() {
+        assert_eq!(decode("hack%20value"), "hack value");
+        assert_eq!(decode("%C3%A9t%c3%a9"), "été");
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+    }
+}
@@ -0,0 +1,407 @@
+// Phonetic algorithms usable as match strategies. The keys of all index
+// entries are computed once when the index is loaded, see
+// IndexReader::build_phonetic_index.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Phonetic {
+    Cologne,    // Kölner Phonetik, for German
+    Caverphone, // Caverphone 2.0, for English
+    Soundex,    // American Soundex, for English names
+    Metaphone,  // Metaphone, for English
+}
+
+impl Phonetic {
+    pub const ALL: [Phonetic; 4] = [
+        Phonetic::Cologne,
+        Phonetic::Caverphone,
+        Phonetic::Soundex,
+        Phonetic::Metaphone,
+    ];
+
+    // Name of the match strategy
+    pub fn name(self) -> &'static str {
+        match self {
+            Phonetic::Cologne => "cologne",
+            Phonetic::Caverphone => "caverphone",
+            Phonetic::Soundex => "soundex",
+            Phonetic::Metaphone => "metaphone",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Phonetic::Cologne => "Match using Cologne phonetics (German)",
+            Phonetic::Caverphone => "Match using Caverphone 2.0 (English)",
+            Phonetic::Soundex => "Match using Soundex (English)",
+            Phonetic::Metaphone => "Match using Metaphone (English)",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Phonetic> {
+        Phonetic::ALL.iter().copied().find(|p| p.name() == name)
+    }
+
+    pub fn key(self, word: &str) -> String {
+        match self {
+            Phonetic::Cologne => cologne(word),
+            Phonetic::Caverphone => caverphone(word),
+            Phonetic::Soundex => soundex(word),
+            Phonetic::Metaphone => metaphone(word),
+        }
+    }
+}
+
+// Kölner Phonetik: every letter is mapped to a digit depending on its
+// neighbours, repeated digits are collapsed and all zeros except a leading
+// one are dropped.
+pub fn cologne(word: &str) -> String {
+    let chars: Vec<char> = word
+        .chars()
+        .flat_map(|c| c.to_uppercase())
+        .filter_map(|c| match c {
+            'Ä' => Some('A'),
+            'Ö' => Some('O'),
+            'Ü' => Some('U'),
+            'ß' => Some('S'),
+            'A'..='Z' => Some(c),
+            _ => None,
+        })
+        .collect();
+    let mut codes = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = if i > 0 { Some(chars[i - 1]) } else { None };
+        let next = chars.get(i + 1).copied();
+        let code = match c {
+            'A' | 'E' | 'I' | 'J' | 'O' | 'U' | 'Y' => "0",
+            'B' => "1",
+            'P' if next == Some('H') => "3",
+            'P' => "1",
+            'D' | 'T' if matches!(next, Some('C' | 'S' | 'Z')) => "8",
+            'D' | 'T' => "2",
+            'F' | 'V' | 'W' => "3",
+            'G' | 'K' | 'Q' => "4",
+            'C' if i == 0 => {
+                if matches!(
+                    next,
+                    Some('A' | 'H' | 'K' | 'L' | 'O' | 'Q' | 'R' | 'U' | 'X')
+                ) {
+                    "4"
+                } else {
+                    "8"
+                }
+            }
+            'C' if matches!(prev, Some('S' | 'Z')) => "8",
+            'C' if matches!(next, Some('A' | 'H' | 'K' | 'O' | 'Q' | 'U' | 'X')) => "4",
+            'C' => "8",
+            'X' if matches!(prev, Some('C' | 'K' | 'Q')) => "8",
+            'X' => "48",
+            'L' => "5",
+            'M' | 'N' => "6",
+            'R' => "7",
+            'S' | 'Z' => "8",
+            _ => "",
+        };
+        codes.push_str(code);
+    }
+    let mut result = String::new();
+    let mut last = None;
+    for (i, ch) in codes.chars().enumerate() {
+        if Some(ch) == last {
+            continue;
+        }
+        last = Some(ch);
+        if ch != '0' || i == 0 {
+            result.push(ch);
+        }
+    }
+    result
+}
+
+// Caverphone 2.0 as specified by the Caversham project, a sequence of
+// rewrite rules resulting in a key of ten characters.
+pub fn caverphone(word: &str) -> String {
+    let mut txt: String = word
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_lowercase())
+        .collect();
+    txt = replace_end(&txt, "e", "");
+    for (from, to) in &[
+        ("cough", "cou2f"),
+        ("rough", "rou2f"),
+        ("tough", "tou2f"),
+        ("enough", "enou2f"),
+        ("trough", "trou2f"),
+        ("gn", "2n"),
+    ] {
+        txt = replace_start(&txt, from, to);
+    }
+    txt = replace_end(&txt, "mb", "m2");
+    for (from, to) in &[
+        ("cq", "2q"),
+        ("ci", "si"),
+        ("ce", "se"),
+        ("cy", "sy"),
+        ("tch", "2ch"),
+        ("c", "k"),
+        ("q", "k"),
+        ("x", "k"),
+        ("v", "f"),
+        ("dg", "2g"),
+        ("tio", "sio"),
+        ("tia", "sia"),
+        ("d", "t"),
+        ("ph", "fh"),
+        ("b", "p"),
+        ("sh", "s2"),
+        ("z", "s"),
+    ] {
+        txt = txt.replace(from, to);
+    }
+    txt = txt
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            'a' | 'e' | 'i' | 'o' | 'u' if i == 0 => 'A',
+            'a' | 'e' | 'i' | 'o' | 'u' => '3',
+            _ => c,
+        })
+        .collect();
+    txt = txt.replace('j', "y");
+    txt = replace_start(&txt, "y3", "Y3");
+    txt = replace_start(&txt, "y", "A");
+    txt = txt.replace('y', "3");
+    txt = txt.replace("3gh3", "3kh3");
+    txt = txt.replace("gh", "22");
+    txt = txt.replace('g', "k");
+    for &(from, to) in &[
+        ('s', 'S'),
+        ('t', 'T'),
+        ('p', 'P'),
+        ('k', 'K'),
+        ('f', 'F'),
+        ('m', 'M'),
+        ('n', 'N'),
+    ] {
+        txt = collapse_runs(&txt, from, to);
+    }
+    txt = txt.replace("w3", "W3");
+    txt = txt.replace("wh3", "Wh3");
+    txt = replace_end(&txt, "w", "3");
+    txt = txt.replace('w', "2");
+    txt = replace_start(&txt, "h", "A");
+    txt = txt.replace('h', "2");
+    txt = txt.replace("r3", "R3");
+    txt = replace_end(&txt, "r", "3");
+    txt = txt.replace('r', "2");
+    txt = txt.replace("l3", "L3");
+    txt = replace_end(&txt, "l", "3");
+    txt = txt.replace('l', "2");
+    txt = txt.replace('2', "");
+    txt = replace_end(&txt, "3", "A");
+    txt = txt.replace('3', "");
+    txt.push_str("1111111111");
+    txt.truncate(10);
+    txt
+}
+
+// American Soundex: the first letter followed by the digits of the next
+// consonants, padded to four characters. Letters with the same digit are
+// coded once when adjacent or separated only by H or W.
+pub fn soundex(word: &str) -> String {
+    let letters: Vec<char> = word
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let first = match letters.first() {
+        Some(&first) => first,
+        None => return String::new(),
+    };
+    let digit = |c: char| match c {
+        'B' | 'F' | 'P' | 'V' => Some('1'),
+        'C' | 'G' | 'J' | 'K' | 'Q' | 'S' | 'X' | 'Z' => Some('2'),
+        'D' | 'T' => Some('3'),
+        'L' => Some('4'),
+        'M' | 'N' => Some('5'),
+        'R' => Some('6'),
+        _ => None,
+    };
+    let mut result = first.to_string();
+    let mut last = digit(first);
+    for &c in &letters[1..] {
+        if c == 'H' || c == 'W' {
+            continue;
+        }
+        let code = digit(c);
+        if let Some(code) = code {
+            if code != last.unwrap_or(' ') {
+                result.push(code);
+            }
+        }
+        last = code;
+        if result.len() == 4 {
+            break;
+        }
+    }
+    while result.len() < 4 {
+        result.push('0');
+    }
+    result
+}
+
+// Metaphone as published by Lawrence Philips: letters are mapped to the
+// sounds they stand for depending on their neighbours, vowels are only kept
+// at the start. "0" stands for "th".
+pub fn metaphone(word: &str) -> String {
+    let mut letters: Vec<char> = word
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    match (letters.first(), letters.get(1)) {
+        (Some('A'), Some('E'))
+        | (Some('G'), Some('N'))
+        | (Some('K'), Some('N'))
+        | (Some('P'), Some('N'))
+        | (Some('W'), Some('R')) => {
+            letters.remove(0);
+        }
+        (Some('X'), _) => letters[0] = 'S',
+        (Some('W'), Some('H')) => {
+            letters.remove(1);
+        }
+        _ => (),
+    }
+    let is_vowel = |c: Option<char>| matches!(c, Some('A' | 'E' | 'I' | 'O' | 'U'));
+    let mut key = String::new();
+    for (i, &c) in letters.iter().enumerate() {
+        let prev = if i > 0 { Some(letters[i - 1]) } else { None };
+        let next = letters.get(i + 1).copied();
+        let after = letters.get(i + 2).copied();
+        // Double letters sound like one, except for CC as in "accident"
+        if prev == Some(c) && c != 'C' {
+            continue;
+        }
+        match c {
+            'A' | 'E' | 'I' | 'O' | 'U' if i == 0 => key.push(c),
+            // Silent in "-mb"
+            'B' if prev == Some('M') && next.is_none() => (),
+            'C' if next == Some('I') && after == Some('A') => key.push('X'),
+            'C' if next == Some('H') && prev == Some('S') => key.push('K'),
+            'C' if next == Some('H') => key.push('X'),
+            // Silent in "sce", "sci" and "scy"
+            'C' if matches!(next, Some('I' | 'E' | 'Y')) && prev == Some('S') => (),
+            'C' if matches!(next, Some('I' | 'E' | 'Y')) => key.push('S'),
+            'C' => key.push('K'),
+            'D' if next == Some('G') && matches!(after, Some('E' | 'I' | 'Y')) => key.push('J'),
+            'D' => key.push('T'),
+            // Silent in "gh" unless at the end or before a vowel, in "-gn"
+            // and "-gned", and after the d of "dge"
+            'G' if next == Some('H') && after.is_some() && !is_vowel(after) => (),
+            'G' if next == Some('N') && (after.is_none() || letters[i + 2..] == ['E', 'D']) => (),
+            'G' if prev == Some('D') && matches!(next, Some('E' | 'I' | 'Y')) => (),
+            'G' if matches!(next, Some('E' | 'I' | 'Y')) => key.push('J'),
+            'G' => key.push('K'),
+            'H' if is_vowel(next) && !matches!(prev, Some('C' | 'G' | 'P' | 'S' | 'T')) => {
+                key.push('H')
+            }
+            'K' if prev != Some('C') => key.push('K'),
+            'P' if next == Some('H') => key.push('F'),
+            'P' => key.push('P'),
+            'Q' => key.push('K'),
+            'S' if next == Some('H') => key.push('X'),
+            'S' if next == Some('I') && matches!(after, Some('O' | 'A')) => key.push('X'),
+            'S' | 'Z' => key.push('S'),
+            'T' if next == Some('I') && matches!(after, Some('O' | 'A')) => key.push('X'),
+            'T' if next == Some('H') => key.push('0'),
+            // Silent in "tch"
+            'T' if next == Some('C') && after == Some('H') => (),
+            'T' => key.push('T'),
+            'V' => key.push('F'),
+            'W' | 'Y' if is_vowel(next) => key.push(c),
+            'X' => key.push_str("KS"),
+            'F' | 'J' | 'L' | 'M' | 'N' | 'R' => key.push(c),
+            _ => (),
+        }
+    }
+    key
+}
+
+fn replace_start(txt: &str, from: &str, to: &str) -> String {
+    match txt.strip_prefix(from) {
+        Some(rest) => format!("{}{}", to, rest),
+        None => txt.to_string(),
+    }
+}
+
+fn replace_end(txt: &str, from: &str, to: &str) -> String {
+    match txt.strip_suffix(from) {
+        Some(rest) => format!("{}{}", rest, to),
+        None => txt.to_string(),
+    }
+}
+
+// Replaces every run of the character from by a single character to
+fn collapse_runs(txt: &str, from: char, to: char) -> String {
+    let mut result = String::with_capacity(txt.len());
+    let mut in_run = false;
+    for c in txt.chars() {
+        if c == from {
+            if !in_run {
+                result.push(to);
+            }
+            in_run = true;
+        } else {
+            result.push(c);
+            in_run = false;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cologne_keys() {
+        assert_eq!(cologne("Wikipedia"), "3412");
+        assert_eq!(cologne("Müller-Lüdenscheidt"), "65752682");
+        assert_eq!(cologne("Meier"), cologne("Mayr"));
+        assert_eq!(cologne(""), "");
+    }
+
+    #[test]
+    fn caverphone_keys() {
+        assert_eq!(caverphone("Peter"), "PTA1111111");
+        assert_eq!(caverphone("Stevenson"), "STFNSN1111");
+        assert_eq!(caverphone("Lee"), "LA11111111");
+        assert_eq!(caverphone("Tom"), "TM11111111");
+    }
+
+    #[test]
+    fn soundex_keys() {
+        assert_eq!(soundex("Robert"), "R163");
+        assert_eq!(soundex("Rupert"), "R163");
+        assert_eq!(soundex("Rubin"), "R150");
+        assert_eq!(soundex("Ashcraft"), "A261");
+        assert_eq!(soundex("Tymczak"), "T522");
+        assert_eq!(soundex("Pfister"), "P236");
+        assert_eq!(soundex("Lee"), "L000");
+        assert_eq!(soundex(""), "");
+    }
+
+    #[test]
+    fn metaphone_keys() {
+        assert_eq!(metaphone("Smith"), "SM0");
+        assert_eq!(metaphone("Smyth"), "SM0");
+        assert_eq!(metaphone("Knight"), "NT");
+        assert_eq!(metaphone("Wright"), "RT");
+        assert_eq!(metaphone("Philips"), "FLPS");
+        assert_eq!(metaphone("Xavier"), "SFR");
+        assert_eq!(metaphone("judge"), "JJ");
+        assert_eq!(metaphone("school"), "SKL");
+        assert_eq!(metaphone(""), "");
+    }
+}
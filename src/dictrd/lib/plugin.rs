@@ -0,0 +1,193 @@
+// A database answered by an external program, like the plugins of dictd,
+// e.g. a script asking a thesaurus API or a morphological analyzer. The
+// program is run for each lookup with its arguments followed by
+//
+//   define -- <word>             the definition on stdout, nothing if
+//                                there is none
+//   match <strategy> -- <word>   the matching headwords on stdout, one per
+//                                line
+//
+// The -- keeps words starting with - from being taken for options. The word
+// is also written to its stdin, followed by a newline. A program
+// exiting with a status other than 0 or running longer than the timeout
+// fails the lookup; it is killed after the timeout.
+use crate::backend::Backend;
+use crate::dictionary::Definition;
+use std::env;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use wait_timeout::ChildExt;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Plugin {
+    // The program and its first arguments
+    command: Vec<String>,
+    timeout: Duration,
+}
+
+impl Plugin {
+    pub fn new(command: Vec<String>, timeout: Duration) -> Plugin {
+        Plugin { command, timeout }
+    }
+
+    // Checks that the program exists, looked up in PATH unless it is given
+    // with a directory, like when it is run
+    pub fn check(&self) -> io::Result<()> {
+        let program = match self.command.first() {
+            Some(program) => program,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no command")),
+        };
+        let found = match Path::new(program).components().count() > 1 {
+            true => Path::new(program).is_file(),
+            false => env::var_os("PATH").is_some_and(|paths| {
+                env::split_paths(&paths).any(|dir| dir.join(program).is_file())
+            }),
+        };
+        match found {
+            true => Ok(()),
+            false => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found", program),
+            )),
+        }
+    }
+
+    // Runs the program with the arguments of a lookup and returns its
+    // output
+    fn run(&self, args: &[&str], word: &str) -> io::Result<String> {
+        let (program, first) = match self.command.split_first() {
+            Some(command) => command,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no command")),
+        };
+        let mut child = Command::new(program)
+            .args(first)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // Written while the timeout runs, a program that does not read its
+        // input cannot hold up the lookup with a full pipe. One that exits
+        // without reading it closes the pipe early.
+        if let Some(mut stdin) = child.stdin.take() {
+            let word = word.to_string();
+            thread::spawn(move || {
+                let _ = writeln!(stdin, "{}", word);
+            });
+        }
+        // Read while the program runs, so that it cannot block on a full
+        // pipe
+        let mut stdout = child.stdout.take().unwrap();
+        let reader = thread::spawn(move || {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).map(|_| output)
+        });
+        let status = match child.wait_timeout(self.timeout)? {
+            Some(status) => status,
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{} timed out after {:?}", program, self.timeout),
+                ));
+            }
+        };
+        let output = reader.join().unwrap()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("{} failed: {}", program, status)));
+        }
+        String::from_utf8(output).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl Backend for Plugin {
+    fn define(&self, word: &str) -> io::Result<Vec<Definition>> {
+        let text = self.run(&["define", "--", word], word)?;
+        if text.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![Definition {
+            headword: word.to_string(),
+            text,
+        }])
+    }
+
+    fn match_words(&self, strategy: &str, word: &str) -> io::Result<Vec<String>> {
+        let text = self.run(&["match", strategy, "--", word], word)?;
+        Ok(text
+            .lines()
+            .map(str::trim)
+            .filter(|headword| !headword.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str) -> Plugin {
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            script.to_string(),
+            "sh".to_string(),
+        ];
+        Plugin::new(command, Duration::from_secs(2))
+    }
+
+    #[test]
+    fn lookups() {
+        let plugin = shell(
+            "case $1 in
+             define) if [ \"$2 $3\" = '-- cat' ]; then echo 'a small animal'; fi ;;
+             match) if [ \"$3 $4\" = '-- cat' ]; then printf 'cat\\ncatalog\\n'; fi ;;
+             esac",
+        );
+        let definitions = plugin.define("cat").unwrap();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].headword, "cat");
+        assert_eq!(definitions[0].text, "a small animal\n");
+        assert!(plugin.define("dog").unwrap().is_empty());
+        assert_eq!(
+            plugin.match_words("prefix", "cat").unwrap(),
+            vec!["cat".to_string(), "catalog".to_string()]
+        );
+    }
+
+    #[test]
+    fn word_on_stdin() {
+        let plugin = shell("read word; echo \"stdin: $word\"");
+        assert_eq!(plugin.define("cat").unwrap()[0].text, "stdin: cat\n");
+        // Words that look like options are no options
+        let plugin = shell("printf '%s\\n' \"$3\"");
+        assert_eq!(plugin.define("-n").unwrap()[0].text, "-n\n");
+    }
+
+    #[test]
+    fn failures() {
+        assert!(shell("exit 3").define("cat").is_err());
+        let mut slow = shell("sleep 10");
+        slow.timeout = Duration::from_millis(100);
+        let e = slow.define("cat").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        // Even with a word filling the pipe the program never reads
+        let e = slow.define(&"x".repeat(100_000)).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn programs_checked() {
+        assert!(shell("true").check().is_ok());
+        let missing = Plugin::new(vec!["dictr-no-such-plugin".to_string()], DEFAULT_TIMEOUT);
+        assert_eq!(missing.check().unwrap_err().kind(), io::ErrorKind::NotFound);
+        let missing = Plugin::new(vec!["/nonexistent/plugin".to_string()], DEFAULT_TIMEOUT);
+        assert!(missing.check().is_err());
+    }
+}
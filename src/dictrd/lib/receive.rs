@@ -0,0 +1,124 @@
+// Receive buffer of a connection. Clients may send several commands at once,
+// or a command in several pieces, so the bytes read are collected here and
+// taken out a line at a time. Lines end in CRLF, a bare LF is accepted too.
+// Of a line longer than RFC 2229 allows only the start is kept, enough to
+// tell it is too long.
+pub struct ReceiveBuffer {
+    buffer: Vec<u8>,
+    // Bytes at the start of the buffer already searched for a line end
+    searched: usize,
+    // Bytes kept of the line not ended yet
+    partial: usize,
+}
+
+// Longest line with its line end (RFC 2229, 2.2)
+pub const MAX_LINE: usize = 1024;
+// Longest command, a line taken out longer than this was cut off
+pub const MAX_COMMAND: usize = MAX_LINE - 2;
+
+impl Default for ReceiveBuffer {
+    fn default() -> Self {
+        ReceiveBuffer::new()
+    }
+}
+
+impl ReceiveBuffer {
+    pub fn new() -> ReceiveBuffer {
+        ReceiveBuffer {
+            buffer: Vec::new(),
+            searched: 0,
+            partial: 0,
+        }
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        for piece in bytes.split_inclusive(|&b| b == b'\n') {
+            let (text, ended) = match piece.split_last() {
+                Some((b'\n', text)) => (text, true),
+                _ => (piece, false),
+            };
+            let kept = text.len().min(MAX_LINE - self.partial);
+            self.buffer.extend_from_slice(&text[..kept]);
+            if ended {
+                self.buffer.push(b'\n');
+                self.partial = 0;
+            } else {
+                self.partial += kept;
+            }
+        }
+    }
+
+    // Takes the next complete line without its line end
+    pub fn next_line(&mut self) -> Option<Vec<u8>> {
+        let end = match self.buffer[self.searched..]
+            .iter()
+            .position(|&b| b == b'\n')
+        {
+            Some(pos) => self.searched + pos,
+            None => {
+                self.searched = self.buffer.len();
+                return None;
+            }
+        };
+        let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+        self.searched = 0;
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Some(line)
+    }
+
+    // Takes the rest of the buffer, the last line of a client that closed
+    // the connection without ending it
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        self.searched = 0;
+        self.partial = 0;
+        match self.buffer.is_empty() {
+            true => None,
+            false => Some(std::mem::take(&mut self.buffer)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipelined_commands() {
+        let mut received = ReceiveBuffer::new();
+        received.extend(b"CLIENT test\r\nDEFINE * hac");
+        assert_eq!(received.next_line().unwrap(), b"CLIENT test");
+        assert_eq!(received.next_line(), None);
+        received.extend(b"ker\r\nSTATUS\nQUIT\r\n");
+        assert_eq!(received.next_line().unwrap(), b"DEFINE * hacker");
+        assert_eq!(received.next_line().unwrap(), b"STATUS");
+        assert_eq!(received.next_line().unwrap(), b"QUIT");
+        assert_eq!(received.next_line(), None);
+        assert_eq!(received.finish(), None);
+
+        received.extend(b"\r\nQUIT");
+        assert_eq!(received.next_line().unwrap(), b"");
+        assert_eq!(received.next_line(), None);
+        assert_eq!(received.finish().unwrap(), b"QUIT");
+    }
+
+    #[test]
+    fn long_lines() {
+        let mut received = ReceiveBuffer::new();
+        let longest = vec![b'a'; MAX_COMMAND];
+        received.extend(&longest);
+        received.extend(b"\r\n");
+        assert_eq!(received.next_line().unwrap(), longest);
+        // The excess is dropped, the next line is kept
+        for _ in 0..100 {
+            received.extend(&[b'b'; 1000]);
+        }
+        assert_eq!(received.next_line(), None);
+        received.extend(b"b\r\nQUIT\r\n");
+        assert!(received.next_line().unwrap().len() > MAX_COMMAND);
+        assert_eq!(received.next_line().unwrap(), b"QUIT");
+        assert!(received.buffer.is_empty());
+    }
+}
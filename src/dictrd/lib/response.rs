@@ -0,0 +1,258 @@
+// Response to a command, written in the form RFC 2229 requires: every line
+// ends in CRLF, and in text blocks a line starting with "." gets a second
+// one, and a line holding only "." ends the block.
+//
+// Commands run to completion writing into the response, which is sent to
+// the client afterwards. Text blocks are sent no faster than the rate limits
+// allow. Large articles are only read from their database while they are
+// sent, a chunk at a time, see article.
+use crate::errors::DictError;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+// Dict file a streamed article is read from. Articles keep the file they were
+// found in, so that a reload while they are sent does not change their text.
+pub trait ArticleSource: Send + Sync {
+    fn read_bytes(&self, offset: u64, len: u64) -> Result<Vec<u8>, DictError>;
+}
+
+impl fmt::Debug for dyn ArticleSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ArticleSource")
+    }
+}
+
+// Sources are equal if they are the same file
+impl PartialEq for dyn ArticleSource {
+    fn eq(&self, other: &dyn ArticleSource) -> bool {
+        std::ptr::eq(
+            self as *const dyn ArticleSource as *const u8,
+            other as *const dyn ArticleSource as *const u8,
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Piece {
+    // Status lines
+    Status(Vec<u8>),
+    // Text blocks, subject to the rate limits
+    Text(Vec<u8>),
+    // A text block of the prefix followed by length bytes of the dict file of
+    // a database at offset, formatted with a BlockEncoder as it is read
+    Article {
+        prefix: String,
+        database: String,
+        source: Arc<dyn ArticleSource>,
+        offset: u64,
+        length: u64,
+    },
+}
+
+pub struct ResponseWriter {
+    peer: SocketAddr,
+    pieces: Vec<Piece>,
+}
+
+impl ResponseWriter {
+    pub fn new(peer: SocketAddr) -> ResponseWriter {
+        ResponseWriter {
+            peer,
+            pieces: Vec::new(),
+        }
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    // Code of the first status line, 0 without one
+    pub fn status_code(&self) -> u16 {
+        match self.pieces.first() {
+            Some(Piece::Status(line)) => line
+                .get(..3)
+                .and_then(|code| std::str::from_utf8(code).ok()?.parse().ok())
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    // Writes a status line like "250 ok"
+    pub fn status(&mut self, line: &str) {
+        let data = [line.as_bytes(), b"\r\n"].concat();
+        match self.pieces.last_mut() {
+            Some(Piece::Status(piece)) => piece.extend_from_slice(&data),
+            _ => self.pieces.push(Piece::Status(data)),
+        }
+    }
+
+    // Writes a text block with its terminating "." line. Returns the number
+    // of bytes written.
+    pub fn text_block(&mut self, text: &str) -> usize {
+        let block = text_block(text);
+        let len = block.len();
+        match self.pieces.last_mut() {
+            Some(Piece::Text(piece)) => piece.extend_from_slice(block.as_bytes()),
+            _ => self.pieces.push(Piece::Text(block.into_bytes())),
+        }
+        len
+    }
+
+    // Writes a text block of the prefix and an article of a database, which
+    // is read when the response is sent, so that the response does not hold
+    // the whole article
+    pub fn article(
+        &mut self,
+        prefix: String,
+        database: &str,
+        source: Arc<dyn ArticleSource>,
+        offset: u64,
+        length: u64,
+    ) {
+        self.pieces.push(Piece::Article {
+            prefix,
+            database: database.to_string(),
+            source,
+            offset,
+            length,
+        });
+    }
+
+    pub fn into_pieces(self) -> Vec<Piece> {
+        self.pieces
+    }
+}
+
+// Formats text read in pieces as a text block, like text_block does with the
+// whole text: line ends become CRLF, a "." starting a line is doubled and
+// finish adds the terminating "." line. Only the state of the current line
+// is kept between pieces.
+pub struct BlockEncoder {
+    at_line_start: bool,
+    // A CR whose line end is not known yet
+    pending_cr: bool,
+}
+
+impl Default for BlockEncoder {
+    fn default() -> Self {
+        BlockEncoder::new()
+    }
+}
+
+impl BlockEncoder {
+    pub fn new() -> BlockEncoder {
+        BlockEncoder {
+            at_line_start: true,
+            pending_cr: false,
+        }
+    }
+
+    pub fn encode(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        for &byte in data {
+            if byte == b'\n' {
+                // The CR of a CRLF is dropped
+                self.pending_cr = false;
+                out.extend_from_slice(b"\r\n");
+                self.at_line_start = true;
+                continue;
+            }
+            if self.pending_cr {
+                out.push(b'\r');
+                self.pending_cr = false;
+            }
+            if byte == b'\r' {
+                self.pending_cr = true;
+            } else {
+                if self.at_line_start && byte == b'.' {
+                    out.push(b'.');
+                }
+                out.push(byte);
+            }
+            self.at_line_start = false;
+        }
+    }
+
+    // Ends the last line and the block
+    pub fn finish(self, out: &mut Vec<u8>) {
+        if self.pending_cr {
+            out.push(b'\r');
+        }
+        if !self.at_line_start {
+            out.extend_from_slice(b"\r\n");
+        }
+        out.extend_from_slice(b".\r\n");
+    }
+}
+
+// Formats text as a text block
+pub fn text_block(text: &str) -> String {
+    let mut block = String::with_capacity(text.len() + text.len() / 32 + 3);
+    for line in text.lines() {
+        if line.starts_with('.') {
+            block.push('.');
+        }
+        block.push_str(line);
+        block.push_str("\r\n");
+    }
+    block.push_str(".\r\n");
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crlf_and_dot_stuffing() {
+        assert_eq!(text_block(""), ".\r\n");
+        assert_eq!(text_block("a\nb"), "a\r\nb\r\n.\r\n");
+        assert_eq!(text_block("a\r\n\r\nb\n"), "a\r\n\r\nb\r\n.\r\n");
+        assert_eq!(
+            text_block(".\n..dots\n .\n"),
+            "..\r\n...dots\r\n .\r\n.\r\n"
+        );
+
+        let mut response = ResponseWriter::new("127.0.0.1:2628".parse().unwrap());
+        response.status("150 1 definition retrieved");
+        assert_eq!(response.text_block(".hidden"), 13);
+        response.status("250 ok");
+        assert_eq!(response.status_code(), 150);
+        assert_eq!(
+            response.into_pieces(),
+            vec![
+                Piece::Status(b"150 1 definition retrieved\r\n".to_vec()),
+                Piece::Text(b"..hidden\r\n.\r\n".to_vec()),
+                Piece::Status(b"250 ok\r\n".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn encoded_in_pieces() {
+        let texts = [
+            "",
+            "a\nb",
+            "a\r\n\r\nb\n",
+            ".\n..dots\n .\n",
+            "cr\r",
+            "\r.x\r\n.y",
+        ];
+        for text in texts.iter() {
+            // Split at every position, including inside CRLF
+            for split in 0..=text.len() {
+                let mut out = Vec::new();
+                let mut encoder = BlockEncoder::new();
+                encoder.encode(&text.as_bytes()[..split], &mut out);
+                encoder.encode(&text.as_bytes()[split..], &mut out);
+                encoder.finish(&mut out);
+                assert_eq!(
+                    String::from_utf8(out).unwrap(),
+                    text_block(text),
+                    "{:?}",
+                    text
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+// SASL authentication for SASLAUTH and SASLRESP (RFC 2229, 3.12). PLAIN
+// (RFC 4616) sends user and password in the clear, CRAM-MD5 (RFC 2195)
+// answers a challenge with an HMAC-MD5 keyed with the shared secret, so the
+// secret never crosses the network.
+use std::collections::HashMap;
+
+// Advertised in the 220 banner, in this order
+pub const MECHANISMS: [&str; 2] = ["PLAIN", "CRAM-MD5"];
+
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    // Sent to the client, which answers with SASLRESP
+    Challenge(Vec<u8>),
+    Success(String),
+    // The user the client claimed to be, if it got that far
+    Failure(Option<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mechanism {
+    Plain,
+    CramMd5,
+}
+
+// An exchange in progress
+#[derive(Debug)]
+pub struct Authenticator {
+    mechanism: Mechanism,
+    // Challenge of CRAM-MD5, and whether it was sent
+    challenge: Vec<u8>,
+    challenged: bool,
+}
+
+impl Authenticator {
+    // Starts an exchange, None if the mechanism is not supported. The
+    // challenge is only used by CRAM-MD5 and must be unique, e.g.
+    // "<random.timestamp@hostname>".
+    pub fn start(mechanism: &str, challenge: &str) -> Option<Authenticator> {
+        let mechanism = match mechanism.to_uppercase().as_str() {
+            "PLAIN" => Mechanism::Plain,
+            "CRAM-MD5" => Mechanism::CramMd5,
+            _ => return None,
+        };
+        Some(Authenticator {
+            mechanism,
+            challenge: challenge.as_bytes().to_vec(),
+            challenged: false,
+        })
+    }
+
+    // Takes the next response of the client, None for SASLAUTH without an
+    // initial response. Secrets maps users to their shared secrets.
+    pub fn step(&mut self, response: Option<&[u8]>, secrets: &HashMap<String, String>) -> Step {
+        match (self.mechanism, response) {
+            (Mechanism::Plain, None) => Step::Challenge(Vec::new()),
+            (Mechanism::Plain, Some(response)) => check_plain(response, secrets),
+            (Mechanism::CramMd5, None) => {
+                self.challenged = true;
+                Step::Challenge(self.challenge.clone())
+            }
+            // CRAM-MD5 has no initial response
+            (Mechanism::CramMd5, Some(_)) if !self.challenged => Step::Failure(None),
+            (Mechanism::CramMd5, Some(response)) => {
+                check_cram_md5(response, &self.challenge, secrets)
+            }
+        }
+    }
+}
+
+// authzid NUL authcid NUL password, only authzid may be empty and must then
+// equal authcid
+fn check_plain(response: &[u8], secrets: &HashMap<String, String>) -> Step {
+    let parts: Vec<&[u8]> = response.split(|&b| b == 0).collect();
+    if parts.len() != 3 {
+        return Step::Failure(None);
+    }
+    let (authzid, user, password) = match (
+        std::str::from_utf8(parts[0]),
+        std::str::from_utf8(parts[1]),
+        std::str::from_utf8(parts[2]),
+    ) {
+        (Ok(authzid), Ok(user), Ok(password)) => (authzid, user, password),
+        _ => return Step::Failure(None),
+    };
+    if !authzid.is_empty() && authzid != user {
+        return Step::Failure(Some(user.to_string()));
+    }
+    match secrets.get(user) {
+        Some(secret) if constant_time_eq(secret.as_bytes(), password.as_bytes()) => {
+            Step::Success(user.to_string())
+        }
+        _ => Step::Failure(Some(user.to_string())),
+    }
+}
+
+// user SP hex(HMAC-MD5(secret, challenge))
+fn check_cram_md5(response: &[u8], challenge: &[u8], secrets: &HashMap<String, String>) -> Step {
+    let response = match std::str::from_utf8(response) {
+        Ok(response) => response,
+        Err(_) => return Step::Failure(None),
+    };
+    let (user, digest) = match response.rfind(' ') {
+        Some(pos) => (&response[..pos], &response[pos + 1..]),
+        None => return Step::Failure(None),
+    };
+    match secrets.get(user) {
+        Some(secret) => {
+            let expected = hex(&hmac_md5(secret.as_bytes(), challenge));
+            if constant_time_eq(expected.as_bytes(), digest.to_lowercase().as_bytes()) {
+                Step::Success(user.to_string())
+            } else {
+                Step::Failure(Some(user.to_string()))
+            }
+        }
+        None => Step::Failure(Some(user.to_string())),
+    }
+}
+
+// Checks the response of AUTH user response (RFC 2229, 3.11): the MD5
+// digest of the msg-id of the banner followed by the shared secret, in hex
+pub fn check_auth(
+    user: &str,
+    response: &str,
+    msg_id: &str,
+    secrets: &HashMap<String, String>,
+) -> Step {
+    match secrets.get(user) {
+        Some(secret) => {
+            let expected = auth_response(secret, msg_id);
+            if constant_time_eq(expected.as_bytes(), response.to_lowercase().as_bytes()) {
+                Step::Success(user.to_string())
+            } else {
+                Step::Failure(Some(user.to_string()))
+            }
+        }
+        None => Step::Failure(Some(user.to_string())),
+    }
+}
+
+// The response of a client to AUTH
+pub fn auth_response(secret: &str, msg_id: &str) -> String {
+    hex(&md5::compute(format!("{}{}", msg_id, secret)).0)
+}
+
+// The answer of a client to a CRAM-MD5 challenge
+pub fn cram_md5_response(user: &str, secret: &str, challenge: &[u8]) -> String {
+    format!("{} {}", user, hex(&hmac_md5(secret.as_bytes(), challenge)))
+}
+
+fn hmac_md5(key: &[u8], message: &[u8]) -> [u8; 16] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..16].copy_from_slice(&md5::compute(key).0);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(message);
+    let mut outer: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&md5::compute(&inner).0);
+    md5::compute(&outer).0
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Compares without returning early, so the time taken does not tell how
+// much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secrets() -> HashMap<String, String> {
+        let mut secrets = HashMap::new();
+        secrets.insert("tim".to_string(), "tanstaaftanstaaf".to_string());
+        secrets
+    }
+
+    #[test]
+    fn plain() {
+        let mut auth = Authenticator::start("plain", "").unwrap();
+        assert_eq!(auth.step(None, &secrets()), Step::Challenge(Vec::new()));
+        assert_eq!(
+            auth.step(Some(b"\0tim\0tanstaaftanstaaf"), &secrets()),
+            Step::Success("tim".to_string())
+        );
+        assert_eq!(
+            auth.step(Some(b"tim\0tim\0wrong"), &secrets()),
+            Step::Failure(Some("tim".to_string()))
+        );
+        assert_eq!(
+            auth.step(Some(b"root\0tim\0tanstaaftanstaaf"), &secrets()),
+            Step::Failure(Some("tim".to_string()))
+        );
+        assert_eq!(auth.step(Some(b"garbage"), &secrets()), Step::Failure(None));
+        assert!(Authenticator::start("GSSAPI", "").is_none());
+    }
+
+    #[test]
+    fn cram_md5() {
+        // The example of RFC 2195
+        let challenge = "<1896.697170952@postoffice.reston.mci.net>";
+        let mut auth = Authenticator::start("CRAM-MD5", challenge).unwrap();
+        assert_eq!(
+            auth.step(None, &secrets()),
+            Step::Challenge(challenge.as_bytes().to_vec())
+        );
+        assert_eq!(
+            cram_md5_response("tim", "tanstaaftanstaaf", challenge.as_bytes()),
+            "tim b913a602c7eda7a495b4e6e7334d3890"
+        );
+        assert_eq!(
+            auth.step(Some(b"tim b913a602c7eda7a495b4e6e7334d3890"), &secrets()),
+            Step::Success("tim".to_string())
+        );
+        assert_eq!(
+            auth.step(Some(b"tim 00000000000000000000000000000000"), &secrets()),
+            Step::Failure(Some("tim".to_string()))
+        );
+        let mut auth = Authenticator::start("CRAM-MD5", challenge).unwrap();
+        assert_eq!(
+            auth.step(Some(b"tim b913a602c7eda7a495b4e6e7334d3890"), &secrets()),
+            Step::Failure(None)
+        );
+    }
+
+    #[test]
+    fn auth() {
+        let msg_id = "<1896.697170952@postoffice.reston.mci.net>";
+        let response = auth_response("tanstaaftanstaaf", msg_id);
+        assert_eq!(response, "d16ff9ac2a65d209022d7eb541ecf24d");
+        assert_eq!(
+            check_auth("tim", &response.to_uppercase(), msg_id, &secrets()),
+            Step::Success("tim".to_string())
+        );
+        assert_eq!(
+            check_auth("tim", &response, "<1.2@elsewhere>", &secrets()),
+            Step::Failure(Some("tim".to_string()))
+        );
+        assert_eq!(
+            check_auth("root", &response, msg_id, &secrets()),
+            Step::Failure(Some("root".to_string()))
+        );
+    }
+}
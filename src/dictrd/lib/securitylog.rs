@@ -0,0 +1,110 @@
+// Audit trail of security relevant events (authentication, administrative
+// commands, database reloads, refused clients) for deployments that have to keep one. It is
+// written separately from the debug log, one event per line, and only ever
+// appended to.
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum SecurityEvent<'a> {
+    AuthSuccess { user: &'a str },
+    AuthFailure { user: &'a str },
+    AdminCommand { command: &'a str },
+    Reload { database: &'a str, ok: bool },
+    AccessDenied,
+}
+
+impl<'a> SecurityEvent<'a> {
+    // Values are quoted and escaped, so clients cannot forge log lines
+    fn describe(&self) -> String {
+        match self {
+            SecurityEvent::AuthSuccess { user } => format!("auth-success user={:?}", user),
+            SecurityEvent::AuthFailure { user } => format!("auth-failure user={:?}", user),
+            SecurityEvent::AdminCommand { command } => format!("admin command={:?}", command),
+            SecurityEvent::Reload { database, ok } => format!(
+                "reload database={:?} result={}",
+                database,
+                if *ok { "ok" } else { "failed" }
+            ),
+            SecurityEvent::AccessDenied => "access-denied".to_string(),
+        }
+    }
+}
+
+pub struct SecurityLog<W: Write> {
+    out: W,
+}
+
+impl SecurityLog<File> {
+    pub fn open(path: &Path) -> io::Result<SecurityLog<File>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(SecurityLog::new(file))
+    }
+}
+
+impl<W: Write> SecurityLog<W> {
+    pub fn new(out: W) -> SecurityLog<W> {
+        SecurityLog { out }
+    }
+
+    // Writes an event with the time and the peer that caused it. Every line
+    // is flushed, an event must not be lost if the server dies.
+    pub fn record(&mut self, peer: &str, event: &SecurityEvent) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{} {} {}",
+            timestamp(SystemTime::now()),
+            peer,
+            event.describe()
+        )?;
+        self.out.flush()
+    }
+}
+
+// Formats a time as ISO 8601 in UTC, e.g. 2020-02-29T13:45:00Z
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (secs / 86400, secs % 86400);
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn record_events() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let leap = UNIX_EPOCH + Duration::from_secs(1_582_983_900);
+        assert_eq!(timestamp(leap), "2020-02-29T13:45:00Z");
+        let mut log = SecurityLog::new(Vec::new());
+        log.record(
+            "127.0.0.1:4711",
+            &SecurityEvent::AuthFailure { user: "x\ny" },
+        )
+        .unwrap();
+        let line = String::from_utf8(log.out).unwrap();
+        assert!(line.ends_with(" 127.0.0.1:4711 auth-failure user=\"x\\ny\"\n"));
+    }
+}
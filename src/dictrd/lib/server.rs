@@ -0,0 +1,3617 @@
+// The DICT server (RFC 2229): the loaded databases, the sessions of the
+// connected clients and the commands they send. dictrd configures and runs
+// it, and other programs can embed it:
+//
+//   let mut server = DictdServer::<File>::new();
+//   server.add_database("jargon".to_string(), metadata, index, dict);
+//   let bound = server.bind("127.0.0.1:0".parse()?)?;
+//   let addr = bound.local_addr()?;
+//   let handle = bound.serve()?;
+//   ...
+//   handle.shutdown();
+use crate::access::{AccessList, Network};
+use crate::accesslog::{AccessLog, AccessRecord, QueryLogging};
+use crate::backend::{with_deadline, Backend};
+use crate::breaker::{BreakerState, CircuitBreaker};
+use crate::cache::LruCache;
+use crate::client::{Definition, Match};
+use crate::compress::BlockCompression;
+use crate::errors::DictError;
+use crate::filter::DefinitionFilter;
+use crate::fulltext::FullTextIndex;
+use crate::metadata::{DatabaseMetadata, Encoding};
+use crate::metrics::Metrics;
+use crate::mime::ContentType;
+use crate::normalize::Normalization;
+use crate::parser::{Cmd, Command, Parser, SubCmd};
+use crate::phonetic::Phonetic;
+use crate::receive::{ReceiveBuffer, MAX_COMMAND};
+use crate::response::{ArticleSource, BlockEncoder, Piece, ResponseWriter};
+use crate::sasl::{check_auth, Authenticator, Step, MECHANISMS};
+use crate::securitylog::{SecurityEvent, SecurityLog};
+use crate::stats::Statistics;
+use crate::strategy::{self, Indexes, MatchStrategy};
+use crate::template::{self, Template};
+use crate::throttle::RateLimiter;
+use crate::xref;
+use crate::{DictReader, IndexEntry, IndexReader, ReadAt};
+use log::{debug, error, info, warn};
+use rand::seq::SliceRandom;
+use socket2::{Domain, SockRef, Socket, Type};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream};
+use tokio::net::TcpListener;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_rustls::TlsAcceptor;
+
+// Minimum trigram similarity of results of the ngram strategy
+const NGRAM_SIMILARITY: f64 = 0.4;
+
+// Strategy of MATCH with the strategy "." unless set_default_strategy says
+// otherwise
+const DEFAULT_STRATEGY: &str = "lev";
+
+// Ends a list of matches cut off at --max-matches or max_matches
+const TRUNCATED: &str = "250 ok - matches truncated";
+
+// A database answering slower than its deadline this many times in a row is
+// left out of searches in all databases for BREAKER_COOLDOWN
+pub const DEFAULT_DEADLINE: Duration = Duration::from_secs(1);
+const BREAKER_THRESHOLD: u32 = 3;
+pub const BREAKER_COOLDOWN: Duration = Duration::from_secs(60);
+
+// Rate limited text blocks are sent in pieces of this size
+const THROTTLE_CHUNK: usize = 4096;
+
+// Commands beyond RFC 2229 the server understands
+const EXTENSIONS: [&str; 7] = [
+    "XDEFINEMATCH",
+    "XNEXT",
+    "XRANDOM",
+    "XREF",
+    "XPING",
+    "XSEARCH",
+    "XSTAT",
+];
+
+// Longer client identifications are cut off
+const MAX_CLIENT_LEN: usize = 200;
+
+// Bytes read from a connection at once
+const RECEIVE_CHUNK: usize = 4096;
+
+// Pending connections of a listening socket
+const LISTEN_BACKLOG: i32 = 1024;
+
+// Clients served at the same time unless --max-connections says otherwise
+const DEFAULT_MAX_CONNECTIONS: usize = 256;
+
+// Articles kept in the definition cache unless --cache-size says otherwise
+const DEFAULT_CACHE_SIZE: usize = 1024;
+
+// DEFINE reads longer articles while sending them, STREAM_CHUNK bytes at a
+// time, unless set_stream_threshold says otherwise
+const DEFAULT_STREAM_THRESHOLD: u64 = 256 * 1024;
+const STREAM_CHUNK: u64 = 64 * 1024;
+
+const HELP_TEXT: &str = "\
+DEFINE database word         -- look up word in database
+MATCH database strategy word -- match word in database using strategy
+SHOW DB                      -- list all accessible databases
+SHOW DATABASES               -- list all accessible databases
+SHOW STRAT                   -- list available matching strategies
+SHOW STRATEGIES              -- list available matching strategies
+SHOW INFO database           -- provide information about the database
+SHOW SERVER                  -- provide site-specific information
+OPTION MIME                  -- use MIME headers
+CLIENT info                  -- identify client to server
+OPTION XCOMPRESS deflate     -- compress text responses
+OPTION DEFLATE               -- compress text responses with zlib
+STATUS                       -- display timing information
+HELP                         -- display this help information
+AUTH user string             -- provide authentication information
+SASLAUTH mechanism [response] -- start SASL authentication
+SASLRESP response            -- answer a SASL challenge
+XRANDOM [database] [RARE]    -- return a random definition, weighted
+                                towards common (or rare) words
+XDEFINEMATCH database strategy word
+                             -- match word and return the definitions
+                                of all matches
+XNEXT token                  -- continue a list of matches cut off after
+                                a page, 501 if the token is not that of
+                                the last page
+XSEARCH database words...    -- list the headwords whose definitions
+                                contain all words
+XREF database word           -- list the headwords the definition of word
+                                refers to in {braces}
+XSTAT [count]                -- list the most used databases and the most
+                                requested words
+XPING                        -- check that all databases can be read
+QUIT                         -- terminate connection
+";
+
+pub struct Database<R: ReadAt> {
+    shortname: String,
+    metadata: DatabaseMetadata,
+    // How queries are normalized before looking them up
+    normalization: Normalization,
+    indexreader: Arc<RwLock<IndexReader>>,
+    // Articles are read at their offset without a lock, so connections
+    // read them side by side. None for a database answered by a backend.
+    dictreader: Option<Arc<DictReader<R>>>,
+    // Answers the lookups of a database without files, see add_backend
+    backend: Option<Arc<dyn Backend>>,
+    // Auxiliary indexes in a different sort order (e.g. "suffix" with
+    // reversed headwords), used by the strategies depending on them
+    aux_indexes: HashMap<String, Arc<RwLock<IndexReader>>>,
+    // Format of the articles
+    content_type: ContentType,
+    // Applied to the articles before they are sent
+    filters: Vec<Arc<dyn DefinitionFilter>>,
+    // Layout of the articles sent
+    template: Option<Template>,
+    breaker: Mutex<CircuitBreaker>,
+    // Checksum of the files the database was loaded from
+    checksum: Option<String>,
+    // Index of the words in the articles, built on the first XSEARCH. It is
+    // shared with the thread building and searching it, see command_search.
+    fulltext: Arc<OnceLock<FullTextIndex>>,
+    // Most matches sent from the database for a MATCH, None for no limit
+    max_matches: Option<usize>,
+    // Strategy of MATCH with the strategy ".", the server's if None
+    default_strategy: Option<String>,
+}
+
+impl<R: ReadAt> Database<R> {
+    fn new(
+        shortname: String,
+        metadata: DatabaseMetadata,
+        normalization: Normalization,
+        indexreader: Arc<RwLock<IndexReader>>,
+        dictreader: Option<DictReader<R>>,
+        backend: Option<Arc<dyn Backend>>,
+    ) -> Database<R> {
+        Database {
+            shortname,
+            metadata,
+            normalization,
+            indexreader,
+            dictreader: dictreader.map(Arc::new),
+            backend,
+            aux_indexes: HashMap::new(),
+            content_type: ContentType::Plain,
+            filters: Vec::new(),
+            template: None,
+            breaker: Mutex::new(CircuitBreaker::new(
+                DEFAULT_DEADLINE,
+                BREAKER_THRESHOLD,
+                BREAKER_COOLDOWN,
+            )),
+            checksum: None,
+            fulltext: Arc::new(OnceLock::new()),
+            max_matches: None,
+            default_strategy: None,
+        }
+    }
+
+    pub fn indexreader(&self) -> &RwLock<IndexReader> {
+        &self.indexreader
+    }
+
+    // Checksum of the files the database was loaded from
+    pub fn checksum(&self) -> Option<&str> {
+        self.checksum.as_deref()
+    }
+
+    pub fn breaker_state(&self, now: Instant) -> BreakerState {
+        self.breaker.lock().unwrap().state(now)
+    }
+
+    // Records the time a query took
+    fn record_query(&self, elapsed: Duration) {
+        let mut breaker = self.breaker.lock().unwrap();
+        if breaker.record(elapsed, Instant::now()) {
+            warn!(
+                "{} exceeded its deadline of {:?}, leaving it out of searches for {:?}",
+                self.shortname,
+                breaker.deadline(),
+                BREAKER_COOLDOWN
+            );
+        }
+    }
+
+    // Prepares an article for sending with the filters and template of the
+    // database
+    fn render(&self, headword: &str, text: &str) -> (ContentType, String) {
+        template::render(
+            &self.filters,
+            self.template.as_ref(),
+            self.content_type,
+            headword,
+            text,
+            &self.shortname,
+            &self.metadata.short,
+        )
+    }
+
+    // Whether articles can be sent as they are read from the dict file: they
+    // are in UTF-8 and neither filtered nor laid out with a template
+    fn streams(&self) -> bool {
+        self.filters.is_empty()
+            && self.template.is_none()
+            && self
+                .dictreader
+                .as_ref()
+                .is_some_and(|dictreader| dictreader.encoding() == Encoding::Utf8)
+    }
+
+    fn indexes(&self) -> Indexes<'_> {
+        Indexes {
+            index: &self.indexreader,
+            aux: &self.aux_indexes,
+        }
+    }
+
+    // Looks up a word like the exact strategy, see Indexes::find_entry
+    fn find_entry(&self, word: &str) -> Option<IndexEntry> {
+        self.indexes().find_entry(word)
+    }
+
+    // Looks up all entries of a word like the exact strategy, see
+    // Indexes::find_entries
+    fn find_entries(&self, word: &str) -> Vec<IndexEntry> {
+        self.indexes().find_entries(word)
+    }
+
+    // Reads the article of the first headword from the dict file, past the
+    // definition cache. Databases answered by a backend are not checked.
+    fn spot_check(&self) -> Result<(), DictError> {
+        let dictreader = match &self.dictreader {
+            Some(dictreader) => dictreader,
+            None => return Ok(()),
+        };
+        let entry = match self.indexreader.read().unwrap().entries().first() {
+            Some(entry) => entry.clone(),
+            None => return Ok(()),
+        };
+        dictreader.find(entry.offset, entry.length).map(|_| ())
+    }
+}
+
+impl<R: ReadAt + Send + Sync> ArticleSource for DictReader<R> {
+    fn read_bytes(&self, offset: u64, len: u64) -> Result<Vec<u8>, DictError> {
+        self.find_bytes(offset, len)
+    }
+}
+
+// Matches of a MATCH by database
+type Matches = Vec<(String, IndexEntry)>;
+
+// A definition found by DEFINE, rendered for its database
+struct Found {
+    database: String,
+    description: String,
+    entry: IndexEntry,
+    content_type: ContentType,
+    text: String,
+    // Dict file, offset and length of an article to send after the text as
+    // it is read, see ResponseWriter::article
+    article: Option<(Arc<dyn ArticleSource>, u64, u64)>,
+}
+
+// Per-connection state. Each connection gets a short random id, and each
+// command a request id derived from it, which prefix all related log lines so
+// that interleaved logs from concurrent clients can be told apart.
+pub struct Session {
+    pub id: String,
+    pub request_id: String,
+    // How text blocks are compressed (OPTION XCOMPRESS or DEFLATE), None
+    // for plain text
+    pub compress: Option<BlockCompression>,
+    // Text blocks are preceded by MIME headers (OPTION MIME)
+    pub mime: bool,
+    // Identification sent with CLIENT
+    pub client: Option<String>,
+    // Address of the client, decides which databases it may use
+    pub peer: Option<IpAddr>,
+    // Unique id sent in the banner, which AUTH responses are computed from
+    pub msg_id: String,
+    // User authenticated with AUTH or SASLAUTH, and an exchange in progress
+    pub user: Option<String>,
+    sasl: Option<Authenticator>,
+    // Matches left over from a MATCH longer than a page, the token to
+    // request them with XNEXT and whether the list was truncated
+    continuation: Option<(String, Matches, bool)>,
+    commands: u64,
+    defines: u64,
+    matches: u64,
+    started: Instant,
+    // Time the last command took to run
+    last_command: Duration,
+    // Bytes of text blocks sent and time spent waiting for the rate limits
+    transfer: Mutex<Transfer>,
+    // Copy of the client's socket, listed with the connection
+    socket: Option<Socket>,
+}
+
+#[derive(Default)]
+struct Transfer {
+    limiter: Option<RateLimiter>,
+    bytes: u64,
+    throttled: Duration,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}
+
+// A unique id of the form <random.timestamp@hostname>, for the banner and
+// the challenges of CRAM-MD5
+fn new_msg_id() -> String {
+    format!(
+        "<{}.{}@{}>",
+        rand::random::<u32>(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs()),
+        hostname::get().map_or_else(
+            |_| "localhost".to_string(),
+            |name| name.to_string_lossy().into_owned()
+        )
+    )
+}
+
+impl Session {
+    pub fn new() -> Session {
+        let id = format!("{:08x}", rand::random::<u32>());
+        Session {
+            request_id: id.clone(),
+            id,
+            compress: None,
+            mime: false,
+            client: None,
+            peer: None,
+            msg_id: new_msg_id(),
+            user: None,
+            sasl: None,
+            continuation: None,
+            commands: 0,
+            defines: 0,
+            matches: 0,
+            started: Instant::now(),
+            last_command: Duration::from_secs(0),
+            transfer: Mutex::new(Transfer::default()),
+            socket: None,
+        }
+    }
+
+    // Starts a new command and returns its request id
+    pub fn next_request(&mut self) -> &str {
+        self.commands += 1;
+        self.request_id = format!("{}-{}", self.id, self.commands);
+        &self.request_id
+    }
+}
+
+// A client connection, as listed by the admin socket
+pub struct ConnectionInfo {
+    pub peer: String,
+    pub client: Option<String>,
+    pub connected: Instant,
+    pub last_active: Instant,
+    pub commands: u64,
+    // Shut down to disconnect the client
+    socket: Option<Socket>,
+}
+
+// Removes a connection from the list of active connections when it ends,
+// also if the connection task panics
+pub struct ConnectionGuard {
+    connections: Arc<Mutex<BTreeMap<String, ConnectionInfo>>>,
+    id: String,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.connections.lock().unwrap().remove(&self.id);
+    }
+}
+
+// Counts a connection against the limit of its address until dropped
+struct PeerSlot {
+    peers: Arc<Mutex<HashMap<IpAddr, usize>>>,
+    ip: IpAddr,
+}
+
+impl Drop for PeerSlot {
+    fn drop(&mut self) {
+        let mut peers = self.peers.lock().unwrap();
+        if let Some(count) = peers.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                peers.remove(&self.ip);
+            }
+        }
+    }
+}
+
+// The slots a connection holds while it is served
+pub struct Admission {
+    _permit: OwnedSemaphorePermit,
+    _peer: PeerSlot,
+}
+
+// Why a client is turned away
+pub enum Refusal {
+    // Its address is not allowed
+    Denied,
+    // Over a connection limit, with the reason
+    Busy(&'static str),
+}
+
+impl Refusal {
+    fn reply(&self) -> &'static [u8] {
+        match self {
+            Refusal::Denied => b"530 Access denied\r\n",
+            Refusal::Busy(_) => b"420 Server temporarily unavailable\r\n",
+        }
+    }
+}
+
+// What happens to clients connecting while all connection slots are taken
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WhenBusy {
+    // Accept them and keep them waiting until a slot is free
+    Queue,
+    // Answer them with 420 and disconnect them
+    Reject,
+}
+
+impl WhenBusy {
+    pub fn from_name(name: &str) -> Option<WhenBusy> {
+        match name {
+            "queue" => Some(WhenBusy::Queue),
+            "reject" => Some(WhenBusy::Reject),
+            _ => None,
+        }
+    }
+}
+
+// How command lines that are not valid UTF-8 are treated
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEncoding {
+    // Reject the command with "500 Invalid character encoding"
+    Strict,
+    // Replace invalid sequences with U+FFFD
+    Lossy,
+    // Decode the line from a legacy charset, e.g. ISO-8859-1
+    Legacy(Encoding),
+}
+
+impl InputEncoding {
+    // Looks up a policy by "strict", "lossy" or the name of a charset known
+    // to encoding_rs, e.g. "latin1" or "koi8-r"
+    pub fn from_name(name: &str) -> Option<InputEncoding> {
+        match name {
+            "strict" => Some(InputEncoding::Strict),
+            "lossy" => Some(InputEncoding::Lossy),
+            label => match Encoding::for_label(label)? {
+                Encoding::Utf8 => Some(InputEncoding::Strict),
+                encoding => Some(InputEncoding::Legacy(encoding)),
+            },
+        }
+    }
+
+    // Decodes a command line, returns None if it has to be rejected
+    pub fn decode(self, line: Vec<u8>) -> Option<String> {
+        match String::from_utf8(line) {
+            Ok(line) => Some(line),
+            Err(e) => match self {
+                InputEncoding::Strict => None,
+                InputEncoding::Lossy => Some(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+                InputEncoding::Legacy(encoding) => encoding.decode(e.into_bytes()).ok(),
+            },
+        }
+    }
+}
+
+// Articles by database, offset and length
+type DefinitionCache = LruCache<(String, u64, u64), String>;
+
+pub struct DictdServer<R: ReadAt> {
+    // Match strategies in the order SHOW STRAT lists them, the built-in
+    // ones first
+    strategies: Arc<RwLock<Vec<Box<dyn MatchStrategy>>>>,
+    // Other names of strategies, e.g. "re" for "regexp"
+    strategy_aliases: Arc<HashMap<String, String>>,
+    // Strategy of MATCH with the strategy "."
+    default_strategy: String,
+    // Position of the databases in answers across several databases
+    priorities: Arc<HashMap<String, usize>>,
+    // Whether MATCH lists a headword found in several databases once
+    dedup_matches: bool,
+    databases: Arc<RwLock<HashMap<String, Database<R>>>>,
+    // Databases visible to clients of this listener, None means all
+    view: Option<Arc<HashSet<String>>>,
+    // Access list of the listener, checked after the server's
+    listener_access: Arc<AccessList>,
+    input_encoding: InputEncoding,
+    // Longest list of matches sent at once, None for no limit
+    match_page_size: Option<usize>,
+    // Most matches sent for a MATCH, None for no limit
+    max_matches: Option<usize>,
+    // Bytes per second each connection may send, None for no limit
+    connection_rate: Option<u64>,
+    // Limit of all connections together
+    global_limiter: Option<Arc<Mutex<RateLimiter>>>,
+    // Permits for the clients served at the same time, shared by all
+    // listeners
+    connection_slots: Arc<Semaphore>,
+    when_busy: WhenBusy,
+    // Addresses clients may connect from
+    access: Arc<AccessList>,
+    // Networks restricted databases are visible to, by database
+    networks: Arc<RwLock<HashMap<String, Vec<Network>>>>,
+    // Databases left out of SHOW DB and searches in all databases, which
+    // clients must name
+    hidden: Arc<RwLock<HashSet<String>>>,
+    // Databases only authenticated clients can see
+    auth_required: Arc<RwLock<HashSet<String>>>,
+    // Clients served at the same time from a single address, None for no
+    // limit
+    max_connections_per_ip: Option<usize>,
+    // Connections by client address, shared by all listeners
+    peers: Arc<Mutex<HashMap<IpAddr, usize>>>,
+    // Commands per second each connection may send, None for no limit
+    command_rate: Option<u64>,
+    // Connections waiting longer for a command are closed, None for no
+    // limit
+    idle_timeout: Option<Duration>,
+    // Commands served on a connection before it is closed, None for no limit
+    max_commands: Option<u64>,
+    // Commands taking longer are logged as slow, None to log none
+    slow_query: Option<Duration>,
+    // Usage counters shared by all connections
+    stats: Arc<Mutex<Statistics>>,
+    // Counters and latencies of this run for the metrics endpoint
+    metrics: Arc<Mutex<Metrics>>,
+    // Recently read articles by database, offset and length, None if
+    // disabled
+    cache: Option<Arc<Mutex<DefinitionCache>>>,
+    // Articles longer than this many bytes are read while they are sent
+    stream_threshold: u64,
+    // Databases that could not be loaded, with the reason
+    unavailable: Arc<RwLock<BTreeMap<String, String>>>,
+    // Active connections by session id
+    connections: Arc<Mutex<BTreeMap<String, ConnectionInfo>>>,
+    // Shared secrets of the users that can authenticate
+    users: Arc<HashMap<String, String>>,
+    // Text of the 220 greeting before the capabilities, None for the host
+    // name and operating system
+    banner: Option<Arc<String>>,
+    // Audit trail of authentication and administration, if enabled
+    security_log: Option<Arc<Mutex<SecurityLog<File>>>>,
+    // Commands are written to the log without an access log
+    access_log: Option<Arc<Mutex<AccessLog>>>,
+    // Whether the words looked up are logged
+    query_logging: QueryLogging,
+    session: Session,
+}
+
+// Every clone serves a single connection and therefore gets a fresh session
+impl<R: ReadAt + Send + Sync + 'static> Clone for DictdServer<R> {
+    fn clone(&self) -> DictdServer<R> {
+        let strategies = self.strategies.clone();
+        let databases = self.databases.clone();
+        DictdServer {
+            strategies,
+            strategy_aliases: self.strategy_aliases.clone(),
+            default_strategy: self.default_strategy.clone(),
+            priorities: self.priorities.clone(),
+            dedup_matches: self.dedup_matches,
+            databases,
+            view: self.view.clone(),
+            listener_access: self.listener_access.clone(),
+            input_encoding: self.input_encoding,
+            match_page_size: self.match_page_size,
+            max_matches: self.max_matches,
+            connection_rate: self.connection_rate,
+            global_limiter: self.global_limiter.clone(),
+            connection_slots: self.connection_slots.clone(),
+            when_busy: self.when_busy,
+            access: self.access.clone(),
+            networks: self.networks.clone(),
+            hidden: self.hidden.clone(),
+            auth_required: self.auth_required.clone(),
+            max_connections_per_ip: self.max_connections_per_ip,
+            peers: self.peers.clone(),
+            command_rate: self.command_rate,
+            idle_timeout: self.idle_timeout,
+            max_commands: self.max_commands,
+            slow_query: self.slow_query,
+            stats: self.stats.clone(),
+            metrics: self.metrics.clone(),
+            cache: self.cache.clone(),
+            stream_threshold: self.stream_threshold,
+            unavailable: self.unavailable.clone(),
+            connections: self.connections.clone(),
+            users: self.users.clone(),
+            banner: self.banner.clone(),
+            security_log: self.security_log.clone(),
+            access_log: self.access_log.clone(),
+            query_logging: self.query_logging.clone(),
+            session: self.new_session(),
+        }
+    }
+}
+
+impl<R: ReadAt + Send + Sync + 'static> Default for DictdServer<R> {
+    fn default() -> Self {
+        DictdServer::new()
+    }
+}
+
+impl<R: ReadAt + Send + Sync + 'static> DictdServer<R> {
+    pub fn new() -> DictdServer<R> {
+        let mut builtin: Vec<Box<dyn MatchStrategy>> = vec![
+            Box::new(strategy::Exact),
+            Box::new(strategy::Prefix),
+            Box::new(strategy::NPrefix),
+            Box::new(strategy::Ngram {
+                min_similarity: NGRAM_SIMILARITY,
+            }),
+            Box::new(strategy::Stem),
+            Box::new(strategy::Lev),
+            Box::new(strategy::Suffix),
+            Box::new(strategy::Substring),
+            Box::new(strategy::Word),
+            Box::new(strategy::First),
+            Box::new(strategy::Last),
+        ];
+        for phonetic in Phonetic::ALL.iter() {
+            builtin.push(Box::new(*phonetic));
+        }
+        let strategies = Arc::new(RwLock::new(builtin));
+        let databases = Arc::new(RwLock::new(HashMap::new()));
+        DictdServer {
+            strategies,
+            strategy_aliases: Arc::new(HashMap::new()),
+            default_strategy: DEFAULT_STRATEGY.to_string(),
+            databases,
+            view: None,
+            listener_access: Arc::new(AccessList::default()),
+            input_encoding: InputEncoding::Strict,
+            match_page_size: None,
+            max_matches: None,
+            connection_rate: None,
+            global_limiter: None,
+            connection_slots: Arc::new(Semaphore::new(DEFAULT_MAX_CONNECTIONS)),
+            when_busy: WhenBusy::Reject,
+            access: Arc::new(AccessList::default()),
+            networks: Arc::new(RwLock::new(HashMap::new())),
+            hidden: Arc::new(RwLock::new(HashSet::new())),
+            auth_required: Arc::new(RwLock::new(HashSet::new())),
+            max_connections_per_ip: None,
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            command_rate: None,
+            idle_timeout: None,
+            max_commands: None,
+            slow_query: None,
+            stats: Arc::new(Mutex::new(Statistics::new())),
+            metrics: Arc::new(Mutex::new(Metrics::new())),
+            cache: Some(Arc::new(Mutex::new(LruCache::new(DEFAULT_CACHE_SIZE)))),
+            stream_threshold: DEFAULT_STREAM_THRESHOLD,
+            unavailable: Arc::new(RwLock::new(BTreeMap::new())),
+            connections: Arc::new(Mutex::new(BTreeMap::new())),
+            users: Arc::new(HashMap::new()),
+            priorities: Arc::new(HashMap::new()),
+            dedup_matches: false,
+            banner: None,
+            security_log: None,
+            access_log: None,
+            query_logging: QueryLogging::Full,
+            session: Session::new(),
+        }
+    }
+
+    // Adds a match strategy, replacing a strategy of the same name in its
+    // place
+    pub fn add_strategy(&mut self, strategy: Box<dyn MatchStrategy>) {
+        let mut strategies = self.strategies.write().unwrap();
+        match strategies.iter_mut().find(|s| s.name() == strategy.name()) {
+            Some(existing) => *existing = strategy,
+            None => strategies.push(strategy),
+        }
+    }
+
+    // Sets the order of the databases in SHOW DB and in the answers to
+    // "*" and "!". Databases not named follow in alphabetical order.
+    pub fn set_database_order(&mut self, names: Vec<String>) {
+        let mut priorities = HashMap::new();
+        for (i, name) in names.into_iter().enumerate() {
+            priorities.entry(name).or_insert(i);
+        }
+        self.priorities = Arc::new(priorities);
+    }
+
+    // Lists a headword matched in several databases only for the first of
+    // them in MATCH. XDEFINEMATCH still answers with all definitions.
+    pub fn set_dedup_matches(&mut self, dedup: bool) {
+        self.dedup_matches = dedup;
+    }
+
+    // Sets the strategy of MATCH with the strategy "." for the databases
+    // without a default strategy of their own
+    pub fn set_default_strategy(&mut self, strategy: &str) -> Result<(), DictError> {
+        self.default_strategy = self.usable_default(strategy)?;
+        Ok(())
+    }
+
+    // Lets clients name strategies by other names, e.g. "re" for "regexp",
+    // or "." for the default strategy. The names of strategies cannot be
+    // taken.
+    pub fn set_strategy_aliases(
+        &mut self,
+        aliases: HashMap<String, String>,
+    ) -> Result<(), DictError> {
+        for (alias, strategy) in aliases.iter() {
+            if self.strategy_exists(alias) {
+                return Err(DictError::InvalidStrategy(alias.clone()));
+            }
+            if strategy != "." && !self.strategy_exists(strategy) {
+                return Err(DictError::InvalidStrategy(strategy.clone()));
+            }
+        }
+        self.strategy_aliases = Arc::new(aliases);
+        Ok(())
+    }
+
+    // Sets the strategy of MATCH with the strategy "." for a database, or
+    // leaves it to the server with None
+    pub fn set_database_strategy(
+        &mut self,
+        shortname: &str,
+        strategy: Option<&str>,
+    ) -> Result<(), DictError> {
+        let strategy = strategy
+            .map(|strategy| self.usable_default(strategy))
+            .transpose()?;
+        if let Some(database) = self.databases.write().unwrap().get_mut(shortname) {
+            database.default_strategy = strategy;
+        }
+        Ok(())
+    }
+
+    // The strategy an alias names, or the strategy itself
+    fn resolve_strategy<'a>(&'a self, strategy: &'a str) -> &'a str {
+        match self.strategy_aliases.get(strategy) {
+            Some(strategy) => strategy,
+            None => strategy,
+        }
+    }
+
+    // The strategy a default names. nprefix cannot be one, its queries are
+    // more than a word.
+    fn usable_default(&self, strategy: &str) -> Result<String, DictError> {
+        let resolved = self.resolve_strategy(strategy);
+        if resolved == "nprefix" || !self.strategy_exists(resolved) {
+            return Err(DictError::InvalidStrategy(strategy.to_string()));
+        }
+        Ok(resolved.to_string())
+    }
+
+    // Returns a server sharing all databases, but only exposing the given
+    // ones to its clients
+    pub fn with_view(&self, databases: Option<Vec<String>>) -> DictdServer<R> {
+        let mut server = self.clone();
+        server.view = databases.map(|dbs| Arc::new(dbs.into_iter().collect()));
+        server
+    }
+
+    // Returns a server sharing all databases, but only letting in the
+    // clients both its access list and the given one permit
+    pub fn with_listener_access(&self, access: AccessList) -> DictdServer<R> {
+        let mut server = self.clone();
+        server.listener_access = Arc::new(access);
+        server
+    }
+
+    // Continues counting from the statistics of previous runs
+    pub fn set_statistics(&mut self, stats: Statistics) {
+        *self.stats.lock().unwrap() = stats;
+    }
+
+    pub fn statistics(&self) -> Arc<Mutex<Statistics>> {
+        self.stats.clone()
+    }
+
+    // Counters and latencies of this run
+    pub fn metrics(&self) -> &Mutex<Metrics> {
+        &self.metrics
+    }
+
+    // The loaded databases by name
+    pub fn databases(&self) -> &RwLock<HashMap<String, Database<R>>> {
+        &self.databases
+    }
+
+    // Databases that could not be loaded, with the reason
+    pub fn unavailable(&self) -> &RwLock<BTreeMap<String, String>> {
+        &self.unavailable
+    }
+
+    // Active connections by session id
+    pub fn connections(&self) -> &Mutex<BTreeMap<String, ConnectionInfo>> {
+        &self.connections
+    }
+
+    pub fn set_security_log(&mut self, security_log: SecurityLog<File>) {
+        self.security_log = Some(Arc::new(Mutex::new(security_log)));
+    }
+
+    pub fn set_access_log(&mut self, access_log: AccessLog) {
+        self.access_log = Some(Arc::new(Mutex::new(access_log)));
+    }
+
+    pub fn set_query_logging(&mut self, query_logging: QueryLogging) {
+        self.query_logging = query_logging;
+    }
+
+    // A word looked up as it is written to the logs, "-" if it is left out
+    fn logged_word<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        self.query_logging.word(word).unwrap_or(Cow::Borrowed("-"))
+    }
+
+    // Writes a line for a command to the access log
+    fn log_access(
+        &self,
+        response: &ResponseWriter,
+        command: &str,
+        database: Option<&str>,
+        word: Option<&str>,
+        duration: Duration,
+    ) {
+        let peer = response.peer_addr().to_string();
+        let word = word.and_then(|word| self.query_logging.word(word));
+        let record = AccessRecord {
+            peer: &peer,
+            client: self.session.client.as_deref(),
+            command,
+            database,
+            word: word.as_deref(),
+            status: response.status_code(),
+            duration,
+        };
+        match self.access_log {
+            Some(ref access_log) => {
+                if let Err(e) = access_log.lock().unwrap().record(&record) {
+                    error!("Could not write access log: {:?}", e);
+                }
+            }
+            None => info!("[{}] {}", self.session.request_id, record),
+        }
+    }
+
+    // Records an event in the security log. The event is still logged if
+    // the security log cannot be written.
+    fn audit(&self, stream: &ResponseWriter, event: SecurityEvent) {
+        let peer = stream.peer_addr().to_string();
+        self.record_security_event(&peer, &event);
+        info!("[{}] {}: {:?}", self.session.request_id, peer, event);
+    }
+
+    pub fn record_security_event(&self, peer: &str, event: &SecurityEvent) {
+        if let Some(ref security_log) = self.security_log {
+            if let Err(e) = security_log.lock().unwrap().record(peer, event) {
+                error!("Could not write security log: {:?}", e);
+            }
+        }
+    }
+
+    pub fn set_users(&mut self, users: HashMap<String, String>) {
+        self.users = Arc::new(users);
+    }
+
+    // Capabilities announced in the banner. SASL mechanisms are only offered
+    // if there are users to authenticate.
+    fn capabilities(&self) -> String {
+        let mut capabilities = vec!["mime".to_string(), "xcompress".to_string()];
+        if !self.users.is_empty() {
+            capabilities.insert(0, "auth".to_string());
+            for mechanism in MECHANISMS.iter() {
+                capabilities.push(format!("sasl-{}", mechanism.to_lowercase()));
+            }
+        }
+        format!("<{}>", capabilities.join("."))
+    }
+
+    // Replaces the text of the 220 greeting. The capabilities and the msg-id
+    // follow it, so it should not contain angle brackets.
+    pub fn set_banner(&mut self, banner: Option<String>) {
+        self.banner = banner.map(Arc::new);
+    }
+
+    // The 220 greeting: text, capabilities and the msg-id of the session
+    fn greeting(&self) -> Result<String, DictError> {
+        let text = match &self.banner {
+            Some(banner) => banner.to_string(),
+            None => {
+                let info = os_info::get();
+                format!(
+                    "{:?} {} on {} {}",
+                    hostname::get()?,
+                    env!("CARGO_PKG_NAME"),
+                    info.os_type(),
+                    info.version()
+                )
+            }
+        };
+        Ok(format!(
+            "220 {} {} {}\r\n",
+            text,
+            self.capabilities(),
+            self.session.msg_id
+        ))
+    }
+
+    // Sets the policy for command lines that are not valid UTF-8
+    pub fn set_input_encoding(&mut self, input_encoding: InputEncoding) {
+        self.input_encoding = input_encoding;
+    }
+
+    // Sends longer lists of matches in pages of the given size, which
+    // clients request one after the other with XNEXT
+    pub fn set_match_page_size(&mut self, match_page_size: Option<usize>) {
+        self.match_page_size = match_page_size;
+    }
+
+    // Truncates longer lists of matches
+    pub fn set_max_matches(&mut self, max_matches: Option<usize>) {
+        self.max_matches = max_matches;
+    }
+
+    // Limits the bytes per second sent to each connection and to all of them
+    pub fn set_rate_limits(&mut self, connection: Option<u64>, global: Option<u64>) {
+        self.connection_rate = connection;
+        self.global_limiter =
+            global.map(|rate| Arc::new(Mutex::new(RateLimiter::new(rate, Instant::now()))));
+        self.session = self.new_session();
+    }
+
+    // Limits the clients served at the same time, further clients are
+    // turned away or wait
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.connection_slots = Arc::new(Semaphore::new(max_connections));
+    }
+
+    pub fn set_when_busy(&mut self, when_busy: WhenBusy) {
+        self.when_busy = when_busy;
+    }
+
+    // Limits the addresses clients may connect from
+    pub fn set_access(&mut self, access: AccessList) {
+        self.access = Arc::new(access);
+    }
+
+    // Shows a database only to clients from the given networks, or to all
+    // clients with None
+    pub fn set_networks(&mut self, shortname: &str, networks: Option<Vec<Network>>) {
+        let mut restricted = self.networks.write().unwrap();
+        match networks {
+            Some(networks) => restricted.insert(shortname.to_string(), networks),
+            None => restricted.remove(shortname),
+        };
+    }
+
+    // Leaves a database out of SHOW DB and of searches in all databases (*
+    // and !). Clients can still use it by its name.
+    pub fn set_hidden(&mut self, shortname: &str, hidden: bool) {
+        let mut databases = self.hidden.write().unwrap();
+        match hidden {
+            true => databases.insert(shortname.to_string()),
+            false => databases.remove(shortname),
+        };
+    }
+
+    // Shows a database only to clients that authenticated with AUTH or
+    // SASLAUTH
+    pub fn set_auth_required(&mut self, shortname: &str, required: bool) {
+        let mut databases = self.auth_required.write().unwrap();
+        match required {
+            true => databases.insert(shortname.to_string()),
+            false => databases.remove(shortname),
+        };
+    }
+
+    // Limits the clients served at the same time from a single address
+    pub fn set_max_connections_per_ip(&mut self, max_connections: Option<usize>) {
+        self.max_connections_per_ip = max_connections;
+    }
+
+    // Limits the commands per second of each connection. Clients sending
+    // faster are told 420 and disconnected.
+    pub fn set_command_rate(&mut self, rate: Option<u64>) {
+        self.command_rate = rate;
+    }
+
+    // Logs the commands taking longer than threshold with their database,
+    // strategy and word
+    pub fn set_slow_query(&mut self, threshold: Option<Duration>) {
+        self.slow_query = threshold;
+    }
+
+    // Closes connections idle for longer than idle_timeout or after
+    // max_commands commands
+    pub fn set_connection_limits(
+        &mut self,
+        idle_timeout: Option<Duration>,
+        max_commands: Option<u64>,
+    ) {
+        self.idle_timeout = idle_timeout;
+        self.max_commands = max_commands;
+    }
+
+    // Keeps up to size articles in the definition cache, 0 disables it
+    pub fn set_cache_size(&mut self, size: usize) {
+        self.cache = match size {
+            0 => None,
+            size => Some(Arc::new(Mutex::new(LruCache::new(size)))),
+        };
+    }
+
+    // DEFINE sends articles longer than threshold bytes as they are read,
+    // keeping a chunk of them in memory at a time instead of the whole
+    // article. Those of databases with filters or a template, or not in
+    // UTF-8, are always read whole, as are all articles of clients that
+    // enabled compression.
+    pub fn set_stream_threshold(&mut self, threshold: u64) {
+        self.stream_threshold = threshold;
+    }
+
+    // Reads the articles at the given offsets and lengths of a database,
+    // from the definition cache where possible
+    fn read_articles(
+        &self,
+        database: &Database<R>,
+        ranges: &[(u64, u64)],
+    ) -> Vec<Result<String, DictError>> {
+        let dictreader = match &database.dictreader {
+            Some(dictreader) => dictreader,
+            None => return ranges.iter().map(|_| Err(no_dict_file())).collect(),
+        };
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return dictreader.find_many(ranges),
+        };
+        let key = |(offset, length): (u64, u64)| (database.shortname.clone(), offset, length);
+        let mut articles: Vec<Option<Result<String, DictError>>> = {
+            let mut cache = cache.lock().unwrap();
+            ranges
+                .iter()
+                .map(|range| cache.get(&key(*range)).cloned().map(Ok))
+                .collect()
+        };
+        let misses: Vec<(u64, u64)> = ranges
+            .iter()
+            .zip(articles.iter())
+            .filter(|(_, article)| article.is_none())
+            .map(|(range, _)| *range)
+            .collect();
+        {
+            let mut stats = self.stats.lock().unwrap();
+            for _ in 0..ranges.len() - misses.len() {
+                stats.record_cache(true);
+            }
+            for _ in 0..misses.len() {
+                stats.record_cache(false);
+            }
+        }
+        if !misses.is_empty() {
+            let read = dictreader.find_many(&misses);
+            let mut cache = cache.lock().unwrap();
+            let mut read = misses.into_iter().zip(read);
+            for article in articles.iter_mut().filter(|article| article.is_none()) {
+                if let Some((range, result)) = read.next() {
+                    if let Ok(text) = &result {
+                        cache.insert(key(range), text.clone());
+                    }
+                    *article = Some(result);
+                }
+            }
+        }
+        articles.into_iter().flatten().collect()
+    }
+
+    // Reads the articles of the most frequent headwords of a database into
+    // the definition cache, its share of the cache if the databases split it
+    // evenly, and returns how many were read. Nothing is read without a
+    // frequency list or a cache.
+    pub fn prime_cache(&self, shortname: &str) -> usize {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return 0,
+        };
+        let databases = self.databases.read().unwrap();
+        let database = match databases.get(shortname) {
+            Some(database) => database,
+            None => return 0,
+        };
+        let dictreader = match &database.dictreader {
+            Some(dictreader) => dictreader,
+            None => return 0,
+        };
+        let count = cache.lock().unwrap().capacity() / databases.len();
+        let ranges: Vec<(u64, u64)> = database
+            .indexreader
+            .read()
+            .unwrap()
+            .most_frequent(count)
+            .into_iter()
+            .map(|entry| (entry.offset, entry.length))
+            .take(count)
+            .collect();
+        let articles = dictreader.find_many(&ranges);
+        let mut cache = cache.lock().unwrap();
+        let mut read = 0;
+        for ((offset, length), article) in ranges.into_iter().zip(articles) {
+            if let Ok(text) = article {
+                cache.insert((database.shortname.clone(), offset, length), text);
+                read += 1;
+            }
+        }
+        read
+    }
+
+    fn read_article(
+        &self,
+        database: &Database<R>,
+        offset: u64,
+        length: u64,
+    ) -> Result<String, DictError> {
+        self.read_articles(database, &[(offset, length)])
+            .pop()
+            .unwrap_or(Err(DictError::NoMatch(String::new())))
+    }
+
+    // Checks the client's address and takes a connection slot, unless one
+    // was taken while waiting for the client, and one of the slots of the
+    // address, or says why the client is turned away
+    fn admit(
+        &self,
+        peer: SocketAddr,
+        slot: Option<OwnedSemaphorePermit>,
+    ) -> Result<Admission, Refusal> {
+        let ip = peer.ip();
+        if !self.permits(ip) {
+            return Err(Refusal::Denied);
+        }
+        let permit = match slot {
+            Some(permit) => permit,
+            None => self
+                .connection_slots
+                .clone()
+                .try_acquire_owned()
+                .map_err(|_| Refusal::Busy("too many connections"))?,
+        };
+        let mut peers = self.peers.lock().unwrap();
+        let count = peers.entry(ip).or_insert(0);
+        if self.max_connections_per_ip.is_some_and(|max| *count >= max) {
+            return Err(Refusal::Busy("too many connections from its address"));
+        }
+        *count += 1;
+        Ok(Admission {
+            _permit: permit,
+            _peer: PeerSlot {
+                peers: self.peers.clone(),
+                ip,
+            },
+        })
+    }
+
+    // Whether the access lists of the server and of the listener let
+    // clients from the address in
+    fn permits(&self, ip: IpAddr) -> bool {
+        self.access.permits(ip) && self.listener_access.permits(ip)
+    }
+
+    fn new_session(&self) -> Session {
+        let session = Session::new();
+        session.transfer.lock().unwrap().limiter = self
+            .connection_rate
+            .map(|rate| RateLimiter::new(rate, Instant::now()));
+        session
+    }
+
+    // Adds a database to the server
+    pub fn add_database(
+        &mut self,
+        shortname: String,
+        metadata: DatabaseMetadata,
+        indexreader: Arc<RwLock<IndexReader>>,
+        dictreader: DictReader<R>,
+    ) {
+        let normalization = indexreader.read().unwrap().normalization();
+        let database = Database::new(
+            shortname.clone(),
+            metadata,
+            normalization,
+            indexreader,
+            Some(dictreader),
+            None,
+        );
+        self.unavailable.write().unwrap().remove(&shortname);
+        self.databases.write().unwrap().insert(shortname, database);
+    }
+
+    // Adds a database without files, whose DEFINE and MATCH lookups the
+    // backend answers with the words as sent, only composed. The commands
+    // searching the index or reading articles directly find nothing in it.
+    pub fn add_backend(
+        &mut self,
+        shortname: String,
+        metadata: DatabaseMetadata,
+        backend: Arc<dyn Backend>,
+    ) {
+        let database = Database::new(
+            shortname.clone(),
+            metadata,
+            Normalization::AllCharsCaseSensitive,
+            Arc::new(RwLock::new(IndexReader::new())),
+            None,
+            Some(backend),
+        );
+        self.unavailable.write().unwrap().remove(&shortname);
+        self.databases.write().unwrap().insert(shortname, database);
+    }
+
+    pub fn set_checksum(&mut self, shortname: &str, checksum: String) {
+        if let Some(database) = self.databases.write().unwrap().get_mut(shortname) {
+            database.checksum = Some(checksum);
+        }
+    }
+
+    // Checksum of the files a database was loaded from
+    pub fn checksum(&self, shortname: &str) -> Option<String> {
+        self.databases
+            .read()
+            .unwrap()
+            .get(shortname)
+            .and_then(|database| database.checksum.clone())
+    }
+
+    // Swaps in the files of a database loaded again, keeping its settings.
+    // The swap waits for running queries, which finish with the old files.
+    // Returns false if the database is not loaded.
+    pub fn replace_database(
+        &mut self,
+        shortname: &str,
+        metadata: DatabaseMetadata,
+        indexreader: IndexReader,
+        dictreader: DictReader<R>,
+        aux_indexes: Vec<(String, IndexReader)>,
+        checksum: Option<String>,
+    ) -> bool {
+        let normalization = indexreader.normalization();
+        let aux_indexes = aux_indexes
+            .into_iter()
+            .map(|(variant, mut indexreader)| {
+                indexreader.normalize_headwords(normalization);
+                (variant, Arc::new(RwLock::new(indexreader)))
+            })
+            .collect();
+        let mut databases = self.databases.write().unwrap();
+        let database = match databases.get_mut(shortname) {
+            Some(database) => database,
+            None => return false,
+        };
+        database.metadata = metadata;
+        database.normalization = normalization;
+        database.indexreader = Arc::new(RwLock::new(indexreader));
+        database.dictreader = Some(Arc::new(dictreader));
+        database.aux_indexes = aux_indexes;
+        database.checksum = checksum;
+        database.fulltext = Arc::new(OnceLock::new());
+        // The offsets of the cached articles refer to the old files
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().retain(|key| key.0 != shortname);
+        }
+        true
+    }
+
+    // Sets the time a database may take to answer a query
+    pub fn set_deadline(&mut self, shortname: &str, deadline: Duration) {
+        if let Some(database) = self.databases.write().unwrap().get_mut(shortname) {
+            database.breaker = Mutex::new(CircuitBreaker::new(
+                deadline,
+                BREAKER_THRESHOLD,
+                BREAKER_COOLDOWN,
+            ));
+        }
+    }
+
+    // Records a database that failed to load. It is listed as unavailable
+    // until it has been loaded.
+    pub fn set_unavailable(&mut self, shortname: &str, reason: String) {
+        self.unavailable
+            .write()
+            .unwrap()
+            .insert(shortname.to_string(), reason);
+    }
+
+    // Checks that all databases were loaded and can be read, for health
+    // probes. Returns the databases failing with the reason.
+    pub fn check_databases(&self) -> BTreeMap<String, String> {
+        let mut failing = self.unavailable.read().unwrap().clone();
+        for (name, database) in self.databases.read().unwrap().iter() {
+            if let Err(e) = database.spot_check() {
+                failing.insert(name.clone(), e.to_string());
+            }
+        }
+        failing
+    }
+
+    // Adds an auxiliary index to a database, e.g. "suffix" for an index of
+    // reversed headwords
+    pub fn add_aux_index(
+        &mut self,
+        shortname: &str,
+        variant: String,
+        indexreader: Arc<RwLock<IndexReader>>,
+    ) {
+        if let Some(database) = self.databases.write().unwrap().get_mut(shortname) {
+            indexreader
+                .write()
+                .unwrap()
+                .normalize_headwords(database.normalization);
+            database.aux_indexes.insert(variant, indexreader);
+        }
+    }
+
+    // Truncates longer lists of matches from a database
+    pub fn set_database_max_matches(&mut self, shortname: &str, max_matches: Option<usize>) {
+        if let Some(database) = self.databases.write().unwrap().get_mut(shortname) {
+            database.max_matches = max_matches;
+        }
+    }
+
+    // Declares the format of a database's articles, plain text by default
+    pub fn set_content_type(&mut self, shortname: &str, content_type: ContentType) {
+        if let Some(database) = self.databases.write().unwrap().get_mut(shortname) {
+            database.content_type = content_type;
+        }
+    }
+
+    // Sets the chain of filters applied to a database's articles
+    pub fn set_filters(&mut self, shortname: &str, filters: Vec<Arc<dyn DefinitionFilter>>) {
+        if let Some(database) = self.databases.write().unwrap().get_mut(shortname) {
+            database.filters = filters;
+        }
+    }
+
+    // Sets the template articles of a database are rendered with
+    pub fn set_template(&mut self, shortname: &str, template: Option<Template>) {
+        if let Some(database) = self.databases.write().unwrap().get_mut(shortname) {
+            database.template = template;
+        }
+    }
+
+    // The socket of the connection about to be registered, so that it can
+    // be disconnected
+    pub fn set_socket(&mut self, socket: Socket) {
+        self.session.socket = Some(socket);
+    }
+
+    // Lists a connection with the active ones until the guard is dropped
+    pub fn register_connection(&mut self, peer: SocketAddr) -> ConnectionGuard {
+        self.stats.lock().unwrap().record_connection();
+        let peer = peer.to_string();
+        let now = Instant::now();
+        self.connections.lock().unwrap().insert(
+            self.session.id.clone(),
+            ConnectionInfo {
+                peer,
+                client: None,
+                connected: now,
+                last_active: now,
+                commands: 0,
+                socket: self.session.socket.take(),
+            },
+        );
+        ConnectionGuard {
+            connections: self.connections.clone(),
+            id: self.session.id.clone(),
+        }
+    }
+
+    // Closes an active connection, whose client reads the end of the
+    // stream. Returns false if there is no connection with the id.
+    pub fn disconnect(&self, id: &str) -> std::io::Result<bool> {
+        let connections = self.connections.lock().unwrap();
+        match connections.get(id).map(|info| &info.socket) {
+            None => Ok(false),
+            Some(Some(socket)) => socket.shutdown(std::net::Shutdown::Both).map(|()| true),
+            Some(None) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "connection without a socket",
+            )),
+        }
+    }
+
+    // Starts the next request of the session and counts it with the
+    // connection
+    fn record_activity(&mut self) {
+        self.session.next_request();
+        if let Some(info) = self.connections.lock().unwrap().get_mut(&self.session.id) {
+            info.last_active = Instant::now();
+            info.commands += 1;
+        }
+    }
+
+    // Admits a client that does not speak DICT, e.g. one of the HTTP
+    // gateway, like a connection: it takes a connection slot and counts
+    // against the limit of its address until the admission is dropped
+    pub fn admit_client(&self, peer: SocketAddr) -> Result<Admission, Refusal> {
+        self.admit(peer, None)
+    }
+
+    // The limiter of the commands of a connection, if their rate is limited
+    pub fn command_limiter(&self) -> Option<RateLimiter> {
+        self.command_rate
+            .map(|rate| RateLimiter::new(rate, Instant::now()))
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    // Looks up a word like DEFINE for a client at peer that does not speak
+    // DICT, e.g. one of the HTTP gateway or on a WebSocket. Like a
+    // connection, each such client is answered by a clone of the server,
+    // whose session it keeps.
+    pub fn define(
+        &mut self,
+        peer: SocketAddr,
+        database: &str,
+        word: &str,
+    ) -> Result<Vec<Definition>, DictError> {
+        self.session.peer = Some(peer.ip());
+        self.record_activity();
+        self.stats.lock().unwrap().record_command("DEFINE");
+        let definitions = self.find_definitions(peer, database, word, false)?;
+        Ok(definitions
+            .into_iter()
+            .map(|found| Definition {
+                headword: found.entry.headword().to_string(),
+                database: found.database,
+                description: found.description,
+                text: found.text,
+                content_type: found.content_type,
+            })
+            .collect())
+    }
+
+    // Matches a word like MATCH, see define. Returns the matches and whether
+    // they were cut off at the limits.
+    pub fn match_words(
+        &mut self,
+        peer: SocketAddr,
+        database: &str,
+        strategy: &str,
+        word: &str,
+    ) -> Result<(Vec<Match>, bool), DictError> {
+        self.session.peer = Some(peer.ip());
+        self.record_activity();
+        self.stats.lock().unwrap().record_command("MATCH");
+        self.session.matches += 1;
+        let (results, truncated) = self.search_matches(peer, "MATCH", database, strategy, word)?;
+        if results.is_empty() {
+            return Err(DictError::NoMatch(word.to_string()));
+        }
+        let matches = results
+            .into_iter()
+            .map(|(database, entry)| Match {
+                headword: entry.headword().to_string(),
+                database,
+            })
+            .collect();
+        Ok((matches, truncated))
+    }
+
+    // Handles a connection from the client
+    pub async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        stream: S,
+        peer: SocketAddr,
+    ) -> Result<(), DictError> {
+        let _guard = self.register_connection(peer);
+        self.session.peer = Some(peer.ip());
+        let mut stream = BufStream::new(stream);
+        let mut parser = Parser::new();
+        let mut command_limiter = self.command_limiter();
+        stream.write_all(self.greeting()?.as_bytes()).await?;
+        let mut received = ReceiveBuffer::new();
+        let mut chunk = [0; RECEIVE_CHUNK];
+        loop {
+            // Commands sent together are answered together, the responses
+            // are flushed once no complete command is left
+            let line = match received.next_line() {
+                Some(line) => line,
+                None => {
+                    stream.flush().await?;
+                    let count = match self.receive(&mut stream, &mut chunk).await? {
+                        Some(count) => count,
+                        None => break,
+                    };
+                    if count > 0 {
+                        received.extend(&chunk[..count]);
+                        continue;
+                    }
+                    match received.finish() {
+                        Some(line) => line,
+                        None => {
+                            debug!("[{}] Connection closed by peer", self.session.id);
+                            break;
+                        }
+                    }
+                }
+            };
+            if line.len() > MAX_COMMAND {
+                info!("[{}] Rejected command line too long", self.session.id);
+                stream.write_all(b"500 Line too long\r\n").await?;
+                continue;
+            }
+
+            let reads = match self.input_encoding.decode(line) {
+                Some(reads) => reads,
+                None => {
+                    info!(
+                        "[{}] Rejected command with invalid character encoding",
+                        self.session.id
+                    );
+                    stream
+                        .write_all(b"500 Invalid character encoding\r\n")
+                        .await?;
+                    continue;
+                }
+            };
+            let query = reads.trim();
+            if !query.is_empty() {
+                if self
+                    .max_commands
+                    .is_some_and(|max| self.session.commands >= max)
+                {
+                    info!(
+                        "[{}] Closing connection after {} commands",
+                        self.session.id, self.session.commands
+                    );
+                    stream
+                        .write_all(b"221 Closing connection, too many commands\r\n")
+                        .await?;
+                    stream.flush().await?;
+                    break;
+                }
+                if let Some(ref mut limiter) = command_limiter {
+                    if limiter.reserve(1, Instant::now()) > Duration::from_secs(0) {
+                        warn!(
+                            "[{}] Closing connection from {}, too many commands",
+                            self.session.id, peer
+                        );
+                        stream
+                            .write_all(b"420 Server temporarily unavailable\r\n")
+                            .await?;
+                        stream.flush().await?;
+                        break;
+                    }
+                }
+                let mut response = ResponseWriter::new(peer);
+                // Commands read the databases with blocking I/O and locks
+                let quit = blocking(|| self.execute(&mut parser, &mut response, query))?;
+                self.send(&mut stream, response).await?;
+                debug!("[{}] ResponseWriter sent", self.session.request_id);
+                if quit {
+                    stream.flush().await?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Reads what the client sent next into chunk. Returns the number of
+    // bytes read, 0 if the client closed the connection, or None if the
+    // connection was idle for too long and has been told so.
+    async fn receive<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: &mut BufStream<S>,
+        chunk: &mut [u8],
+    ) -> Result<Option<usize>, DictError> {
+        let read = stream.read(chunk);
+        let idle_timeout = match self.idle_timeout {
+            Some(idle_timeout) => idle_timeout,
+            None => return Ok(Some(read.await?)),
+        };
+        match tokio::time::timeout(idle_timeout, read).await {
+            Ok(count) => Ok(Some(count?)),
+            Err(_) => {
+                info!("[{}] Closing idle connection", self.session.id);
+                stream
+                    .write_all(b"221 Closing connection, idle too long\r\n")
+                    .await?;
+                stream.flush().await?;
+                Ok(None)
+            }
+        }
+    }
+
+    // Runs a command and writes its response. Returns true if the client
+    // ended the connection.
+    fn execute(
+        &mut self,
+        parser: &mut Parser,
+        stream: &mut ResponseWriter,
+        query: &str,
+    ) -> Result<bool, DictError> {
+        let started = Instant::now();
+        self.record_activity();
+        // Only the command unless the words looked up may be logged
+        let logged = match self.query_logging {
+            QueryLogging::Full => query,
+            _ => query.split_whitespace().next().unwrap_or(""),
+        };
+        match self.session.client {
+            Some(ref client) => debug!(
+                "[{}] {} {:?}: Received query: {}",
+                self.session.request_id,
+                stream.peer_addr(),
+                client,
+                logged
+            ),
+            None => debug!(
+                "[{}] {}: Received query: {}",
+                self.session.request_id,
+                stream.peer_addr(),
+                logged
+            ),
+        }
+        let result = parser.parse(query);
+        let cmd = match result {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                debug!(
+                    "[{}] Could not parse query: {:?}",
+                    self.session.request_id, e
+                );
+                stream.status(e.response());
+                self.log_access(stream, "-", None, None, started.elapsed());
+                return Ok(false);
+            }
+        };
+        debug!("[{}] Parsed command {:?}", self.session.request_id, cmd.cmd);
+        // Other commands may carry secrets and are logged without arguments
+        let command = cmd.params[0].to_uppercase();
+        // Made up commands are counted together
+        let known = cmd.cmd != Cmd::Unknown || EXTENSIONS.contains(&command.as_str());
+        let counted = if known { command.as_str() } else { "unknown" }.to_string();
+        self.stats.lock().unwrap().record_command(&counted);
+        let (database, word) = match cmd.params.len() {
+            3 if cmd.cmd == Cmd::Define => {
+                (Some(cmd.params[1].clone()), Some(cmd.params[2].clone()))
+            }
+            4 if cmd.cmd == Cmd::Match || command == "XDEFINEMATCH" => {
+                (Some(cmd.params[1].clone()), Some(cmd.params[3].clone()))
+            }
+            _ if cmd.cmd == Cmd::Random => (Some(cmd.database.clone()), None),
+            3 if command == "XREF" => (Some(cmd.params[1].clone()), Some(cmd.params[2].clone())),
+            n if n >= 3 && command == "XSEARCH" => {
+                (Some(cmd.params[1].clone()), Some(cmd.params[2..].join(" ")))
+            }
+            _ => (None, None),
+        };
+        let strategy = match cmd.params.len() {
+            4 if cmd.cmd == Cmd::Match || command == "XDEFINEMATCH" => Some(cmd.params[2].clone()),
+            _ => None,
+        };
+        let quit = match self.dispatch(stream, cmd) {
+            Ok(quit) => quit,
+            // Errors of the query are answered, others end the connection
+            Err(e) if e.status_code() >= 500 => {
+                stream.status(e.protocol_line());
+                false
+            }
+            Err(e) => return Err(e),
+        };
+        self.session.last_command = started.elapsed();
+        self.stats
+            .lock()
+            .unwrap()
+            .record_latency(&counted, self.session.last_command);
+        if self
+            .slow_query
+            .is_some_and(|threshold| self.session.last_command > threshold)
+        {
+            warn!(
+                "[{}] Slow {} from {}: {:.3}s, database {}, strategy {}, word {}",
+                self.session.request_id,
+                command,
+                stream.peer_addr(),
+                self.session.last_command.as_secs_f64(),
+                database.as_deref().unwrap_or("-"),
+                strategy.as_deref().unwrap_or("-"),
+                word.as_deref()
+                    .map_or(Cow::Borrowed("-"), |word| self.logged_word(word))
+            );
+        }
+        if command == "MATCH" {
+            self.metrics
+                .lock()
+                .unwrap()
+                .match_latency
+                .observe(self.session.last_command.as_secs_f64());
+        }
+        self.log_access(
+            stream,
+            &command,
+            database.as_deref(),
+            word.as_deref(),
+            started.elapsed(),
+        );
+        Ok(quit)
+    }
+
+    // Runs a parsed command. Returns true if the client ended the
+    // connection.
+    fn dispatch(&mut self, stream: &mut ResponseWriter, cmd: Command) -> Result<bool, DictError> {
+        match cmd.cmd {
+            Cmd::Define => self.command_define(&mut *stream, cmd)?,
+            Cmd::Help => self.command_help(&mut *stream)?,
+            Cmd::Match => self.command_match(&mut *stream, cmd)?,
+            Cmd::Show => self.command_show(&mut *stream, cmd)?,
+            Cmd::Status => self.command_status(&mut *stream)?,
+            Cmd::Quit => {
+                self.command_quit(&mut *stream)?;
+                return Ok(true);
+            }
+            Cmd::Option => self.command_option(&mut *stream, cmd)?,
+            Cmd::Auth => self.command_auth(&mut *stream, cmd)?,
+            Cmd::Client => self.command_client(&mut *stream, cmd)?,
+            Cmd::SaslAuth => self.command_saslauth(&mut *stream, cmd)?,
+            Cmd::SaslResp => self.command_saslresp(&mut *stream, cmd)?,
+            Cmd::Random => self.command_random(&mut *stream, cmd)?,
+            // Commands are case-insensitive, the extensions too
+            Cmd::Unknown => match cmd.params[0].to_uppercase().as_str() {
+                "XDEFINEMATCH" => self.command_define_match(&mut *stream, cmd)?,
+                "XNEXT" => self.command_next(&mut *stream, cmd)?,
+                "XSEARCH" => self.command_search(&mut *stream, cmd)?,
+                "XREF" => self.command_xref(&mut *stream, cmd)?,
+                "XSTAT" => self.command_stat(&mut *stream, cmd)?,
+                "XPING" => self.command_ping(&mut *stream, cmd)?,
+                _ => stream.status("500 Syntax error, command not recognized"),
+            },
+        }
+        Ok(false)
+    }
+
+    // Sends a response to the client. Limited pieces are sent in chunks no
+    // faster than the rate limits of the connection and the server allow,
+    // waiting before each chunk as long as the slower limit requires.
+    async fn send<W: AsyncWrite + Unpin>(
+        &self,
+        stream: &mut W,
+        response: ResponseWriter,
+    ) -> Result<(), DictError> {
+        for piece in response.into_pieces() {
+            match piece {
+                Piece::Status(lines) => stream.write_all(&lines).await?,
+                Piece::Text(block) => self.send_limited(stream, &block).await?,
+                Piece::Article {
+                    prefix,
+                    database,
+                    source,
+                    offset,
+                    length,
+                } => {
+                    self.send_article(stream, &prefix, &database, &*source, offset, length)
+                        .await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Sends a text block no faster than the rate limits allow
+    async fn send_limited<W: AsyncWrite + Unpin>(
+        &self,
+        stream: &mut W,
+        block: &[u8],
+    ) -> Result<(), DictError> {
+        for chunk in block.chunks(THROTTLE_CHUNK) {
+            let wait = self.reserve(chunk.len());
+            if wait > Duration::from_secs(0) {
+                stream.flush().await?;
+                tokio::time::sleep(wait).await;
+            }
+            stream.write_all(chunk).await?;
+        }
+        Ok(())
+    }
+
+    // Sends the text block of a prefix and an article, reading and sending
+    // STREAM_CHUNK bytes of the article at a time from the dict file it was
+    // found in. An article that cannot be read to its end is cut off there,
+    // as the client was already told that it follows.
+    async fn send_article<W: AsyncWrite + Unpin>(
+        &self,
+        stream: &mut W,
+        prefix: &str,
+        database: &str,
+        source: &dyn ArticleSource,
+        offset: u64,
+        length: u64,
+    ) -> Result<(), DictError> {
+        let mut encoder = BlockEncoder::new();
+        let mut block = Vec::new();
+        encoder.encode(prefix.as_bytes(), &mut block);
+        let end = offset + length;
+        let mut position = offset;
+        while position < end {
+            let len = (end - position).min(STREAM_CHUNK);
+            // Blocking I/O like that of the commands
+            let chunk = blocking(|| source.read_bytes(position, len));
+            match chunk {
+                Ok(chunk) => encoder.encode(&chunk, &mut block),
+                Err(e) => {
+                    warn!(
+                        "[{}] Could not read {} bytes at {} from {}: {:?}",
+                        self.session.request_id, len, position, database, e
+                    );
+                    break;
+                }
+            }
+            position += len;
+            self.send_limited(stream, &block).await?;
+            block.clear();
+        }
+        encoder.finish(&mut block);
+        self.send_limited(stream, &block).await
+    }
+
+    // Takes bytes from the rate limits and returns how long to wait before
+    // sending them
+    fn reserve(&self, bytes: usize) -> Duration {
+        let mut transfer = self.session.transfer.lock().unwrap();
+        let now = Instant::now();
+        let mut wait = match transfer.limiter {
+            Some(ref mut limiter) => limiter.reserve(bytes, now),
+            None => Duration::from_secs(0),
+        };
+        if let Some(ref global) = self.global_limiter {
+            wait = wait.max(global.lock().unwrap().reserve(bytes, now));
+        }
+        transfer.throttled += wait;
+        wait
+    }
+
+    // Helper function
+    fn database_exists(&self, database: &str) -> bool {
+        if self.databases.read().unwrap().contains_key(database) {
+            return self.database_visible(database);
+        }
+        false
+    }
+
+    fn database_visible(&self, database: &str) -> bool {
+        if let Some(view) = &self.view {
+            if !view.contains(database) {
+                return false;
+            }
+        }
+        if self.session.user.is_none() && self.auth_required.read().unwrap().contains(database) {
+            return false;
+        }
+        match (
+            self.networks.read().unwrap().get(database),
+            self.session.peer,
+        ) {
+            (Some(networks), Some(ip)) => networks.iter().any(|network| network.contains(ip)),
+            _ => true,
+        }
+    }
+
+    // Whether a database takes part in searches in all databases (* and !)
+    fn breaker_allows(&self, database: &str) -> bool {
+        match self.databases.read().unwrap().get(database) {
+            Some(database) => database.breaker.lock().unwrap().allows(Instant::now()),
+            None => false,
+        }
+    }
+
+    // Whether a database is listed by SHOW DB and searched by * and !
+    fn database_listed(&self, database: &str) -> bool {
+        self.database_visible(database) && !self.hidden.read().unwrap().contains(database)
+    }
+
+    // The databases searched by * and !
+    fn visible_databases(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .databases
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|d| self.database_listed(d))
+            .cloned()
+            .collect();
+        names.sort_by(|a, b| self.order(a).cmp(&self.order(b)));
+        names
+    }
+
+    // Sort key of a database, the configured priority, then the name
+    fn order<'a>(&self, name: &'a str) -> (usize, &'a str) {
+        (*self.priorities.get(name).unwrap_or(&usize::MAX), name)
+    }
+
+    fn strategy_exists(&self, strategy: &str) -> bool {
+        self.strategies
+            .read()
+            .unwrap()
+            .iter()
+            .any(|s| s.name() == strategy)
+    }
+
+    // Writes a text block followed by the terminating "." line, compressed
+    // if the client negotiated OPTION XCOMPRESS
+    fn write_text_block(&self, stream: &mut ResponseWriter, text: &str) -> Result<(), DictError> {
+        self.write_typed_block(stream, ContentType::Plain, text)
+    }
+
+    // Writes a text block of the given content type, which is announced in
+    // the MIME headers if the client enabled them
+    fn write_typed_block(
+        &self,
+        stream: &mut ResponseWriter,
+        content_type: ContentType,
+        text: &str,
+    ) -> Result<(), DictError> {
+        let text = if self.session.mime {
+            content_type.header() + text
+        } else {
+            text.to_string()
+        };
+        let text = match self.session.compress {
+            Some(compression) => match compression.compress(&text) {
+                Ok(block) => block,
+                Err(_) => return Err(DictError::IllegalParameters),
+            },
+            None => text,
+        };
+        let bytes = stream.text_block(&text);
+        self.session.transfer.lock().unwrap().bytes += bytes as u64;
+        Ok(())
+    }
+
+    fn command_help(&self, stream: &mut ResponseWriter) -> Result<(), DictError> {
+        stream.status("113 help text follows");
+        self.write_text_block(stream, HELP_TEXT)?;
+        stream.status("250 ok");
+        Ok(())
+    }
+
+    fn command_option(
+        &mut self,
+        stream: &mut ResponseWriter,
+        cmd: Command,
+    ) -> Result<(), DictError> {
+        match cmd.params[1].to_uppercase().as_str() {
+            option @ ("XCOMPRESS" | "DEFLATE") => {
+                let compression = match option {
+                    "DEFLATE" if cmd.params.len() == 2 => BlockCompression::Zlib,
+                    "XCOMPRESS"
+                        if cmd.params.len() == 3
+                            && cmd.params[2].eq_ignore_ascii_case("deflate") =>
+                    {
+                        BlockCompression::Deflate
+                    }
+                    _ => return Err(DictError::IllegalParameters),
+                };
+                self.session.compress = Some(compression);
+                info!(
+                    "[{}] Enabled {:?} compression of text responses",
+                    self.session.request_id, compression
+                );
+                stream.status(match compression {
+                    BlockCompression::Deflate => "250 ok - deflate compression enabled",
+                    BlockCompression::Zlib => "250 ok - zlib compression enabled",
+                });
+            }
+            "MIME" => {
+                self.session.mime = true;
+                stream.status("250 ok - using MIME headers");
+            }
+            _ => stream.status("502 OPTION not implemented"),
+        }
+        Ok(())
+    }
+
+    // DEFINE database word
+    // With "*" the word is looked up in all databases, with "!" in the
+    // databases in turn until one has it
+    fn command_define(
+        &mut self,
+        stream: &mut ResponseWriter,
+        cmd: Command,
+    ) -> Result<(), DictError> {
+        // Compressed blocks are made of the whole text
+        let streamed = self.session.compress.is_none();
+        let definitions =
+            self.find_definitions(stream.peer_addr(), &cmd.database, &cmd.params[2], streamed)?;
+        match definitions.len() {
+            1 => stream.status("150 1 definition retrieved"),
+            n => stream.status(&format!("150 {} definitions retrieved", n)),
+        }
+        for found in definitions {
+            stream.status(&format!(
+                "151 \"{}\" {} \"{}\"",
+                found.entry.headword(),
+                found.database,
+                found.description
+            ));
+            match found.article {
+                Some((source, offset, length)) => {
+                    let prefix = match self.session.mime {
+                        true => found.content_type.header() + &found.text,
+                        false => found.text,
+                    };
+                    // Counted as read, before line ends and dots are added
+                    self.session.transfer.lock().unwrap().bytes += prefix.len() as u64 + length;
+                    stream.article(prefix, &found.database, source, offset, length);
+                }
+                None => self.write_typed_block(stream, found.content_type, &found.text)?,
+            }
+        }
+        stream.status("250 ok");
+        Ok(())
+    }
+
+    // Looks up a word in a database, or in all of them with * and only
+    // until one has it with !, for a client at peer. The articles are
+    // rendered for sending. With streamed, articles longer than the stream
+    // threshold are left to be read as they are sent where possible.
+    fn find_definitions(
+        &mut self,
+        peer: SocketAddr,
+        database: &str,
+        word: &str,
+        streamed: bool,
+    ) -> Result<Vec<Found>, DictError> {
+        let mut first_only = false;
+        let mut names = Vec::<String>::new();
+        match database {
+            "*" | "!" => {
+                first_only = database == "!";
+                names.append(&mut self.visible_databases());
+                names.retain(|db| self.breaker_allows(db));
+            }
+            _ => {
+                if !database.is_empty() && !self.database_exists(database) {
+                    return Err(DictError::InvalidDatabase(database.to_string()));
+                }
+                names.push(database.to_string());
+            }
+        }
+        info!(
+            "[{}] DEFINE from {}: DEFINE {} {}",
+            self.session.request_id,
+            peer,
+            database,
+            self.logged_word(word)
+        );
+        self.session.defines += 1;
+        self.stats.lock().unwrap().record_word(word);
+        let databases = self.databases.read().unwrap();
+        let mut definitions = Vec::new();
+        for name in names.iter() {
+            let database = &databases[name];
+            let query = database.normalization.apply(word);
+            if let Some(backend) = &database.backend {
+                definitions.append(&mut self.backend_definitions(database, &**backend, &query));
+                if first_only && !definitions.is_empty() {
+                    break;
+                }
+                continue;
+            }
+            let start = Instant::now();
+            let entries = database.find_entries(query.as_str());
+            database.record_query(start.elapsed());
+            self.stats
+                .lock()
+                .unwrap()
+                .record_define(&database.shortname, !entries.is_empty());
+            let before = definitions.len();
+            // Every headword with the key of the query, e.g. "C" and "C++"
+            for entry in entries {
+                debug!(
+                    "[{}] {}: offset = {}, length = {}",
+                    self.session.request_id, name, entry.offset, entry.length
+                );
+                if let Some(found) = self.prepare_definition(database, entry, &query, streamed) {
+                    self.metrics.lock().unwrap().record_definition(name);
+                    definitions.push(found);
+                }
+            }
+            if first_only && definitions.len() > before {
+                break;
+            }
+        }
+        if definitions.is_empty() {
+            return Err(DictError::NoMatch(word.to_string()));
+        }
+        Ok(definitions)
+    }
+
+    // Prepares the definition of an entry found for a query. Long articles
+    // are left to be read as they are sent with streamed, the others are
+    // read and rendered. None if the article cannot be read.
+    fn prepare_definition(
+        &self,
+        database: &Database<R>,
+        entry: IndexEntry,
+        query: &str,
+        streamed: bool,
+    ) -> Option<Found> {
+        // Found through an alias, inflected form or transliteration
+        let note = if entry.word != query {
+            format!("\"{}\" refers to \"{}\"\n\n", query, entry.word)
+        } else {
+            String::new()
+        };
+        // Only articles in the dict file are streamed, from the file they
+        // were found in
+        let in_file = database
+            .dictreader
+            .as_ref()
+            .filter(|dictreader| entry.offset + entry.length <= dictreader.len());
+        let streams = streamed && entry.length > self.stream_threshold && database.streams();
+        if let Some(dictreader) = in_file.filter(|_| streams) {
+            return Some(Found {
+                database: database.shortname.clone(),
+                description: database.metadata.short.clone(),
+                content_type: database.content_type,
+                text: note,
+                article: Some((dictreader.clone(), entry.offset, entry.length)),
+                entry,
+            });
+        }
+        match self.read_article(database, entry.offset, entry.length) {
+            Ok(article) => {
+                let (content_type, text) = database.render(entry.headword(), &(note + &article));
+                Some(Found {
+                    database: database.shortname.clone(),
+                    description: database.metadata.short.clone(),
+                    entry,
+                    content_type,
+                    text,
+                    article: None,
+                })
+            }
+            Err(e) => {
+                warn!(
+                    "[{}] Could not read \"{}\" from {}: {:?}",
+                    self.session.request_id,
+                    self.logged_word(&entry.word),
+                    database.shortname,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    // Looks up a word in a database answered by a backend
+    fn backend_definitions(
+        &self,
+        database: &Database<R>,
+        backend: &dyn Backend,
+        word: &str,
+    ) -> Vec<Found> {
+        let start = Instant::now();
+        let found = backend.define(word);
+        database.record_query(start.elapsed());
+        let found = found.unwrap_or_else(|e| {
+            warn!(
+                "[{}] Could not look up \"{}\" in {}: {:?}",
+                self.session.request_id,
+                self.logged_word(word),
+                database.shortname,
+                e
+            );
+            Vec::new()
+        });
+        self.stats
+            .lock()
+            .unwrap()
+            .record_define(&database.shortname, !found.is_empty());
+        found
+            .into_iter()
+            .map(|definition| {
+                self.metrics
+                    .lock()
+                    .unwrap()
+                    .record_definition(&database.shortname);
+                let (content_type, text) = database.render(&definition.headword, &definition.text);
+                Found {
+                    database: database.shortname.clone(),
+                    description: database.metadata.short.clone(),
+                    entry: backend_entry(definition.headword),
+                    content_type,
+                    text,
+                    article: None,
+                }
+            })
+            .collect()
+    }
+
+    // MATCH database strategy word
+    fn command_match(
+        &mut self,
+        stream: &mut ResponseWriter,
+        cmd: Command,
+    ) -> Result<(), DictError> {
+        let (results, truncated) = self.find_matches(stream.peer_addr(), &cmd)?;
+        self.session.matches += 1;
+        if !results.is_empty() {
+            self.write_matches(stream, results, truncated)?;
+        } else {
+            self.session.continuation = None;
+            return Err(DictError::NoMatch(cmd.params[3].clone()));
+        }
+        Ok(())
+    }
+
+    // XNEXT token
+    // Continues a list of matches cut off after a page
+    fn command_next(&mut self, stream: &mut ResponseWriter, cmd: Command) -> Result<(), DictError> {
+        if cmd.params.len() != 2 {
+            return Err(DictError::IllegalParameters);
+        }
+        match self.session.continuation.take() {
+            Some((token, results, truncated)) if token == cmd.params[1] => {
+                self.write_matches(stream, results, truncated)?
+            }
+            continuation => {
+                self.session.continuation = continuation;
+                stream.status("501 Unknown continuation token, repeat the MATCH");
+            }
+        }
+        Ok(())
+    }
+
+    // Writes a list of matches. Lists longer than the match page size are cut
+    // off, the status line then names the token to request the rest with
+    // XNEXT.
+    fn write_matches(
+        &mut self,
+        stream: &mut ResponseWriter,
+        mut results: Matches,
+        truncated: bool,
+    ) -> Result<(), DictError> {
+        let rest = match self.match_page_size {
+            Some(size) if results.len() > size => results.split_off(size),
+            _ => Vec::new(),
+        };
+        stream.status(&format!(
+            "152 {} matche(s) found: list follows",
+            results.len()
+        ));
+        let mut text = String::new();
+        for (database, entry) in results {
+            text.push_str(&format!("{} \"{}\"\n", database, entry.headword()));
+        }
+        self.write_text_block(stream, &text)?;
+        if rest.is_empty() {
+            self.session.continuation = None;
+            stream.status(match truncated {
+                true => TRUNCATED,
+                false => "250 ok",
+            });
+        } else {
+            let token = format!("{:08x}", rand::random::<u32>());
+            stream.status(&format!(
+                "250 ok - {} more matches, XNEXT {}",
+                rest.len(),
+                token
+            ));
+            self.session.continuation = Some((token, rest, truncated));
+        }
+        Ok(())
+    }
+
+    // XDEFINEMATCH database strategy word
+    // Like MATCH, but answers with the definitions of the matches, in the
+    // format of DEFINE
+    fn command_define_match(
+        &mut self,
+        stream: &mut ResponseWriter,
+        cmd: Command,
+    ) -> Result<(), DictError> {
+        let (results, truncated) = self.find_matches(stream.peer_addr(), &cmd)?;
+        self.session.matches += 1;
+        // The articles of each database are read in one go
+        let mut names: Vec<&String> = Vec::new();
+        for (db, _) in &results {
+            if !names.contains(&db) {
+                names.push(db);
+            }
+        }
+        let mut definitions = Vec::new();
+        let databases = self.databases.read().unwrap();
+        for name in names {
+            let entries: Vec<&IndexEntry> = results
+                .iter()
+                .filter(|(db, _)| db == name)
+                .map(|(_, entry)| entry)
+                .collect();
+            // The matches of a backend have no articles to read, they are
+            // looked up
+            if let Some(backend) = &databases[name].backend {
+                for entry in entries {
+                    match backend.define(&entry.word) {
+                        Ok(found) => definitions.extend(
+                            found
+                                .into_iter()
+                                .map(|definition| (name, entry, definition.text)),
+                        ),
+                        Err(e) => warn!(
+                            "[{}] Could not look up \"{}\" in {}: {:?}",
+                            self.session.request_id,
+                            self.logged_word(&entry.word),
+                            name,
+                            e
+                        ),
+                    }
+                }
+                continue;
+            }
+            let ranges: Vec<(u64, u64)> = entries.iter().map(|e| (e.offset, e.length)).collect();
+            let articles = self.read_articles(&databases[name], &ranges);
+            for (entry, article) in entries.into_iter().zip(articles) {
+                match article {
+                    Ok(article) => definitions.push((name, entry, article)),
+                    Err(e) => warn!(
+                        "[{}] Could not read \"{}\" from {}: {:?}",
+                        self.session.request_id,
+                        self.logged_word(&entry.word),
+                        name,
+                        e
+                    ),
+                }
+            }
+        }
+        if definitions.is_empty() {
+            return Err(DictError::NoMatch(cmd.params[3].clone()));
+        }
+        match definitions.len() {
+            1 => stream.status("150 1 definition retrieved"),
+            n => stream.status(&format!("150 {} definitions retrieved", n)),
+        }
+        for (name, entry, article) in definitions {
+            let database = &databases[name];
+            stream.status(&format!(
+                "151 \"{}\" {} \"{}\"",
+                entry.headword(),
+                database.shortname,
+                database.metadata.short
+            ));
+            let (content_type, text) = database.render(entry.headword(), &article);
+            self.write_typed_block(stream, content_type, &text)?;
+        }
+        stream.status(match truncated {
+            true => TRUNCATED,
+            false => "250 ok",
+        });
+        Ok(())
+    }
+
+    // Validates a MATCH style command and runs the strategy on the requested
+    // databases. Returns the matches and whether they were truncated to the
+    // limits.
+    fn find_matches(&self, peer: SocketAddr, cmd: &Command) -> Result<(Matches, bool), DictError> {
+        if cmd.params.len() != 4 {
+            return Err(DictError::IllegalParameters);
+        }
+        self.search_matches(
+            peer,
+            &cmd.params[0],
+            &cmd.params[1],
+            &cmd.params[2],
+            &cmd.params[3],
+        )
+    }
+
+    // Runs a strategy, "." for the default one of each database, on a
+    // database or on all of them for a client at peer
+    fn search_matches(
+        &self,
+        peer: SocketAddr,
+        command: &str,
+        database: &str,
+        strategy: &str,
+        word: &str,
+    ) -> Result<(Matches, bool), DictError> {
+        let strategy = self.resolve_strategy(strategy);
+        if strategy != "." && !self.strategy_exists(strategy) {
+            return Err(DictError::InvalidStrategy(strategy.to_string()));
+        }
+        // nprefix takes the matches to skip and send off the prefix
+        let (window, word) = match strategy {
+            "nprefix" => match strategy::parse_nprefix(word) {
+                Some((skip, count, prefix)) => (Some((skip, count)), prefix),
+                None => {
+                    return Err(DictError::IllegalParameters);
+                }
+            },
+            _ => (None, word),
+        };
+        let mut _match_all = false;
+        let mut _match_one = false;
+        let mut databases = Vec::<String>::new();
+        match database {
+            "*" | "!" => {
+                if database == "*" {
+                    _match_all = true;
+                } else {
+                    _match_one = true;
+                }
+                databases.append(&mut self.visible_databases());
+                databases.retain(|db| self.breaker_allows(db));
+            }
+            _ => {
+                if !database.is_empty() && !self.database_exists(database) {
+                    return Err(DictError::InvalidDatabase(database.to_string()));
+                }
+                databases.push(database.to_string());
+            }
+        }
+        info!(
+            "[{}] {} from {}: {} {:?} {} {}",
+            self.session.request_id,
+            command,
+            peer,
+            command,
+            database,
+            strategy,
+            self.logged_word(word)
+        );
+
+        let mut results: Vec<(String, IndexEntry)> = Vec::<(String, IndexEntry)>::new();
+        // Whether each result is only a partial match of the query
+        let mut inexact = Vec::<bool>::new();
+        let mut truncated = false;
+
+        let strategies = self.strategies.read().unwrap();
+        // Whether a database could be searched with the strategy, those
+        // lacking its index fail with InvalidStrategy
+        let mut searchable = databases.is_empty();
+        for db in databases {
+            let found = results.len();
+            let start = Instant::now();
+            let databases = self.databases.read().unwrap();
+            // The query is normalized like the headwords of the database
+            let query = databases[&db].normalization.apply(word);
+            // "." selects the default strategy
+            let strategy = match strategy {
+                "." => databases[&db]
+                    .default_strategy
+                    .as_deref()
+                    .unwrap_or(&self.default_strategy),
+                strategy => strategy,
+            };
+            let searched = match &databases[&db].backend {
+                Some(backend) => match backend.match_words(strategy, &query) {
+                    Ok(headwords) => Ok(headwords.into_iter().map(backend_entry).collect()),
+                    Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+                        Err(DictError::InvalidStrategy(strategy.to_string()))
+                    }
+                    Err(e) => {
+                        warn!(
+                            "[{}] Could not match \"{}\" in {}: {:?}",
+                            self.session.request_id,
+                            self.logged_word(word),
+                            db,
+                            e
+                        );
+                        Err(DictError::from(e))
+                    }
+                },
+                None => match strategies.iter().find(|s| s.name() == strategy) {
+                    Some(found) => databases[&db].indexes().search(found.as_ref(), &query),
+                    None => Err(DictError::InvalidStrategy(strategy.to_string())),
+                },
+            };
+            searchable |= !matches!(searched, Err(DictError::InvalidStrategy(_)));
+            if let Ok(mut res) = searched {
+                if let Some(max) = databases[&db].max_matches {
+                    truncated |= res.len() > max;
+                    res.truncate(max);
+                }
+                for entry in res {
+                    inexact.push(entry.word != query);
+                    results.push((db.clone(), entry));
+                }
+            }
+            databases[&db].record_query(start.elapsed());
+            self.stats
+                .lock()
+                .unwrap()
+                .record_match(&db, results.len() > found);
+        }
+        if !searchable {
+            return Err(DictError::InvalidStrategy(strategy.to_string()));
+        }
+        // Exact matches first, each group in the order of the databases and
+        // within a database in the order of the strategy
+        let mut ranked: Vec<(bool, (String, IndexEntry))> =
+            inexact.into_iter().zip(results).collect();
+        ranked.sort_by_key(|(inexact, _)| *inexact);
+        results = ranked.into_iter().map(|(_, result)| result).collect();
+        if self.dedup_matches && command == "MATCH" {
+            let mut seen = HashSet::new();
+            results.retain(|(_, entry)| seen.insert(entry.headword().to_string()));
+        }
+        if let Some((skip, count)) = window {
+            results = results.into_iter().skip(skip).take(count).collect();
+        }
+        if let Some(max) = self.max_matches {
+            truncated |= results.len() > max;
+            results.truncate(max);
+        }
+
+        Ok((results, truncated))
+    }
+
+    // XSEARCH database words...
+    // Lists the headwords whose definitions contain all words, the
+    // definitions mentioning them most often first
+    fn command_search(
+        &mut self,
+        stream: &mut ResponseWriter,
+        cmd: Command,
+    ) -> Result<(), DictError> {
+        if cmd.params.len() < 3 {
+            return Err(DictError::IllegalParameters);
+        }
+        let databases = match cmd.params[1].as_str() {
+            "*" => {
+                let mut databases = self.visible_databases();
+                databases.retain(|db| self.breaker_allows(db));
+                databases
+            }
+            database if self.database_exists(database) => vec![database.to_string()],
+            _ => {
+                return Err(DictError::InvalidDatabase(cmd.params[1].clone()));
+            }
+        };
+        let query = cmd.params[2..].join(" ");
+        let mut results = Vec::new();
+        // Databases that did not answer within their deadline
+        let mut timed_out = Vec::new();
+        for db in databases {
+            let databases = self.databases.read().unwrap();
+            let database = &databases[&db];
+            let dictreader = match &database.dictreader {
+                Some(dictreader) => dictreader.clone(),
+                None => continue,
+            };
+            // Building the index reads every article, a search giving up
+            // at the deadline leaves it to be built in the background
+            let fulltext = database.fulltext.clone();
+            let indexreader = database.indexreader.clone();
+            let (name, terms) = (db.clone(), query.clone());
+            let start = Instant::now();
+            let deadline = database.breaker.lock().unwrap().deadline();
+            let found = with_deadline(deadline, move || {
+                let fulltext = fulltext.get_or_init(|| {
+                    let start = Instant::now();
+                    let fulltext = FullTextIndex::build(&indexreader.read().unwrap(), &dictreader);
+                    info!(
+                        "Built full-text index of {}: {} words in {:.2}s",
+                        name,
+                        fulltext.len(),
+                        start.elapsed().as_secs_f64()
+                    );
+                    fulltext
+                });
+                fulltext
+                    .search(&terms)
+                    .into_iter()
+                    .cloned()
+                    .collect::<Vec<IndexEntry>>()
+            });
+            database.record_query(start.elapsed());
+            match found {
+                Ok(found) => results.extend(found.into_iter().map(|entry| (db.clone(), entry))),
+                Err(e) => {
+                    warn!(
+                        "[{}] Could not search {}: {}",
+                        self.session.request_id, db, e
+                    );
+                    timed_out.push(db.clone());
+                }
+            }
+        }
+        // Nothing found in time is a temporary problem
+        if results.is_empty() && !timed_out.is_empty() {
+            stream.status(&format!(
+                "420 Server temporarily unavailable, failing databases: {}",
+                timed_out.join(" ")
+            ));
+            return Ok(());
+        }
+        if results.is_empty() {
+            return Err(DictError::NoMatch(cmd.params[2..].join(" ")));
+        }
+        self.write_matches(stream, results, false)
+    }
+
+    // XREF database word
+    // Lists the headwords the definition of the word refers to in {braces},
+    // those the database has, in the order they are referred to
+    fn command_xref(&mut self, stream: &mut ResponseWriter, cmd: Command) -> Result<(), DictError> {
+        if cmd.params.len() != 3 {
+            return Err(DictError::IllegalParameters);
+        }
+        let names = match cmd.params[1].as_str() {
+            "*" => {
+                let mut databases = self.visible_databases();
+                databases.retain(|db| self.breaker_allows(db));
+                databases
+            }
+            database if self.database_exists(database) => vec![database.to_string()],
+            _ => {
+                return Err(DictError::InvalidDatabase(cmd.params[1].clone()));
+            }
+        };
+        let mut results: Matches = Vec::new();
+        for name in names {
+            let databases = self.databases.read().unwrap();
+            let database = &databases[&name];
+            let entry = match database.find_entry(&database.normalization.apply(&cmd.params[2])) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let article = match self.read_article(database, entry.offset, entry.length) {
+                Ok(article) => article,
+                Err(e) => {
+                    warn!(
+                        "[{}] Could not read \"{}\" from {}: {:?}",
+                        self.session.request_id,
+                        self.logged_word(&entry.word),
+                        name,
+                        e
+                    );
+                    continue;
+                }
+            };
+            for reference in xref::references(&article) {
+                let target = database.find_entry(&database.normalization.apply(&reference));
+                if let Some(target) = target {
+                    if !results
+                        .iter()
+                        .any(|(db, entry)| *db == name && entry.word == target.word)
+                    {
+                        results.push((name.clone(), target));
+                    }
+                }
+            }
+        }
+        if results.is_empty() {
+            return Err(DictError::NoMatch(cmd.params[2].clone()));
+        }
+        self.write_matches(stream, results, false)
+    }
+
+    // XRANDOM [database] [RARE]
+    // Sends a random definition of the database, of a random database with
+    // "*", weighted towards common words or with RARE towards rare ones
+    fn command_random(&self, stream: &mut ResponseWriter, cmd: Command) -> Result<(), DictError> {
+        let rare = cmd.params[1..]
+            .last()
+            .is_some_and(|param| param.eq_ignore_ascii_case("RARE"));
+        let name = match cmd.database.as_str() {
+            "*" => {
+                let mut names = self.visible_databases();
+                names.retain(|db| self.breaker_allows(db));
+                match names.choose(&mut rand::thread_rng()) {
+                    Some(name) => name.clone(),
+                    None => {
+                        return Err(DictError::NoMatch(String::new()));
+                    }
+                }
+            }
+            name if self.database_exists(name) => name.to_string(),
+            _ => {
+                return Err(DictError::InvalidDatabase(cmd.database.clone()));
+            }
+        };
+        if let Some(database) = self.databases.read().unwrap().get(&name) {
+            if let Ok((word, offset, length)) = database
+                .indexreader
+                .read()
+                .unwrap()
+                .find_random_weighted(rare)
+            {
+                debug!(
+                    "[{}] offset = {}, length = {}",
+                    self.session.request_id, offset, length
+                );
+                if let Ok(res) = self.read_article(database, offset, length) {
+                    stream.status("150 1 definition retrieved");
+                    stream.status(&format!(
+                        "151 \"{}\" {} \"{}\"",
+                        word, database.shortname, database.metadata.short
+                    ));
+                    let (content_type, res) = database.render(&word, &res);
+                    self.write_typed_block(stream, content_type, &res)?;
+                    stream.status("250 ok");
+                    return Ok(());
+                }
+            }
+        }
+        Err(DictError::NoMatch(String::new()))
+    }
+
+    // XSTAT [count]
+    // Sends the databases looked up most and the words requested most, the
+    // first 10 of each unless count says otherwise
+    fn command_stat(&self, stream: &mut ResponseWriter, cmd: Command) -> Result<(), DictError> {
+        let count = match cmd.params.len() {
+            1 => 10,
+            2 => match cmd.params[1].parse::<usize>() {
+                Ok(count) => count,
+                Err(_) => return Err(DictError::IllegalParameters),
+            },
+            _ => return Err(DictError::IllegalParameters),
+        };
+        let mut text = String::new();
+        {
+            let stats = self.stats.lock().unwrap();
+            let mut databases: Vec<(&String, u64)> = stats
+                .databases
+                .iter()
+                .filter(|(name, _)| self.database_listed(name))
+                .map(|(name, database)| (name, database.defines + database.matches))
+                .collect();
+            databases.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            text.push_str("database         lookups\n");
+            for (name, lookups) in databases.into_iter().take(count) {
+                text.push_str(&format!("{:<12} {:>11}\n", name, lookups));
+            }
+            text.push_str("\nword             lookups\n");
+            if stats.words.enabled() {
+                for (word, lookups) in stats.words.top(count) {
+                    text.push_str(&format!("{:<12} {:>11}\n", word, lookups));
+                }
+            } else {
+                text.push_str("words are not counted\n");
+            }
+        }
+        // Not 114, which clients take for the answer to SHOW SERVER
+        stream.status("115 statistics follow");
+        self.write_text_block(stream, &text)?;
+        stream.status("250 ok");
+        Ok(())
+    }
+
+    // XPING
+    // Answers 250 if all databases were loaded and their first article can
+    // be read, 420 naming the failing databases otherwise
+    fn command_ping(&self, stream: &mut ResponseWriter, cmd: Command) -> Result<(), DictError> {
+        if cmd.params.len() != 1 {
+            return Err(DictError::IllegalParameters);
+        }
+        let failing = self.check_databases();
+        if failing.is_empty() {
+            let count = self.databases.read().unwrap().len();
+            stream.status(&format!("250 ok, {} databases readable", count));
+        } else {
+            for (name, reason) in failing.iter() {
+                warn!(
+                    "[{}] {} failed XPING: {}",
+                    self.session.request_id, name, reason
+                );
+            }
+            let names: Vec<&str> = failing.keys().map(String::as_str).collect();
+            stream.status(&format!(
+                "420 Server temporarily unavailable, failing databases: {}",
+                names.join(" ")
+            ));
+        }
+        Ok(())
+    }
+
+    // AUTH user response, the response being the MD5 digest of the msg-id
+    // of the banner followed by the shared secret of the user
+    fn command_auth(&mut self, stream: &mut ResponseWriter, cmd: Command) -> Result<(), DictError> {
+        self.session.sasl = None;
+        let step = check_auth(
+            &cmd.params[1],
+            &cmd.params[2],
+            &self.session.msg_id,
+            &self.users,
+        );
+        self.sasl_step(stream, None, step)
+    }
+
+    // CLIENT info, the client identifies itself for the logs
+    fn command_client(
+        &mut self,
+        stream: &mut ResponseWriter,
+        cmd: Command,
+    ) -> Result<(), DictError> {
+        self.identify(&cmd.params[1..].join(" "));
+        stream.status("250 ok");
+        Ok(())
+    }
+
+    // Records the identification of the client, like CLIENT
+    pub fn identify(&mut self, client: &str) {
+        let client: String = client.chars().take(MAX_CLIENT_LEN).collect();
+        info!("[{}] Client is {:?}", self.session.request_id, client);
+        if let Some(info) = self.connections.lock().unwrap().get_mut(&self.session.id) {
+            info.client = Some(client.clone());
+        }
+        self.session.client = Some(client);
+    }
+
+    // SASLAUTH mechanism [initial-response], challenges and responses are
+    // base64 encoded, "=" standing for an empty one
+    fn command_saslauth(
+        &mut self,
+        stream: &mut ResponseWriter,
+        cmd: Command,
+    ) -> Result<(), DictError> {
+        let challenge = new_msg_id();
+        self.session.sasl = None;
+        let mut authenticator = match Authenticator::start(&cmd.params[1], &challenge) {
+            Some(authenticator) => authenticator,
+            None => {
+                stream.status("531 Access denied, unsupported mechanism");
+                return Ok(());
+            }
+        };
+        let step = match cmd.params.get(2).map(|response| decode_sasl(response)) {
+            Some(Some(response)) => authenticator.step(Some(&response), &self.users),
+            Some(None) => Step::Failure(None),
+            None => authenticator.step(None, &self.users),
+        };
+        self.sasl_step(stream, Some(authenticator), step)
+    }
+
+    // SASLRESP response
+    fn command_saslresp(
+        &mut self,
+        stream: &mut ResponseWriter,
+        cmd: Command,
+    ) -> Result<(), DictError> {
+        let mut authenticator = match self.session.sasl.take() {
+            Some(authenticator) => authenticator,
+            _ => {
+                return Err(DictError::IllegalParameters);
+            }
+        };
+        let step = match decode_sasl(&cmd.params[1]) {
+            Some(response) => authenticator.step(Some(&response), &self.users),
+            None => Step::Failure(None),
+        };
+        self.sasl_step(stream, Some(authenticator), step)
+    }
+
+    fn sasl_step(
+        &mut self,
+        stream: &mut ResponseWriter,
+        authenticator: Option<Authenticator>,
+        step: Step,
+    ) -> Result<(), DictError> {
+        match step {
+            Step::Challenge(challenge) => {
+                self.session.sasl = authenticator;
+                let challenge = if challenge.is_empty() {
+                    "=".to_string()
+                } else {
+                    base64::encode(&challenge)
+                };
+                stream.status(&format!("330 {}", challenge));
+            }
+            Step::Success(user) => {
+                self.audit(stream, SecurityEvent::AuthSuccess { user: &user });
+                self.session.user = Some(user);
+                stream.status("230 Authentication successful");
+            }
+            Step::Failure(user) => {
+                self.audit(
+                    stream,
+                    SecurityEvent::AuthFailure {
+                        user: user.as_deref().unwrap_or(""),
+                    },
+                );
+                stream.status("531 Access denied, use \"SHOW INFO\" for server information");
+            }
+        }
+        Ok(())
+    }
+
+    fn command_quit(&self, stream: &mut ResponseWriter) -> Result<(), DictError> {
+        stream.status("221 Closing connection. kthxb.");
+        Ok(())
+    }
+
+    fn command_show(&self, stream: &mut ResponseWriter, cmd: Command) -> Result<(), DictError> {
+        match cmd.subcmd {
+            SubCmd::Database => {
+                let shortnames = self.visible_databases();
+                let databases = &*self.databases.read().unwrap();
+                let mut text = String::new();
+                let mut count = 0;
+                for shortname in &shortnames {
+                    let database = &databases[shortname];
+                    text.push_str(&format!(
+                        "{} \"{}\"\n",
+                        shortname, database.metadata.short
+                    ));
+                    count += 1;
+                }
+                // Listed so that clients can tell them from unknown databases
+                for shortname in self.unavailable.read().unwrap().keys() {
+                    if self.database_listed(shortname) {
+                        text.push_str(&format!("{} \"(unavailable)\"\n", shortname));
+                        count += 1;
+                    }
+                }
+                stream.status(&format!("110 {} database(s) present", count));
+                self.write_text_block(stream, &text)?;
+                stream.status("250 ok");
+            }
+            SubCmd::Strategies => {
+                stream.status(&format!(
+                    "111 {} strategies present",
+                    self.strategies.read().unwrap().len()
+                ));
+                let strategies = &*self.strategies.read().unwrap();
+                let mut text = String::new();
+                for strategy in strategies {
+                    text.push_str(&format!(
+                        "{} \"{}\"\n",
+                        strategy.name(),
+                        strategy.description()
+                    ));
+                }
+                self.write_text_block(stream, &text)?;
+                stream.status("250 ok");
+            }
+            SubCmd::Server => {
+                stream.status("114 server information");
+                let (count, headwords) = self
+                    .databases
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .filter(|(name, _)| self.database_listed(name))
+                    .fold((0, 0), |(count, headwords), (_, database)| {
+                        (
+                            count + 1,
+                            headwords + database.indexreader.read().unwrap().entries().len(),
+                        )
+                    });
+                let open = self.connections.lock().unwrap().len();
+                let stats = self.stats.lock().unwrap();
+                let mut text = format!(
+                    "dictrd {}: up {}s, {}s in total\n",
+                    env!("CARGO_PKG_VERSION"),
+                    stats.uptime(),
+                    stats.total_uptime()
+                );
+                text.push_str(&format!(
+                    "{} databases, {} headwords\n{} connections, {} in total\n\n",
+                    count, headwords, open, stats.connections
+                ));
+                text.push_str(&format!(
+                    "cache {} hits, {} misses\n\n",
+                    stats.cache_hits, stats.cache_misses
+                ));
+                text.push_str("database         defines   matches    misses\n");
+                for (name, database) in stats.databases.iter() {
+                    if self.database_listed(name) {
+                        text.push_str(&format!(
+                            "{:<12} {:>11} {:>9} {:>9}\n",
+                            name, database.defines, database.matches, database.misses
+                        ));
+                    }
+                }
+                text.push_str("\ncommand            count\n");
+                for (command, count) in stats.commands.iter() {
+                    text.push_str(&format!("{:<12} {:>11}\n", command, count));
+                }
+                let now = Instant::now();
+                for (name, database) in self.databases.read().unwrap().iter() {
+                    let state = database.breaker.lock().unwrap().state(now);
+                    if let BreakerState::Open(remaining) = state {
+                        if self.database_listed(name) {
+                            text.push_str(&format!(
+                                "\n{} is too slow, left out of * and ! for {}s\n",
+                                name,
+                                remaining.as_secs()
+                            ));
+                        }
+                    }
+                }
+                self.write_text_block(stream, &text)?;
+                stream.status("250 ok");
+            }
+            SubCmd::Info => {
+                let database = &cmd.database;
+                let unavailable = match self.unavailable.read().unwrap().get(database) {
+                    Some(reason) if self.database_visible(database) => Some(reason.clone()),
+                    _ => None,
+                };
+                if let Some(reason) = unavailable {
+                    stream.status("112 database information follows");
+                    self.write_text_block(
+                        stream,
+                        &format!("{} is unavailable: {}\n", database, reason),
+                    )?;
+                    stream.status("250 ok");
+                } else if !self.database_exists(database) {
+                    return Err(DictError::InvalidDatabase(database.clone()));
+                } else {
+                    let database = &self.databases.read().unwrap()[database];
+                    stream.status("112 database information follows");
+                    let metadata = &database.metadata;
+                    // The info entry verbatim, then what the server knows
+                    let mut text = format!("{}\n\n", metadata.info);
+                    if let Some(url) = &metadata.url {
+                        text.push_str(&format!("URL: {}\n", url));
+                    }
+                    text.push_str(&format!("Encoding: {:?}\n", metadata.encoding()));
+                    if let Some(dictfmt) = &metadata.dictfmt {
+                        text.push_str(&format!("Built with dictfmt {}\n", dictfmt));
+                    }
+                    if database.content_type != ContentType::Plain {
+                        text.push_str(&format!("Content type: {}\n", database.content_type.mime()));
+                    }
+                    self.write_text_block(stream, &text)?;
+                    stream.status("250 ok");
+                }
+            }
+            _ => return Err(DictError::IllegalParameters),
+        }
+        Ok(())
+    }
+
+    fn command_status(&self, stream: &mut ResponseWriter) -> Result<(), DictError> {
+        // Like dictd: defines/matches/commands, the real time of the
+        // connection and the time the last command took, then the bytes sent
+        // and the time they were throttled. After that the uptime of the
+        // server and the connections it accepted.
+        let (uptime, connections) = {
+            let stats = self.stats.lock().unwrap();
+            (stats.uptime(), stats.connections)
+        };
+        let transfer = self.session.transfer.lock().unwrap();
+        stream.status(&format!("210 status [d/m/c = {}/{}/{}; {:.3}r {:.3}l; {} bytes sent, {:.3}s throttled] up {}, {} connections",
+                self.session.defines,
+                self.session.matches,
+                self.session.commands,
+                self.session.started.elapsed().as_secs_f64(),
+                self.session.last_command.as_secs_f64(),
+                transfer.bytes,
+                transfer.throttled.as_secs_f64(),
+                format_uptime(uptime),
+                connections
+            ));
+        Ok(())
+    }
+}
+
+// Days, hours and minutes like dictd, e.g. 2+03:14
+// The entry of a headword found by a backend, which has no article in a
+// dict file
+fn backend_entry(headword: String) -> IndexEntry {
+    IndexEntry {
+        word: headword,
+        offset: 0,
+        length: 0,
+        original: None,
+    }
+}
+
+// Error reading an article of a database answered by a backend
+fn no_dict_file() -> DictError {
+    DictError::IoError(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "database without dict file",
+    ))
+}
+
+// Runs blocking work of a connection's task. On a multi-threaded runtime
+// the other tasks move to another worker meanwhile, a current-thread
+// runtime has none and waits.
+fn blocking<T>(work: impl FnOnce() -> T) -> T {
+    match Handle::current().runtime_flavor() {
+        RuntimeFlavor::MultiThread => tokio::task::block_in_place(work),
+        _ => work(),
+    }
+}
+
+fn format_uptime(secs: u64) -> String {
+    format!(
+        "{}+{:02}:{:02}",
+        secs / 86400,
+        secs % 86400 / 3600,
+        secs % 3600 / 60
+    )
+}
+
+// Decodes a base64 SASL response, "=" is an empty one
+fn decode_sasl(response: &str) -> Option<Vec<u8>> {
+    if response == "=" {
+        return Some(Vec::new());
+    }
+    base64::decode(response).ok()
+}
+
+impl DictdServer<File> {
+    // Accepts clients on the listener, each served by its own task. Clients
+    // beyond the connection limit get "420 Server temporarily unavailable".
+    // With an acceptor the clients talk TLS from the start.
+    pub async fn serve(self, listener: TcpListener, tls: Option<TlsAcceptor>) {
+        let listen_addr = listener
+            .local_addr()
+            .map_or("unknown".to_string(), |addr| addr.to_string());
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(client) => client,
+                Err(e) => {
+                    error!("Could not accept connection: {:?}", e);
+                    continue;
+                }
+            };
+            let mut dictd_server = self.clone();
+            if let Ok(socket) = SockRef::from(&stream).try_clone() {
+                dictd_server.set_socket(socket);
+            }
+            let listen_addr = listen_addr.clone();
+            let tls = tls.clone();
+            tokio::spawn(async move {
+                // Queued clients wait for a free slot here, so that no
+                // listener holds one while waiting for a client
+                let slot = match dictd_server.when_busy {
+                    WhenBusy::Queue if dictd_server.permits(peer.ip()) => dictd_server
+                        .connection_slots
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .ok(),
+                    _ => None,
+                };
+                let admission = dictd_server.admit(peer, slot);
+                match &admission {
+                    Err(Refusal::Denied) => {
+                        warn!("Denied access to {} on listener {}", peer, listen_addr);
+                        dictd_server
+                            .record_security_event(&peer.to_string(), &SecurityEvent::AccessDenied);
+                    }
+                    Err(Refusal::Busy(reason)) => warn!(
+                        "Turned away {} on listener {}, {}",
+                        peer, listen_addr, reason
+                    ),
+                    Ok(_) => info!(
+                        "[{}] New client connection from {} to {} on listener {}",
+                        dictd_server.session.id,
+                        peer,
+                        stream
+                            .local_addr()
+                            .map_or("unknown".to_string(), |addr| addr.to_string()),
+                        listen_addr
+                    ),
+                }
+                let result = match tls {
+                    Some(tls) => match tls.accept(stream).await {
+                        Ok(stream) => dictd_server.accepted(stream, peer, admission).await,
+                        Err(e) => Err(DictError::IoError(e)),
+                    },
+                    None => dictd_server.accepted(stream, peer, admission).await,
+                };
+                if let Err(e) = result {
+                    error!("[{}] Connection failed: {:?}", dictd_server.session.id, e);
+                }
+            });
+        }
+    }
+
+    // Serves a connected client, or turns it away if it was not admitted
+    async fn accepted<S: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        mut stream: S,
+        peer: SocketAddr,
+        admission: Result<Admission, Refusal>,
+    ) -> Result<(), DictError> {
+        let _admission = match admission {
+            Ok(admission) => admission,
+            Err(refusal) => {
+                stream.write_all(refusal.reply()).await?;
+                stream.shutdown().await?;
+                return Ok(());
+            }
+        };
+        self.handle_connection(stream, peer).await
+    }
+}
+
+// Binds a listening socket. IPv6 sockets only take IPv6 clients, so
+// [::]:2628 and 0.0.0.0:2628 can be bound side by side.
+pub fn bind(addr: SocketAddr) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    Ok(socket.into())
+}
+
+impl DictdServer<File> {
+    // Binds a listening socket for the server, e.g. on 127.0.0.1:0 for a
+    // free port
+    pub fn bind(self, addr: SocketAddr) -> std::io::Result<BoundServer> {
+        Ok(BoundServer {
+            server: self,
+            listener: bind(addr)?,
+        })
+    }
+}
+
+// A server with its listening socket, see DictdServer::bind
+pub struct BoundServer {
+    server: DictdServer<File>,
+    listener: std::net::TcpListener,
+}
+
+impl BoundServer {
+    // Address the socket is bound to, with the port chosen for port 0
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Serves clients in a task of the current tokio runtime until the handle
+    // shuts the server down. On a current-thread runtime the commands of
+    // one client hold up the others while they read the databases, a
+    // multi-threaded runtime serves them side by side.
+    pub fn serve(self) -> std::io::Result<ShutdownHandle> {
+        let listener = TcpListener::from_std(self.listener)?;
+        let task = tokio::spawn(self.server.serve(listener, None));
+        Ok(ShutdownHandle { task })
+    }
+}
+
+// Stops a server serving clients. Clients connected by then are served
+// until they quit.
+pub struct ShutdownHandle {
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.task.abort();
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::DictClient;
+    use crate::compress::inflate_zlib_block;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn embedded_server() {
+        let dir = std::env::temp_dir().join(format!("dictr-server-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let text = "hack n. A quick job, see {kluge}.\nkluge n. A clever hack.\n";
+        std::fs::write(dir.join("test.dict"), text).unwrap();
+        let mut index = IndexReader::new();
+        index.parse_dict_index("hack\tA\ti\nkluge\ti\tX\n".as_bytes());
+        index.normalize_headwords(index.normalization());
+        let dict = File::open(dir.join("test.dict")).unwrap();
+        let dictreader = DictReader::new(BufReader::new(dict)).unwrap();
+        let metadata = DatabaseMetadata {
+            short: "Test database".to_string(),
+            ..DatabaseMetadata::default()
+        };
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _guard = rt.enter();
+        let mut server = DictdServer::<File>::new();
+        server.add_database(
+            "test".to_string(),
+            metadata,
+            Arc::new(RwLock::new(index)),
+            dictreader,
+        );
+        assert!(server.check_databases().is_empty());
+        let aliases = |alias: &str, strategy: &str| {
+            HashMap::from([(alias.to_string(), strategy.to_string())])
+        };
+        server
+            .set_strategy_aliases(aliases("pre", "prefix"))
+            .unwrap();
+        assert!(server
+            .set_strategy_aliases(aliases("exact", "prefix"))
+            .is_err());
+        assert!(server
+            .set_strategy_aliases(aliases("re", "regexp"))
+            .is_err());
+        assert!(server.set_default_strategy("nprefix").is_err());
+        server.set_database_strategy("test", Some("pre")).unwrap();
+        // Lookups of clients that do not speak DICT
+        let mut lookup = server.clone();
+        let peer = "127.0.0.1:1".parse().unwrap();
+        let definitions = lookup.define(peer, "!", "HACK").unwrap();
+        assert_eq!(definitions[0].headword, "hack");
+        let (matches, truncated) = lookup.match_words(peer, "*", "prefix", "KLU").unwrap();
+        assert_eq!((matches[0].headword.as_str(), truncated), ("kluge", false));
+        let (matches, _) = lookup.match_words(peer, "test", ".", "KL").unwrap();
+        assert_eq!(matches[0].headword, "kluge");
+        assert_eq!(
+            lookup.match_words(peer, "*", "pre", "h").unwrap().0.len(),
+            1
+        );
+        assert!(lookup.define(peer, "zork", "hack").is_err());
+
+        // Articles are sent as they are read, and read whole for clients
+        // that enabled compression
+        server.set_stream_threshold(8);
+        let bound = server.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let port = bound.local_addr().unwrap().port();
+        let handle = bound.serve().unwrap();
+
+        let mut client = DictClient::connect("127.0.0.1", port).unwrap();
+        let databases = client.show_databases().unwrap();
+        assert_eq!(databases.len(), 1);
+        assert_eq!(databases[0].name, "test");
+        // Strategies are listed in the same order every time
+        let strategies: Vec<String> = client
+            .show_strategies()
+            .unwrap()
+            .into_iter()
+            .map(|item| item.name)
+            .collect();
+        assert_eq!(strategies[..3], ["exact", "prefix", "nprefix"]);
+        assert_eq!(strategies.last().unwrap(), Phonetic::ALL[3].name());
+        let definitions = client.define("*", "Kluge").unwrap();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].text, "kluge n. A clever hack.\n");
+        assert!(client.compress().unwrap());
+        assert_eq!(client.define("*", "Kluge").unwrap(), definitions);
+        let matches = client.match_("test", "prefix", "h").unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].headword, "hack");
+        assert!(client.define("test", "zork").unwrap().is_empty());
+        // Extension commands are case-insensitive like the others
+        let code = |client: &mut DictClient, line: &str| client.command(line).unwrap().code;
+        assert_eq!(code(&mut client, "xdefinematch test exact hack"), 150);
+        assert_eq!(client.read_status().unwrap().code, 151);
+        client.read_text().unwrap();
+        assert_eq!(client.read_status().unwrap().code, 250);
+        assert_eq!(code(&mut client, "xnext 0"), 501);
+        assert_eq!(code(&mut client, "xsearch test clever"), 152);
+        assert_eq!(client.read_text().unwrap(), "test \"kluge\"\n");
+        assert_eq!(client.read_status().unwrap().code, 250);
+        assert_eq!(code(&mut client, "xref test hack"), 152);
+        assert_eq!(client.read_text().unwrap(), "test \"kluge\"\n");
+        assert_eq!(client.read_status().unwrap().code, 250);
+        assert_eq!(code(&mut client, "xping"), 250);
+        assert_eq!(code(&mut client, "xstat 1"), 115);
+        client.read_text().unwrap();
+        assert_eq!(client.read_status().unwrap().code, 250);
+        client.quit().unwrap();
+
+        // OPTION DEFLATE sends the text blocks as zlib streams
+        let mut client = DictClient::connect("127.0.0.1", port).unwrap();
+        assert_eq!(client.command("OPTION DEFLATE").unwrap().code, 250);
+        assert_eq!(client.command("DEFINE test kluge").unwrap().code, 150);
+        assert_eq!(client.read_status().unwrap().code, 151);
+        let block = client.read_text().unwrap();
+        assert_eq!(
+            inflate_zlib_block(&block).unwrap(),
+            "kluge n. A clever hack."
+        );
+        assert_eq!(client.read_status().unwrap().code, 250);
+        client.quit().unwrap();
+
+        handle.shutdown();
+        rt.block_on(async {
+            while !handle.is_shut_down() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        assert!(DictClient::connect("127.0.0.1", port).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn current_thread_runtime() {
+        let dir = std::env::temp_dir().join(format!("dictr-current-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("test.dict"), "A hack.\n").unwrap();
+        let mut index = IndexReader::new();
+        index.parse_dict_index("hack\tA\tI\n".as_bytes());
+        index.normalize_headwords(index.normalization());
+        let dict = File::open(dir.join("test.dict")).unwrap();
+        let dictreader = DictReader::new(BufReader::new(dict)).unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let _guard = rt.enter();
+        let mut server = DictdServer::<File>::new();
+        server.add_database(
+            "test".to_string(),
+            DatabaseMetadata::default(),
+            Arc::new(RwLock::new(index)),
+            dictreader,
+        );
+        let bound = server.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let port = bound.local_addr().unwrap().port();
+        let handle = bound.serve().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut client = DictClient::connect("127.0.0.1", port).unwrap();
+            let definitions = client.define("*", "hack").unwrap();
+            client.quit().unwrap();
+            definitions
+        });
+        rt.block_on(async {
+            while !client.is_finished() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        assert_eq!(client.join().unwrap()[0].text, "A hack.\n");
+        handle.shutdown();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn define_shared_key() {
+        let mut server = DictdServer::<Cursor<Vec<u8>>>::new();
+        let mut indexreader = IndexReader::new();
+        indexreader.parse_dict_index("C\tA\tQ\nC++\tQ\tI\n".as_bytes());
+        indexreader.normalize_headwords(indexreader.normalization());
+        server.add_database(
+            "foldoc".to_string(),
+            DatabaseMetadata::default(),
+            Arc::new(RwLock::new(indexreader)),
+            DictReader::from_bytes(b"C is a language.C++ too.".to_vec()).unwrap(),
+        );
+        let peer = "127.0.0.1:2628".parse().unwrap();
+        let definitions = server.define(peer, "*", "c").unwrap();
+        let found: Vec<(&str, &str)> = definitions
+            .iter()
+            .map(|d| (d.headword.as_str(), d.text.as_str()))
+            .collect();
+        assert_eq!(found, [("C", "C is a language."), ("C++", "C++ too.")]);
+    }
+
+    #[test]
+    fn primed_cache() {
+        let mut server = DictdServer::<Cursor<Vec<u8>>>::new();
+        server.set_cache_size(1);
+        let mut indexreader = IndexReader::new();
+        indexreader.parse_dict_index("hack\tA\tH\nkluge\tH\tI\n".as_bytes());
+        indexreader.add_frequencies("hack 10\nkluge 20\n".as_bytes());
+        server.add_database(
+            "jargon".to_string(),
+            DatabaseMetadata::default(),
+            Arc::new(RwLock::new(indexreader)),
+            DictReader::from_bytes(b"A hack.A kluge.".to_vec()).unwrap(),
+        );
+        assert_eq!(server.prime_cache("jargon"), 1);
+        assert_eq!(server.prime_cache("zork"), 0);
+        let peer = "127.0.0.1:2628".parse().unwrap();
+        assert_eq!(
+            server.define(peer, "*", "kluge").unwrap()[0].text,
+            "A kluge."
+        );
+        let stats = server.statistics();
+        let stats = stats.lock().unwrap();
+        assert_eq!((stats.cache_hits, stats.cache_misses), (1, 0));
+    }
+
+    #[test]
+    fn phonetic_without_keys() {
+        let mut server = DictdServer::<Cursor<Vec<u8>>>::new();
+        for (name, phonetic) in [("jargon", None), ("duden", Some(Phonetic::Cologne))] {
+            let mut indexreader = IndexReader::new();
+            indexreader.parse_dict_index("meier\tA\tB\n".as_bytes());
+            if let Some(phonetic) = phonetic {
+                indexreader.build_phonetic_index(phonetic);
+            }
+            server.add_database(
+                name.to_string(),
+                DatabaseMetadata::default(),
+                Arc::new(RwLock::new(indexreader)),
+                DictReader::from_bytes(vec![b'x'; 8]).unwrap(),
+            );
+        }
+        let peer = "127.0.0.1:2628".parse().unwrap();
+        let (matches, _) = server.match_words(peer, "*", "cologne", "mayer").unwrap();
+        assert_eq!(matches.len(), 1);
+        let status = |result: Result<_, DictError>| result.err().map(|e| e.status_code());
+        assert_eq!(
+            status(server.match_words(peer, "jargon", "cologne", "mayer")),
+            Some(551)
+        );
+        assert_eq!(
+            status(server.match_words(peer, "duden", "cologne", "x")),
+            Some(552)
+        );
+    }
+
+    #[test]
+    fn match_ranking() {
+        let mut server = DictdServer::<Cursor<Vec<u8>>>::new();
+        for (name, index) in [
+            ("jargon", "hack\tA\tB\nhacker\tB\tB\n"),
+            ("foldoc", "hack\tA\tB\nhacked\tB\tB\nhackish\tC\tB\n"),
+        ] {
+            let mut indexreader = IndexReader::new();
+            indexreader.parse_dict_index(index.as_bytes());
+            server.add_database(
+                name.to_string(),
+                DatabaseMetadata::default(),
+                Arc::new(RwLock::new(indexreader)),
+                DictReader::from_bytes(vec![b'x'; 8]).unwrap(),
+            );
+        }
+        server.set_database_order(vec!["jargon".to_string()]);
+        let peer = "127.0.0.1:2628".parse().unwrap();
+        let found = |server: &mut DictdServer<Cursor<Vec<u8>>>| -> Vec<String> {
+            let (matches, _) = server.match_words(peer, "*", "prefix", "hack").unwrap();
+            matches
+                .iter()
+                .map(|m| format!("{} {}", m.database, m.headword))
+                .collect()
+        };
+        // Exact matches first, then by database and headword
+        assert_eq!(
+            found(&mut server),
+            [
+                "jargon hack",
+                "foldoc hack",
+                "jargon hacker",
+                "foldoc hacked",
+                "foldoc hackish"
+            ]
+        );
+        server.set_dedup_matches(true);
+        assert_eq!(
+            found(&mut server),
+            [
+                "jargon hack",
+                "jargon hacker",
+                "foldoc hacked",
+                "foldoc hackish"
+            ]
+        );
+    }
+}
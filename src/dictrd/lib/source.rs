@@ -0,0 +1,231 @@
+// Reads the plain text formats dictfmt builds databases from:
+//
+//   c5       dictfmt -c5: every article is preceded by a line of at least 5
+//            underscores, followed by a line with its headwords (after any
+//            empty lines) and the lines of its body. This is what unformat
+//            writes.
+//   tsv      a headword, a tab and the definition on each line, with \n
+//            standing for a line break and \\ for a backslash
+//   jargon   dictfmt -j: an article starts with a line :headword: followed
+//            by the first line of its body, the body goes on until the next
+//            line starting with a colon
+//
+// Text before the first article is kept as the preamble, dictfmt adds it to
+// the info of the database. Headwords sharing an article are split at a
+// separator, like dictfmt --headword-separator does.
+use crate::errors::DictError;
+use crate::writer::Article;
+use std::io::BufRead;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SourceFormat {
+    C5,
+    Tsv,
+    Jargon,
+}
+
+impl SourceFormat {
+    pub fn from_name(name: &str) -> Option<SourceFormat> {
+        match name {
+            "c5" => Some(SourceFormat::C5),
+            "tsv" => Some(SourceFormat::Tsv),
+            "jargon" => Some(SourceFormat::Jargon),
+            _ => None,
+        }
+    }
+}
+
+pub struct Source {
+    pub preamble: String,
+    pub articles: Vec<Article>,
+}
+
+// An article under the headwords of a line, which dictfmt repeats as the
+// first line of the definition
+struct Builder {
+    headwords: Vec<String>,
+    definition: String,
+}
+
+impl Builder {
+    fn new(line: &str, separator: Option<&str>) -> Builder {
+        let headwords = match separator {
+            Some(separator) => line
+                .split(separator)
+                .map(str::trim)
+                .filter(|headword| !headword.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => vec![line.trim().to_string()],
+        };
+        Builder {
+            headwords,
+            definition: format!("{}\n", line.trim()),
+        }
+    }
+
+    fn push_line(&mut self, line: &str) {
+        self.definition.push_str(line.trim_end());
+        self.definition.push('\n');
+    }
+
+    // The article, without the empty lines ending its body
+    fn finish(mut self) -> Option<Article> {
+        if self.headwords.is_empty() {
+            return None;
+        }
+        let len = self.definition.trim_end().len();
+        self.definition.truncate(len);
+        self.definition.push('\n');
+        Some(Article {
+            headwords: self.headwords,
+            definition: self.definition,
+        })
+    }
+}
+
+pub fn read_source<R: BufRead>(
+    input: R,
+    format: SourceFormat,
+    separator: Option<&str>,
+) -> Result<Source, DictError> {
+    let mut preamble = String::new();
+    let mut articles = Vec::new();
+    let mut current: Option<Builder> = None;
+    // c5 only: a separator line was read, the headwords are next
+    let mut expecting_headwords = false;
+    for line in input.lines() {
+        let line = line?;
+        match format {
+            SourceFormat::C5 => {
+                let trimmed = line.trim();
+                if trimmed.len() >= 5 && trimmed.chars().all(|c| c == '_') {
+                    articles.extend(current.take().and_then(Builder::finish));
+                    expecting_headwords = true;
+                    continue;
+                }
+                if expecting_headwords {
+                    if !trimmed.is_empty() {
+                        current = Some(Builder::new(&line, separator));
+                        expecting_headwords = false;
+                    }
+                    continue;
+                }
+            }
+            SourceFormat::Tsv => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (headword, definition) = line
+                    .split_once('\t')
+                    .ok_or(DictError::SyntaxError("TSV line without a tab"))?;
+                let mut builder = Builder::new(headword, separator);
+                for line in unescape(definition).lines() {
+                    builder.push_line(line);
+                }
+                articles.extend(builder.finish());
+                continue;
+            }
+            SourceFormat::Jargon => {
+                if let Some(rest) = line.strip_prefix(':') {
+                    if let Some((headword, body)) = rest.split_once(':') {
+                        articles.extend(current.take().and_then(Builder::finish));
+                        let mut builder = Builder::new(headword, separator);
+                        if !body.trim().is_empty() {
+                            builder.push_line(body.trim_start());
+                        }
+                        current = Some(builder);
+                        continue;
+                    }
+                }
+            }
+        }
+        match current.as_mut() {
+            Some(builder) => builder.push_line(&line),
+            None => {
+                preamble.push_str(&line);
+                preamble.push('\n');
+            }
+        }
+    }
+    articles.extend(current.take().and_then(Builder::finish));
+    Ok(Source {
+        preamble: preamble.trim().to_string(),
+        articles,
+    })
+}
+
+// Replaces \n with a line break and \\ with a backslash
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                result.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                result.push('\\');
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str, format: SourceFormat) -> Source {
+        read_source(text.as_bytes(), format, Some("; ")).unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        let c5 = read(
+            "A test database.\n_____\n\ncolor; Colour\nA hue.\n\n_____\nmouse\nA rodent.\n",
+            SourceFormat::C5,
+        );
+        assert_eq!(c5.preamble, "A test database.");
+        assert_eq!(
+            c5.articles,
+            vec![
+                Article {
+                    headwords: vec!["color".to_string(), "Colour".to_string()],
+                    definition: "color; Colour\nA hue.\n".to_string(),
+                },
+                Article {
+                    headwords: vec!["mouse".to_string()],
+                    definition: "mouse\nA rodent.\n".to_string(),
+                },
+            ]
+        );
+
+        let tsv = read(
+            "hack\tA quick job.\\nSee kluge.\n\nkluge\tC:\\\\\n",
+            SourceFormat::Tsv,
+        );
+        assert_eq!(tsv.articles.len(), 2);
+        assert_eq!(
+            tsv.articles[0].definition,
+            "hack\nA quick job.\nSee kluge.\n"
+        );
+        assert_eq!(tsv.articles[1].definition, "kluge\nC:\\\n");
+        assert!(read_source("no tab\n".as_bytes(), SourceFormat::Tsv, None).is_err());
+
+        let jargon = read(
+            "The Jargon File\n\n:hack: n. 1. Originally,\na quick job.\n\n:kluge:\nA clever hack.\n",
+            SourceFormat::Jargon,
+        );
+        assert_eq!(jargon.preamble, "The Jargon File");
+        assert_eq!(jargon.articles[0].headwords, vec!["hack"]);
+        assert_eq!(
+            jargon.articles[0].definition,
+            "hack\nn. 1. Originally,\na quick job.\n"
+        );
+        assert_eq!(jargon.articles[1].definition, "kluge\nA clever hack.\n");
+    }
+}
@@ -0,0 +1,198 @@
+// A database kept in SQLite instead of .index and .dict files, for very
+// large databases or ones that change often: rows written while the server
+// runs are found by the next lookup, without reloading. Its table is
+//
+//   CREATE TABLE entries (
+//       headword TEXT NOT NULL,
+//       key TEXT NOT NULL,
+//       definition TEXT NOT NULL
+//   );
+//   CREATE INDEX entries_key ON entries (key);
+//
+// where key is the headword normalized like the headwords of a dictfmt
+// index, see key(). Programs writing to the database while the server runs
+// should switch it to WAL mode, so that lookups do not wait for them.
+//
+// DEFINE finds the rows with the key of the word. MATCH knows the exact,
+// prefix, nprefix, substring and suffix strategies, the others fail with
+// Unsupported.
+use crate::backend::Backend;
+use crate::dictionary::Definition;
+use crate::normalize::Normalization;
+use rusqlite::{params, Connection, OpenFlags};
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS entries (
+    headword TEXT NOT NULL,
+    key TEXT NOT NULL,
+    definition TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS entries_key ON entries (key);
+";
+
+pub struct SqliteDictionary {
+    // Lookups take turns on the connection
+    connection: Mutex<Connection>,
+}
+
+// The key of a headword, normalized like the headwords of a dictfmt index
+pub fn key(headword: &str) -> String {
+    Normalization::Default.apply(headword)
+}
+
+impl SqliteDictionary {
+    // Opens a database for lookups
+    pub fn open(path: &Path) -> rusqlite::Result<SqliteDictionary> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        // A file without the table fails here rather than on each lookup
+        connection.prepare("SELECT headword, key, definition FROM entries")?;
+        Ok(SqliteDictionary {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    // Opens a database for writing, creating the table and its index if
+    // they do not exist
+    pub fn create(path: &Path) -> rusqlite::Result<SqliteDictionary> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(SqliteDictionary {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    // Adds articles in a single transaction. Returns the number added.
+    pub fn insert<I, H, D>(&self, articles: I) -> rusqlite::Result<usize>
+    where
+        I: IntoIterator<Item = (H, D)>,
+        H: AsRef<str>,
+        D: AsRef<str>,
+    {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut count = 0;
+        {
+            let mut insert = transaction
+                .prepare("INSERT INTO entries (headword, key, definition) VALUES (?1, ?2, ?3)")?;
+            for (headword, definition) in articles {
+                let (headword, definition) = (headword.as_ref(), definition.as_ref());
+                insert.execute(params![headword, key(headword), definition])?;
+                count += 1;
+            }
+        }
+        transaction.commit()?;
+        Ok(count)
+    }
+
+    // The headwords of the rows a condition on the key selects, with the
+    // parameters of the condition
+    fn headwords(&self, condition: &str, parameters: &[&str]) -> rusqlite::Result<Vec<String>> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection.prepare_cached(&format!(
+            "SELECT headword FROM entries WHERE {} ORDER BY key, rowid",
+            condition
+        ))?;
+        let rows = select.query_map(rusqlite::params_from_iter(parameters), |row| row.get(0))?;
+        let mut seen = HashSet::new();
+        let mut headwords = Vec::new();
+        for headword in rows {
+            let headword: String = headword?;
+            if seen.insert(headword.clone()) {
+                headwords.push(headword);
+            }
+        }
+        Ok(headwords)
+    }
+}
+
+impl Backend for SqliteDictionary {
+    fn define(&self, word: &str) -> io::Result<Vec<Definition>> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection
+            .prepare_cached(
+                "SELECT headword, definition FROM entries WHERE key = ?1 ORDER BY rowid",
+            )
+            .map_err(io::Error::other)?;
+        let rows = select
+            .query_map(params![key(word)], |row| {
+                Ok(Definition {
+                    headword: row.get(0)?,
+                    text: row.get(1)?,
+                })
+            })
+            .map_err(io::Error::other)?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(io::Error::other)
+    }
+
+    fn match_words(&self, strategy: &str, word: &str) -> io::Result<Vec<String>> {
+        let key = key(word);
+        // Keys starting with the prefix sort below the prefix followed by
+        // the highest character, which keeps the search on the index
+        let upper = format!("{}{}", key, char::MAX);
+        let found = match strategy {
+            "exact" => self.headwords("key = ?1", &[&key]),
+            "prefix" | "nprefix" => self.headwords("key >= ?1 AND key < ?2", &[&key, &upper]),
+            "substring" => self.headwords("instr(key, ?1) > 0", &[&key]),
+            "suffix" => self.headwords("substr(key, -length(?1)) = ?1", &[&key]),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("no strategy {} in SQLite", strategy),
+                ))
+            }
+        };
+        found.map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups() {
+        let path = std::env::temp_dir().join(format!("dictr-sqlite-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let writer = SqliteDictionary::create(&path).unwrap();
+        let added = writer
+            .insert(vec![
+                ("Hack", "hack n. 1. Originally, a quick job.\n"),
+                ("hacker", "hacker n. A person who enjoys hacking.\n"),
+                ("kluge", "kluge n. A clever hack.\n"),
+            ])
+            .unwrap();
+        assert_eq!(added, 3);
+
+        let dictionary = SqliteDictionary::open(&path).unwrap();
+        let definitions = dictionary.define("HACK").unwrap();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].headword, "Hack");
+        assert!(dictionary.define("zork").unwrap().is_empty());
+        assert_eq!(
+            dictionary.match_words("prefix", "hac").unwrap(),
+            vec!["Hack".to_string(), "hacker".to_string()]
+        );
+        assert_eq!(
+            dictionary.match_words("suffix", "ge").unwrap(),
+            vec!["kluge"]
+        );
+        assert_eq!(dictionary.match_words("substring", "ck").unwrap().len(), 2);
+        assert_eq!(
+            dictionary
+                .match_words("soundex", "hack")
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::Unsupported
+        );
+
+        // Rows written while the database is open are found
+        writer.insert(vec![("zork", "zork n. A game.\n")]).unwrap();
+        assert_eq!(dictionary.define("zork").unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+// Usage counters of a server, kept across restarts in a small state file of
+// whitespace separated lines:
+//
+//   uptime <seconds>
+//   connections <count>
+//   db <database> <defines> <matches> <misses>
+//   cmd <command> <count>
+//   cache <hits> <misses>
+//   word <count> <word>
+//
+// The times commands took are only kept for this run.
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+
+// Times of a command percentiles are computed from, the latest ones
+const LATENCY_SAMPLES: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DatabaseStats {
+    pub defines: u64,
+    pub matches: u64,
+    // DEFINEs and MATCHes without result
+    pub misses: u64,
+}
+
+// Times a command took, in seconds
+#[derive(Clone, Debug, Default)]
+pub struct Latencies {
+    // The latest LATENCY_SAMPLES, overwritten in turn once full
+    samples: Vec<f64>,
+    next: usize,
+    pub count: u64,
+    pub max: f64,
+}
+
+impl Latencies {
+    pub fn record(&mut self, seconds: f64) {
+        if self.samples.len() < LATENCY_SAMPLES {
+            self.samples.push(seconds);
+        } else {
+            self.samples[self.next] = seconds;
+            self.next = (self.next + 1) % LATENCY_SAMPLES;
+        }
+        self.count += 1;
+        self.max = self.max.max(seconds);
+    }
+
+    // The time a share of the latest commands took at most, e.g. 0.99 for
+    // the 99th percentile. 0 before the first command.
+    pub fn percentile(&self, share: f64) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let rank = (share * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+}
+
+// How often words were looked up with DEFINE, counted once enabled with
+// Statistics::count_words. Only the most requested words are kept.
+#[derive(Debug, Default)]
+pub struct WordCounts {
+    counts: HashMap<String, u64>,
+    // Words kept, 0 if words are not counted
+    limit: usize,
+}
+
+impl WordCounts {
+    fn record(&mut self, word: &str) {
+        if self.limit == 0 {
+            return;
+        }
+        *self.counts.entry(word.trim().to_lowercase()).or_default() += 1;
+        // Twice the limit leaves room for new words to catch up
+        if self.counts.len() > 2 * self.limit {
+            let kept: HashMap<String, u64> = self.top(self.limit).into_iter().collect();
+            self.counts = kept;
+        }
+    }
+
+    // The most requested words with their counts, most requested first
+    pub fn top(&self, count: usize) -> Vec<(String, u64)> {
+        let mut words: Vec<(String, u64)> = self
+            .counts
+            .iter()
+            .map(|(word, count)| (word.clone(), *count))
+            .collect();
+        words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        words.truncate(count);
+        words
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.limit > 0
+    }
+}
+
+pub struct Statistics {
+    pub databases: BTreeMap<String, DatabaseStats>,
+    // Commands received by name
+    pub commands: BTreeMap<String, u64>,
+    // Connections accepted
+    pub connections: u64,
+    // Articles found in the definition cache and read from dict files
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    // Times of the commands of this run by name
+    pub latencies: BTreeMap<String, Latencies>,
+    pub words: WordCounts,
+    // Uptime of previous runs in seconds
+    previous_uptime: u64,
+    started: Instant,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Statistics::new()
+    }
+}
+
+impl Statistics {
+    pub fn new() -> Statistics {
+        Statistics {
+            databases: BTreeMap::new(),
+            commands: BTreeMap::new(),
+            connections: 0,
+            cache_hits: 0,
+            cache_misses: 0,
+            latencies: BTreeMap::new(),
+            words: WordCounts::default(),
+            previous_uptime: 0,
+            started: Instant::now(),
+        }
+    }
+
+    // Reads the counters of previous runs. Malformed lines are skipped.
+    pub fn load<B: BufRead>(buf: B) -> Statistics {
+        let mut stats = Statistics::new();
+        for line in buf.lines().map_while(Result::ok) {
+            // Words may contain spaces
+            if let Some(counted) = line.strip_prefix("word ") {
+                if let Some((count, word)) = counted.split_once(' ') {
+                    if let Ok(count) = count.parse() {
+                        stats.words.counts.insert(word.to_string(), count);
+                    }
+                }
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let numbers: Vec<u64> = fields.iter().filter_map(|f| f.parse().ok()).collect();
+            match (fields.as_slice(), numbers.as_slice()) {
+                (["uptime", _], [uptime]) => stats.previous_uptime = *uptime,
+                (["connections", _], [connections]) => stats.connections = *connections,
+                (["db", name, _, _, _], [defines, matches, misses]) => {
+                    let database = DatabaseStats {
+                        defines: *defines,
+                        matches: *matches,
+                        misses: *misses,
+                    };
+                    stats.databases.insert(name.to_string(), database);
+                }
+                (["cmd", name, _], [count]) => {
+                    stats.commands.insert(name.to_string(), *count);
+                }
+                (["cache", _, _], [hits, misses]) => {
+                    stats.cache_hits = *hits;
+                    stats.cache_misses = *misses;
+                }
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    pub fn save<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        writeln!(out, "uptime {}", self.total_uptime())?;
+        writeln!(out, "connections {}", self.connections)?;
+        for (name, database) in self.databases.iter() {
+            writeln!(
+                out,
+                "db {} {} {} {}",
+                name, database.defines, database.matches, database.misses
+            )?;
+        }
+        for (name, count) in self.commands.iter() {
+            writeln!(out, "cmd {} {}", name, count)?;
+        }
+        writeln!(out, "cache {} {}", self.cache_hits, self.cache_misses)?;
+        // Words counted before are dropped once words are no longer counted
+        if self.words.enabled() {
+            for (word, count) in self.words.top(self.words.limit) {
+                writeln!(out, "word {} {}", count, word)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn record_define(&mut self, database: &str, found: bool) {
+        let stats = self.databases.entry(database.to_string()).or_default();
+        stats.defines += 1;
+        if !found {
+            stats.misses += 1;
+        }
+    }
+
+    pub fn record_match(&mut self, database: &str, found: bool) {
+        let stats = self.databases.entry(database.to_string()).or_default();
+        stats.matches += 1;
+        if !found {
+            stats.misses += 1;
+        }
+    }
+
+    pub fn record_connection(&mut self) {
+        self.connections += 1;
+    }
+
+    pub fn record_command(&mut self, command: &str) {
+        *self.commands.entry(command.to_string()).or_default() += 1;
+    }
+
+    // Counts the words looked up from now on, keeping the limit most
+    // requested ones
+    pub fn count_words(&mut self, limit: usize) {
+        self.words.limit = limit;
+    }
+
+    pub fn record_word(&mut self, word: &str) {
+        self.words.record(word);
+    }
+
+    pub fn record_latency(&mut self, command: &str, elapsed: Duration) {
+        self.latencies
+            .entry(command.to_string())
+            .or_default()
+            .record(elapsed.as_secs_f64());
+    }
+
+    pub fn record_cache(&mut self, hit: bool) {
+        if hit {
+            self.cache_hits += 1;
+        } else {
+            self.cache_misses += 1;
+        }
+    }
+
+    // Seconds since this run started
+    pub fn uptime(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    // Seconds of all runs together
+    pub fn total_uptime(&self) -> u64 {
+        self.previous_uptime + self.uptime()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let mut stats = Statistics::load(
+            "uptime 100\nconnections 7\ndb jargon 3 2 1\ncmd DEFINE 3\ncache 5 2\nbroken line\n"
+                .as_bytes(),
+        );
+        stats.record_define("jargon", false);
+        stats.record_match("devils", true);
+        stats.record_connection();
+        stats.record_command("DEFINE");
+        stats.record_command("MATCH");
+        stats.record_cache(true);
+        let mut out = Vec::new();
+        stats.save(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "uptime 100\nconnections 8\ndb devils 0 1 0\ndb jargon 4 2 2\ncmd DEFINE 4\ncmd MATCH 1\ncache 6 2\n"
+        );
+    }
+
+    #[test]
+    fn words() {
+        let mut stats = Statistics::load("word 3 free software\nword x broken\n".as_bytes());
+        // Words are not counted until enabled
+        stats.record_word("hacker");
+        assert_eq!(stats.words.top(10), vec![("free software".to_string(), 3)]);
+        stats.count_words(2);
+        for word in ["Hacker", "kluge", "hacker", "zork", "hacker "].iter() {
+            stats.record_word(word);
+        }
+        assert_eq!(
+            stats.words.top(2),
+            vec![("free software".to_string(), 3), ("hacker".to_string(), 3)]
+        );
+        let mut out = Vec::new();
+        stats.save(&mut out).unwrap();
+        let saved = String::from_utf8(out).unwrap();
+        assert!(saved.ends_with("word 3 free software\nword 3 hacker\n"));
+        stats.count_words(0);
+        let mut out = Vec::new();
+        stats.save(&mut out).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("word"));
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut latencies = Latencies::default();
+        assert_eq!(latencies.percentile(0.5), 0.0);
+        for ms in 1..=100 {
+            latencies.record(ms as f64 / 1000.0);
+        }
+        assert_eq!(latencies.percentile(0.5), 0.05);
+        assert_eq!(latencies.percentile(0.99), 0.099);
+        assert_eq!(latencies.percentile(1.0), 0.1);
+        // Only the latest times count for the percentiles
+        for _ in 0..LATENCY_SAMPLES {
+            latencies.record(0.002);
+        }
+        assert_eq!(latencies.percentile(0.99), 0.002);
+        assert_eq!((latencies.count, latencies.max), (1124, 0.1));
+    }
+}
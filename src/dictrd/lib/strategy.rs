@@ -0,0 +1,433 @@
+// Match strategies of MATCH. The server knows the strategies below, embedders
+// can add their own by implementing MatchStrategy and registering it with
+// the server.
+use crate::errors::DictError;
+use crate::phonetic::Phonetic;
+use crate::{IndexEntry, IndexReader};
+use rust_stemmers::Algorithm;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+// The indexes of a database: its primary index and auxiliary indexes in a
+// different sort order or of different headwords, e.g. "suffix" with the
+// headwords reversed or "forms" with inflected forms
+pub struct Indexes<'a> {
+    pub index: &'a RwLock<IndexReader>,
+    pub aux: &'a HashMap<String, Arc<RwLock<IndexReader>>>,
+}
+
+impl<'a> Indexes<'a> {
+    // Looks up a word in the primary index, returning the first entry with
+    // the headword, see find_entries
+    pub fn find_entry(&self, word: &str) -> Option<IndexEntry> {
+        self.find_entries(word).into_iter().next()
+    }
+
+    // Looks up a word in the primary index, returning every entry with the
+    // headword. On a miss the "forms" index of inflected forms is consulted,
+    // whose entries point at the article of the canonical headword in the
+    // same .dict file.
+    pub fn find_entries(&self, word: &str) -> Vec<IndexEntry> {
+        if let Ok(entries) = self.index.read().unwrap().find_entries(word) {
+            return entries;
+        }
+        let forms = match self.aux.get("forms") {
+            Some(forms) => forms.read().unwrap().find_entries(word),
+            None => return Vec::new(),
+        };
+        let index = self.index.read().unwrap();
+        let mut entries: Vec<IndexEntry> = Vec::new();
+        for form in forms.unwrap_or_default() {
+            let entry = index
+                .find_entry_at(form.offset, form.length)
+                .unwrap_or(form);
+            // Several forms of a headword point at the same article
+            if !entries
+                .iter()
+                .any(|e| e.offset == entry.offset && e.length == entry.length)
+            {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+
+    // Searches with a strategy. If nothing matches and the database has a
+    // "translit" index of transliterated headwords, the transliterated query
+    // is searched in that one, so that "moskva" finds "Москва".
+    pub fn search(
+        &self,
+        strategy: &dyn MatchStrategy,
+        query: &str,
+    ) -> Result<Vec<IndexEntry>, DictError> {
+        let found = strategy.search(self, query)?;
+        let translit = match self.aux.get("translit") {
+            Some(translit) if found.is_empty() => translit,
+            _ => return Ok(found),
+        };
+        let query = match self.index.read().unwrap().transliterate(query) {
+            Some(query) => query,
+            None => return Ok(found),
+        };
+        let aux = HashMap::new();
+        let indexes = Indexes {
+            index: translit,
+            aux: &aux,
+        };
+        Ok(strategy.search(&indexes, &query).unwrap_or(found))
+    }
+}
+
+pub trait MatchStrategy: Send + Sync {
+    // Name used in MATCH and listed by SHOW STRAT
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    // Whether an entry matches the query, for strategies that compare every
+    // entry
+    fn matches(&self, _entry: &IndexEntry, _query: &str) -> bool {
+        false
+    }
+
+    // Returns the entries of a database matching the query, best matches
+    // first. An error means the database lacks what the strategy needs and
+    // is skipped. By default every entry of the primary index is compared
+    // with matches.
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        Ok(indexes
+            .index
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|entry| self.matches(entry, query))
+            .cloned()
+            .collect())
+    }
+}
+
+pub struct Exact;
+
+impl MatchStrategy for Exact {
+    fn name(&self) -> &str {
+        "exact"
+    }
+
+    fn description(&self) -> &str {
+        "Match headwords exactly"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        Ok(indexes.find_entries(query))
+    }
+}
+
+pub struct Prefix;
+
+impl MatchStrategy for Prefix {
+    fn name(&self) -> &str {
+        "prefix"
+    }
+
+    fn description(&self) -> &str {
+        "Match prefixes"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        indexes.index.read().unwrap().find_words_by_prefix(query)
+    }
+}
+
+// Prefix matches a page at a time, for clients that list a large number of
+// them. The query is "skip#count#prefix": the server searches with the prefix
+// and leaves out the first skip matches of all databases, sending at most
+// count.
+pub struct NPrefix;
+
+impl MatchStrategy for NPrefix {
+    fn name(&self) -> &str {
+        "nprefix"
+    }
+
+    fn description(&self) -> &str {
+        "Match prefixes (skip#count#prefix)"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        Prefix.search(indexes, query)
+    }
+}
+
+// Splits a query of nprefix into skip, count and prefix
+pub fn parse_nprefix(query: &str) -> Option<(usize, usize, &str)> {
+    let mut parts = query.splitn(3, '#');
+    let skip = parts.next()?.parse().ok()?;
+    let count = parts.next()?.parse().ok()?;
+    Some((skip, count, parts.next()?))
+}
+
+pub struct Suffix;
+
+impl MatchStrategy for Suffix {
+    fn name(&self) -> &str {
+        "suffix"
+    }
+
+    fn description(&self) -> &str {
+        "Match suffixes"
+    }
+
+    // A "suffix" index saves scanning the primary index
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        match indexes.aux.get("suffix") {
+            Some(index) => index.read().unwrap().find_words_by_reversed_suffix(query),
+            None => indexes.index.read().unwrap().find_words_by_suffix(query),
+        }
+    }
+}
+
+pub struct Substring;
+
+impl MatchStrategy for Substring {
+    fn name(&self) -> &str {
+        "substring"
+    }
+
+    fn description(&self) -> &str {
+        "Match substring occurring anywhere in a headword"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        indexes.index.read().unwrap().find_words_by_substring(query)
+    }
+}
+
+pub struct Word;
+
+impl MatchStrategy for Word {
+    fn name(&self) -> &str {
+        "word"
+    }
+
+    fn description(&self) -> &str {
+        "Match separate words within headwords"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        indexes.index.read().unwrap().find_words_by_word(query)
+    }
+}
+
+pub struct First;
+
+impl MatchStrategy for First {
+    fn name(&self) -> &str {
+        "first"
+    }
+
+    fn description(&self) -> &str {
+        "Match the first word within headwords"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        indexes
+            .index
+            .read()
+            .unwrap()
+            .find_words_by_first_word(query)
+    }
+}
+
+pub struct Last;
+
+impl MatchStrategy for Last {
+    fn name(&self) -> &str {
+        "last"
+    }
+
+    fn description(&self) -> &str {
+        "Match the last word within headwords"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        indexes.index.read().unwrap().find_words_by_last_word(query)
+    }
+}
+
+pub struct Ngram {
+    // Minimum trigram similarity of the results
+    pub min_similarity: f64,
+}
+
+impl MatchStrategy for Ngram {
+    fn name(&self) -> &str {
+        "ngram"
+    }
+
+    fn description(&self) -> &str {
+        "Match similar headwords by trigram overlap"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        indexes
+            .index
+            .read()
+            .unwrap()
+            .find_words_by_ngram(query, self.min_similarity)
+    }
+}
+
+pub struct Lev;
+
+impl MatchStrategy for Lev {
+    fn name(&self) -> &str {
+        "lev"
+    }
+
+    fn description(&self) -> &str {
+        "Match headwords within a small edit distance"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        indexes.index.read().unwrap().find_words_by_distance(query)
+    }
+}
+
+pub struct Stem;
+
+// Looks up the stemming language of a database by its English name, e.g.
+// "german", or its ISO 639-1 code, e.g. "de"
+pub fn stem_language(name: &str) -> Option<Algorithm> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "arabic" | "ar" => Algorithm::Arabic,
+        "danish" | "da" => Algorithm::Danish,
+        "dutch" | "nl" => Algorithm::Dutch,
+        "english" | "en" => Algorithm::English,
+        "finnish" | "fi" => Algorithm::Finnish,
+        "french" | "fr" => Algorithm::French,
+        "german" | "de" => Algorithm::German,
+        "greek" | "el" => Algorithm::Greek,
+        "hungarian" | "hu" => Algorithm::Hungarian,
+        "italian" | "it" => Algorithm::Italian,
+        "norwegian" | "no" | "nb" | "nn" => Algorithm::Norwegian,
+        "portuguese" | "pt" => Algorithm::Portuguese,
+        "romanian" | "ro" => Algorithm::Romanian,
+        "russian" | "ru" => Algorithm::Russian,
+        "spanish" | "es" => Algorithm::Spanish,
+        "swedish" | "sv" => Algorithm::Swedish,
+        "tamil" | "ta" => Algorithm::Tamil,
+        "turkish" | "tr" => Algorithm::Turkish,
+        _ => return None,
+    })
+}
+
+impl MatchStrategy for Stem {
+    fn name(&self) -> &str {
+        "stem"
+    }
+
+    fn description(&self) -> &str {
+        "Match headwords with the same stem"
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        indexes.index.read().unwrap().find_words_by_stem(query)
+    }
+}
+
+// Databases without keys for the algorithm are skipped
+impl MatchStrategy for Phonetic {
+    fn name(&self) -> &str {
+        Phonetic::name(*self)
+    }
+
+    fn description(&self) -> &str {
+        Phonetic::description(*self)
+    }
+
+    fn search(&self, indexes: &Indexes, query: &str) -> Result<Vec<IndexEntry>, DictError> {
+        indexes
+            .index
+            .read()
+            .unwrap()
+            .find_words_by_phonetic(*self, query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translit::Transliteration;
+
+    // Matches headwords of the same length as the query
+    struct SameLength;
+
+    impl MatchStrategy for SameLength {
+        fn name(&self) -> &str {
+            "length"
+        }
+
+        fn description(&self) -> &str {
+            "Match headwords of the same length"
+        }
+
+        fn matches(&self, entry: &IndexEntry, query: &str) -> bool {
+            entry.word.chars().count() == query.chars().count()
+        }
+    }
+
+    #[test]
+    fn custom_and_builtin() {
+        let mut index = IndexReader::new();
+        index.parse_dict_index(&b"cat\tA\tB\ndog\tB\tB\nhorse\tC\tB\n"[..]);
+        let mut forms = IndexReader::new();
+        forms.parse_dict_index(&b"dogs\tB\tB\n"[..]);
+        let mut aux = HashMap::new();
+        aux.insert("forms".to_string(), Arc::new(RwLock::new(forms)));
+        let index = RwLock::new(index);
+        let indexes = Indexes {
+            index: &index,
+            aux: &aux,
+        };
+        let words = |res: Result<Vec<IndexEntry>, DictError>| -> Vec<String> {
+            res.unwrap().into_iter().map(|entry| entry.word).collect()
+        };
+        assert_eq!(
+            words(SameLength.search(&indexes, "cow")),
+            vec!["cat", "dog"]
+        );
+        assert_eq!(words(Exact.search(&indexes, "dogs")), vec!["dog"]);
+        assert_eq!(words(Suffix.search(&indexes, "se")), vec!["horse"]);
+        assert!(Phonetic::Soundex.search(&indexes, "dog").is_err());
+    }
+
+    #[test]
+    fn transliterated_matches() {
+        let mut index = IndexReader::new();
+        index.parse_dict_index("москва\tA\tB\nминск\tB\tB\n".as_bytes());
+        index.set_transliterations(vec![Transliteration::Cyrillic]);
+        let mut aux = HashMap::new();
+        let translit = index.transliterated_index().unwrap();
+        aux.insert("translit".to_string(), Arc::new(RwLock::new(translit)));
+        let index = RwLock::new(index);
+        let indexes = Indexes {
+            index: &index,
+            aux: &aux,
+        };
+        let found = indexes.search(&Prefix, "mosk").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].headword(), "москва");
+        assert_eq!(indexes.search(&Prefix, "мин").unwrap()[0].word, "минск");
+        assert!(indexes.search(&Prefix, "kiev").unwrap().is_empty());
+    }
+
+    #[test]
+    fn nprefix_query() {
+        assert_eq!(parse_nprefix("0#10#hack"), Some((0, 10, "hack")));
+        assert_eq!(parse_nprefix("20#10#"), Some((20, 10, "")));
+        assert_eq!(parse_nprefix("5#1#a#b"), Some((5, 1, "a#b")));
+        assert_eq!(parse_nprefix("hack"), None);
+        assert_eq!(parse_nprefix("0#10"), None);
+        assert_eq!(parse_nprefix("-1#10#hack"), None);
+    }
+}
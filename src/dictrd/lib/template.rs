@@ -0,0 +1,187 @@
+// Output templates, so that articles of databases built from different
+// sources are presented the same way. A template is text with the fields
+// {headword}, {pronunciation}, {body}, {database} and {source}. Lines with a
+// field that is empty for an article, e.g. a missing pronunciation, are left
+// out.
+use crate::errors::DictError;
+use crate::filter::{apply_filters, DefinitionFilter};
+use crate::mime::ContentType;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Headword,
+    Pronunciation,
+    Body,
+    Database,
+    Source,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Clone, Debug)]
+pub struct Template {
+    lines: Vec<Vec<Part>>,
+}
+
+// An article split into the parts a template can place
+#[derive(Debug)]
+pub struct Article<'a> {
+    pub headword: &'a str,
+    pub pronunciation: Option<String>,
+    pub body: String,
+    pub database: &'a str,
+    pub source: &'a str,
+}
+
+impl<'a> Article<'a> {
+    // Splits off the line repeating the headword that dictfmt puts at the
+    // start of articles, and a pronunciation in brackets or slashes following
+    // the headword on that line
+    pub fn new(headword: &'a str, text: &str, database: &'a str, source: &'a str) -> Article<'a> {
+        let text = text.trim_start_matches('\n');
+        let (first, rest) = match text.find('\n') {
+            Some(pos) => (&text[..pos], &text[pos + 1..]),
+            None => (text, ""),
+        };
+        let mut pronunciation = None;
+        let mut body = text;
+        let repeated = first
+            .get(..headword.len())
+            .is_some_and(|start| start.to_lowercase() == headword.to_lowercase());
+        if repeated {
+            let tail = first[headword.len()..].trim();
+            let enclosed = |open: char, close: char| {
+                tail.len() > 2 && tail.starts_with(open) && tail.ends_with(close)
+            };
+            if tail.is_empty() {
+                body = rest;
+            } else if enclosed('[', ']') || enclosed('/', '/') {
+                pronunciation = Some(tail.to_string());
+                body = rest;
+            }
+        }
+        Article {
+            headword,
+            pronunciation,
+            body: body.trim_end().to_string(),
+            database,
+            source,
+        }
+    }
+
+    fn field(&self, field: Field) -> &str {
+        match field {
+            Field::Headword => self.headword,
+            Field::Pronunciation => self.pronunciation.as_deref().unwrap_or(""),
+            Field::Body => &self.body,
+            Field::Database => self.database,
+            Field::Source => self.source,
+        }
+    }
+}
+
+impl Template {
+    pub fn new(template: &str) -> Result<Template, DictError> {
+        let mut lines = Vec::new();
+        for line in template.lines() {
+            let mut parts = Vec::new();
+            let mut rest = line;
+            while let Some(start) = rest.find('{') {
+                let end = match rest[start..].find('}') {
+                    Some(end) => start + end,
+                    None => return Err(DictError::SyntaxError("Unterminated template field")),
+                };
+                let field = match &rest[start + 1..end] {
+                    "headword" => Field::Headword,
+                    "pronunciation" => Field::Pronunciation,
+                    "body" => Field::Body,
+                    "database" => Field::Database,
+                    "source" => Field::Source,
+                    _ => return Err(DictError::SyntaxError("Unknown template field")),
+                };
+                if start > 0 {
+                    parts.push(Part::Text(rest[..start].to_string()));
+                }
+                parts.push(Part::Field(field));
+                rest = &rest[end + 1..];
+            }
+            if !rest.is_empty() {
+                parts.push(Part::Text(rest.to_string()));
+            }
+            lines.push(parts);
+        }
+        Ok(Template { lines })
+    }
+
+    pub fn render(&self, article: &Article) -> String {
+        let mut result = String::new();
+        for parts in self.lines.iter() {
+            let missing = parts.iter().any(|part| match part {
+                Part::Field(field) => article.field(*field).is_empty(),
+                Part::Text(_) => false,
+            });
+            if missing {
+                continue;
+            }
+            for part in parts.iter() {
+                match part {
+                    Part::Text(text) => result.push_str(text),
+                    Part::Field(field) => result.push_str(article.field(*field)),
+                }
+            }
+            result.push('\n');
+        }
+        result
+    }
+}
+
+// Prepares an article of a database for sending: the filters are applied in
+// order, then the template lays out the result
+pub fn render(
+    filters: &[Arc<dyn DefinitionFilter>],
+    template: Option<&Template>,
+    content_type: ContentType,
+    headword: &str,
+    text: &str,
+    database: &str,
+    source: &str,
+) -> (ContentType, String) {
+    let (content_type, text) = apply_filters(filters, content_type, text);
+    match template {
+        Some(template) => {
+            let article = Article::new(headword, &text, database, source);
+            (content_type, template.render(&article))
+        }
+        None => (content_type, text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_template() {
+        let template =
+            Template::new("{headword}\n{pronunciation}\n\n{body}\n\n-- {source}").unwrap();
+        let article = Article::new("foo", "foo [fu:]\nA word.\n\n", "db", "Source");
+        assert_eq!(
+            template.render(&article),
+            "foo\n[fu:]\n\nA word.\n\n-- Source\n"
+        );
+        // No pronunciation, the headword line is repeated in the body
+        let article = Article::new("bar", "BAR, n. A word.\n", "db", "Source");
+        assert_eq!(article.pronunciation, None);
+        assert_eq!(
+            template.render(&article),
+            "bar\n\nBAR, n. A word.\n\n-- Source\n"
+        );
+        assert!(Template::new("{word}").is_err());
+        assert!(Template::new("{body").is_err());
+    }
+}
@@ -0,0 +1,62 @@
+// Token bucket limiting the rate data is sent at, or commands are accepted
+// at. The bucket holds at most a second's worth of bytes, so after a pause a
+// burst of that size goes out at once. Bytes taken from an empty bucket are a
+// debt that the caller pays by waiting.
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    // Bytes per second
+    rate: u64,
+    available: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64, now: Instant) -> RateLimiter {
+        RateLimiter {
+            rate,
+            available: rate as f64,
+            last: now,
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    // Takes bytes from the bucket and returns how long to wait before
+    // sending them
+    pub fn reserve(&mut self, bytes: usize, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.available = (self.available + elapsed * self.rate as f64).min(self.rate as f64);
+        self.last = now;
+        self.available -= bytes as f64;
+        if self.available >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(-self.available / self.rate as f64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(1000, start);
+        assert_eq!(limiter.reserve(1000, start), Duration::from_secs(0));
+        assert_eq!(limiter.reserve(500, start), Duration::from_millis(500));
+        // The debt is paid after half a second, then the bucket refills
+        let later = start + Duration::from_millis(1500);
+        assert_eq!(limiter.reserve(1000, later), Duration::from_secs(0));
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(
+            limiter.reserve(1500, much_later),
+            Duration::from_millis(500)
+        );
+    }
+}
@@ -0,0 +1,151 @@
+// Transliteration of headwords and queries to plain Latin, so that e.g.
+// "moskva" finds "Москва". Enabled per database with
+// IndexReader::set_transliterations.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Transliteration {
+    Cyrillic, // Russian, Ukrainian, ... to Latin
+    Greek,    // Greek to Latin
+    Pinyin,   // Pinyin without tone marks or tone numbers
+}
+
+impl Transliteration {
+    pub const ALL: [Transliteration; 3] = [
+        Transliteration::Cyrillic,
+        Transliteration::Greek,
+        Transliteration::Pinyin,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Transliteration::Cyrillic => "cyrillic",
+            Transliteration::Greek => "greek",
+            Transliteration::Pinyin => "pinyin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Transliteration> {
+        Transliteration::ALL
+            .iter()
+            .copied()
+            .find(|t| t.name() == name)
+    }
+
+    // Returns the Latin replacement for a lowercase character, if this
+    // transliteration handles it
+    fn map(self, c: char) -> Option<&'static str> {
+        match self {
+            Transliteration::Cyrillic => cyrillic(c),
+            Transliteration::Greek => greek(c),
+            Transliteration::Pinyin => pinyin(c),
+        }
+    }
+}
+
+// Transliterates a word with all given transliterations. Characters not
+// covered by any of them are kept (lowercased).
+pub fn transliterate(word: &str, transliterations: &[Transliteration]) -> String {
+    let mut result = String::with_capacity(word.len());
+    for c in word.chars().flat_map(|c| c.to_lowercase()) {
+        match transliterations.iter().find_map(|t| t.map(c)) {
+            Some(latin) => result.push_str(latin),
+            None => result.push(c),
+        }
+    }
+    result
+}
+
+fn cyrillic(c: char) -> Option<&'static str> {
+    Some(match c {
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' | 'ґ' => "g",
+        'д' => "d",
+        'е' | 'ё' | 'э' => "e",
+        'є' => "ye",
+        'ж' => "zh",
+        'з' => "z",
+        'и' | 'і' => "i",
+        'ї' => "yi",
+        'й' | 'ы' => "y",
+        'к' => "k",
+        'л' => "l",
+        'м' => "m",
+        'н' => "n",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'у' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ю' => "yu",
+        'я' => "ya",
+        _ => return None,
+    })
+}
+
+fn greek(c: char) -> Option<&'static str> {
+    Some(match c {
+        'α' | 'ά' => "a",
+        'β' => "v",
+        'γ' => "g",
+        'δ' => "d",
+        'ε' | 'έ' => "e",
+        'ζ' => "z",
+        'η' | 'ή' | 'ι' | 'ί' | 'ϊ' | 'ΐ' => "i",
+        'θ' => "th",
+        'κ' => "k",
+        'λ' => "l",
+        'μ' => "m",
+        'ν' => "n",
+        'ξ' => "x",
+        'ο' | 'ό' | 'ω' | 'ώ' => "o",
+        'π' => "p",
+        'ρ' => "r",
+        'σ' | 'ς' => "s",
+        'τ' => "t",
+        'υ' | 'ύ' | 'ϋ' | 'ΰ' => "y",
+        'φ' => "f",
+        'χ' => "ch",
+        'ψ' => "ps",
+        _ => return None,
+    })
+}
+
+fn pinyin(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ā' | 'á' | 'ǎ' | 'à' => "a",
+        'ē' | 'é' | 'ě' | 'è' => "e",
+        'ī' | 'í' | 'ǐ' | 'ì' => "i",
+        'ō' | 'ó' | 'ǒ' | 'ò' => "o",
+        'ū' | 'ú' | 'ǔ' | 'ù' => "u",
+        'ǖ' | 'ǘ' | 'ǚ' | 'ǜ' | 'ü' => "v",
+        // Tone numbers and syllable separators
+        '1'..='5' | '\'' => "",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transliterate_words() {
+        let all = Transliteration::ALL;
+        assert_eq!(transliterate("Москва", &all), "moskva");
+        assert_eq!(transliterate("moskva", &all), "moskva");
+        assert_eq!(transliterate("Αθήνα", &all), "athina");
+        assert_eq!(transliterate("nǐ hǎo", &all), "ni hao");
+        assert_eq!(transliterate("ni3 hao3", &all), "ni hao");
+        assert_eq!(transliterate("Москва", &[Transliteration::Greek]), "москва");
+    }
+}
@@ -0,0 +1,78 @@
+// Decompiles a database into the source format of dictfmt -c5, the inverse of
+// building it: every article is preceded by a line of underscores, an empty
+// line and a line with its headwords. Headwords sharing an article are joined
+// by a separator, to be split again with dictfmt --headword-separator.
+use crate::errors::DictError;
+use crate::normalize::Normalization;
+use crate::{DictReader, IndexEntry, IndexReader, ReadAt};
+use std::io::Write;
+
+pub fn unformat<R: ReadAt, W: Write>(
+    indexreader: &IndexReader,
+    dictreader: &DictReader<R>,
+    separator: &str,
+    out: &mut W,
+) -> Result<usize, DictError> {
+    // Articles in the order of the .dict file, with all their headwords
+    let mut entries: Vec<&IndexEntry> = indexreader.iter().collect();
+    entries.sort_by_key(|entry| (entry.offset, entry.length));
+    let mut articles: Vec<(u64, u64, Vec<&str>)> = Vec::new();
+    for entry in entries {
+        match articles.last_mut() {
+            Some((offset, length, headwords))
+                if *offset == entry.offset && *length == entry.length =>
+            {
+                headwords.push(entry.headword())
+            }
+            _ => articles.push((entry.offset, entry.length, vec![entry.headword()])),
+        }
+    }
+    for (offset, length, headwords) in articles.iter() {
+        let text = dictreader.find(*offset, *length)?;
+        let mut headwords = headwords.clone();
+        // dictfmt repeats the headword as the first line of the article, in
+        // its original spelling which is kept instead of the normalized one
+        let (first, rest) = text.split_once('\n').unwrap_or((&text, ""));
+        let body = match headwords
+            .iter()
+            .position(|headword| normalized(first) == normalized(headword))
+        {
+            Some(pos) => {
+                headwords[pos] = first.trim();
+                rest
+            }
+            None => text.as_str(),
+        };
+        writeln!(out, "_____\n\n{}", headwords.join(separator))?;
+        out.write_all(body.as_bytes())?;
+        if !body.ends_with('\n') {
+            writeln!(out)?;
+        }
+    }
+    Ok(articles.len())
+}
+
+fn normalized(headword: &str) -> String {
+    let headword = Normalization::Default.apply(headword);
+    headword.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn unformat_articles() {
+        let dict = "Colour\nA hue.\nmouse\nA rodent.\n";
+        let mut di = IndexReader::new();
+        di.parse_dict_index("color\tA\tO\ncolour\tA\tO\nmouse\tO\tQ\n".as_bytes());
+        let dr = DictReader::new(BufReader::new(Cursor::new(dict))).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(unformat(&di, &dr, "; ", &mut out).unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "_____\n\ncolor; Colour\nA hue.\n_____\n\nmouse\nA rodent.\n"
+        );
+    }
+}
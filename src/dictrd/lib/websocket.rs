@@ -0,0 +1,215 @@
+// The WebSocket protocol (RFC 6455) on the server side: the key answering
+// the opening handshake and the frames of the messages. Messages from the
+// client are masked, those of the server are not.
+use sha1::{Digest, Sha1};
+use std::io::{self, Read, Write};
+
+// Appended to the client's key before hashing it
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // Close frame, with its status code if there is one
+    Close(Option<u16>),
+}
+
+// Value of the Sec-WebSocket-Accept header answering Sec-WebSocket-Key
+pub fn accept_key(key: &str) -> String {
+    base64::encode(Sha1::digest(format!("{}{}", key.trim(), GUID)))
+}
+
+// Reads the messages of a client, joining fragmented ones. Messages, and
+// the fragments read so far, longer than max bytes are an error, as are
+// frames a client has to mask but did not.
+pub struct Receiver<R> {
+    input: R,
+    max: usize,
+    // Opcode and payload of a fragmented message being received
+    fragments: Option<(u8, Vec<u8>)>,
+}
+
+impl<R: Read> Receiver<R> {
+    pub fn new(input: R, max: usize) -> Receiver<R> {
+        Receiver {
+            input,
+            max,
+            fragments: None,
+        }
+    }
+
+    // The next message, None if the connection was closed without a close
+    // frame
+    pub fn next_message(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            let mut head = [0; 2];
+            match self.input.read_exact(&mut head) {
+                Ok(()) => (),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && self.fragments.is_none() => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e),
+            }
+            let fin = head[0] & 0x80 != 0;
+            let opcode = head[0] & 0x0f;
+            if head[1] & 0x80 == 0 {
+                return Err(invalid("unmasked frame"));
+            }
+            let len = match head[1] & 0x7f {
+                126 => {
+                    let mut len = [0; 2];
+                    self.input.read_exact(&mut len)?;
+                    u16::from_be_bytes(len) as u64
+                }
+                127 => {
+                    let mut len = [0; 8];
+                    self.input.read_exact(&mut len)?;
+                    u64::from_be_bytes(len)
+                }
+                len => len as u64,
+            };
+            let buffered = self.fragments.as_ref().map_or(0, |(_, data)| data.len());
+            if len > (self.max - buffered.min(self.max)) as u64 {
+                return Err(invalid("message too long"));
+            }
+            let mut mask = [0; 4];
+            self.input.read_exact(&mut mask)?;
+            let mut payload = vec![0; len as usize];
+            self.input.read_exact(&mut payload)?;
+            for (i, b) in payload.iter_mut().enumerate() {
+                *b ^= mask[i % 4];
+            }
+
+            let (opcode, payload) = match opcode {
+                // Control frames may come between the fragments of a message
+                PING => return Ok(Some(Message::Ping(payload))),
+                PONG => return Ok(Some(Message::Pong(payload))),
+                CLOSE => {
+                    let code = payload
+                        .get(..2)
+                        .map(|code| u16::from_be_bytes([code[0], code[1]]));
+                    return Ok(Some(Message::Close(code)));
+                }
+                CONTINUATION => match self.fragments.take() {
+                    Some((opcode, mut data)) => {
+                        data.extend_from_slice(&payload);
+                        (opcode, data)
+                    }
+                    None => return Err(invalid("continuation without a message")),
+                },
+                TEXT | BINARY if self.fragments.is_none() => (opcode, payload),
+                TEXT | BINARY => return Err(invalid("message within a fragmented one")),
+                _ => return Err(invalid("unknown opcode")),
+            };
+            if !fin {
+                self.fragments = Some((opcode, payload));
+                continue;
+            }
+            return match opcode {
+                TEXT => match String::from_utf8(payload) {
+                    Ok(text) => Ok(Some(Message::Text(text))),
+                    Err(_) => Err(invalid("text message is not UTF-8")),
+                },
+                _ => Ok(Some(Message::Binary(payload))),
+            };
+        }
+    }
+}
+
+// Writes a message in a single frame
+pub fn write_message<W: Write>(out: &mut W, message: &Message) -> io::Result<()> {
+    let close;
+    let (opcode, payload) = match message {
+        Message::Text(text) => (TEXT, text.as_bytes()),
+        Message::Binary(data) => (BINARY, data.as_slice()),
+        Message::Ping(data) => (PING, data.as_slice()),
+        Message::Pong(data) => (PONG, data.as_slice()),
+        Message::Close(code) => {
+            close = code.map(u16::to_be_bytes);
+            (CLOSE, close.as_ref().map_or(&[][..], |code| &code[..]))
+        }
+    };
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    out.write_all(&frame)?;
+    out.flush()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A frame as a client sends it, masked
+    fn client_frame(first: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![first, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    #[test]
+    fn handshake_and_frames() {
+        // The example of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        let mut input = client_frame(0x81, b"Hello");
+        input.extend(client_frame(0x01, b"Hel"));
+        input.extend(client_frame(0x89, b"ping"));
+        input.extend(client_frame(0x80, b"lo again"));
+        input.extend(client_frame(0x88, &[0x03, 0xe8]));
+        let mut receiver = Receiver::new(Cursor::new(input), 16);
+        let mut next = || receiver.next_message().unwrap();
+        assert_eq!(next(), Some(Message::Text("Hello".to_string())));
+        assert_eq!(next(), Some(Message::Ping(b"ping".to_vec())));
+        assert_eq!(next(), Some(Message::Text("Hello again".to_string())));
+        assert_eq!(next(), Some(Message::Close(Some(1000))));
+        assert_eq!(next(), None);
+
+        let long = client_frame(0x81, &[b'a'; 17]);
+        assert!(Receiver::new(Cursor::new(long), 16).next_message().is_err());
+        let unmasked = vec![0x81, 0x01, b'a'];
+        assert!(Receiver::new(Cursor::new(unmasked), 16)
+            .next_message()
+            .is_err());
+
+        let mut out = Vec::new();
+        write_message(&mut out, &Message::Text("Hello".to_string())).unwrap();
+        assert_eq!(out, b"\x81\x05Hello");
+        out.clear();
+        write_message(&mut out, &Message::Text("a".repeat(200))).unwrap();
+        assert_eq!(&out[..4], &[0x81, 126, 0, 200]);
+        out.clear();
+        write_message(&mut out, &Message::Close(Some(1000))).unwrap();
+        assert_eq!(out, &[0x88, 0x02, 0x03, 0xe8]);
+    }
+}
@@ -0,0 +1,260 @@
+// Builds databases in the dictd format, like dictfmt: the definitions are
+// appended to the .dict file and the .index file lists the normalized
+// headwords in sorted order with the base64 encoded offset and length of
+// their definitions. Headwords changed by the normalization keep their
+// original spelling in a fourth column.
+use crate::errors::DictError;
+use crate::metadata::{is_metadata_headword, DatabaseMetadata};
+use crate::normalize::Normalization;
+use crate::IndexEntry;
+use std::io::{self, Write};
+
+pub struct DictWriter<W: Write> {
+    out: W,
+    offset: u64,
+}
+
+impl<W: Write> DictWriter<W> {
+    pub fn new(out: W) -> DictWriter<W> {
+        DictWriter { out, offset: 0 }
+    }
+
+    // Appends a definition and returns its offset and length
+    pub fn write(&mut self, definition: &str) -> io::Result<(u64, u64)> {
+        let offset = self.offset;
+        let length = definition.len() as u64;
+        self.out.write_all(definition.as_bytes())?;
+        self.offset += length;
+        Ok((offset, length))
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+pub struct IndexWriter {
+    entries: Vec<IndexEntry>,
+    normalization: Normalization,
+}
+
+impl IndexWriter {
+    pub fn new(normalization: Normalization) -> IndexWriter {
+        IndexWriter {
+            entries: Vec::new(),
+            normalization,
+        }
+    }
+
+    pub fn add(&mut self, headword: &str, offset: u64, length: u64) {
+        let headword = headword.trim();
+        let word = self.normalization.apply(headword);
+        // dictfmt names the metadata entries by their normalized form only
+        let original = if word != headword && !is_metadata_headword(headword) {
+            Some(headword.to_string())
+        } else {
+            None
+        };
+        self.entries.push(IndexEntry {
+            word,
+            offset,
+            length,
+            original,
+        });
+    }
+
+    // Writes the entries sorted in the byte order IndexReader looks them up
+    // in, entries of the same headword in the order of the .dict file.
+    // Returns the number of lines written.
+    pub fn write<W: Write>(mut self, mut out: W) -> io::Result<usize> {
+        self.entries
+            .sort_by(|e1, e2| (&e1.word, e1.offset).cmp(&(&e2.word, e2.offset)));
+        for entry in self.entries.iter() {
+            writeln!(out, "{}", entry.to_line())?;
+        }
+        out.flush()?;
+        Ok(self.entries.len())
+    }
+}
+
+// An article of an imported dictionary with all the headwords leading to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Article {
+    pub headwords: Vec<String>,
+    pub definition: String,
+}
+
+impl Article {
+    // An article with the headwords on the first line like dictfmt writes
+    // them, followed by the indented body
+    pub fn new(headwords: Vec<String>, body: &str) -> Article {
+        let mut definition = headwords.join(", ");
+        definition.push('\n');
+        for line in body.trim().lines() {
+            let line = line.trim_end();
+            if !line.is_empty() {
+                definition.push_str("   ");
+                definition.push_str(line);
+            }
+            definition.push('\n');
+        }
+        Article {
+            headwords,
+            definition,
+        }
+    }
+}
+
+// Writes a whole database from pairs of headwords and definitions, together
+// with the 00-database-* entries for its metadata. Definitions are written
+// as given, dictfmt starts them with a line repeating the headword. Returns
+// the number of definitions written, not counting the metadata.
+pub fn write_database<I, H, D, WI, WD>(
+    entries: I,
+    metadata: &DatabaseMetadata,
+    normalization: Normalization,
+    index: WI,
+    dict: WD,
+) -> Result<usize, DictError>
+where
+    I: IntoIterator<Item = (H, D)>,
+    H: AsRef<str>,
+    D: AsRef<str>,
+    WI: Write,
+    WD: Write,
+{
+    let articles = entries.into_iter().map(|(headword, definition)| Article {
+        headwords: vec![headword.as_ref().to_string()],
+        definition: definition.as_ref().to_string(),
+    });
+    write_articles(articles, metadata, normalization, index, dict)
+}
+
+// Like write_database, but every article is written once for all its
+// headwords
+pub fn write_articles<I, WI, WD>(
+    articles: I,
+    metadata: &DatabaseMetadata,
+    normalization: Normalization,
+    index: WI,
+    dict: WD,
+) -> Result<usize, DictError>
+where
+    I: IntoIterator<Item = Article>,
+    WI: Write,
+    WD: Write,
+{
+    let mut dictwriter = DictWriter::new(dict);
+    let mut indexwriter = IndexWriter::new(normalization);
+    // Entries with a value, and flags without
+    let mut meta = vec![
+        ("short", Some(metadata.short.as_str())),
+        ("info", Some(metadata.info.as_str())),
+    ];
+    if let Some(url) = &metadata.url {
+        meta.push(("url", Some(url.as_str())));
+    }
+    if metadata.utf8 {
+        meta.push(("utf8", None));
+    }
+    if metadata.eightbit_new {
+        meta.push(("8bit-new", None));
+    }
+    if normalization.allchars() {
+        meta.push(("allchars", None));
+    }
+    if normalization.case_sensitive() {
+        meta.push(("case-sensitive", None));
+    }
+    for (name, value) in meta {
+        let headword = format!("00-database-{}", name);
+        let definition = match value {
+            Some(value) => format!("{}\n{}\n", headword, value),
+            None => format!("{}\n", headword),
+        };
+        let (offset, length) = dictwriter.write(&definition)?;
+        indexwriter.add(&headword, offset, length);
+    }
+    let mut count = 0;
+    for article in articles {
+        let (offset, length) = dictwriter.write(&article.definition)?;
+        for headword in article.headwords.iter() {
+            indexwriter.add(headword, offset, length);
+        }
+        count += 1;
+    }
+    dictwriter.finish()?;
+    indexwriter.write(index)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DictReader, IndexReader};
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn roundtrip() {
+        let entries = [
+            ("Zebra", "Zebra\n   A striped horse.\n"),
+            ("AT&T", "AT&T\n   A telephone company.\n"),
+            ("apple", "apple\n   A fruit.\n"),
+            ("apple", "apple\n   A computer company.\n"),
+        ];
+        let metadata = DatabaseMetadata {
+            short: "Test dictionary".to_string(),
+            info: "Written by the tests".to_string(),
+            url: None,
+            utf8: true,
+            eightbit_new: false,
+            dictfmt: None,
+        };
+        let mut index = Vec::new();
+        let mut dict = Vec::new();
+        let count = write_database(
+            entries,
+            &metadata,
+            Normalization::Default,
+            &mut index,
+            &mut dict,
+        )
+        .unwrap();
+        assert_eq!(count, 4);
+
+        let text = String::from_utf8(index.clone()).unwrap();
+        let words: Vec<&str> = text
+            .lines()
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(
+            words,
+            vec![
+                "00databaseinfo",
+                "00databaseshort",
+                "00databaseutf8",
+                "apple",
+                "apple",
+                "att",
+                "zebra"
+            ]
+        );
+        assert!(text.contains("\tAT&T\n"));
+
+        let mut di = IndexReader::new();
+        di.parse_dict_index(Cursor::new(index));
+        let dr = DictReader::new(BufReader::new(Cursor::new(dict))).unwrap();
+        let loaded = DatabaseMetadata::load(&di, &dr);
+        assert_eq!(loaded.short, "Test dictionary");
+        assert_eq!(loaded.info, "Written by the tests");
+        assert!(loaded.utf8);
+        let (offset, length) = di.find_word("att").unwrap();
+        assert_eq!(
+            dr.find(offset, length).unwrap(),
+            "AT&T\n   A telephone company.\n"
+        );
+        let apples = di.find_words_by_prefix("apple").unwrap();
+        assert_eq!(apples.len(), 2);
+    }
+}
@@ -0,0 +1,177 @@
+// Reads XDXF dictionaries, both the old visual format with <full_name> and
+// <ar> directly below <xdxf> and the newer one with <meta_info> and
+// <lexicon>. Every <ar> becomes an article with its <k> keys as headwords.
+// The markup of the articles is either kept as it is or reduced to plain
+// text, with <kref> references written as {word} like dictfmt does.
+use crate::errors::DictError;
+use crate::metadata::DatabaseMetadata;
+use crate::writer::Article;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::BufRead;
+
+pub struct Xdxf {
+    pub metadata: DatabaseMetadata,
+    pub articles: Vec<Article>,
+}
+
+// Metadata element whose text is being read
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Short,
+    Info,
+}
+
+pub fn read_xdxf<B: BufRead>(input: B, keep_markup: bool) -> Result<Xdxf, DictError> {
+    let malformed = |_| DictError::SyntaxError("Malformed XDXF");
+    let mut reader = Reader::from_reader(input);
+    let mut metadata = DatabaseMetadata {
+        utf8: true,
+        ..DatabaseMetadata::default()
+    };
+    let mut articles = Vec::new();
+    let mut field = None;
+    let mut in_article = false;
+    let mut in_key = false;
+    let mut key = String::new();
+    let mut headwords: Vec<String> = Vec::new();
+    let mut body = String::new();
+    let mut buf = Vec::new();
+    loop {
+        let event = reader.read_event_into(&mut buf).map_err(malformed)?;
+        match event {
+            Event::Start(e) => match e.name().as_ref() {
+                b"ar" => {
+                    in_article = true;
+                    headwords.clear();
+                    body.clear();
+                }
+                b"k" if in_article => {
+                    in_key = true;
+                    key.clear();
+                }
+                // Tags inside keys, like <opt>, only add their text
+                _ if in_key => {}
+                name if in_article => {
+                    if keep_markup {
+                        body.push('<');
+                        body.push_str(&String::from_utf8_lossy(&e));
+                        body.push('>');
+                    } else if name == b"kref" {
+                        body.push('{');
+                    }
+                }
+                b"full_name" | b"full_title" => field = Some(Field::Short),
+                // The short title of the newer format, if there is no full one
+                b"title" if metadata.short == "Unknown" => field = Some(Field::Short),
+                b"description" => field = Some(Field::Info),
+                _ => {}
+            },
+            Event::End(e) => match e.name().as_ref() {
+                b"ar" if in_article => {
+                    in_article = false;
+                    if !headwords.is_empty() {
+                        articles.push(Article::new(headwords.clone(), &body));
+                    }
+                }
+                b"k" if in_key => {
+                    in_key = false;
+                    let headword = key.split_whitespace().collect::<Vec<&str>>().join(" ");
+                    if !headword.is_empty() {
+                        headwords.push(headword);
+                    }
+                }
+                _ if in_key => {}
+                name if in_article => {
+                    if keep_markup {
+                        body.push_str("</");
+                        body.push_str(&String::from_utf8_lossy(name));
+                        body.push('>');
+                    } else if name == b"kref" {
+                        body.push('}');
+                    }
+                }
+                _ => field = None,
+            },
+            Event::Empty(e) if in_article && !in_key => {
+                if keep_markup {
+                    body.push('<');
+                    body.push_str(&String::from_utf8_lossy(&e));
+                    body.push_str("/>");
+                } else if e.name().as_ref() == b"br" {
+                    body.push('\n');
+                }
+            }
+            Event::Text(e) => {
+                let text = e.unescape().map_err(malformed)?;
+                if in_key {
+                    key.push_str(&text);
+                } else if in_article {
+                    if keep_markup {
+                        body.push_str(&String::from_utf8_lossy(&e));
+                    } else {
+                        body.push_str(&text);
+                    }
+                } else if let Some(field) = field {
+                    let text = text.trim().to_string();
+                    match field {
+                        Field::Short => metadata.short = text,
+                        Field::Info => metadata.info = text,
+                    }
+                }
+            }
+            Event::CData(e) => {
+                let text = String::from_utf8_lossy(&e);
+                if in_key {
+                    key.push_str(&text);
+                } else if in_article {
+                    body.push_str(&text);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(Xdxf { metadata, articles })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XDXF: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xdxf lang_from="ENG" lang_to="NOR" format="visual">
+<full_name>English-Norwegian</full_name>
+<description>A small test dictionary</description>
+<ar><k>apple</k><k>apples</k>
+<tr>ˈæpl</tr> <i>n</i> eple, see also <kref>pear</kref><br/>fruit &amp; tree</ar>
+<ar><k>pe<opt>a</opt>r</k>
+pære</ar>
+</xdxf>"#;
+
+    #[test]
+    fn plain_text() {
+        let xdxf = read_xdxf(XDXF.as_bytes(), false).unwrap();
+        assert_eq!(xdxf.metadata.short, "English-Norwegian");
+        assert_eq!(xdxf.metadata.info, "A small test dictionary");
+        assert_eq!(xdxf.articles.len(), 2);
+        assert_eq!(xdxf.articles[0].headwords, vec!["apple", "apples"]);
+        assert_eq!(
+            xdxf.articles[0].definition,
+            "apple, apples\n   ˈæpl n eple, see also {pear}\n   fruit & tree\n"
+        );
+        assert_eq!(xdxf.articles[1].headwords, vec!["pear"]);
+        assert_eq!(xdxf.articles[1].definition, "pear\n   pære\n");
+    }
+
+    #[test]
+    fn keep_markup() {
+        let xdxf = read_xdxf(XDXF.as_bytes(), true).unwrap();
+        assert_eq!(
+            xdxf.articles[0].definition,
+            "apple, apples\n   <tr>ˈæpl</tr> <i>n</i> eple, see also <kref>pear</kref><br/>fruit &amp; tree\n"
+        );
+        assert!(read_xdxf("<xdxf><ar><k>a</k></b></ar></xdxf>".as_bytes(), false).is_err());
+    }
+}
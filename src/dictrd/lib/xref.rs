@@ -0,0 +1,37 @@
+// Cross references of definitions. dictfmt databases mark the headwords a
+// definition refers to in braces, e.g. "see {hacker ethic}", and long
+// references may be broken across lines.
+
+// Returns the references of a definition in the order they first appear,
+// with the line breaks and indentation within them collapsed
+pub fn references(text: &str) -> Vec<String> {
+    let mut references: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(end) = rest.find('}') {
+        if let Some(start) = rest[..end].rfind('{') {
+            let reference = rest[start + 1..end]
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ");
+            if !reference.is_empty() && !references.contains(&reference) {
+                references.push(reference);
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn braced_references() {
+        let text = "hack n. 1. Originally, a quick job (see {kluge}).\n\
+                    See also {hacker\n     ethic}, {kluge} and {}.\n\
+                    Unbalanced } and {{nested} and {open";
+        assert_eq!(references(text), vec!["kluge", "hacker ethic", "nested"]);
+        assert!(references("no references").is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+extern crate dictrdlib;
+
+use clap::{App, Arg, ArgMatches};
+use dictrdlib::dictzip::{self, CHUNK_LEN};
+use dictrdlib::errors::DictError;
+use dictrdlib::metadata::{is_metadata_headword, DatabaseMetadata};
+use dictrdlib::normalize::Normalization;
+use dictrdlib::source::{read_source, Source, SourceFormat};
+use dictrdlib::writer::write_articles;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::process::exit;
+
+// Reads the source from a file, or from stdin for "-"
+fn read_input(matches: &ArgMatches) -> Source {
+    let input = matches.value_of("input").unwrap();
+    let format = SourceFormat::from_name(matches.value_of("format").unwrap_or("c5")).unwrap();
+    let separator = matches.value_of("separator");
+    let reader: Box<dyn BufRead> = if input == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        match File::open(input) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("Could not open {}: {}", input, e);
+                exit(1);
+            }
+        }
+    };
+    match read_source(reader, format, separator) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", input, e);
+            exit(1);
+        }
+    }
+}
+
+// Removes the 00-database-* entries of the source, e.g. of a database
+// decompiled with dictrutil unformat, and returns their values by name
+fn take_metadata(source: &mut Source) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    source.articles.retain(|article| {
+        let headword = article.headwords[0].as_str();
+        if !is_metadata_headword(headword) {
+            return true;
+        }
+        let name = headword
+            .trim_start_matches("00-database-")
+            .trim_start_matches("00database");
+        // The definition starts with the headword
+        let value = article
+            .definition
+            .split_once('\n')
+            .map_or("", |(_, value)| value);
+        metadata.insert(name.to_string(), value.trim().to_string());
+        false
+    });
+    metadata
+}
+
+// Writes the .index and the .dict file, or the .dict.dz file with --dictzip,
+// which is compressed once the whole .dict is written
+// Options take precedence over the metadata of the source
+fn write(matches: &ArgMatches, mut source: Source) -> Result<(String, String, usize), DictError> {
+    let basename = matches.value_of("basename").unwrap();
+    let mut given = take_metadata(&mut source);
+    let short = matches
+        .value_of("short")
+        .map(str::to_string)
+        .or_else(|| given.remove("short"))
+        .unwrap_or_else(|| basename.to_string());
+    let info = match given.remove("info") {
+        Some(info) => info,
+        None if !source.preamble.is_empty() => source.preamble,
+        None => short.clone(),
+    };
+    let metadata = DatabaseMetadata {
+        short,
+        info,
+        url: matches
+            .value_of("url")
+            .map(str::to_string)
+            .or_else(|| given.remove("url")),
+        utf8: true,
+        ..DatabaseMetadata::default()
+    };
+    let normalization = Normalization::from_flags(
+        matches.is_present("allchars"),
+        matches.is_present("case-sensitive"),
+    );
+    let index = format!("{}.index", basename);
+    let index_file = BufWriter::new(File::create(&index)?);
+    if matches.is_present("dictzip") {
+        let dict = format!("{}.dict.dz", basename);
+        let mut data = Vec::new();
+        let count = write_articles(
+            source.articles,
+            &metadata,
+            normalization,
+            index_file,
+            &mut data,
+        )?;
+        dictzip::compress(&data, CHUNK_LEN, BufWriter::new(File::create(&dict)?))?;
+        Ok((index, dict, count))
+    } else {
+        let dict = format!("{}.dict", basename);
+        let dict_file = BufWriter::new(File::create(&dict)?);
+        let count = write_articles(
+            source.articles,
+            &metadata,
+            normalization,
+            index_file,
+            dict_file,
+        )?;
+        Ok((index, dict, count))
+    }
+}
+
+fn main() {
+    let matches = App::new("dictrfmt")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Jan Schreiber <jan@mecinus.com>")
+        .about("Builds a dictd database from plain text, like dictfmt")
+        .arg(
+            Arg::with_name("input")
+                .help("source of the dictionary, - for stdin")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("basename")
+                .help("name of the database, written to basename.index and basename.dict")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .short("f")
+                .value_name("format")
+                .possible_values(&["c5", "tsv", "jargon"])
+                .help("c5 (default): articles after lines of underscores, tsv: headword and definition separated by a tab, jargon: articles starting with :headword:")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("short")
+                .long("short")
+                .short("s")
+                .value_name("name")
+                .help("short name of the database shown by SHOW DB")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .short("u")
+                .value_name("url")
+                .help("URL of the source of the database")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("separator")
+                .long("headword-separator")
+                .value_name("separator")
+                .help("separator of headwords sharing an article")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("allchars")
+                .long("allchars")
+                .help("keep all characters of headwords like dictfmt --allchars"),
+        )
+        .arg(
+            Arg::with_name("case-sensitive")
+                .long("case-sensitive")
+                .help("keep the case of headwords like dictfmt --case-sensitive"),
+        )
+        .arg(
+            Arg::with_name("dictzip")
+                .long("dictzip")
+                .short("z")
+                .help("compress the .dict file with dictzip to basename.dict.dz"),
+        )
+        .get_matches();
+    let source = read_input(&matches);
+    match write(&matches, source) {
+        Ok((index, dict, count)) => eprintln!("Wrote {} articles to {} and {}", count, index, dict),
+        Err(e) => {
+            eprintln!("Could not write database: {}", e);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,547 @@
+extern crate dictrdlib;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use dictrdlib::audit::{invalid_entries, transcode};
+use dictrdlib::clean::{check, write_clean_index, Problem};
+use dictrdlib::collation::Collation;
+use dictrdlib::dictionary::Dictionary;
+use dictrdlib::dsl::read_dsl;
+use dictrdlib::errors::DictError;
+use dictrdlib::export::{export, Format};
+use dictrdlib::metadata::{is_metadata_headword, DatabaseMetadata};
+use dictrdlib::normalize::Normalization;
+#[cfg(feature = "sqlite")]
+use dictrdlib::sqlite::SqliteDictionary;
+use dictrdlib::unformat::unformat;
+use dictrdlib::writer::{write_articles, Article};
+use dictrdlib::xdxf::read_xdxf;
+use dictrdlib::{DictReader, IndexEntry, IndexReader};
+use flate2::bufread::GzDecoder;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::process::exit;
+
+// Opens the .index and .dict files of a database
+fn open_database(matches: &ArgMatches) -> (IndexReader, DictReader<File>) {
+    let index = matches.value_of("index").unwrap();
+    let dict = matches.value_of("dict").unwrap();
+    let mut di = IndexReader::new();
+    match File::open(index) {
+        Ok(file) => di.parse_dict_index(BufReader::new(file)),
+        Err(e) => {
+            eprintln!("Could not open {}: {}", index, e);
+            exit(1);
+        }
+    }
+    let dr = match File::open(dict).and_then(|file| DictReader::new(BufReader::new(file))) {
+        Ok(dr) => dr,
+        Err(e) => {
+            eprintln!("Could not open {}: {}", dict, e);
+            exit(1);
+        }
+    };
+    (di, dr)
+}
+
+// Opens the output file, stdout if none is given
+fn open_output(matches: &ArgMatches) -> Box<dyn Write> {
+    match matches.value_of("output") {
+        Some(output) => match File::create(output) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("Could not create {}: {}", output, e);
+                exit(1);
+            }
+        },
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    }
+}
+
+fn command_unformat(matches: &ArgMatches) {
+    let (di, dr) = open_database(matches);
+    let mut out = open_output(matches);
+    let separator = matches.value_of("separator").unwrap_or("; ");
+    match unformat(&di, &dr, separator, &mut out) {
+        Ok(count) => eprintln!("Wrote {} articles", count),
+        Err(e) => {
+            eprintln!("Could not decompile database: {:?}", e);
+            exit(1);
+        }
+    }
+}
+
+// Dictionary source is written like unformat does, the other formats from
+// the articles of the opened dictionary, without its 00-database entries
+fn command_export(matches: &ArgMatches) {
+    let format = match matches.value_of("format").unwrap_or("jsonl") {
+        "c5" => return command_unformat(matches),
+        name => match Format::from_name(name) {
+            Some(format) => format,
+            None => {
+                eprintln!("Unknown format {}, use jsonl, csv or c5", name);
+                exit(1);
+            }
+        },
+    };
+    let index = matches.value_of("index").unwrap();
+    let dict = matches.value_of("dict").unwrap();
+    let dictionary = match Dictionary::open(index, dict) {
+        Ok(dictionary) => dictionary,
+        Err(e) => {
+            eprintln!("Could not open {} and {}: {}", index, dict, e);
+            exit(1);
+        }
+    };
+    let mut out = open_output(matches);
+    match export(dictionary.iter(), format, &mut out).and_then(|count| {
+        out.flush()?;
+        Ok(count)
+    }) {
+        Ok(count) => eprintln!("Wrote {} articles", count),
+        Err(e) => {
+            eprintln!("Could not export database: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn command_clean(matches: &ArgMatches) {
+    let (di, _) = open_database(matches);
+    let dict = matches.value_of("dict").unwrap();
+    let dict_len = match std::fs::metadata(dict) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            eprintln!("Could not read {}: {}", dict, e);
+            exit(1);
+        }
+    };
+    let problems = check(&di, dict_len);
+    for (pos, problem) in problems.iter() {
+        let entry = &di.entries()[*pos];
+        match problem {
+            Problem::Duplicate => eprintln!("Duplicate entry: {}", entry.to_line()),
+            Problem::SharedRange(word) => {
+                eprintln!("Same range as \"{}\": {}", word, entry.to_line())
+            }
+            Problem::Dangling => eprintln!("Range exceeds dict file: {}", entry.to_line()),
+        }
+    }
+    if !matches.is_present("fix") {
+        eprintln!("Found {} problems", problems.len());
+        if !problems.is_empty() {
+            exit(2);
+        }
+        return;
+    }
+    let output = rewrite_index(matches, |out| {
+        let removed = write_clean_index(&di, &problems, matches.is_present("shared"), out)?;
+        eprintln!("Removed {} entries", removed);
+        Ok(())
+    });
+    eprintln!("Wrote {}", output);
+}
+
+fn command_sort(matches: &ArgMatches) {
+    let index = matches.value_of("index").unwrap();
+    let mut di = IndexReader::new();
+    if let Some(locale) = matches.value_of("locale") {
+        match Collation::new(locale) {
+            Ok(collation) => di.set_collation(collation),
+            Err(_) => {
+                eprintln!("Unknown locale {}", locale);
+                exit(1);
+            }
+        }
+    }
+    let lines = match std::fs::read_to_string(index) {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", index, e);
+            exit(1);
+        }
+    };
+    di.parse_dict_index(lines.as_bytes());
+    if matches.is_present("normalize") {
+        let normalization = di.normalization();
+        let changed = di.normalize_headwords(normalization);
+        eprintln!("Normalized {} headwords", changed);
+    }
+    let normalization = di.normalization();
+    let unsorted = lines
+        .lines()
+        .map(|line| search_key(normalization, line.split('\t').next().unwrap_or("")))
+        .collect::<Vec<String>>()
+        .windows(2)
+        .filter(|pair| di.compare_headwords(&pair[0], &pair[1]) == Ordering::Greater)
+        .count();
+    eprintln!("Found {} lines out of order", unsorted);
+    let output = rewrite_index(matches, |out| {
+        for entry in search_order(&di) {
+            writeln!(out, "{}", entry.to_line())?;
+        }
+        Ok(())
+    });
+    eprintln!("Wrote {}", output);
+}
+
+// The entries in the order the server searches them: by their headwords
+// normalized like queries, e.g. case folded and without punctuation unless
+// the index keeps them, in the order of the index's locale
+fn search_order(di: &IndexReader) -> Vec<&IndexEntry> {
+    let normalization = di.normalization();
+    let mut entries: Vec<(String, &IndexEntry)> = di
+        .iter()
+        .map(|entry| (search_key(normalization, &entry.word), entry))
+        .collect();
+    entries.sort_by(|a, b| di.compare_headwords(&a.0, &b.0));
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+// The 00-database-* entries keep their names, like in the server
+fn search_key(normalization: Normalization, headword: &str) -> String {
+    match is_metadata_headword(headword) {
+        true => headword.to_string(),
+        false => normalization.apply(headword),
+    }
+}
+
+fn command_utf8(matches: &ArgMatches) {
+    let (di, dr) = open_database(matches);
+    let invalid = match invalid_entries(&di, &dr) {
+        Ok(invalid) => invalid,
+        Err(e) => {
+            eprintln!("Could not read articles, run clean first: {:?}", e);
+            exit(1);
+        }
+    };
+    for pos in invalid.iter() {
+        eprintln!("Invalid UTF-8: {}", di.entries()[*pos].headword());
+    }
+    eprintln!("Found {} entries with invalid UTF-8", invalid.len());
+    if !matches.is_present("fix") {
+        if !invalid.is_empty() {
+            exit(2);
+        }
+        return;
+    }
+    // Both files are written to temporary files first and replaced together
+    let index = matches.value_of("index").unwrap();
+    let dict = matches.value_of("dict").unwrap();
+    let (index_tmp, dict_tmp) = (format!("{}.tmp", index), format!("{}.tmp", dict));
+    let result = File::create(&index_tmp)
+        .and_then(|index_file| Ok((index_file, File::create(&dict_tmp)?)))
+        .map_err(DictError::from)
+        .and_then(|(index_file, dict_file)| {
+            let mut index_out = BufWriter::new(index_file);
+            let mut dict_out = BufWriter::new(dict_file);
+            let transcoded = transcode(&di, &dr, &mut dict_out, &mut index_out)?;
+            index_out.flush()?;
+            dict_out.flush()?;
+            std::fs::rename(&dict_tmp, dict)?;
+            std::fs::rename(&index_tmp, index)?;
+            Ok(transcoded)
+        });
+    match result {
+        Ok(transcoded) => eprintln!("Transcoded {} articles", transcoded),
+        Err(e) => {
+            eprintln!("Could not transcode database: {:?}", e);
+            exit(1);
+        }
+    }
+}
+
+// Reads a dictionary in the format given by its file name extension
+fn read_dictionary(
+    input: &str,
+    file: BufReader<File>,
+    keep_markup: bool,
+) -> Result<(DatabaseMetadata, Vec<Article>), DictError> {
+    if input.ends_with(".xdxf") {
+        let xdxf = read_xdxf(file, keep_markup)?;
+        Ok((xdxf.metadata, xdxf.articles))
+    } else if input.ends_with(".dsl.dz") {
+        let dsl = read_dsl(GzDecoder::new(file), keep_markup)?;
+        Ok((dsl.metadata, dsl.articles))
+    } else if input.ends_with(".dsl") {
+        let dsl = read_dsl(file, keep_markup)?;
+        Ok((dsl.metadata, dsl.articles))
+    } else {
+        Err(DictError::SyntaxError("Unknown dictionary format"))
+    }
+}
+
+// Converts a dictionary in another format to a .index and .dict file
+fn command_import(matches: &ArgMatches) {
+    let input = matches.value_of("input").unwrap();
+    let database = matches.value_of("database").unwrap();
+    let keep_markup = matches.is_present("keep-markup");
+    let file = match File::open(input) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => {
+            eprintln!("Could not open {}: {}", input, e);
+            exit(1);
+        }
+    };
+    let (metadata, articles) = match read_dictionary(input, file, keep_markup) {
+        Ok(imported) => imported,
+        Err(e) => {
+            eprintln!("Could not read {}: {:?}", input, e);
+            exit(1);
+        }
+    };
+    let normalization = Normalization::from_flags(
+        matches.is_present("allchars"),
+        matches.is_present("case-sensitive"),
+    );
+    let (index, dict) = (format!("{}.index", database), format!("{}.dict", database));
+    let result = File::create(&index)
+        .and_then(|index_file| Ok((index_file, File::create(&dict)?)))
+        .map_err(DictError::from)
+        .and_then(|(index_file, dict_file)| {
+            write_articles(
+                articles,
+                &metadata,
+                normalization,
+                BufWriter::new(index_file),
+                BufWriter::new(dict_file),
+            )
+        });
+    match result {
+        Ok(count) => eprintln!("Wrote {} articles to {} and {}", count, index, dict),
+        Err(e) => {
+            eprintln!("Could not write database: {:?}", e);
+            exit(1);
+        }
+    }
+}
+
+// Copies the articles of a database to a SQLite database for dictrd
+#[cfg(feature = "sqlite")]
+fn command_sqlite(matches: &ArgMatches) {
+    let (mut di, dr) = open_database(matches);
+    di.remove_metadata_entries();
+    let output = matches.value_of("output").unwrap();
+    let result = SqliteDictionary::create(std::path::Path::new(output)).and_then(|sqlite| {
+        sqlite.insert(di.entries().iter().filter_map(|entry| {
+            match dr.find(entry.offset, entry.length) {
+                Ok(article) => Some((entry.headword(), article)),
+                Err(e) => {
+                    eprintln!("Could not read {}: {:?}", entry.headword(), e);
+                    None
+                }
+            }
+        }))
+    });
+    match result {
+        Ok(count) => eprintln!("Wrote {} articles to {}", count, output),
+        Err(e) => {
+            eprintln!("Could not write {}: {}", output, e);
+            exit(1);
+        }
+    }
+}
+
+// Writes an index to the output file, by default replacing the input index.
+// The file is replaced only after the new index has been written completely.
+fn rewrite_index<'a, F>(matches: &'a ArgMatches, write: F) -> &'a str
+where
+    F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+{
+    let index = matches.value_of("index").unwrap();
+    let output = matches.value_of("output").unwrap_or(index);
+    let tmp = format!("{}.tmp", output);
+    let result = File::create(&tmp).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        out.flush()?;
+        std::fs::rename(&tmp, output)
+    });
+    if let Err(e) = result {
+        eprintln!("Could not write {}: {}", output, e);
+        exit(1);
+    }
+    output
+}
+
+fn main() {
+    let database_args = [
+        Arg::with_name("index")
+            .help("index file of the database")
+            .required(true)
+            .index(1),
+        Arg::with_name("dict")
+            .help("dict file of the database")
+            .required(true)
+            .index(2),
+    ];
+    let output_arg = Arg::with_name("output")
+        .long("output")
+        .short("o")
+        .value_name("file")
+        .help("write to file instead of stdout")
+        .takes_value(true);
+    let app = App::new("dictrutil")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Jan Schreiber <jan@mecinus.com>")
+        .about("Maintenance tools for dictd databases")
+        .subcommand(
+            SubCommand::with_name("unformat")
+                .about("decompile a database to dictfmt -c5 source")
+                .args(&database_args)
+                .arg(output_arg.clone())
+                .arg(
+                    Arg::with_name("separator")
+                        .long("headword-separator")
+                        .value_name("separator")
+                        .help("separator of headwords sharing an article")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("dump the articles of a database as JSON Lines, CSV or dictfmt -c5 source")
+                .args(&database_args)
+                .arg(output_arg.clone())
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .short("f")
+                        .value_name("format")
+                        .possible_values(&["jsonl", "csv", "c5"])
+                        .help("jsonl (default), csv or c5")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("separator")
+                        .long("headword-separator")
+                        .value_name("separator")
+                        .help("separator of headwords sharing an article in c5 source")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clean")
+                .about("find duplicate and dangling index entries")
+                .args(&database_args)
+                .arg(
+                    Arg::with_name("fix")
+                        .long("fix")
+                        .help("rewrite the index without the entries found"),
+                )
+                .arg(
+                    Arg::with_name("shared")
+                        .long("shared-ranges")
+                        .help("also remove headwords sharing an article with another one"),
+                )
+                .arg(output_arg.clone().help("write the clean index to file")),
+        )
+        .subcommand(
+            SubCommand::with_name("sort")
+                .about("re-sort an index in the order lookups expect")
+                .arg(
+                    Arg::with_name("index")
+                        .help("index file of the database")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("locale")
+                        .long("locale")
+                        .value_name("locale")
+                        .help("sort with the collation of locale instead of bytes")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("normalize")
+                        .long("normalize")
+                        .help("normalize headwords, keeping the original as fourth column"),
+                )
+                .arg(output_arg.clone().help("write the sorted index to file")),
+        )
+        .subcommand(
+            SubCommand::with_name("utf8")
+                .about("find articles that are not valid UTF-8")
+                .args(&database_args)
+                .arg(
+                    Arg::with_name("fix")
+                        .long("fix")
+                        .help("transcode them from a guessed legacy encoding"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("convert an XDXF or DSL dictionary to a database")
+                .arg(
+                    Arg::with_name("input")
+                        .help("dictionary to convert (.xdxf, .dsl or .dsl.dz)")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("database")
+                        .help("name of the database, written to database.index and database.dict")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("keep-markup")
+                        .long("keep-markup")
+                        .help("keep the markup of the articles instead of converting them to text"),
+                )
+                .arg(
+                    Arg::with_name("allchars")
+                        .long("allchars")
+                        .help("keep all characters of headwords like dictfmt --allchars"),
+                )
+                .arg(
+                    Arg::with_name("case-sensitive")
+                        .long("case-sensitive")
+                        .help("keep the case of headwords like dictfmt --case-sensitive"),
+                ),
+        );
+    #[cfg(feature = "sqlite")]
+    let app = app.subcommand(
+        SubCommand::with_name("sqlite")
+            .about("copy the articles of a database to a SQLite database")
+            .args(&database_args)
+            .arg(
+                output_arg
+                    .clone()
+                    .required(true)
+                    .help("SQLite database to add the articles to"),
+            ),
+    );
+    let matches = app.get_matches();
+    match matches.subcommand() {
+        ("unformat", Some(matches)) => command_unformat(matches),
+        ("export", Some(matches)) => command_export(matches),
+        ("clean", Some(matches)) => command_clean(matches),
+        ("sort", Some(matches)) => command_sort(matches),
+        ("utf8", Some(matches)) => command_utf8(matches),
+        ("import", Some(matches)) => command_import(matches),
+        #[cfg(feature = "sqlite")]
+        ("sqlite", Some(matches)) => command_sqlite(matches),
+        _ => {
+            eprintln!("{}", matches.usage());
+            exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_order() {
+        let mut di = IndexReader::new();
+        di.parse_dict_index("Zork\tA\tB\na-b\tB\tB\n00-database-url\tC\tB\nAb\tD\tB\n".as_bytes());
+        let headwords: Vec<&str> = search_order(&di)
+            .iter()
+            .map(|entry| entry.word.as_str())
+            .collect();
+        // Case and punctuation are ignored, Ab and a-b meet as ab
+        assert_eq!(headwords, ["00-database-url", "Ab", "a-b", "Zork"]);
+    }
+}